@@ -1,6 +1,9 @@
 use std::path::Path;
+use tracing::debug;
+use crate::config::{PatternRule, PatternSyntax, TextSniffing};
 use crate::error::{OrchestratorError, Result};
 
+/// A file's category, as detected from its content or extension
 #[derive(Debug, Clone, PartialEq)]
 pub enum FileType {
     Image,
@@ -22,40 +25,94 @@ impl FileType {
             FileType::Unknown => "unknown",
         }
     }
+
+    /// The type of a built-in category; `None` for custom ones
+    pub fn from_category(category: &str) -> Option<Self> {
+        match category {
+            "images" => Some(FileType::Image),
+            "videos" => Some(FileType::Video),
+            "music" => Some(FileType::Audio),
+            "documents" => Some(FileType::Document),
+            "archives" => Some(FileType::Archive),
+            _ => None,
+        }
+    }
+
+    /// Whether files of this type usually shrink under general-purpose
+    /// compression (images, video, audio and archives are already compressed)
+    pub fn is_compressible(&self) -> bool {
+        matches!(self, FileType::Document)
+    }
 }
 
+/// Detects file types; see also [`FileRules`](crate::config::FileRules) for
+/// the configured extension lists
 pub struct FileClassifier;
 
 impl FileClassifier {
-    /// Classify file by reading its magic bytes (more reliable than extension)
-    pub fn classify_by_content<P: AsRef<Path>>(path: P) -> Result<FileType> {
-        let kind = infer::get_from_path(path.as_ref())
-            .map_err(|e| OrchestratorError::Classification(format!("Failed to read file: {}", e)))?;
+    /// Classify file by reading its magic bytes (more reliable than extension).
+    /// Also returns the detected MIME type, if the content was recognised.
+    /// A file that can't be read is [`OrchestratorError::Unreadable`].
+    pub fn classify_by_content<P: AsRef<Path>>(path: P) -> Result<(FileType, Option<&'static str>)> {
+        let kind = infer::get_from_path(path.as_ref()).map_err(|e| {
+            debug!("Can't read {} to detect its type: {}", path.as_ref().display(), e);
+            OrchestratorError::Unreadable(format!("{}: {}", path.as_ref().display(), e))
+        })?;
+        let mime = kind.map(|kind| kind.mime_type());
 
-        if let Some(file_type) = kind {
-            let mime = file_type.mime_type();
-            
+        if let Some(mime) = mime {
             if mime.starts_with("image/") {
-                return Ok(FileType::Image);
+                return Ok((FileType::Image, Some(mime)));
             } else if mime.starts_with("video/") {
-                return Ok(FileType::Video);
+                return Ok((FileType::Video, Some(mime)));
             } else if mime.starts_with("audio/") {
-                return Ok(FileType::Audio);
+                return Ok((FileType::Audio, Some(mime)));
             } else if mime == "application/pdf" 
                 || mime.contains("word") 
                 || mime.contains("document") 
                 || mime.contains("text") {
-                return Ok(FileType::Document);
+                return Ok((FileType::Document, Some(mime)));
             } else if mime.contains("zip") 
                 || mime.contains("rar") 
                 || mime.contains("archive") 
                 || mime.contains("compressed") {
-                return Ok(FileType::Archive);
+                return Ok((FileType::Archive, Some(mime)));
             }
         }
 
         // Fallback to extension-based classification
-        Self::classify_by_extension(path)
+        Ok((Self::classify_by_extension(path)?, mime))
+    }
+
+    /// Whether the start of the file reads as text: valid UTF-8, no NUL
+    /// bytes, and few enough other control bytes. Empty files don't count.
+    pub fn looks_like_text<P: AsRef<Path>>(path: P, sniffing: &TextSniffing) -> Result<bool> {
+        use std::io::Read;
+
+        let file = std::fs::File::open(path.as_ref())
+            .map_err(|e| OrchestratorError::Classification(format!("Failed to read file: {}", e)))?;
+        let mut sample = Vec::with_capacity(sniffing.sample_bytes);
+        file.take(sniffing.sample_bytes as u64)
+            .read_to_end(&mut sample)
+            .map_err(|e| OrchestratorError::Classification(format!("Failed to read file: {}", e)))?;
+
+        if sample.is_empty() || sample.contains(&0) {
+            return Ok(false);
+        }
+        // The sample may end part-way through a multi-byte character
+        let valid = match std::str::from_utf8(&sample) {
+            Ok(_) => true,
+            Err(e) => e.error_len().is_none(),
+        };
+        if !valid {
+            return Ok(false);
+        }
+
+        let control = sample
+            .iter()
+            .filter(|&&b| (b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0c)) || b == 0x7f)
+            .count();
+        Ok(control as f64 <= sample.len() as f64 * sniffing.max_control_ratio)
     }
 
     /// Classify file by extension (fallback method)
@@ -93,19 +150,38 @@ impl FileClassifier {
         Ok(file_type)
     }
 
-    /// Get comprehensive file info
+    /// Get comprehensive file info. Content that can't be read is an
+    /// [`OrchestratorError::Unreadable`] error rather than a guess from the
+    /// extension, so the caller can try again later.
     pub fn get_file_info<P: AsRef<Path>>(path: P) -> Result<FileInfo> {
-        let path = path.as_ref();
+        Self::file_info(path.as_ref(), None)
+    }
+
+    /// File info for a file whose type is already known (e.g. from an
+    /// unambiguous extension), without opening it; `mime` is `None`
+    pub fn get_file_info_as<P: AsRef<Path>>(path: P, file_type: FileType) -> Result<FileInfo> {
+        Self::file_info(path.as_ref(), Some(file_type))
+    }
+
+    fn file_info(path: &Path, known: Option<FileType>) -> Result<FileInfo> {
         let metadata = std::fs::metadata(path)
             .map_err(|e| OrchestratorError::Classification(format!("Failed to read metadata: {}", e)))?;
 
-        let file_type = Self::classify_by_content(path)
-            .unwrap_or_else(|_| Self::classify_by_extension(path).unwrap_or(FileType::Unknown));
+        let (file_type, mime) = match known {
+            Some(file_type) => (file_type, None),
+            // Reading a FIFO or device could block or never end
+            None if !metadata.is_file() => (Self::classify_by_extension(path).unwrap_or(FileType::Unknown), None),
+            None => match Self::classify_by_content(path) {
+                Err(e @ OrchestratorError::Unreadable(_)) => return Err(e),
+                result => result.unwrap_or_else(|_| (Self::classify_by_extension(path).unwrap_or(FileType::Unknown), None)),
+            },
+        };
 
         Ok(FileInfo {
             path: path.to_path_buf(),
             size: metadata.len(),
             file_type,
+            mime,
             extension: path.extension()
                 .and_then(|e| e.to_str())
                 .map(|s| s.to_lowercase()),
@@ -113,13 +189,76 @@ impl FileClassifier {
     }
 }
 
+/// Filename pattern rules from the config, checked before type-based
+/// classification
+#[derive(Default)]
+pub struct PatternClassifier {
+    rules: Vec<(PatternMatcher, bool, String)>,
+}
+
+enum PatternMatcher {
+    Glob(globset::GlobMatcher),
+    Regex(regex::Regex),
+}
+
+impl PatternClassifier {
+    /// Compile the configured patterns, naming the first invalid one
+    pub fn new(rules: &[PatternRule], syntax: PatternSyntax) -> Result<Self> {
+        let mut compiled = Vec::with_capacity(rules.len());
+
+        for rule in rules {
+            let invalid = |e: String| {
+                OrchestratorError::Config(format!("Invalid pattern '{}': {}", rule.pattern, e))
+            };
+
+            let matcher = match syntax {
+                PatternSyntax::Glob => PatternMatcher::Glob(
+                    globset::GlobBuilder::new(&rule.pattern)
+                        .case_insensitive(true)
+                        .literal_separator(true)
+                        .build()
+                        .map_err(|e| invalid(e.to_string()))?
+                        .compile_matcher(),
+                ),
+                PatternSyntax::Regex => PatternMatcher::Regex(
+                    regex::Regex::new(&rule.pattern).map_err(|e| invalid(e.to_string()))?,
+                ),
+            };
+
+            compiled.push((matcher, rule.pattern.contains('/'), rule.category.clone()));
+        }
+
+        Ok(Self { rules: compiled })
+    }
+
+    /// Category of the first pattern matching `relative_path` (relative to
+    /// the source directory)
+    pub fn classify(&self, relative_path: &Path) -> Option<&str> {
+        let file_name = relative_path.file_name()?.to_string_lossy();
+        let full_path = relative_path.to_string_lossy().replace('\\', "/");
+
+        self.rules
+            .iter()
+            .find(|(matcher, whole_path, _)| {
+                let subject = if *whole_path { full_path.as_str() } else { file_name.as_ref() };
+                match matcher {
+                    PatternMatcher::Glob(glob) => glob.is_match(subject),
+                    PatternMatcher::Regex(regex) => regex.is_match(subject),
+                }
+            })
+            .map(|(_, _, category)| category.as_str())
+    }
+}
+
+/// A file's detected type and basic metadata
 #[derive(Debug, Clone)]
 pub struct FileInfo {
-    #[allow(dead_code)]
     pub path: std::path::PathBuf,
     pub size: u64,
     pub file_type: FileType,
-    #[allow(dead_code)]
+    /// MIME type detected from the content; `None` for formats `infer`
+    /// doesn't recognise, such as plain text and SVG
+    pub mime: Option<&'static str>,
     pub extension: Option<String>,
 }
 
@@ -145,4 +284,70 @@ mod tests {
             assert_eq!(result, expected, "Failed for {}", filename);
         }
     }
+
+    #[test]
+    fn test_unreadable_is_distinct_from_unknown() {
+        let dir = tempfile::TempDir::new().unwrap();
+
+        // Readable but not recognised: falls back to the extension
+        let notes = dir.path().join("notes.pdf");
+        std::fs::write(&notes, b"not really a pdf").unwrap();
+        assert_eq!(FileClassifier::get_file_info(&notes).unwrap().file_type, FileType::Document);
+
+        // Can't be read at all: a retryable error, not Unknown
+        let gone = dir.path().join("gone.jpg");
+        let err = FileClassifier::classify_by_content(&gone).unwrap_err();
+        assert!(matches!(err, OrchestratorError::Unreadable(_)), "got {:?}", err);
+    }
+
+    #[test]
+    fn test_pattern_classifier() {
+        let rule = |pattern: &str, category: &str| PatternRule {
+            pattern: pattern.to_string(),
+            category: category.to_string(),
+        };
+        let rules = vec![
+            rule("Screenshot_*.png", "screenshots"),
+            rule("IMG_*", "camera"),
+            rule("work/**/*.pdf", "work"),
+        ];
+        let patterns = PatternClassifier::new(&rules, PatternSyntax::Glob).unwrap();
+
+        assert_eq!(patterns.classify(Path::new("phone/Screenshot_01.PNG")), Some("screenshots"));
+        assert_eq!(patterns.classify(Path::new("IMG_0001.jpg")), Some("camera"));
+        assert_eq!(patterns.classify(Path::new("work/2024/q1/report.pdf")), Some("work"));
+        assert_eq!(patterns.classify(Path::new("home/report.pdf")), None);
+
+        let regex = PatternClassifier::new(&[rule(r"^DSC\d+\.jpg$", "camera")], PatternSyntax::Regex).unwrap();
+        assert_eq!(regex.classify(Path::new("DSC0042.jpg")), Some("camera"));
+
+        let err = PatternClassifier::new(&[rule("IMG_[", "camera")], PatternSyntax::Glob).err().unwrap();
+        assert!(err.to_string().contains("IMG_["));
+    }
+
+    #[test]
+    fn test_looks_like_text() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let sniffing = TextSniffing::default();
+        let check = |name: &str, contents: &[u8]| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, contents).unwrap();
+            FileClassifier::looks_like_text(&path, &sniffing).unwrap()
+        };
+
+        assert!(check("README", "# Notes\n\tcafé ☕\r\n".as_bytes()));
+        assert!(check("Makefile", b"all:\n\tcargo build\n"));
+        assert!(!check("empty", b""));
+        assert!(!check("nul", b"text\0more text"));
+        assert!(!check("latin1", b"caf\xe9 au lait"));
+        assert!(!check("escapes", &[0x1b; 64]));
+        // An ELF header: binary mostly printable except a few bytes
+        assert!(!check("binary", b"\x7fELF\x02\x01\x01\x00\x00\x00"));
+
+        // A multi-byte character cut off by the sample size is still text
+        let cut = TextSniffing { sample_bytes: 4, ..sniffing };
+        let path = dir.path().join("cut");
+        std::fs::write(&path, "abc☕".as_bytes()).unwrap();
+        assert!(FileClassifier::looks_like_text(&path, &cut).unwrap());
+    }
 }
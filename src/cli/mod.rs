@@ -1,5 +1,8 @@
 use clap::{Parser, Subcommand};
 use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::Level;
+use crate::report::{ReportFormat, ReportPeriod};
 
 #[derive(Parser)]
 #[command(name = "file-orchestrator")]
@@ -11,10 +14,19 @@ pub struct Cli {
     #[arg(short, long, default_value = "config.toml")]
     pub config: PathBuf,
 
-    /// Database path for state management
+    /// Database path for state management; a relative path is in the
+    /// config file's folder
     #[arg(short, long, default_value = ".orchestrator.db")]
     pub db: PathBuf,
 
+    /// Log more detail: -v for debug, -vv for trace
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    pub verbose: u8,
+
+    /// Only log warnings and errors
+    #[arg(short, long, default_value_t = false, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -30,21 +42,38 @@ pub enum Commands {
         /// Overwrite existing config file
         #[arg(short, long, default_value_t = false)]
         force: bool,
+
+        /// Explain every key (and list the optional ones) in comments
+        #[arg(long, default_value_t = false)]
+        with_comments: bool,
+    },
+
+    /// Print every config key with its type and meaning
+    Schema {
+        /// Print a JSON Schema of config.toml instead, for editors and other
+        /// tools that check or generate configs
+        #[arg(long, default_value_t = false)]
+        json: bool,
     },
 
     /// Register a new USB drive
     RegisterDrive {
         /// Drive label or name
-        #[arg(short, long)]
-        label: String,
+        #[arg(short, long, required_unless_present = "auto_map")]
+        label: Option<String>,
 
         /// File category this drive should handle (images, videos, music, documents, archives)
-        #[arg(short, long)]
-        category: String,
+        #[arg(short, long, required_unless_present = "auto_map")]
+        category: Option<String>,
 
         /// Optional: Specific mount point/path
-        #[arg(short, long)]
+        #[arg(short, long, conflicts_with = "auto_map")]
         path: Option<PathBuf>,
+
+        /// Register every connected removable drive, using the category
+        /// folders already on it (prompts when that is ambiguous)
+        #[arg(long, default_value_t = false, conflicts_with_all = ["label", "category"])]
+        auto_map: bool,
     },
 
     /// List all registered drives
@@ -58,21 +87,117 @@ pub enum Commands {
         /// Specific file to sync (optional)
         #[arg(short, long)]
         file: Option<PathBuf>,
+
+        /// Only sync files modified within this long ago (e.g. 30m, 24h, 7d)
+        #[arg(long, value_parser = parse_duration, conflicts_with = "file")]
+        since: Option<Duration>,
+
+        /// Stop after this many files that weren't synced yet
+        #[arg(long, conflicts_with_all = ["file", "since"])]
+        limit: Option<usize>,
+
+        /// Continue from where the last --limit run stopped
+        #[arg(long, default_value_t = false, conflicts_with_all = ["file", "since"])]
+        resume: bool,
+
+        /// Read every folder again instead of using the cached listings
+        /// kept with `dir_cache`
+        #[arg(long, default_value_t = false, conflicts_with = "file")]
+        no_cache: bool,
+
+        /// Send every file to <DIR>/<category>/ instead of the registered
+        /// drives, e.g. to try out rules. Its sync records are kept in a
+        /// database inside <DIR>, apart from the real ones.
+        #[arg(long, value_name = "DIR")]
+        target_root: Option<PathBuf>,
     },
 
     /// Start the orchestrator in watch mode (monitors for changes)
     Run {
-        /// Check interval for drive connections (seconds)
-        #[arg(short, long, default_value_t = 10)]
+        /// Check interval for drive connections (seconds, at least 1)
+        #[arg(short, long, default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
         interval: u64,
+
+        /// Don't sync files that changed while fo wasn't running before
+        /// starting to watch
+        #[arg(long, default_value_t = false)]
+        no_startup_scan: bool,
+
+        /// Stream sync events as JSON lines to clients of this Unix socket
+        /// (a named pipe like \\.\pipe\fo-events on Windows)
+        #[arg(long)]
+        events_socket: Option<PathBuf>,
     },
 
     /// Show current sync status and statistics
-    Status,
+    Status {
+        /// Keep reprinting the totals and connected drives until Ctrl+C
+        #[arg(long, default_value_t = false)]
+        watch: bool,
+
+        /// Seconds between refreshes with --watch (at least 1)
+        #[arg(long, default_value_t = 2, requires = "watch", value_parser = clap::value_parser!(u64).range(1..))]
+        interval: u64,
+    },
 
     /// Process pending syncs for connected drives
     ProcessPending,
 
+    /// Move files queued for one drive to another drive
+    ReassignPending {
+        /// Drive UUID the files are queued for
+        #[arg(long)]
+        from: String,
+
+        /// Drive UUID to queue them for instead
+        #[arg(long)]
+        to: String,
+
+        /// Only move files of this category
+        #[arg(long)]
+        category: Option<String>,
+    },
+
+    /// Find queued files and sync records for drives that are no longer in
+    /// the config
+    Fsck {
+        /// Queue those files for the drive now taking their category (or
+        /// drop them) and forget copies on the removed drives
+        #[arg(long, default_value_t = false)]
+        fix: bool,
+    },
+
+    /// Move files staged with `sync.staging` into place on their drives
+    /// and record them as synced
+    Commit {
+        /// Only files staged on this drive UUID
+        #[arg(long)]
+        drive: Option<String>,
+    },
+
+    /// Delete files staged with `sync.staging`; they are copied (and
+    /// staged) again on the next sync
+    Discard {
+        /// Only files staged on this drive UUID
+        #[arg(long)]
+        drive: Option<String>,
+    },
+
+    /// List the files waiting for their drive to be connected
+    ListPending {
+        /// Only files queued for this drive UUID
+        #[arg(long)]
+        drive: Option<String>,
+
+        /// Only files of this category
+        #[arg(long)]
+        category: Option<String>,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+        format: ReportFormat,
+    },
+
     /// Clear all sync state (WARNING: This will reset all history)
     Clear {
         /// Confirm the clear operation
@@ -80,9 +205,145 @@ pub enum Commands {
         confirm: bool,
     },
 
+    /// Copy files added directly on a drive back into the source directory
+    Pull {
+        /// UUID of the drive to pull from
+        #[arg(long)]
+        drive: String,
+    },
+
+    /// List the snapshots on a drive with `snapshots = true`
+    ListSnapshots {
+        /// UUID of the drive
+        #[arg(long)]
+        drive: String,
+    },
+
     /// Validate configuration file
     Validate,
 
+    /// Check the whole setup (config, source, database, drives, space) and
+    /// suggest fixes
+    Doctor,
+
+    /// Check that synced files still exist (and are intact) on connected drives
+    Verify {
+        /// Only check files synced to this drive UUID
+        #[arg(long)]
+        drive: Option<String>,
+
+        /// Re-hash target files and compare against the recorded hash
+        #[arg(long, default_value_t = false)]
+        rehash: bool,
+
+        /// Queue missing or corrupt files whose source still exists as
+        /// pending for re-sync; corrupt copies are left for the conflict policy
+        #[arg(long, default_value_t = false)]
+        requeue: bool,
+
+        /// Check against the manifests on the drives instead of the state
+        /// database (needs `drive_manifests`)
+        #[arg(long, default_value_t = false, conflicts_with = "requeue")]
+        manifest: bool,
+    },
+
+    /// Move synced copies to where the current rules would send them
+    Reroute {
+        /// Only list the moves that would be made
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
+
+    /// Revert the copies made by the last full sync, pending run, commit or
+    /// discard
+    Undo {
+        /// Batch to revert instead of the latest (printed when it is recorded)
+        #[arg(long)]
+        batch: Option<u64>,
+    },
+
+    /// Summarize how much has been synced per day, week or month
+    Report {
+        /// Bucket size
+        #[arg(long, value_enum, default_value_t = ReportPeriod::Day)]
+        by: ReportPeriod,
+
+        /// Only include syncs from this date (YYYY-MM-DD) or this long ago (e.g. 30d)
+        #[arg(long, value_parser = parse_time)]
+        since: Option<u64>,
+
+        /// Only include syncs before this date (YYYY-MM-DD) or this long ago
+        #[arg(long, value_parser = parse_time)]
+        until: Option<u64>,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+        format: ReportFormat,
+    },
+
+    /// Tag a synced file (e.g. "favorite", "to-review") or attach a note
+    #[command(arg_required_else_help = true)]
+    Tag {
+        /// The source file
+        file: PathBuf,
+
+        /// Tags to add
+        tags: Vec<String>,
+
+        /// Remove these tags instead of adding them
+        #[arg(long, default_value_t = false)]
+        remove: bool,
+
+        /// Set the file's note; an empty string clears it
+        #[arg(long)]
+        note: Option<String>,
+    },
+
+    /// Find synced files by tag
+    Find {
+        /// Only files with this tag
+        #[arg(long)]
+        tag: String,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+        format: ReportFormat,
+    },
+
+    /// Show how the most recent sync run went
+    LastRun {
+        /// Output format
+        #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+        format: ReportFormat,
+    },
+
+    /// List recent sync runs, newest first
+    Runs {
+        /// How many runs to show
+        #[arg(long, default_value_t = 10)]
+        limit: usize,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+        format: ReportFormat,
+    },
+
+    /// Recover an unreadable state database, rebuilding sync records from
+    /// the connected drives if it has to be replaced
+    Repair {
+        /// Rescan connected drives even if the database could be kept
+        #[arg(long, default_value_t = false)]
+        rescan: bool,
+    },
+
+    /// Shrink the state database after many removals (clears, undos,
+    /// pruned snapshots); needs `fo run` to be stopped
+    Compact,
+
+    #[cfg(feature = "tui")]
+    /// Show a live terminal dashboard
+    Tui,
+
     #[cfg(feature = "gui")]
     /// Launch the graphical user interface
     Gui,
@@ -92,12 +353,110 @@ impl Cli {
     pub fn parse_args() -> Self {
         Self::parse()
     }
+
+    /// Log level picked by `--quiet` / `--verbose`
+    pub fn log_level(&self) -> Level {
+        match (self.quiet, self.verbose) {
+            (true, _) => Level::WARN,
+            (false, 0) => Level::INFO,
+            (false, 1) => Level::DEBUG,
+            (false, _) => Level::TRACE,
+        }
+    }
+
+    /// Filter directives for [`log_level`](Self::log_level). Extra detail is
+    /// only turned on for fo itself; dependencies (sled, notify, ...) stay
+    /// at info unless `RUST_LOG` asks for them.
+    pub fn log_filter(&self) -> String {
+        match self.log_level() {
+            level if level > Level::INFO => format!("info,file_orchestrator={0},fo={0}", level),
+            level => level.to_string(),
+        }
+    }
+}
+
+/// Parse a duration like `90s`, `30m`, `24h`, `7d` or `2w`
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| format!("missing unit in '{}' (use s, m, h, d or w)", value))?;
+    let (amount, unit) = value.split_at(split);
+
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| format!("invalid duration '{}'", value))?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(format!("unknown unit '{}' (use s, m, h, d or w)", unit)),
+    };
+
+    amount
+        .checked_mul(seconds)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("duration '{}' is too long", value))
+}
+
+/// Parse a point in time as a local date (`2024-03-01`) or a duration
+/// before now (`30d`), returning seconds since the Unix epoch
+fn parse_time(value: &str) -> Result<u64, String> {
+    if let Ok(date) = chrono::NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d") {
+        return date
+            .and_hms_opt(0, 0, 0)
+            .and_then(|midnight| midnight.and_local_timezone(chrono::Local).earliest())
+            .map(|time| time.timestamp().max(0) as u64)
+            .ok_or_else(|| format!("invalid date '{}'", value));
+    }
+
+    let ago = parse_duration(value).map_err(|e| format!("{} (or use a date like 2024-03-01)", e))?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    Ok(now.saturating_sub(ago).as_secs())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("24h"), Ok(Duration::from_secs(86_400)));
+        assert_eq!(parse_duration("7d"), Ok(Duration::from_secs(604_800)));
+        assert!(parse_duration("7").is_err());
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("3y").is_err());
+        assert!(parse_duration("99999999999999w").is_err());
+    }
+
+    #[test]
+    fn test_parse_time() {
+        let midnight = chrono::NaiveDate::from_ymd_opt(2024, 3, 1).unwrap()
+            .and_hms_opt(0, 0, 0).unwrap()
+            .and_local_timezone(chrono::Local).unwrap();
+        assert_eq!(parse_time("2024-03-01"), Ok(midnight.timestamp() as u64));
+
+        let day_ago = parse_time("1d").unwrap();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        assert!((now - 86_400).abs_diff(day_ago) <= 1);
+        assert!(parse_time("2024-13-01").is_err());
+    }
+
+    #[test]
+    fn test_log_level_flags() {
+        let parse = |args: &[&str]| Cli::try_parse_from(args).unwrap();
+        assert_eq!(parse(&["fo", "status"]).log_level(), Level::INFO);
+        assert_eq!(parse(&["fo", "-v", "status"]).log_level(), Level::DEBUG);
+        // Global, so also accepted after the subcommand
+        assert_eq!(parse(&["fo", "status", "-vvv"]).log_level(), Level::TRACE);
+        assert_eq!(parse(&["fo", "status", "--quiet"]).log_filter(), "WARN");
+        assert_eq!(parse(&["fo", "-v", "status"]).log_filter(), "info,file_orchestrator=DEBUG,fo=DEBUG");
+        assert!(Cli::try_parse_from(["fo", "-q", "-v", "status"]).is_err());
+    }
+
     #[test]
     fn verify_cli() {
         use clap::CommandFactory;
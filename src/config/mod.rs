@@ -1,21 +1,245 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::path::{Path, PathBuf};
+use crate::classifier::PatternClassifier;
+use crate::sanitize::NameNormalization;
+use crate::state::HashAlgorithm;
 use crate::error::{OrchestratorError, Result};
+use tracing::{info, warn};
 
+/// The categories the classifier can produce
+pub const BUILTIN_CATEGORIES: [&str; 5] = ["images", "videos", "music", "documents", "archives"];
+
+/// Version of the config file layout written by this build
+pub const CURRENT_CONFIG_VERSION: u32 = 1;
+
+/// Sidecar extensions used when `sync.sidecar_extensions` isn't set:
+/// subtitles and Kodi/Jellyfin .nfo files. Images aren't listed, since a
+/// phone's Live Photo still (`IMG_1.jpg` beside `IMG_1.mov`) would then
+/// follow its video.
+pub const SIDECAR_EXTENSIONS: &[&str] = &["srt", "ass", "ssa", "sub", "idx", "vtt", "nfo"];
+
+/// Folder at a drive's root holding copies waiting for `fo commit`, with
+/// `sync.staging`
+pub const STAGING_DIR: &str = ".staging";
+
+/// UUID prefix of the stand-in drives made by [`Config::with_target_root`];
+/// sync records on them are test copies, not copies on a registered drive
+pub const TARGET_ROOT_DRIVE: &str = "target-root";
+
+/// Everything read from `config.toml`
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
+    /// Config layout version, see [`CURRENT_CONFIG_VERSION`]
+    #[serde(default)]
+    pub version: u32,
     pub source: SourceConfig,
     pub rules: FileRules,
     pub drives: HashMap<String, DriveConfig>,
+    #[serde(default)]
+    pub sync: SyncConfig,
+    #[serde(default)]
+    pub notifications: NotificationsConfig,
+    #[serde(default)]
+    pub hooks: HooksConfig,
+    /// Folder name on the drive for each category, e.g. `images = "Photos"`.
+    /// Unmapped categories use the category name.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub folder_names: HashMap<String, String>,
+    /// Address for the Prometheus `/metrics` endpoint while `fo run` is
+    /// watching, e.g. `127.0.0.1:9898` (needs the `metrics` feature)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metrics_addr: Option<String>,
+    /// Set when `[rules]` pulls in an external file with `include`
+    #[serde(skip)]
+    pub rules_include: Option<RulesInclude>,
+    /// Paths written relative to the config file's directory
+    #[serde(skip)]
+    pub relative_paths: RelativePaths,
+}
+
+/// An external rules file referenced from the main config
+#[derive(Debug, Clone)]
+pub struct RulesInclude {
+    /// The included file, resolved against the config file's directory
+    pub path: PathBuf,
+    /// `[rules]` exactly as written in the main config, so saving writes
+    /// the `include` back instead of inlining the merged rules
+    written: toml::Table,
+}
+
+/// The source and drive paths a config file gives relative to its own
+/// directory. `Config::load` resolves them; saving writes them back as
+/// written unless they were changed since.
+#[derive(Debug, Clone, Default)]
+pub struct RelativePaths {
+    base_dir: PathBuf,
+    source: Option<PathBuf>,
+    drives: HashMap<String, PathBuf>,
+}
+
+impl RelativePaths {
+    fn is_empty(&self) -> bool {
+        self.source.is_none() && self.drives.is_empty()
+    }
+
+    /// The path as written, if `resolved` is still what it resolved to
+    fn written<'a>(&self, written: Option<&'a PathBuf>, resolved: Option<&Path>) -> Option<&'a PathBuf> {
+        written.filter(|written| resolved == Some(self.base_dir.join(written).as_path()))
+    }
 }
 
+/// Folders that operating systems and desktops keep for themselves (trash,
+/// recycle bin, search indexes), skipped unless `skip_dirs` replaces the list.
+/// A trailing `*` matches any suffix.
+pub const SYSTEM_DIRS: &[&str] = &[
+    "$RECYCLE.BIN",
+    "RECYCLER",
+    "System Volume Information",
+    ".Trash",
+    ".Trashes",
+    ".Trash-*",
+    ".Spotlight-V100",
+    ".fseventsd",
+    ".DocumentRevisions-V100",
+    ".TemporaryItems",
+    "lost+found",
+];
+
+/// The `[source]` table: the folder that is watched and synced
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SourceConfig {
     pub path: PathBuf,
+    /// How many directory levels below `path` to scan; files directly in
+    /// `path` are at depth 0
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scan_max_depth: Option<usize>,
+    /// Only scan and watch these subdirectories (relative to `path`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub include_dirs: Option<Vec<PathBuf>>,
+    /// Leave out hidden files and folders: names starting with a dot on
+    /// Unix, the hidden attribute on Windows
+    #[serde(default = "default_true")]
+    pub skip_hidden: bool,
+    /// Folder names never scanned or watched, compared case-insensitively;
+    /// [`SYSTEM_DIRS`] when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skip_dirs: Option<Vec<String>>,
+    /// Remember each folder's entries in the state database and only read
+    /// folders again once their modification time has moved on
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dir_cache: bool,
+    /// How many file events `fo run` holds for syncing before repeated
+    /// events for the same file are merged and, past that, the watcher waits
+    #[serde(default = "default_watch_queue_capacity")]
+    pub watch_queue_capacity: usize,
+}
+
+impl Default for SourceConfig {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::new(),
+            scan_max_depth: None,
+            include_dirs: None,
+            skip_hidden: true,
+            skip_dirs: None,
+            dir_cache: false,
+            watch_queue_capacity: default_watch_queue_capacity(),
+        }
+    }
+}
+
+impl SourceConfig {
+    /// Whether a file under the source directory is within the scanned
+    /// subtrees and depth. Paths outside the source directory are not
+    /// restricted.
+    pub fn in_scope(&self, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.path) else {
+            return true;
+        };
+
+        if let Some(ref dirs) = self.include_dirs {
+            if !dirs.iter().any(|dir| relative.starts_with(dir)) {
+                return false;
+            }
+        }
+
+        if let Some(parent) = relative.parent() {
+            if self.skips_any_dir(parent) {
+                return false;
+            }
+        }
+        if self.skip_hidden && self.is_hidden(relative) {
+            return false;
+        }
+
+        let depth = relative.components().count().saturating_sub(1);
+        self.scan_max_depth.is_none_or(|max| depth <= max)
+    }
+
+    /// Whether scanning should descend into a directory: it is on the way
+    /// to, or inside, an included subtree and not deeper than the limit
+    pub fn should_descend(&self, dir: &Path) -> bool {
+        let Ok(relative) = dir.strip_prefix(&self.path) else {
+            return true;
+        };
+
+        if let Some(ref dirs) = self.include_dirs {
+            if !dirs.iter().any(|inc| relative.starts_with(inc) || inc.starts_with(relative)) {
+                return false;
+            }
+        }
+
+        if self.skips_any_dir(relative) || (self.skip_hidden && self.is_hidden(relative)) {
+            return false;
+        }
+
+        self.scan_max_depth.is_none_or(|max| relative.components().count() <= max)
+    }
+
+    /// Whether any folder of a path relative to the source is in the skip list
+    fn skips_any_dir(&self, relative: &Path) -> bool {
+        let matches = |name: &str, pattern: &str| match pattern.strip_suffix('*') {
+            Some(prefix) => name.len() >= prefix.len()
+                && name.is_char_boundary(prefix.len())
+                && name[..prefix.len()].eq_ignore_ascii_case(prefix),
+            None => name.eq_ignore_ascii_case(pattern),
+        };
+
+        relative.components().any(|component| {
+            let name = component.as_os_str().to_string_lossy();
+            match self.skip_dirs {
+                Some(ref dirs) => dirs.iter().any(|dir| matches(&name, dir)),
+                None => SYSTEM_DIRS.iter().any(|dir| matches(&name, dir)),
+            }
+        })
+    }
+
+    /// Whether a path relative to the source, or any folder on the way to
+    /// it, is hidden
+    #[cfg(not(windows))]
+    fn is_hidden(&self, relative: &Path) -> bool {
+        relative
+            .components()
+            .any(|component| component.as_os_str().to_string_lossy().starts_with('.'))
+    }
+
+    #[cfg(windows)]
+    fn is_hidden(&self, relative: &Path) -> bool {
+        use std::os::windows::fs::MetadataExt;
+        const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+
+        let mut path = self.path.clone();
+        relative.components().any(|component| {
+            path.push(component);
+            fs::symlink_metadata(&path)
+                .is_ok_and(|meta| meta.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0)
+        })
+    }
 }
 
+/// The `[rules]` table: which files belong to which category
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileRules {
     pub images: Vec<String>,
@@ -23,35 +247,955 @@ pub struct FileRules {
     pub music: Vec<String>,
     pub documents: Option<Vec<String>>,
     pub archives: Option<Vec<String>>,
+    /// Syntax of the `pattern` strings in `patterns`
+    #[serde(default)]
+    pub pattern_syntax: PatternSyntax,
+    /// Filename patterns checked in order before the extension lists; the
+    /// first match decides the category, which may be a custom one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub patterns: Vec<PatternRule>,
+    /// Subfolders of a category's folder picked by filename pattern, e.g.
+    /// screenshots in `images/Screenshots`; checked in order, and files no
+    /// rule matches stay in the category folder
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub subfolders: HashMap<String, Vec<SubfolderRule>>,
+    /// MIME types each category accepts or refuses, checked against the
+    /// type detected from the file's content
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub mime: HashMap<String, MimeFilter>,
+    /// Sync extensionless files whose content looks like text (README,
+    /// Makefile, dotfiles) as documents. Off unless the table is present.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text_sniffing: Option<TextSniffing>,
+    /// Which category wins when an extension is in more than one list, or
+    /// its list disagrees with the detected content; earliest first.
+    /// Unlisted categories come after, in the built-in order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub category_priority: Vec<String>,
+    /// Whether a file's content is read to check its type even when the
+    /// extension alone settles its category
+    #[serde(default)]
+    pub classify_strategy: ClassifyStrategy,
 }
 
+/// How much `[rules]` trusts extensions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ClassifyStrategy {
+    /// Read every file's magic bytes, so mislabeled files (a PNG named
+    /// `.pdf`) go by what they really are and `category_priority` can
+    /// settle a disagreement between content and extension
+    #[default]
+    Content,
+    /// An extension in exactly one list, for a category without a MIME
+    /// filter, decides without reading the file, so the content never gets
+    /// a say. Extensionless and ambiguous files are still sniffed.
+    ExtensionFirst,
+}
+
+impl FileRules {
+    /// The categories whose extension list has `ext`, in the built-in order
+    fn categories_listing(&self, ext: &str) -> Vec<&'static str> {
+        let lists: [(&'static str, Option<&Vec<String>>); 5] = [
+            ("images", Some(&self.images)),
+            ("videos", Some(&self.videos)),
+            ("music", Some(&self.music)),
+            ("documents", self.documents.as_ref()),
+            ("archives", self.archives.as_ref()),
+        ];
+        lists
+            .into_iter()
+            .filter(|(_, list)| list.is_some_and(|list| list.iter().any(|e| e == ext)))
+            .map(|(category, _)| category)
+            .collect()
+    }
+
+    /// The category an extension settles on its own under `extension-first`:
+    /// the one list that has it, unless that category filters by MIME type
+    /// and so needs the content anyway
+    pub fn confident_category(&self, ext: &str) -> Option<&'static str> {
+        if self.classify_strategy == ClassifyStrategy::Content {
+            return None;
+        }
+        match self.categories_listing(ext)[..] {
+            [category] if !self.mime.contains_key(category) => Some(category),
+            _ => None,
+        }
+    }
+
+    /// The candidate that comes first in `category_priority`; the first
+    /// candidate when none are ranked
+    pub fn preferred<'a>(&self, candidates: &[&'a str]) -> Option<&'a str> {
+        candidates
+            .iter()
+            .copied()
+            .min_by_key(|candidate| {
+                self.category_priority.iter().position(|c| c == candidate).unwrap_or(usize::MAX)
+            })
+    }
+
+    /// Extensions in more than one list with nothing in `category_priority`
+    /// to decide between them, with the categories that list them
+    pub fn ambiguous_extensions(&self) -> Vec<(String, Vec<&'static str>)> {
+        let mut extensions: Vec<&String> = self.images
+            .iter()
+            .chain(&self.videos)
+            .chain(&self.music)
+            .chain(self.documents.iter().flatten())
+            .chain(self.archives.iter().flatten())
+            .collect();
+        extensions.sort();
+        extensions.dedup();
+
+        extensions
+            .into_iter()
+            .filter_map(|ext| {
+                let categories = self.categories_listing(ext);
+                let ranked = categories.iter().any(|c| self.category_priority.iter().any(|p| p == c));
+                (categories.len() > 1 && !ranked).then(|| (ext.clone(), categories))
+            })
+            .collect()
+    }
+
+    /// Each category's `subfolders` rules, compiled; the "category" a rule
+    /// gives is its subfolder
+    pub fn subfolder_classifiers(&self) -> Result<HashMap<String, PatternClassifier>> {
+        self.subfolders
+            .iter()
+            .map(|(category, rules)| {
+                let rules: Vec<PatternRule> = rules
+                    .iter()
+                    .map(|rule| PatternRule {
+                        pattern: rule.pattern.clone(),
+                        category: rule.folder.to_string_lossy().into_owned(),
+                    })
+                    .collect();
+                let classifier = PatternClassifier::new(&rules, self.pattern_syntax).map_err(|e| {
+                    OrchestratorError::Config(format!("rules.subfolders.{}: {}", category, e))
+                })?;
+                Ok((category.clone(), classifier))
+            })
+            .collect()
+    }
+
+    /// Why a file of `category` with the detected `mime` type is refused
+    /// by the category's MIME filter, if it is
+    pub fn mime_rejection(&self, category: &str, mime: Option<&str>) -> Option<String> {
+        let filter = self.mime.get(category)?;
+        match mime {
+            Some(mime) if filter.deny.iter().any(|pattern| mime_matches(pattern, mime)) => {
+                Some(format!("{} files are denied for {}", mime, category))
+            }
+            Some(mime) if filter.allow.as_ref().is_some_and(|allow| !allow.iter().any(|pattern| mime_matches(pattern, mime))) => {
+                Some(format!("{} is not an allowed type for {}", mime, category))
+            }
+            // An allow list only lets through content that was recognised
+            None if filter.allow.is_some() => Some(format!("content type unknown, {} only allows listed types", category)),
+            _ => None,
+        }
+    }
+}
+
+/// Allowed and denied MIME types for a category. Entries are exact types
+/// like `image/jpeg`, or `image/*` for a whole family.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MimeFilter {
+    /// Only files detected as one of these types are synced
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allow: Option<Vec<String>>,
+    /// Files detected as one of these types are skipped
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deny: Vec<String>,
+}
+
+/// How the start of an unrecognised file is judged to be text
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TextSniffing {
+    /// Bytes read from the start of the file
+    #[serde(default = "default_sample_bytes")]
+    pub sample_bytes: usize,
+    /// Largest share of the sample (0.0-1.0) that may be control bytes
+    /// other than tabs, line breaks and form feeds
+    #[serde(default = "default_max_control_ratio")]
+    pub max_control_ratio: f64,
+}
+
+impl Default for TextSniffing {
+    fn default() -> Self {
+        Self {
+            sample_bytes: default_sample_bytes(),
+            max_control_ratio: default_max_control_ratio(),
+        }
+    }
+}
+
+fn default_sample_bytes() -> usize {
+    8192
+}
+
+fn default_max_control_ratio() -> f64 {
+    0.01
+}
+
+fn mime_matches(pattern: &str, mime: &str) -> bool {
+    match pattern.strip_suffix("/*") {
+        Some(family) => mime.split('/').next().is_some_and(|f| f.eq_ignore_ascii_case(family)),
+        None => pattern.eq_ignore_ascii_case(mime),
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PatternSyntax {
+    /// Shell-style globs like `IMG_*.jpg`, matched case-insensitively
+    #[default]
+    Glob,
+    Regex,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PatternRule {
+    /// Matched against the file name, or against the path relative to the
+    /// source directory if the pattern contains a `/`
+    pub pattern: String,
+    pub category: String,
+}
+
+/// A `[[rules.subfolders.<category>]]` entry
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubfolderRule {
+    /// Matched like [`PatternRule::pattern`], in `pattern_syntax`
+    pub pattern: String,
+    /// Folder (relative to the category folder) for matching files
+    pub folder: PathBuf,
+}
+
+/// A registered drive and the category it takes, keyed by volume UUID in
+/// [`Config::drives`]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct DriveConfig {
     pub label: String,
     pub target: String,
     pub path: Option<PathBuf>,
     pub last_seen: Option<String>,
+    /// Store zstd-compressed copies (`name.ext.zst`) of compressible files
+    #[serde(default)]
+    pub compress: bool,
+    /// `path` is a network share; it counts as connected whenever it is
+    /// reachable, even if it doesn't appear in the local mount table
+    #[serde(default)]
+    pub network: bool,
+    /// A plain folder that counts as connected as long as it exists, never
+    /// looked up among the mounted drives; set on the stand-in drives of
+    /// [`Config::with_target_root`], not read from the config file
+    #[serde(skip)]
+    pub always_connected: bool,
+    /// Only accept files of the category with these extensions; files this
+    /// drive doesn't accept go to the next drive for the category
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accept_extensions: Option<Vec<String>>,
+    /// Also copy files added directly on the drive back into the source
+    /// whenever the drive is connected
+    #[serde(default)]
+    pub bidirectional: bool,
+    /// Put every file directly in the category folder instead of mirroring
+    /// the source tree; same-named files go through the conflict policy
+    #[serde(default)]
+    pub flatten: bool,
+    /// File system UUID recorded when the drive was first bound to a mount
+    /// point; used to follow the drive if its mount point changes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volume_uuid: Option<String>,
+    /// Id written to the drive's `.orchestrator-id` marker file at
+    /// registration; found on whichever mount point the drive turns up at
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub marker_id: Option<String>,
+    /// Keep holes in sparse files (disk images, VM disks) instead of
+    /// writing them out as zeros; ignored on FAT/exFAT, which can't store them
+    #[serde(default)]
+    pub sparse_copy: bool,
+    /// Put each full sync's files in a new `snapshots/<run>/` folder, with
+    /// unchanged files hard-linked to the previous snapshot's copy
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub snapshots: bool,
+    /// Delete the oldest snapshots beyond this many after each full sync;
+    /// unset keeps them all
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snapshot_retention: Option<usize>,
+}
+
+impl DriveConfig {
+    /// Whether this drive takes a file with the given extension
+    pub fn accepts_extension(&self, extension: Option<&str>) -> bool {
+        match (&self.accept_extensions, extension) {
+            (None, _) => true,
+            (Some(accepted), Some(ext)) => accepted.iter().any(|a| a.eq_ignore_ascii_case(ext)),
+            (Some(_), None) => false,
+        }
+    }
+}
+
+/// The `[sync]` table: how files are copied
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncConfig {
+    /// What to do when the target path already holds a different file
+    #[serde(default)]
+    pub conflict_policy: ConflictPolicy,
+    /// Record a drive's mount point (and volume UUID) in the config the first
+    /// time it is unambiguously found, and follow it when it moves
+    #[serde(default = "default_true")]
+    pub auto_bind_path: bool,
+    /// Order in which pending files are copied when their drive reconnects
+    #[serde(default)]
+    pub pending_order: PendingOrder,
+    /// Which drive gets a file when several take its category
+    #[serde(default)]
+    pub drive_selection: DriveSelection,
+    /// Let several drives take the same category with `drive_selection =
+    /// "first"`, neither replicated nor split by `accept_extensions`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub allow_multiple_per_category: bool,
+    /// Files smaller than this are skipped, e.g. 0-byte placeholders.
+    /// Bytes, or a string like "10KB".
+    #[serde(default, deserialize_with = "deserialize_size", skip_serializing_if = "Option::is_none")]
+    pub min_file_size: Option<u64>,
+    /// Files larger than this are skipped, e.g. disk images
+    #[serde(default, deserialize_with = "deserialize_size", skip_serializing_if = "Option::is_none")]
+    pub max_file_size: Option<u64>,
+    /// Sync zero-byte files; off skips them like a `min_file_size` of 1
+    #[serde(default = "default_true")]
+    pub sync_empty_files: bool,
+    /// Permission bits given to synced files on Unix, e.g. "0644"; unset
+    /// leaves them to the umask
+    #[serde(default, deserialize_with = "deserialize_mode", serialize_with = "serialize_mode", skip_serializing_if = "Option::is_none")]
+    pub target_file_mode: Option<u32>,
+    /// Permission bits given to the folders fo creates on a drive, on Unix
+    #[serde(default, deserialize_with = "deserialize_mode", serialize_with = "serialize_mode", skip_serializing_if = "Option::is_none")]
+    pub target_dir_mode: Option<u32>,
+    /// Hash used for newly synced files. Existing records keep the
+    /// algorithm they were made with until the file is synced again.
+    #[serde(default)]
+    pub hash_algorithm: HashAlgorithm,
+    /// Threads hashing files ahead of the copies during a batch sync or
+    /// pending drain; 0 hashes each file only when it is synced. Copies
+    /// still run one at a time.
+    #[serde(default)]
+    pub hash_workers: usize,
+    /// Log progress (with an ETA) every this many files during a batch
+    /// sync; 0 turns it off
+    #[serde(default = "default_progress_every")]
+    pub progress_every: usize,
+    /// Give up on copying a single file after this many seconds, e.g. when
+    /// a drive stops responding; the file is queued for the drive's next
+    /// check. No limit when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub per_file_timeout_secs: Option<u64>,
+    /// Only sync files last modified at least this many seconds ago, so a
+    /// download or camera import still being written isn't copied half
+    /// done; 0 syncs files straight away
+    #[serde(default)]
+    pub settle_seconds: u64,
+    /// Where files of unknown type are put aside instead of being skipped.
+    /// A relative path is taken from the source directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quarantine_dir: Option<PathBuf>,
+    /// Whether quarantined files are moved out of the source or copied
+    #[serde(default)]
+    pub quarantine_mode: QuarantineMode,
+    /// Flush sync records to disk at most this often (milliseconds) instead
+    /// of after every write; a crash loses at most this window of records.
+    /// Queue, quarantine, staged and batch records are always flushed at
+    /// once. 0 flushes every write.
+    #[serde(default = "default_state_flush_interval_ms")]
+    pub state_flush_interval_ms: u64,
+    /// Up to this many seconds, chosen at random, added to each wait of
+    /// `fo run`'s drive check, so several watchers don't poll in lockstep
+    #[serde(default = "default_interval_jitter")]
+    pub interval_jitter: u64,
+    /// Unicode-normalize (NFC) source names in sync records and target
+    /// paths, so NFD and NFC spellings of a name are the same file
+    #[serde(default)]
+    pub normalize_unicode: bool,
+    /// Lowercase source names in sync records and target paths
+    #[serde(default)]
+    pub lowercase_names: bool,
+    /// Keep a `.orchestrator-manifest.json` in each category folder on the
+    /// drives, listing what fo put there, for `fo verify --manifest`
+    #[serde(default)]
+    pub drive_manifests: bool,
+    /// Refuse to queue a file for an unplugged drive when the drive's queue
+    /// would outgrow its last-seen size, instead of only warning
+    #[serde(default)]
+    pub reject_over_capacity: bool,
+    /// Stop copying to a connected drive once it is at least this full
+    /// (percent of its size), leaving its files queued until space is freed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pause_at_percent_full: Option<u8>,
+    /// Categories copied to every drive that takes them, rather than to
+    /// one of them; a drive that is away gets its copy when it returns
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub replicate: Vec<String>,
+    /// How a file is put on a drive that is on the source's own device
+    #[serde(default)]
+    pub same_device_strategy: SameDeviceStrategy,
+    /// Copy each file's extended attributes (Finder tags, quarantine
+    /// flags, resource forks) after its data, on Linux and macOS
+    #[serde(default)]
+    pub copy_xattrs: bool,
+    /// Times to try a copy or hash read again when the file is briefly
+    /// busy (locked by a virus scanner or indexer); 0 fails at once
+    #[serde(default = "default_transient_retries")]
+    pub transient_retries: u32,
+    /// Wait between those tries, in milliseconds
+    #[serde(default = "default_transient_retry_delay_ms")]
+    pub transient_retry_delay_ms: u64,
+    /// Send sidecar files (subtitles, .nfo) to the drive and
+    /// category of the file in the same folder whose name they extend, so
+    /// `movie.en.srt` goes with `movie.mkv`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub group_sidecars: bool,
+    /// Extensions treated as sidecars; [`SIDECAR_EXTENSIONS`] when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sidecar_extensions: Option<Vec<String>>,
+    /// Keep a hash of every piece of this size of a copied file, so a file
+    /// that only grew since its last sync has just its new part written and
+    /// checked on the drive. The kept pieces aren't read back. Bytes, or a
+    /// string like "64MB".
+    #[serde(default, deserialize_with = "deserialize_size", skip_serializing_if = "Option::is_none")]
+    pub chunk_hash: Option<u64>,
+    /// Copy new files into each drive's [`STAGING_DIR`] and leave them out
+    /// of the records until `fo commit` moves them into place
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub staging: bool,
+}
+
+/// What to put on a target that shares the source's device
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SameDeviceStrategy {
+    /// A full, separate copy
+    Copy,
+    /// A hard link: one file under two names, so editing one edits both
+    Hardlink,
+    /// A symbolic link to the source path
+    Symlink,
+    /// A copy-on-write clone where the file system supports them, a full
+    /// copy where it doesn't
+    #[default]
+    Reflink,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuarantineMode {
+    #[default]
+    Move,
+    Copy,
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self {
+            conflict_policy: ConflictPolicy::default(),
+            auto_bind_path: true,
+            pending_order: PendingOrder::default(),
+            drive_selection: DriveSelection::default(),
+            allow_multiple_per_category: false,
+            min_file_size: None,
+            max_file_size: None,
+            sync_empty_files: true,
+            target_file_mode: None,
+            target_dir_mode: None,
+            hash_algorithm: HashAlgorithm::default(),
+            hash_workers: 0,
+            progress_every: default_progress_every(),
+            per_file_timeout_secs: None,
+            settle_seconds: 0,
+            quarantine_dir: None,
+            quarantine_mode: QuarantineMode::default(),
+            state_flush_interval_ms: default_state_flush_interval_ms(),
+            interval_jitter: default_interval_jitter(),
+            normalize_unicode: false,
+            lowercase_names: false,
+            drive_manifests: false,
+            reject_over_capacity: false,
+            pause_at_percent_full: None,
+            replicate: Vec::new(),
+            same_device_strategy: SameDeviceStrategy::default(),
+            copy_xattrs: false,
+            transient_retries: default_transient_retries(),
+            transient_retry_delay_ms: default_transient_retry_delay_ms(),
+            group_sidecars: false,
+            sidecar_extensions: None,
+            chunk_hash: None,
+            staging: false,
+        }
+    }
+}
+
+impl SyncConfig {
+    /// How source names are normalized for sync records and target paths
+    pub fn name_normalization(&self) -> NameNormalization {
+        NameNormalization { nfc: self.normalize_unicode, lowercase: self.lowercase_names }
+    }
+
+    /// Why a file of this size is outside the configured bounds, if it is
+    pub fn size_rejection(&self, size: u64) -> Option<String> {
+        if size == 0 && !self.sync_empty_files {
+            return Some("Empty file (sync_empty_files is off)".to_string());
+        }
+        match (self.min_file_size, self.max_file_size) {
+            (Some(min), _) if size < min => {
+                Some(format!("Smaller than min_file_size ({} < {} bytes)", size, min))
+            }
+            (_, Some(max)) if size > max => {
+                Some(format!("Larger than max_file_size ({} > {} bytes)", size, max))
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether files of `category` go to every drive that takes them
+    pub fn replicates(&self, category: &str) -> bool {
+        self.replicate.iter().any(|replicated| replicated == category)
+    }
+
+    /// Whether a file with this (lowercase) extension follows its primary
+    /// file under `group_sidecars`
+    pub fn is_sidecar_extension(&self, extension: &str) -> bool {
+        match self.sidecar_extensions {
+            Some(ref extensions) => extensions.iter().any(|listed| listed.eq_ignore_ascii_case(extension)),
+            None => SIDECAR_EXTENSIONS.contains(&extension),
+        }
+    }
+}
+
+/// Parse a size like `512`, `10KB`, `1.5 GB` or `2GiB`. Units are binary,
+/// so `1KB` is 1024 bytes.
+fn parse_size(value: &str) -> std::result::Result<u64, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+
+    let amount: f64 = amount
+        .parse()
+        .map_err(|_| format!("invalid size '{}'", value))?;
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        "T" | "TB" | "TIB" => 1 << 40,
+        other => return Err(format!("unknown size unit '{}' in '{}'", other, value)),
+    };
+
+    Ok((amount * multiplier as f64) as u64)
+}
+
+fn deserialize_size<'de, D>(deserializer: D) -> std::result::Result<Option<u64>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Size {
+        Bytes(u64),
+        Text(String),
+    }
+
+    match Size::deserialize(deserializer)? {
+        Size::Bytes(bytes) => Ok(Some(bytes)),
+        Size::Text(text) => parse_size(&text).map(Some).map_err(serde::de::Error::custom),
+    }
+}
+
+/// Parse permission bits written in octal: "0644", "644" or "0o644"
+fn parse_mode(value: &str) -> std::result::Result<u32, String> {
+    let digits = value.trim();
+    let digits = digits.strip_prefix("0o").unwrap_or(digits);
+    match u32::from_str_radix(digits, 8) {
+        Ok(mode) if mode <= 0o7777 => Ok(mode),
+        _ => Err(format!("'{}' is not an octal permission mode like \"0644\"", value)),
+    }
+}
+
+/// A mode is an octal string, or a TOML integer (`0o644` in octal notation)
+fn deserialize_mode<'de, D>(deserializer: D) -> std::result::Result<Option<u32>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Mode {
+        Bits(u32),
+        Text(String),
+    }
+
+    match Mode::deserialize(deserializer)? {
+        Mode::Bits(bits) if bits <= 0o7777 => Ok(Some(bits)),
+        Mode::Bits(bits) => Err(serde::de::Error::custom(format!("permission mode {:o} is out of range", bits))),
+        Mode::Text(text) => parse_mode(&text).map(Some).map_err(serde::de::Error::custom),
+    }
+}
+
+/// Written back as an octal string, which reads better than the decimal
+/// integer TOML would get
+fn serialize_mode<S>(mode: &Option<u32>, serializer: S) -> std::result::Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    match mode {
+        Some(mode) => serializer.serialize_str(&format!("{:04o}", mode)),
+        None => serializer.serialize_none(),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationsConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Which events to notify about
+    #[serde(default = "default_notify_events")]
+    pub on_events: Vec<NotifyEvent>,
+    /// Show desktop notifications
+    #[serde(default = "default_true")]
+    pub desktop: bool,
+    /// POST a JSON description of each event to this URL
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook_url: Option<String>,
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            on_events: default_notify_events(),
+            desktop: true,
+            webhook_url: None,
+        }
+    }
+}
+
+/// Commands run around each file's copy. Templates may use `{source}`,
+/// `{target}`, `{category}`, `{drive}` and `{hash}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HooksConfig {
+    /// Run before copying; a non-zero exit skips the file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_sync: Option<String>,
+    /// Run after a file is synced; its exit status is only logged
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_sync: Option<String>,
+    /// Kill a hook that runs longer than this
+    #[serde(default = "default_hook_timeout")]
+    pub timeout_secs: u64,
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        Self {
+            pre_sync: None,
+            post_sync: None,
+            timeout_secs: default_hook_timeout(),
+        }
+    }
+}
+
+fn default_hook_timeout() -> u64 {
+    30
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NotifyEvent {
+    Synced,
+    Failed,
+    DriveConnected,
+}
+
+fn default_notify_events() -> Vec<NotifyEvent> {
+    vec![NotifyEvent::Synced, NotifyEvent::Failed, NotifyEvent::DriveConnected]
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PendingOrder {
+    /// Oldest queued first
+    #[default]
+    Fifo,
+    /// Most files transferred if the drive is unplugged early
+    SmallestFirst,
+    LargestFirst,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DriveSelection {
+    /// A drive listing the extension in `accept_extensions`, then by UUID;
+    /// the file waits for that drive if it isn't connected
+    #[default]
+    First,
+    /// The connected drive with room for the file that has written fastest
+    Fastest,
+}
+
+fn default_state_flush_interval_ms() -> u64 {
+    1000
+}
+
+fn default_interval_jitter() -> u64 {
+    2
+}
+
+fn default_progress_every() -> usize {
+    100
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_watch_queue_capacity() -> usize {
+    4096
+}
+
+fn default_transient_retries() -> u32 {
+    3
+}
+
+fn default_transient_retry_delay_ms() -> u64 {
+    500
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    /// Replace the existing file
+    #[default]
+    Overwrite,
+    /// Leave the existing file alone and don't sync
+    Skip,
+    /// Write next to it as `name-1.ext`, `name-2.ext`, ...
+    Rename,
+    /// Report the file as failed
+    Fail,
 }
 
 impl Config {
     /// Load configuration from a TOML file
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
         let content = fs::read_to_string(path)
             .map_err(|e| OrchestratorError::Config(format!("Failed to read config file: {}", e)))?;
         
-        let config: Config = toml::from_str(&content)?;
+        let mut table = Self::parse_table(&content)?;
+        let base_dir = path.parent().unwrap_or(Path::new("."));
+        let rules_include = Self::resolve_rules_include(&mut table, base_dir)?;
+
+        let mut config: Self = table.try_into()?;
+        config.rules_include = rules_include;
+        config.resolve_relative_paths(base_dir);
         config.validate()?;
+
+        for (ext, categories) in config.rules.ambiguous_extensions() {
+            warn!(
+                "Extension '{}' is listed under {}; using {} until rules.category_priority says otherwise",
+                ext, categories.join(" and "), categories[0]
+            );
+        }
         Ok(config)
     }
 
+    /// Parse config TOML, upgrading older layouts to the current version.
+    /// Unlike `load`, a rules `include` is not resolved.
+    pub fn parse(content: &str) -> Result<Self> {
+        Ok(Self::parse_table(content)?.try_into()?)
+    }
+
+    fn parse_table(content: &str) -> Result<toml::Table> {
+        let mut table: toml::Table = toml::from_str(content)?;
+
+        // Configs written before versioning have no version field
+        let version = match table.get("version") {
+            None => 0,
+            Some(v) => v.as_integer()
+                .and_then(|v| u32::try_from(v).ok())
+                .ok_or_else(|| OrchestratorError::Config(format!("Invalid config version: {}", v)))?,
+        };
+
+        if version > CURRENT_CONFIG_VERSION {
+            return Err(OrchestratorError::Config(format!(
+                "Config version {} is newer than this build supports (up to {}). Please upgrade file-orchestrator.",
+                version, CURRENT_CONFIG_VERSION
+            )));
+        }
+
+        if version < CURRENT_CONFIG_VERSION {
+            info!("Migrating config from version {} to {}", version, CURRENT_CONFIG_VERSION);
+            Self::migrate(&mut table, version);
+        }
+
+        Ok(table)
+    }
+
+    /// Replace `[rules] include = "file"` with the file's rules. Keys set
+    /// inline next to `include` override the file's.
+    fn resolve_rules_include(table: &mut toml::Table, base_dir: &Path) -> Result<Option<RulesInclude>> {
+        let Some(toml::Value::Table(written)) = table.get("rules") else {
+            return Ok(None);
+        };
+        let Some(include) = written.get("include") else {
+            return Ok(None);
+        };
+
+        let include = include.as_str().ok_or_else(|| {
+            OrchestratorError::Config("rules.include must be a path string".to_string())
+        })?;
+        let path = base_dir.join(include);
+
+        let content = fs::read_to_string(&path).map_err(|e| {
+            OrchestratorError::Config(format!("Failed to read rules file {}: {}", path.display(), e))
+        })?;
+        let mut file: toml::Table = toml::from_str(&content).map_err(|e| {
+            OrchestratorError::Config(format!("Invalid rules file {}: {}", path.display(), e))
+        })?;
+
+        // The file may hold the lists at top level or under its own [rules]
+        let mut merged = match file.remove("rules") {
+            Some(toml::Value::Table(rules)) => rules,
+            _ => file,
+        };
+        for (key, value) in written {
+            if key != "include" {
+                merged.insert(key.clone(), value.clone());
+            }
+        }
+
+        // Deserialize here so shape errors name the rules file
+        FileRules::deserialize(toml::Value::Table(merged.clone())).map_err(|e| {
+            OrchestratorError::Config(format!("Invalid rules in {}: {}", path.display(), e))
+        })?;
+
+        let include = RulesInclude { path, written: written.clone() };
+        table.insert("rules".to_string(), toml::Value::Table(merged));
+        Ok(Some(include))
+    }
+
+    /// `path` given on the command line or in the config, resolved against
+    /// the directory holding the config file when it is relative, so a
+    /// service started elsewhere finds the same files
+    pub fn resolve_path(config_path: &Path, path: &Path) -> PathBuf {
+        if path.is_absolute() {
+            return path.to_path_buf();
+        }
+        let config_path = std::path::absolute(config_path).unwrap_or_else(|_| config_path.to_path_buf());
+        config_path.parent().unwrap_or(Path::new(".")).join(path)
+    }
+
+    /// Make a relative source path and drive paths relative to the config
+    /// file's directory instead of the working directory
+    fn resolve_relative_paths(&mut self, base_dir: &Path) {
+        let base_dir = std::path::absolute(base_dir).unwrap_or_else(|_| base_dir.to_path_buf());
+        let mut relative = RelativePaths { base_dir, ..Default::default() };
+
+        if self.source.path.is_relative() {
+            relative.source = Some(self.source.path.clone());
+            self.source.path = relative.base_dir.join(&self.source.path);
+        }
+        for (uuid, drive) in &mut self.drives {
+            if let Some(path) = drive.path.as_mut().filter(|path| path.is_relative()) {
+                relative.drives.insert(uuid.clone(), path.clone());
+                *path = relative.base_dir.join(&*path);
+            }
+        }
+        self.relative_paths = relative;
+    }
+
+    /// Upgrade a raw config table one version at a time
+    fn migrate(table: &mut toml::Table, from: u32) {
+        for version in from..CURRENT_CONFIG_VERSION {
+            match version {
+                // v0 -> v1: only adds the version field; every section added
+                // since then has serde defaults
+                0 => {}
+                _ => unreachable!("no migration from config version {}", version),
+            }
+        }
+
+        table.insert("version".to_string(), toml::Value::Integer(CURRENT_CONFIG_VERSION as i64));
+    }
+
     /// Save configuration to a TOML file
     pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        let content = toml::to_string_pretty(self)?;
+        let content = if self.rules_include.is_none() && self.relative_paths.is_empty() {
+            toml::to_string_pretty(self)?
+        } else {
+            let mut table = toml::Table::try_from(self)?;
+            if let Some(include) = &self.rules_include {
+                table.insert("rules".to_string(), toml::Value::Table(include.written.clone()));
+            }
+            let relative = &self.relative_paths;
+            if let Some(source) = relative.written(relative.source.as_ref(), Some(&self.source.path)) {
+                if let Some(toml::Value::Table(table)) = table.get_mut("source") {
+                    table.insert("path".to_string(), toml::Value::String(source.to_string_lossy().into_owned()));
+                }
+            }
+            for (uuid, drive) in &self.drives {
+                let Some(written) = relative.written(relative.drives.get(uuid), drive.path.as_deref()) else {
+                    continue;
+                };
+                if let Some(toml::Value::Table(drives)) = table.get_mut("drives") {
+                    if let Some(toml::Value::Table(table)) = drives.get_mut(uuid) {
+                        table.insert("path".to_string(), toml::Value::String(written.to_string_lossy().into_owned()));
+                    }
+                }
+            }
+            toml::to_string_pretty(&table)?
+        };
         fs::write(path, content)
             .map_err(|e| OrchestratorError::Config(format!("Failed to write config file: {}", e)))?;
         Ok(())
     }
 
+    /// Write the `path` and `volume_uuid` of these drives into the config
+    /// file in place, keeping its comments and layout. Drives the file
+    /// doesn't list are left out.
+    pub fn save_drive_bindings<P: AsRef<Path>>(&self, path: P, drive_uuids: &[String]) -> Result<()> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)
+            .map_err(|e| OrchestratorError::Config(format!("Failed to read config file: {}", e)))?;
+        let mut document: toml_edit::DocumentMut = content
+            .parse()
+            .map_err(|e| OrchestratorError::Config(format!("Failed to parse config file: {}", e)))?;
+
+        for uuid in drive_uuids {
+            let Some(drive) = self.drives.get(uuid) else {
+                continue;
+            };
+            let Some(table) = document
+                .get_mut("drives")
+                .and_then(|drives| drives.get_mut(uuid))
+                .and_then(|table| table.as_table_like_mut())
+            else {
+                continue;
+            };
+            let relative = &self.relative_paths;
+            let written = relative.written(relative.drives.get(uuid), drive.path.as_deref());
+            if let Some(path) = written.map(PathBuf::as_path).or(drive.path.as_deref()) {
+                table.insert("path", toml_edit::value(path.to_string_lossy().into_owned()));
+            }
+            if let Some(ref volume_uuid) = drive.volume_uuid {
+                table.insert("volume_uuid", toml_edit::value(volume_uuid.as_str()));
+            }
+        }
+
+        fs::write(path, document.to_string())
+            .map_err(|e| OrchestratorError::Config(format!("Failed to write config file: {}", e)))?;
+        Ok(())
+    }
+
     /// Validate configuration
     fn validate(&self) -> Result<()> {
         if !self.source.path.exists() {
@@ -66,6 +1210,180 @@ impl Config {
             ));
         }
 
+        // Names the offending pattern, and the rules file it came from
+        PatternClassifier::new(&self.rules.patterns, self.rules.pattern_syntax).map_err(|e| {
+            match &self.rules_include {
+                Some(include) => OrchestratorError::Config(format!("{} (in {})", e, include.path.display())),
+                None => e,
+            }
+        })?;
+
+        let known = self.known_categories();
+        for category in &self.rules.category_priority {
+            if !known.contains(category) {
+                return Err(OrchestratorError::Config(format!(
+                    "rules.category_priority: '{}' is not a known category (expected one of: {})",
+                    category, known.join(", ")
+                )));
+            }
+        }
+
+        self.rules.subfolder_classifiers()?;
+        for (category, rules) in &self.rules.subfolders {
+            if !known.contains(category) {
+                return Err(OrchestratorError::Config(format!(
+                    "rules.subfolders: '{}' is not a known category (expected one of: {})",
+                    category, known.join(", ")
+                )));
+            }
+            for rule in rules {
+                let relative = rule.folder.components().next().is_some()
+                    && rule.folder.components().all(|c| matches!(c, std::path::Component::Normal(_)));
+                if !relative {
+                    return Err(OrchestratorError::Config(format!(
+                        "rules.subfolders.{}: folder must be a path inside the category folder, got '{}'",
+                        category, rule.folder.display()
+                    )));
+                }
+            }
+        }
+
+        for category in &self.sync.replicate {
+            if !known.contains(category) {
+                return Err(OrchestratorError::Config(format!(
+                    "sync.replicate: '{}' is not a known category (expected one of: {})",
+                    category, known.join(", ")
+                )));
+            }
+        }
+
+        let mut drives: Vec<_> = self.drives.iter().collect();
+        drives.sort_by_key(|(uuid, _)| uuid.as_str());
+        for (uuid, drive) in drives {
+            if !known.contains(&drive.target) {
+                return Err(OrchestratorError::Config(format!(
+                    "drives.{}.target '{}' is not a known category (expected one of: {})",
+                    uuid, drive.target, known.join(", ")
+                )));
+            }
+            if drive.snapshots && drive.bidirectional {
+                return Err(OrchestratorError::Config(format!(
+                    "drives.{}: snapshots and bidirectional can't both be set; files added on the drive have no snapshot to go in",
+                    uuid
+                )));
+            }
+            if drive.snapshot_retention == Some(0) {
+                return Err(OrchestratorError::Config(format!(
+                    "drives.{}.snapshot_retention must be at least 1",
+                    uuid
+                )));
+            }
+        }
+
+        self.check_shared_targets()?;
+
+        for (category, folder) in &self.folder_names {
+            let mut components = Path::new(folder).components();
+            let single = matches!(
+                (components.next(), components.next()),
+                (Some(std::path::Component::Normal(_)), None)
+            );
+            if !single {
+                return Err(OrchestratorError::Config(format!(
+                    "folder_names.{} must be a single folder name, got '{}'",
+                    category, folder
+                )));
+            }
+        }
+
+        for dir in self.source.include_dirs.iter().flatten() {
+            if dir.is_absolute() {
+                return Err(OrchestratorError::Config(format!(
+                    "include_dirs must be relative to the source path: {}",
+                    dir.display()
+                )));
+            }
+        }
+
+        for (category, filter) in &self.rules.mime {
+            for mime in filter.allow.iter().flatten().chain(&filter.deny) {
+                if !mime.contains('/') {
+                    return Err(OrchestratorError::Config(format!(
+                        "rules.mime.{}: '{}' is not a MIME type like image/jpeg or image/*",
+                        category, mime
+                    )));
+                }
+            }
+        }
+
+        if self.source.watch_queue_capacity == 0 {
+            return Err(OrchestratorError::Config("source.watch_queue_capacity must be at least 1".to_string()));
+        }
+
+        if let Some(sniffing) = self.rules.text_sniffing {
+            if sniffing.sample_bytes == 0 || !(0.0..=1.0).contains(&sniffing.max_control_ratio) {
+                return Err(OrchestratorError::Config(
+                    "rules.text_sniffing needs sample_bytes above 0 and max_control_ratio between 0.0 and 1.0".to_string()
+                ));
+            }
+        }
+
+        if let Some(ref addr) = self.metrics_addr {
+            addr.parse::<std::net::SocketAddr>().map_err(|e| OrchestratorError::Config(
+                format!("metrics_addr must be an address like 127.0.0.1:9898, got '{}': {}", addr, e)
+            ))?;
+        }
+
+        if let (Some(min), Some(max)) = (self.sync.min_file_size, self.sync.max_file_size) {
+            if min > max {
+                return Err(OrchestratorError::Config(format!(
+                    "min_file_size ({} bytes) is larger than max_file_size ({} bytes)",
+                    min, max
+                )));
+            }
+        }
+
+        if self.sync.pause_at_percent_full.is_some_and(|percent| percent == 0 || percent > 100) {
+            return Err(OrchestratorError::Config("pause_at_percent_full must be between 1 and 100".to_string()));
+        }
+
+        if self.sync.chunk_hash == Some(0) {
+            return Err(OrchestratorError::Config("chunk_hash must be a size above 0".to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// Refuse drives that share a category when nothing says how: the
+    /// category isn't replicated, drives aren't picked by speed, and more
+    /// than one of them takes every extension. Such a setup used to pick
+    /// whichever drive came first, which is rarely what was meant.
+    pub fn check_shared_targets(&self) -> Result<()> {
+        if self.sync.allow_multiple_per_category || self.sync.drive_selection == DriveSelection::Fastest {
+            return Ok(());
+        }
+
+        let mut by_category: BTreeMap<&str, Vec<(&String, &DriveConfig)>> = BTreeMap::new();
+        for (uuid, drive) in &self.drives {
+            by_category.entry(drive.target.as_str()).or_default().push((uuid, drive));
+        }
+        for (category, mut drives) in by_category {
+            let catch_all = drives.iter().filter(|(_, drive)| drive.accept_extensions.is_none()).count();
+            if drives.len() < 2 || catch_all < 2 || self.sync.replicates(category) {
+                continue;
+            }
+            drives.sort_by_key(|(uuid, _)| uuid.as_str());
+            let named: Vec<String> = drives
+                .iter()
+                .map(|(uuid, drive)| format!("{} ({})", uuid, drive.label))
+                .collect();
+            return Err(OrchestratorError::Config(format!(
+                "drives {} all take the category '{}'. Set sync.allow_multiple_per_category = true to use the first of them by UUID, \
+                 add '{}' to sync.replicate to copy to each, set sync.drive_selection = \"fastest\", \
+                 or give the drives accept_extensions",
+                named.join(", "), category, category
+            )));
+        }
         Ok(())
     }
 
@@ -80,6 +1398,7 @@ impl Config {
                 target: "images".to_string(),
                 path: None,
                 last_seen: None,
+                ..Default::default()
             },
         );
 
@@ -90,6 +1409,7 @@ impl Config {
                 target: "videos".to_string(),
                 path: None,
                 last_seen: None,
+                ..Default::default()
             },
         );
 
@@ -100,74 +1420,146 @@ impl Config {
                 target: "music".to_string(),
                 path: None,
                 last_seen: None,
+                ..Default::default()
             },
         );
 
         Config {
+            version: CURRENT_CONFIG_VERSION,
             source: SourceConfig {
                 path: PathBuf::from("D:/MainStorage"),
+                ..Default::default()
             },
             rules: FileRules {
-                images: vec!["jpg", "jpeg", "png", "gif", "bmp", "webp", "svg"]
+                images: ["jpg", "jpeg", "png", "gif", "bmp", "webp", "svg"]
                     .iter()
                     .map(|s| s.to_string())
                     .collect(),
-                videos: vec!["mp4", "avi", "mov", "mkv", "flv", "wmv", "webm"]
+                videos: ["mp4", "avi", "mov", "mkv", "flv", "wmv", "webm"]
                     .iter()
                     .map(|s| s.to_string())
                     .collect(),
-                music: vec!["mp3", "wav", "flac", "aac", "ogg", "m4a", "wma"]
+                music: ["mp3", "wav", "flac", "aac", "ogg", "m4a", "wma"]
                     .iter()
                     .map(|s| s.to_string())
                     .collect(),
                 documents: Some(
-                    vec!["pdf", "doc", "docx", "txt", "xlsx", "pptx"]
+                    ["pdf", "doc", "docx", "txt", "xlsx", "pptx"]
                         .iter()
                         .map(|s| s.to_string())
                         .collect(),
                 ),
                 archives: Some(
-                    vec!["zip", "rar", "7z", "tar", "gz"]
+                    ["zip", "rar", "7z", "tar", "gz"]
                         .iter()
                         .map(|s| s.to_string())
                         .collect(),
                 ),
+                pattern_syntax: PatternSyntax::default(),
+                patterns: Vec::new(),
+                subfolders: HashMap::new(),
+                mime: HashMap::new(),
+                text_sniffing: None,
+                category_priority: Vec::new(),
+                classify_strategy: ClassifyStrategy::default(),
             },
             drives,
+            sync: SyncConfig::default(),
+            notifications: NotificationsConfig::default(),
+            hooks: HooksConfig::default(),
+            folder_names: HashMap::new(),
+            metrics_addr: None,
+            rules_include: None,
+            relative_paths: RelativePaths::default(),
         }
     }
 
-    /// Get file category based on extension
-    #[allow(dead_code)]
+    /// The folder a category's files go in on its drive
+    pub fn folder_for<'a>(&'a self, category: &'a str) -> &'a str {
+        self.folder_names.get(category).map(String::as_str).unwrap_or(category)
+    }
+
+    /// Get file category based on extension. An extension in several lists
+    /// goes to the one `category_priority` ranks first.
     pub fn get_file_category(&self, extension: &str) -> Option<String> {
         let ext = extension.to_lowercase();
+        let candidates = self.rules.categories_listing(&ext);
+        self.rules.preferred(&candidates).map(str::to_string)
+    }
 
-        if self.rules.images.contains(&ext) {
-            return Some("images".to_string());
-        }
-        if self.rules.videos.contains(&ext) {
-            return Some("videos".to_string());
-        }
-        if self.rules.music.contains(&ext) {
-            return Some("music".to_string());
+    /// This config with every file sent to `root/<category>/<relative path>`
+    /// instead of to the registered drives, for `fo sync-once --target-root`:
+    /// one always-connected stand-in drive per known category, with the
+    /// default drive options and no folder_names. Unknown files are skipped
+    /// rather than quarantined, so the source isn't touched, and hooks and
+    /// notifications are switched off so a trial run doesn't fire them.
+    pub fn with_target_root(mut self, root: &Path) -> Result<Self> {
+        if root.starts_with(&self.source.path) {
+            return Err(OrchestratorError::Config(format!(
+                "--target-root {} is inside the source folder; pick a folder outside it",
+                root.display()
+            )));
         }
-        if let Some(docs) = &self.rules.documents {
-            if docs.contains(&ext) {
-                return Some("documents".to_string());
-            }
-        }
-        if let Some(archives) = &self.rules.archives {
-            if archives.contains(&ext) {
-                return Some("archives".to_string());
+
+        self.drives = self
+            .known_categories()
+            .into_iter()
+            .map(|category| {
+                let drive = DriveConfig {
+                    label: format!("{} ({})", root.display(), category),
+                    target: category.clone(),
+                    path: Some(root.to_path_buf()),
+                    always_connected: true,
+                    ..Default::default()
+                };
+                (format!("{}:{}", TARGET_ROOT_DRIVE, category), drive)
+            })
+            .collect();
+        self.folder_names.clear();
+        self.sync.quarantine_dir = None;
+        self.hooks = HooksConfig { timeout_secs: self.hooks.timeout_secs, ..Default::default() };
+        self.notifications.enabled = false;
+        Ok(self)
+    }
+
+    /// Find drive UUID for a given category, the first by UUID when
+    /// several take it
+    pub fn find_drive_for_category(&self, category: &str) -> Option<(&String, &DriveConfig)> {
+        self.drives
+            .iter()
+            .filter(|(_, drive)| drive.target == category)
+            .min_by_key(|(uuid, _)| uuid.as_str())
+    }
+
+    /// The built-in categories plus any custom ones named by pattern rules
+    pub fn known_categories(&self) -> Vec<String> {
+        let mut categories: Vec<String> = BUILTIN_CATEGORIES.iter().map(|c| c.to_string()).collect();
+        for rule in &self.rules.patterns {
+            if !categories.contains(&rule.category) {
+                categories.push(rule.category.clone());
             }
         }
+        categories
+    }
 
-        None
+    /// The first drive taking `category` that accepts `extension`
+    pub fn find_drive_for_file(&self, category: &str, extension: Option<&str>) -> Option<(&String, &DriveConfig)> {
+        self.drives_for_file(category, extension).into_iter().next()
     }
 
-    /// Find drive UUID for a given category
-    pub fn find_drive_for_category(&self, category: &str) -> Option<(&String, &DriveConfig)> {
-        self.drives.iter().find(|(_, drive)| drive.target == category)
+    /// Every drive that takes a file of `category` with `extension`, in
+    /// the order [`find_drive_for_file`](Self::find_drive_for_file) prefers them.
+    /// Drives that explicitly list the extension in `accept_extensions` come
+    /// before drives that accept everything; ties go by UUID so the choice is
+    /// stable across runs.
+    pub fn drives_for_file(&self, category: &str, extension: Option<&str>) -> Vec<(&String, &DriveConfig)> {
+        let mut candidates: Vec<_> = self.drives
+            .iter()
+            .filter(|(_, drive)| drive.target == category && drive.accepts_extension(extension))
+            .collect();
+
+        candidates.sort_by_key(|(uuid, drive)| (drive.accept_extensions.is_none(), uuid.as_str()));
+        candidates
     }
 }
 
@@ -184,4 +1576,326 @@ mod tests {
         assert_eq!(config.get_file_category("mp3"), Some("music".to_string()));
         assert_eq!(config.get_file_category("unknown"), None);
     }
+
+    #[test]
+    fn test_category_priority() {
+        let mut config = Config::default_config();
+        config.rules.documents.as_mut().unwrap().push("ogg".to_string());
+
+        // ogg is music first in the built-in order, and nothing decides
+        assert_eq!(config.get_file_category("ogg"), Some("music".to_string()));
+        let ambiguous = config.rules.ambiguous_extensions();
+        assert_eq!(ambiguous, vec![("ogg".to_string(), vec!["music", "documents"])]);
+
+        config.rules.category_priority = vec!["documents".to_string()];
+        assert_eq!(config.get_file_category("OGG"), Some("documents".to_string()));
+        assert!(config.rules.ambiguous_extensions().is_empty());
+        assert_eq!(config.rules.preferred(&["archives", "documents"]), Some("documents"));
+        assert_eq!(config.rules.preferred(&["archives", "images"]), Some("archives"));
+
+        let dir = tempfile::TempDir::new().unwrap();
+        config.source.path = dir.path().to_path_buf();
+        config.rules.category_priority.push("docs".to_string());
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_mime_rejection() {
+        let mut rules = Config::default_config().rules;
+        rules.mime.insert("images".to_string(), MimeFilter {
+            allow: Some(vec!["image/jpeg".to_string(), "image/png".to_string()]),
+            deny: Vec::new(),
+        });
+        rules.mime.insert("videos".to_string(), MimeFilter {
+            allow: None,
+            deny: vec!["video/*".to_string()],
+        });
+
+        assert!(rules.mime_rejection("images", Some("image/png")).is_none());
+        assert!(rules.mime_rejection("images", Some("image/x-icon")).is_some());
+        assert!(rules.mime_rejection("images", None).is_some());
+        assert!(rules.mime_rejection("videos", Some("video/mp4")).is_some());
+        assert!(rules.mime_rejection("videos", None).is_none());
+        assert!(rules.mime_rejection("music", Some("audio/mpeg")).is_none());
+    }
+
+    #[test]
+    fn test_parse_migrates_unversioned_config() {
+        let mut old = toml::to_string(&Config::default_config()).unwrap();
+        old = old.replace(&format!("version = {}\n", CURRENT_CONFIG_VERSION), "");
+        assert!(!old.contains("version"));
+
+        let config = Config::parse(&old).unwrap();
+        assert_eq!(config.version, CURRENT_CONFIG_VERSION);
+        assert!(toml::to_string(&config).unwrap().contains("version = "));
+    }
+
+    #[test]
+    fn test_validate_drive_targets() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config::default_config();
+        config.source.path = dir.path().to_path_buf();
+        config.drives.get_mut("example-uuid-1").unwrap().target = "imagess".to_string();
+
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("drives.example-uuid-1.target 'imagess'"), "{}", err);
+
+        // Custom categories come from pattern rules
+        config.drives.get_mut("example-uuid-1").unwrap().target = "screenshots".to_string();
+        config.rules.patterns.push(PatternRule {
+            pattern: "Screenshot_*.png".to_string(),
+            category: "screenshots".to_string(),
+        });
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn test_validate_subfolders() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config::default_config();
+        config.source.path = dir.path().to_path_buf();
+        let rule = |pattern: &str, folder: &str| SubfolderRule {
+            pattern: pattern.to_string(),
+            folder: PathBuf::from(folder),
+        };
+
+        config.rules.subfolders.insert("images".to_string(), vec![rule("Screenshot_*", "Screenshots")]);
+        config.validate().unwrap();
+
+        config.rules.subfolders.insert("images".to_string(), vec![rule("IMG_*", "../Camera")]);
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("rules.subfolders.images"), "{}", err);
+
+        config.rules.subfolders.insert("images".to_string(), vec![rule("IMG_[", "Camera")]);
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("rules.subfolders.images") && err.contains("IMG_["), "{}", err);
+
+        config.rules.subfolders.clear();
+        config.rules.subfolders.insert("photos".to_string(), vec![rule("IMG_*", "Camera")]);
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_rejects_unexplained_shared_targets() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config::default_config();
+        config.source.path = dir.path().to_path_buf();
+        config.drives.get_mut("example-uuid-2").unwrap().target = "images".to_string();
+
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("example-uuid-1 (ImageUSB), example-uuid-2 (VideoUSB)"), "{}", err);
+        assert!(err.contains("'images'"), "{}", err);
+
+        // Any setting that says how the drives share it will do
+        config.drives.get_mut("example-uuid-2").unwrap().accept_extensions = Some(vec!["heic".to_string()]);
+        config.validate().unwrap();
+        config.drives.get_mut("example-uuid-2").unwrap().accept_extensions = None;
+        config.sync.replicate = vec!["images".to_string()];
+        config.validate().unwrap();
+        config.sync.replicate.clear();
+        config.sync.allow_multiple_per_category = true;
+        config.validate().unwrap();
+        assert_eq!(config.find_drive_for_category("images").unwrap().0, "example-uuid-1");
+    }
+
+    #[test]
+    fn test_parse_rejects_future_version() {
+        let mut config = Config::default_config();
+        config.version = CURRENT_CONFIG_VERSION + 1;
+        let content = toml::to_string(&config).unwrap();
+
+        let err = Config::parse(&content).unwrap_err().to_string();
+        assert!(err.contains("upgrade"), "{}", err);
+    }
+
+    #[test]
+    fn test_save_drive_bindings_keeps_comments() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config::default_config();
+        config.source.path = dir.path().to_path_buf();
+        let content = format!("# My drives\n{}", toml::to_string(&config).unwrap());
+        let content = content.replace("[drives.example-uuid-1]", "# The camera card\n[drives.example-uuid-1]");
+        let config_path = dir.path().join("config.toml");
+        fs::write(&config_path, &content).unwrap();
+
+        let mut config = Config::load(&config_path).unwrap();
+        let drive = config.drives.get_mut("example-uuid-1").unwrap();
+        drive.path = Some(dir.path().join("mnt"));
+        drive.volume_uuid = Some("1234-ABCD".to_string());
+        config.save_drive_bindings(&config_path, &["example-uuid-1".to_string()]).unwrap();
+
+        let saved = fs::read_to_string(&config_path).unwrap();
+        assert!(saved.starts_with("# My drives\n"));
+        assert!(saved.contains("# The camera card\n"));
+        let reloaded = Config::load(&config_path).unwrap();
+        assert_eq!(reloaded.drives["example-uuid-1"].path, Some(dir.path().join("mnt")));
+        assert_eq!(reloaded.drives["example-uuid-1"].volume_uuid.as_deref(), Some("1234-ABCD"));
+    }
+
+    #[test]
+    fn test_load_rules_include() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config::default_config();
+        config.source.path = dir.path().to_path_buf();
+
+        let main = toml::to_string(&config).unwrap();
+        let start = main.find("[rules]").unwrap();
+        let end = start + main[start..].find("\n[").unwrap() + 1;
+        let main = format!(
+            "{}[rules]\ninclude = \"shared/rules.toml\"\nmusic = [\"opus\"]\n{}",
+            &main[..start],
+            &main[end..]
+        );
+        fs::create_dir(dir.path().join("shared")).unwrap();
+        fs::write(
+            dir.path().join("shared/rules.toml"),
+            "images = [\"jpg\"]\nvideos = [\"mp4\"]\nmusic = [\"mp3\"]\ndocuments = [\"pdf\"]\narchives = [\"zip\"]\n",
+        )
+        .unwrap();
+        let config_path = dir.path().join("config.toml");
+        fs::write(&config_path, &main).unwrap();
+
+        let config = Config::load(&config_path).unwrap();
+        assert_eq!(config.rules.images, ["jpg"]);
+        assert_eq!(config.rules.music, ["opus"]);
+
+        // Saving keeps the include rather than inlining the file
+        config.save(&config_path).unwrap();
+        let saved = fs::read_to_string(&config_path).unwrap();
+        assert!(saved.contains("include = \"shared/rules.toml\""));
+        assert!(!saved.contains("\"mp4\""));
+
+        fs::write(dir.path().join("shared/rules.toml"), "images = \"jpg\"\n").unwrap();
+        let err = Config::load(&config_path).unwrap_err().to_string();
+        assert!(err.contains("rules.toml"), "{}", err);
+    }
+
+    #[test]
+    fn test_relative_paths_resolve_against_the_config_folder() {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::create_dir(dir.path().join("files")).unwrap();
+        let mut config = Config::default_config();
+        config.source.path = PathBuf::from("files");
+        config.drives.get_mut("example-uuid-1").unwrap().path = Some(PathBuf::from("drives/images"));
+        config.drives.get_mut("example-uuid-2").unwrap().path = Some(PathBuf::from("drives/videos"));
+        let config_path = dir.path().join("config.toml");
+        fs::write(&config_path, toml::to_string(&config).unwrap()).unwrap();
+
+        // Loaded from elsewhere, as a service would
+        let mut config = Config::load(&config_path).unwrap();
+        assert_eq!(config.source.path, dir.path().join("files"));
+        assert_eq!(config.drives["example-uuid-1"].path.as_deref(), Some(dir.path().join("drives/images").as_path()));
+        assert_eq!(Config::resolve_path(&config_path, Path::new("state.db")), dir.path().join("state.db"));
+        assert_eq!(Config::resolve_path(&config_path, Path::new("/var/lib/fo.db")), Path::new("/var/lib/fo.db"));
+
+        // Saved as written, except for a path that has since changed
+        config.drives.get_mut("example-uuid-2").unwrap().path = Some(PathBuf::from("/media/videos"));
+        config.save(&config_path).unwrap();
+        let saved = Config::parse(&fs::read_to_string(&config_path).unwrap()).unwrap();
+        assert_eq!(saved.source.path, Path::new("files"));
+        assert_eq!(saved.drives["example-uuid-1"].path.as_deref(), Some(Path::new("drives/images")));
+        assert_eq!(saved.drives["example-uuid-2"].path.as_deref(), Some(Path::new("/media/videos")));
+    }
+
+    #[test]
+    fn test_source_scope() {
+        let source = SourceConfig {
+            path: PathBuf::from("/src"),
+            scan_max_depth: Some(2),
+            include_dirs: Some(vec![PathBuf::from("photos/2024")]),
+            ..Default::default()
+        };
+
+        assert!(source.should_descend(Path::new("/src/photos")));
+        assert!(source.should_descend(Path::new("/src/photos/2024")));
+        assert!(!source.should_descend(Path::new("/src/videos")));
+        assert!(!source.should_descend(Path::new("/src/photos/2024/trip")));
+
+        assert!(source.in_scope(Path::new("/src/photos/2024/a.jpg")));
+        assert!(!source.in_scope(Path::new("/src/photos/b.jpg")));
+        assert!(!source.in_scope(Path::new("/src/photos/2024/trip/c.jpg")));
+        assert!(source.in_scope(Path::new("/elsewhere/d.jpg")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_hidden_and_system_dirs_are_skipped() {
+        let mut source = SourceConfig { path: PathBuf::from("/src"), ..Default::default() };
+
+        assert!(!source.should_descend(Path::new("/src/.git")));
+        assert!(!source.should_descend(Path::new("/src/$RECYCLE.BIN")));
+        assert!(!source.should_descend(Path::new("/src/photos/.Trash-1000")));
+        assert!(!source.in_scope(Path::new("/src/System Volume Information/a.jpg")));
+        assert!(!source.in_scope(Path::new("/src/photos/.cache/thumb.jpg")));
+        assert!(!source.in_scope(Path::new("/src/photos/.hidden.jpg")));
+        assert!(source.in_scope(Path::new("/src/photos/visible.jpg")));
+
+        source.skip_hidden = false;
+        source.skip_dirs = Some(vec!["node_modules".to_string()]);
+        assert!(source.in_scope(Path::new("/src/photos/.hidden.jpg")));
+        assert!(source.should_descend(Path::new("/src/$RECYCLE.BIN")));
+        assert!(!source.in_scope(Path::new("/src/app/Node_Modules/logo.png")));
+    }
+
+    #[test]
+    fn test_file_size_bounds() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("10KB"), Ok(10 * 1024));
+        assert_eq!(parse_size("1.5 GB"), Ok(3 * 512 * 1024 * 1024));
+        assert!(parse_size("10XB").is_err());
+
+        let content = toml::to_string(&Config::default_config())
+            .unwrap()
+            .replace("[sync]\n", "[sync]\nmin_file_size = 1\nmax_file_size = \"4GB\"\n");
+        let config = Config::parse(&content).unwrap();
+        assert_eq!(config.sync.max_file_size, Some(4 << 30));
+
+        assert!(config.sync.size_rejection(0).is_some());
+        assert!(config.sync.size_rejection(1024).is_none());
+        assert!(config.sync.size_rejection(5 << 30).is_some());
+    }
+
+    #[test]
+    fn test_target_modes_are_octal() {
+        assert_eq!(parse_mode("0644"), Ok(0o644));
+        assert_eq!(parse_mode("755"), Ok(0o755));
+        assert_eq!(parse_mode("0o2775"), Ok(0o2775));
+        assert!(parse_mode("0855").is_err());
+        assert!(parse_mode("17777").is_err());
+
+        let content = toml::to_string(&Config::default_config())
+            .unwrap()
+            .replace("[sync]\n", "[sync]\ntarget_file_mode = \"0640\"\ntarget_dir_mode = 0o750\n");
+        let config = Config::parse(&content).unwrap();
+        assert_eq!(config.sync.target_file_mode, Some(0o640));
+        assert_eq!(config.sync.target_dir_mode, Some(0o750));
+
+        // Saved back as octal strings, which load the same
+        let saved = toml::to_string(&config).unwrap();
+        assert!(saved.contains("target_dir_mode = \"0750\""), "{}", saved);
+        assert_eq!(Config::parse(&saved).unwrap().sync.target_dir_mode, Some(0o750));
+    }
+
+    #[test]
+    fn test_find_drive_for_file_honors_accept_extensions() {
+        let mut config = Config::default_config();
+        config.drives.insert(
+            "raw-drive".to_string(),
+            DriveConfig {
+                label: "RawUSB".to_string(),
+                target: "images".to_string(),
+                accept_extensions: Some(vec!["cr2".to_string(), "NEF".to_string()]),
+                ..Default::default()
+            },
+        );
+
+        let (uuid, _) = config.find_drive_for_file("images", Some("nef")).unwrap();
+        assert_eq!(uuid, "raw-drive");
+
+        let (uuid, _) = config.find_drive_for_file("images", Some("jpg")).unwrap();
+        assert_eq!(uuid, "example-uuid-1");
+
+        config.drives.get_mut("example-uuid-1").unwrap().accept_extensions = Some(vec!["png".to_string()]);
+        assert!(config.find_drive_for_file("images", Some("jpg")).is_none());
+    }
 }
@@ -0,0 +1,142 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tokio::sync::mpsc;
+use crate::error::{OrchestratorError, Result};
+use crate::state::SyncStats;
+
+/// Commands a running watcher accepts from outside the process
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlCommand {
+    Pause,
+    Resume,
+}
+
+/// Control commands arriving for this process, from [`listen`]
+pub struct ControlListener {
+    rx: mpsc::UnboundedReceiver<ControlCommand>,
+    /// Keeps the channel open when no signal source could be set up
+    _tx: mpsc::UnboundedSender<ControlCommand>,
+}
+
+impl ControlListener {
+    /// The next command; never ends while the listener is alive
+    pub async fn recv(&mut self) -> Option<ControlCommand> {
+        self.rx.recv().await
+    }
+}
+
+/// Listen for control commands. On Unix, SIGUSR1 pauses and SIGUSR2
+/// resumes; elsewhere nothing arrives.
+pub fn listen() -> ControlListener {
+    let (tx, rx) = mpsc::unbounded_channel();
+
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        for (kind, command) in [
+            (SignalKind::user_defined1(), ControlCommand::Pause),
+            (SignalKind::user_defined2(), ControlCommand::Resume),
+        ] {
+            let tx = tx.clone();
+            match signal(kind) {
+                Ok(mut stream) => {
+                    tokio::spawn(async move {
+                        while stream.recv().await.is_some() {
+                            if tx.send(command).is_err() {
+                                break;
+                            }
+                        }
+                    });
+                }
+                Err(e) => tracing::warn!("Failed to install {:?} handler: {}", command, e),
+            }
+        }
+    }
+
+    ControlListener { rx, _tx: tx }
+}
+
+/// Send a control command to a watcher process
+pub fn send(pid: u32, command: ControlCommand) -> Result<()> {
+    #[cfg(unix)]
+    {
+        let signal = match command {
+            ControlCommand::Pause => libc::SIGUSR1,
+            ControlCommand::Resume => libc::SIGUSR2,
+        };
+        // 0 and negative ids would signal whole process groups
+        let pid = libc::pid_t::try_from(pid)
+            .ok()
+            .filter(|pid| *pid > 0)
+            .ok_or_else(|| OrchestratorError::Sync(format!("Invalid watcher process id {}", pid)))?;
+
+        // SAFETY: kill takes no pointers; a positive pid names one process
+        if unsafe { libc::kill(pid, signal) } != 0 {
+            return Err(OrchestratorError::Sync(format!(
+                "Failed to signal watcher process {}: {}",
+                pid,
+                std::io::Error::last_os_error()
+            )));
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = (pid, command);
+        Err(OrchestratorError::Sync("Pausing the watcher is only supported on Unix".to_string()))
+    }
+}
+
+/// Record whether the watcher for a database is paused, so `status` and
+/// the GUI can show it
+pub fn set_paused(db_path: &Path, paused: bool) -> Result<()> {
+    let marker = paused_marker(db_path);
+
+    if paused {
+        fs::write(&marker, b"")?;
+    } else if marker.exists() {
+        fs::remove_file(&marker)?;
+    }
+    Ok(())
+}
+
+/// Whether the watcher for a database was last told to pause. Only
+/// meaningful while the watcher holds the instance lock.
+pub fn is_paused(db_path: &Path) -> bool {
+    paused_marker(db_path).exists()
+}
+
+/// Publish the watcher's current totals for `status --watch`, which can't
+/// open the database while the watcher has it
+pub fn write_stats(db_path: &Path, stats: &SyncStats) -> Result<()> {
+    let path = stats_file(db_path);
+    let mut temp = path.clone().into_os_string();
+    temp.push(".tmp");
+    fs::write(&temp, serde_json::to_vec(stats)?)?;
+    fs::rename(&temp, &path)?;
+    Ok(())
+}
+
+/// The totals the watcher last published, and when. Only meaningful while
+/// the watcher holds the instance lock.
+pub fn read_stats(db_path: &Path) -> Option<(SystemTime, SyncStats)> {
+    let path = stats_file(db_path);
+    let written = fs::metadata(&path).and_then(|meta| meta.modified()).ok()?;
+    let stats = serde_json::from_slice(&fs::read(&path).ok()?).ok()?;
+    Some((written, stats))
+}
+
+fn stats_file(db_path: &Path) -> PathBuf {
+    let mut name = db_path.file_name().unwrap_or_default().to_os_string();
+    name.push(".stats.json");
+    db_path.with_file_name(name)
+}
+
+fn paused_marker(db_path: &Path) -> PathBuf {
+    let mut name = db_path.file_name().unwrap_or_default().to_os_string();
+    name.push(".paused");
+    db_path.with_file_name(name)
+}
@@ -1,85 +1,216 @@
 use sysinfo::Disks;
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 
-#[derive(Debug, Clone)]
+/// File at a drive's root holding the id `register-drive` gave it
+pub const MARKER_FILE_NAME: &str = ".orchestrator-id";
+
+/// A mounted drive as reported by a [`DriveProvider`]
+#[derive(Debug, Clone, Default)]
 pub struct DriveInfo {
-    pub name: String,
+    /// What the OS calls the device: `/dev/sdb1` on Linux, the drive
+    /// letter (`E:`) on Windows, the volume name on macOS
+    pub device_name: String,
+    /// The label the volume was formatted with, when it has one and the
+    /// platform reports it
+    pub volume_label: Option<String>,
     pub mount_point: PathBuf,
     pub total_space: u64,
     pub available_space: u64,
     pub file_system: String,
     pub is_removable: bool,
+    /// Remote mount (SMB/CIFS, NFS, ...) rather than a local disk
+    pub is_network: bool,
+    /// Stable volume identity when the platform exposes one: the file
+    /// system UUID on Linux, the volume serial number on Windows
+    pub volume_uuid: Option<String>,
+    /// Id read from the [`MARKER_FILE_NAME`] file at the drive's root, if
+    /// it has one; the same on every OS and mount point
+    pub marker_id: Option<String>,
 }
 
-pub struct DriveDetector {
-    disks: Disks,
+/// File system types reported for network mounts
+const NETWORK_FILE_SYSTEMS: &[&str] = &[
+    "cifs", "smbfs", "smb2", "smb3", "nfs", "nfs4", "afpfs", "webdav", "davfs", "fuse.sshfs",
+];
+
+/// Whether a file system type name denotes a network mount
+pub fn is_network_file_system(file_system: &str) -> bool {
+    let fs = file_system.to_lowercase();
+    NETWORK_FILE_SYSTEMS.contains(&fs.as_str())
 }
 
-impl DriveDetector {
-    /// Create a new drive detector
-    pub fn new() -> Self {
-        let mut disks = Disks::new_with_refreshed_list();
-        disks.refresh_list();
-        
-        Self { disks }
+/// Source of the drives attached right now. [`DriveDetector`] reads them
+/// from the OS; tests can use `MockDriveProvider` (with the `test-util`
+/// feature) to plug and unplug drives deterministically.
+pub trait DriveProvider: Send + Sync {
+    /// Re-read the attached drives
+    fn refresh(&mut self);
+
+    /// Get all currently connected drives
+    fn get_all_drives(&self) -> Vec<DriveInfo>;
+
+    /// Find the drive mounted exactly at `mount_point`
+    fn get_drive_by_mount_point(&self, mount_point: &Path) -> Option<DriveInfo> {
+        self.get_all_drives()
+            .into_iter()
+            .find(|drive| drive.mount_point == mount_point)
     }
 
-    /// Refresh the list of available drives
-    pub fn refresh(&mut self) {
-        self.disks.refresh_list();
+    /// Find a connected drive by its file system UUID
+    fn find_drive_by_volume_uuid(&self, volume_uuid: &str) -> Option<DriveInfo> {
+        self.get_all_drives()
+            .into_iter()
+            .find(|drive| drive.volume_uuid.as_deref() == Some(volume_uuid))
     }
 
-    /// Get all currently connected drives
-    pub fn get_all_drives(&self) -> Vec<DriveInfo> {
-        self.disks
+    /// Find a connected drive by the id in its marker file
+    fn find_drive_by_marker(&self, marker_id: &str) -> Option<DriveInfo> {
+        self.get_all_drives()
+            .into_iter()
+            .find(|drive| drive.marker_id.as_deref() == Some(marker_id))
+    }
+
+    /// Find the drive whose volume label is exactly `label`
+    /// (case-insensitive), or failing that whose device name or mount point
+    /// directory is, but only if exactly one drive matches. Unlike
+    /// [`find_drive_by_label`](Self::find_drive_by_label) this is safe to act
+    /// on without asking the user.
+    fn find_unique_drive_by_label(&self, label: &str) -> Option<DriveInfo> {
+        let drives = self.get_all_drives();
+        let by_volume_label: Vec<&DriveInfo> = drives
             .iter()
-            .map(|disk| DriveInfo {
-                name: disk.name().to_string_lossy().to_string(),
-                mount_point: disk.mount_point().to_path_buf(),
-                total_space: disk.total_space(),
-                available_space: disk.available_space(),
-                file_system: disk.file_system().to_string_lossy().to_string(),
-                is_removable: disk.is_removable(),
-            })
-            .collect()
+            .filter(|drive| drive.volume_label.as_deref().is_some_and(|name| name.eq_ignore_ascii_case(label)))
+            .collect();
+        match by_volume_label.as_slice() {
+            [found] => return Some((*found).clone()),
+            [] => {}
+            _ => return None,
+        }
+
+        let mut matches = drives.into_iter().filter(|drive| {
+            drive.device_name.eq_ignore_ascii_case(label)
+                || drive.mount_point
+                    .file_name()
+                    .map(|n| n.to_string_lossy().eq_ignore_ascii_case(label))
+                    .unwrap_or(false)
+        });
+
+        let found = matches.next()?;
+        if matches.next().is_some() {
+            return None;
+        }
+        Some(found)
     }
 
     /// Get only removable drives (USB drives)
-    #[allow(dead_code)]
-    pub fn get_removable_drives(&self) -> Vec<DriveInfo> {
+    fn get_removable_drives(&self) -> Vec<DriveInfo> {
         self.get_all_drives()
             .into_iter()
             .filter(|drive| drive.is_removable)
             .collect()
     }
 
-    /// Check if a specific drive is connected by mount point
-    pub fn is_drive_connected(&self, mount_point: &PathBuf) -> bool {
-        self.disks
-            .iter()
-            .any(|disk| disk.mount_point() == mount_point)
+    /// Check if a specific drive is connected by mount point.
+    /// Network mounts can stay in the mount table after the server goes away,
+    /// so for those the mount point must also be reachable.
+    fn is_drive_connected(&self, mount_point: &Path) -> bool {
+        self.get_drive_by_mount_point(mount_point)
+            .map(|drive| !drive.is_network || DriveDetector::is_path_reachable(mount_point))
+            .unwrap_or(false)
     }
 
-    /// Find drive by label/name (case-insensitive partial match)
-    pub fn find_drive_by_label(&self, label: &str) -> Option<DriveInfo> {
-        let label_lower = label.to_lowercase();
-        
-        self.get_all_drives()
-            .into_iter()
-            .find(|drive| {
-                drive.name.to_lowercase().contains(&label_lower) ||
-                drive.mount_point.to_string_lossy().to_lowercase().contains(&label_lower)
-            })
+    /// Like [`is_drive_connected`](Self::is_drive_connected), but when the
+    /// registered drive has a recorded marker or volume id the drive mounted
+    /// there must carry the same one. A different stick mounted at the same
+    /// letter or path is not the registered drive. The marker decides when
+    /// both sides have one; a drive whose marker is missing (deleted, or
+    /// never written because the drive was read-only) falls back to the
+    /// volume id.
+    fn is_registered_drive_connected(&self, mount_point: &Path, marker_id: Option<&str>, volume_uuid: Option<&str>) -> bool {
+        if !self.is_drive_connected(mount_point) {
+            return false;
+        }
+        let Some(drive) = self.get_drive_by_mount_point(mount_point) else {
+            return true;
+        };
+
+        if let (Some(expected), Some(found)) = (marker_id, drive.marker_id.as_deref()) {
+            return found == expected;
+        }
+        match volume_uuid {
+            Some(expected) => drive.volume_uuid.as_deref().is_none_or(|id| id == expected),
+            None => true,
+        }
+    }
+
+    /// Find a registered drive by its marker id, then its exact volume id,
+    /// when those are recorded. The fuzzy label match is the fallback, and
+    /// then only among drives without a conflicting id, so two sticks
+    /// sharing a label aren't confused.
+    fn find_registered_drive(&self, marker_id: Option<&str>, volume_uuid: Option<&str>, label: &str) -> Option<DriveInfo> {
+        let mut drives = self.get_all_drives();
+
+        if let Some(expected) = marker_id {
+            if let Some(drive) = drives.iter().find(|drive| drive.marker_id.as_deref() == Some(expected)) {
+                return Some(drive.clone());
+            }
+            drives.retain(|drive| drive.marker_id.is_none());
+        }
+
+        let Some(expected) = volume_uuid else {
+            return best_label_match(drives, label);
+        };
+
+        if let Some(drive) = drives.iter().find(|drive| drive.volume_uuid.as_deref() == Some(expected)) {
+            return Some(drive.clone());
+        }
+        drives.retain(|drive| drive.volume_uuid.is_none());
+        best_label_match(drives, label)
+    }
+
+    /// Find drive by label (case-insensitive partial match), preferring
+    /// volume labels over device names and mount points
+    fn find_drive_by_label(&self, label: &str) -> Option<DriveInfo> {
+        self.find_registered_drive(None, None, label)
     }
 
     /// Get drive info for a specific path
-    #[allow(dead_code)]
-    pub fn get_drive_for_path(&self, path: &PathBuf) -> Option<DriveInfo> {
-        // Find the disk that contains this path
+    fn get_drive_for_path(&self, path: &Path) -> Option<DriveInfo> {
+        // The innermost mount containing the path, not `/` for everything
         self.get_all_drives()
             .into_iter()
-            .find(|drive| path.starts_with(&drive.mount_point))
+            .filter(|drive| path.starts_with(&drive.mount_point))
+            .max_by_key(|drive| drive.mount_point.components().count())
+    }
+}
+
+/// The [`DriveProvider`] that asks the operating system
+pub struct DriveDetector {
+    disks: Disks,
+}
+
+impl DriveDetector {
+    /// Create a new drive detector
+    pub fn new() -> Self {
+        let mut disks = Disks::new_with_refreshed_list();
+        disks.refresh_list();
+        
+        Self { disks }
+    }
+
+    /// Refresh space figures for the drives already known, without
+    /// rescanning mounts. Cheaper than `refresh` when only free space matters,
+    /// e.g. after a large copy.
+    pub fn refresh_usage(&mut self) {
+        self.disks.refresh();
+    }
+
+    /// Check that a path (e.g. a network share like `\\server\share`) can be
+    /// accessed right now, independent of the mount table
+    pub fn is_path_reachable(path: &Path) -> bool {
+        std::fs::metadata(path).map(|m| m.is_dir()).unwrap_or(false)
     }
 
     /// Create a simple UUID-like identifier from drive info
@@ -90,7 +221,7 @@ impl DriveDetector {
         use std::hash::{Hash, Hasher};
 
         let mut hasher = DefaultHasher::new();
-        drive.name.hash(&mut hasher);
+        drive.device_name.hash(&mut hasher);
         drive.mount_point.hash(&mut hasher);
         drive.total_space.hash(&mut hasher);
         
@@ -99,7 +230,6 @@ impl DriveDetector {
 
     /// Monitor for newly connected drives (blocking)
     /// Returns a list of newly detected drives
-    #[allow(dead_code)]
     pub fn wait_for_new_drives(&mut self, timeout_secs: u64) -> Vec<DriveInfo> {
         let initial_drives = self.get_drive_map();
         let start = std::time::Instant::now();
@@ -126,7 +256,6 @@ impl DriveDetector {
     }
 
     /// Get a map of mount points to drive info
-    #[allow(dead_code)]
     fn get_drive_map(&self) -> HashMap<PathBuf, DriveInfo> {
         self.get_all_drives()
             .into_iter()
@@ -138,18 +267,313 @@ impl DriveDetector {
     pub fn print_drives(&self) {
         println!("\n=== Connected Drives ===");
         for drive in self.get_all_drives() {
-            println!("\nDrive: {}", drive.name);
+            println!("\nDrive: {}", drive.display_name());
+            println!("  Device: {}", drive.device_name);
+            println!("  Volume Label: {}", drive.volume_label.as_deref().unwrap_or("(none)"));
             println!("  Mount Point: {}", drive.mount_point.display());
             println!("  Total Space: {} GB", drive.total_space / 1_000_000_000);
             println!("  Available: {} GB", drive.available_space / 1_000_000_000);
             println!("  File System: {}", drive.file_system);
             println!("  Removable: {}", drive.is_removable);
+            println!("  Network: {}", drive.is_network);
+            if let Some(ref uuid) = drive.volume_uuid {
+                println!("  Volume UUID: {}", uuid);
+            }
+            if let Some(ref marker) = drive.marker_id {
+                println!("  Marker ID: {}", marker);
+            }
             println!("  Drive ID: {}", Self::generate_drive_id(&drive));
         }
         println!("\n========================\n");
     }
 }
 
+impl DriveProvider for DriveDetector {
+    /// Refresh the list of available drives and their usage
+    fn refresh(&mut self) {
+        self.disks.refresh_list();
+        self.refresh_usage();
+    }
+
+    /// Get all currently connected drives
+    fn get_all_drives(&self) -> Vec<DriveInfo> {
+        let volume_uuids = device_links("/dev/disk/by-uuid");
+        let volume_labels = device_links("/dev/disk/by-label");
+
+        self.disks
+            .iter()
+            .map(|disk| {
+                let name = disk.name().to_string_lossy().to_string();
+                let file_system = disk.file_system().to_string_lossy().to_string();
+                let volume_uuid = volume_id(&name, disk.mount_point(), &volume_uuids);
+                let (device_name, volume_label) = device_and_label(&name, disk.mount_point(), &volume_labels);
+                let is_network = is_network_file_system(&file_system);
+                // Reading from a network mount whose server went away can hang
+                let marker_id = if is_network { None } else { read_marker(disk.mount_point()) };
+
+                DriveInfo {
+                    device_name,
+                    volume_label,
+                    mount_point: disk.mount_point().to_path_buf(),
+                    total_space: disk.total_space(),
+                    available_space: disk.available_space(),
+                    is_network,
+                    file_system,
+                    is_removable: disk.is_removable(),
+                    volume_uuid,
+                    marker_id,
+                }
+            })
+            .collect()
+    }
+
+    /// Reads the mount table directly instead of building the whole drive
+    /// list, since this runs for every file synced
+    fn is_drive_connected(&self, mount_point: &Path) -> bool {
+        self.disks
+            .iter()
+            .find(|disk| disk.mount_point() == mount_point)
+            .map(|disk| {
+                !is_network_file_system(&disk.file_system().to_string_lossy())
+                    || Self::is_path_reachable(mount_point)
+            })
+            .unwrap_or(false)
+    }
+}
+
+/// In-memory drive list for tests. Clones share the list, so a test can keep
+/// one to plug and unplug drives after handing another to a `SyncManager`.
+#[cfg(any(test, feature = "test-util"))]
+#[derive(Clone, Default)]
+pub struct MockDriveProvider {
+    drives: std::sync::Arc<std::sync::Mutex<Vec<DriveInfo>>>,
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl MockDriveProvider {
+    /// Attach a removable drive mounted at `mount_point` (e.g. a tempdir)
+    pub fn connect(&self, name: &str, mount_point: &Path) {
+        self.connect_with_file_system(name, mount_point, "vfat");
+    }
+
+    /// Attach a removable drive that reports the given file system type;
+    /// `name` is its volume label
+    pub fn connect_with_file_system(&self, name: &str, mount_point: &Path, file_system: &str) {
+        let mut drives = self.drives.lock().unwrap();
+        let device_name = format!("/dev/mock{}", drives.len());
+        drives.push(DriveInfo {
+            device_name,
+            volume_label: Some(name.to_string()),
+            mount_point: mount_point.to_path_buf(),
+            total_space: 64 * 1024 * 1024 * 1024,
+            available_space: 32 * 1024 * 1024 * 1024,
+            file_system: file_system.to_string(),
+            is_removable: true,
+            is_network: false,
+            volume_uuid: None,
+            marker_id: None,
+        });
+    }
+
+    /// Report `available` bytes free on whatever is mounted at `mount_point`
+    pub fn set_available_space(&self, mount_point: &Path, available: u64) {
+        for drive in self.drives.lock().unwrap().iter_mut().filter(|drive| drive.mount_point == mount_point) {
+            drive.available_space = available;
+        }
+    }
+
+    /// Detach whatever is mounted at `mount_point`
+    pub fn disconnect(&self, mount_point: &Path) {
+        self.drives.lock().unwrap().retain(|drive| drive.mount_point != mount_point);
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl DriveProvider for MockDriveProvider {
+    fn refresh(&mut self) {}
+
+    /// Marker files are read on every call, as the real detector does
+    fn get_all_drives(&self) -> Vec<DriveInfo> {
+        self.drives
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|drive| DriveInfo { marker_id: read_marker(&drive.mount_point), ..drive.clone() })
+            .collect()
+    }
+}
+
+/// Map of device paths (e.g. `/dev/sdb1`) to the names of the links to
+/// them in `dir`: file system UUIDs in `/dev/disk/by-uuid`, volume labels
+/// in `/dev/disk/by-label`
+#[cfg(target_os = "linux")]
+fn device_links(dir: &str) -> HashMap<PathBuf, String> {
+    let mut names = HashMap::new();
+
+    // Each entry is a symlink named after the UUID or label pointing at the device
+    if let Ok(entries) = std::fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            if let Ok(device) = std::fs::canonicalize(entry.path()) {
+                names.insert(device, unescape_udev(&entry.file_name().to_string_lossy()));
+            }
+        }
+    }
+
+    names
+}
+
+#[cfg(not(target_os = "linux"))]
+fn device_links(_dir: &str) -> HashMap<PathBuf, String> {
+    HashMap::new()
+}
+
+/// udev writes spaces and other unsafe bytes in link names as `\x20`
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn unescape_udev(name: &str) -> String {
+    let mut bytes = Vec::with_capacity(name.len());
+    let mut rest = name.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = tail
+            .strip_prefix(b"x")
+            .and_then(|hex| hex.get(..2))
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(decoded) if byte == b'\\' => {
+                bytes.push(decoded);
+                rest = &tail[3..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Device name and volume label for one disk. On Linux sysinfo's name is
+/// the device node, whose label is looked up in the `/dev/disk/by-label` map.
+#[cfg(target_os = "linux")]
+fn device_and_label(name: &str, _mount_point: &Path, by_device: &HashMap<PathBuf, String>) -> (String, Option<String>) {
+    let label = std::fs::canonicalize(name)
+        .ok()
+        .and_then(|device| by_device.get(&device).cloned());
+    (name.to_string(), label)
+}
+
+/// On Windows sysinfo's name is the volume label (empty when there is
+/// none), so the device is named by its drive letter
+#[cfg(windows)]
+fn device_and_label(name: &str, mount_point: &Path, _by_device: &HashMap<PathBuf, String>) -> (String, Option<String>) {
+    let letter = mount_point.to_string_lossy().trim_end_matches('\\').to_string();
+    (letter, (!name.is_empty()).then(|| name.to_string()))
+}
+
+/// Elsewhere (macOS) sysinfo's name is the volume name, which is both
+#[cfg(not(any(target_os = "linux", windows)))]
+fn device_and_label(name: &str, _mount_point: &Path, _by_device: &HashMap<PathBuf, String>) -> (String, Option<String>) {
+    (name.to_string(), (!name.is_empty()).then(|| name.to_string()))
+}
+
+/// Volume identity for one disk. On Linux the device node is looked up in
+/// the `/dev/disk/by-uuid` map.
+#[cfg(target_os = "linux")]
+fn volume_id(device_name: &str, _mount_point: &Path, by_device: &HashMap<PathBuf, String>) -> Option<String> {
+    std::fs::canonicalize(device_name)
+        .ok()
+        .and_then(|device| by_device.get(&device).cloned())
+}
+
+/// Volume identity for one disk. On Windows this is the volume serial
+/// number, since sysinfo's name is only the (often shared or empty) label.
+#[cfg(windows)]
+fn volume_id(_device_name: &str, mount_point: &Path, _by_device: &HashMap<PathBuf, String>) -> Option<String> {
+    use std::os::windows::ffi::OsStrExt;
+    use winapi::um::fileapi::GetVolumeInformationW;
+
+    // The root must be given with its trailing backslash, e.g. `E:\`
+    let root: Vec<u16> = mount_point.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut serial: u32 = 0;
+
+    let ok = unsafe {
+        GetVolumeInformationW(
+            root.as_ptr(),
+            std::ptr::null_mut(),
+            0,
+            &mut serial,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            0,
+        )
+    };
+
+    (ok != 0).then(|| format_volume_serial(serial))
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+fn volume_id(_device_name: &str, _mount_point: &Path, _by_device: &HashMap<PathBuf, String>) -> Option<String> {
+    None
+}
+
+/// Format a volume serial the way `vol` prints it, e.g. `1A2B-3C4D`
+#[cfg_attr(not(windows), allow(dead_code))]
+fn format_volume_serial(serial: u32) -> String {
+    format!("{:04X}-{:04X}", serial >> 16, serial & 0xFFFF)
+}
+
+/// The id in the marker file at `root`: its first line, trimmed. Missing,
+/// unreadable or empty markers are `None`.
+pub fn read_marker(root: &Path) -> Option<String> {
+    let mut contents = String::new();
+    // An id is short; don't read a whole file someone replaced it with
+    std::fs::File::open(root.join(MARKER_FILE_NAME))
+        .ok()?
+        .take(256)
+        .read_to_string(&mut contents)
+        .ok()?;
+
+    let id = contents.lines().next()?.trim();
+    (!id.is_empty()).then(|| id.to_string())
+}
+
+/// The drive at `root`'s marker id, writing `id` as a new marker if it has
+/// none yet. Fails when the marker can't be written, e.g. on a read-only
+/// drive.
+pub fn ensure_marker(root: &Path, id: &str) -> std::io::Result<String> {
+    if let Some(existing) = read_marker(root) {
+        return Ok(existing);
+    }
+    std::fs::write(root.join(MARKER_FILE_NAME), format!("{}\n", id))?;
+    Ok(id.to_string())
+}
+
+/// Case-insensitive partial match on a drive's volume label, device name
+/// or mount point
+fn label_matches(drive: &DriveInfo, label: &str) -> bool {
+    let label_lower = label.to_lowercase();
+    drive.volume_label.as_deref().is_some_and(|name| name.to_lowercase().contains(&label_lower))
+        || drive.device_name.to_lowercase().contains(&label_lower)
+        || drive.mount_point.to_string_lossy().to_lowercase().contains(&label_lower)
+}
+
+/// The drive `label` names best: one whose volume label is exactly it,
+/// else the first that [`label_matches`]
+fn best_label_match(drives: Vec<DriveInfo>, label: &str) -> Option<DriveInfo> {
+    let exact = drives
+        .iter()
+        .position(|drive| drive.volume_label.as_deref().is_some_and(|name| name.eq_ignore_ascii_case(label)));
+    let index = exact.or_else(|| drives.iter().position(|drive| label_matches(drive, label)))?;
+    drives.into_iter().nth(index)
+}
+
+impl DriveInfo {
+    /// The volume label, or the device name for unlabelled volumes
+    pub fn display_name(&self) -> &str {
+        self.volume_label.as_deref().unwrap_or(&self.device_name)
+    }
+}
+
 impl Default for DriveDetector {
     fn default() -> Self {
         Self::new()
@@ -169,19 +593,83 @@ mod tests {
         assert!(!drives.is_empty(), "Should detect at least one drive");
     }
 
+    #[test]
+    fn test_format_volume_serial() {
+        assert_eq!(format_volume_serial(0x1A2B_3C4D), "1A2B-3C4D");
+        assert_eq!(format_volume_serial(0x0000_00FF), "0000-00FF");
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_system_drive_has_volume_serial() {
+        let serial = volume_id("", Path::new("C:\\"), &HashMap::new());
+        assert!(serial.is_some_and(|s| s.len() == 9));
+    }
+
+    #[test]
+    fn test_marker_identifies_drive_at_any_mount_point() {
+        let first = tempfile::TempDir::new().unwrap();
+        let second = tempfile::TempDir::new().unwrap();
+        assert_eq!(read_marker(first.path()), None);
+        assert_eq!(ensure_marker(first.path(), "stick-a").unwrap(), "stick-a");
+        // An existing marker keeps its id
+        assert_eq!(ensure_marker(first.path(), "other").unwrap(), "stick-a");
+        std::fs::write(second.path().join(MARKER_FILE_NAME), "  stick-b \nignored\n").unwrap();
+        assert_eq!(read_marker(second.path()).as_deref(), Some("stick-b"));
+
+        let drives = MockDriveProvider::default();
+        drives.connect("USB", second.path());
+        assert!(!drives.is_registered_drive_connected(second.path(), Some("stick-a"), None));
+        assert!(drives.is_registered_drive_connected(second.path(), Some("stick-b"), Some("other-volume")));
+        drives.connect("USB", first.path());
+        let found = drives.find_registered_drive(Some("stick-a"), None, "USB").unwrap();
+        assert_eq!(found.mount_point, first.path());
+    }
+
+    #[test]
+    fn test_label_matching_prefers_volume_label() {
+        let drives = MockDriveProvider::default();
+        for (device, label, mount) in [("/dev/sdb1", "Backup-Photos", "/media/a"), ("/dev/sdc1", "Photos", "/media/b")] {
+            drives.drives.lock().unwrap().push(DriveInfo {
+                device_name: device.to_string(),
+                volume_label: Some(label.to_string()),
+                mount_point: PathBuf::from(mount),
+                ..Default::default()
+            });
+        }
+
+        assert_eq!(drives.find_drive_by_label("photos").unwrap().device_name, "/dev/sdc1");
+        assert_eq!(drives.find_unique_drive_by_label("PHOTOS").unwrap().mount_point, Path::new("/media/b"));
+        assert_eq!(drives.find_drive_by_label("sdb1").unwrap().display_name(), "Backup-Photos");
+        assert_eq!(unescape_udev("My\\x20Stick"), "My Stick");
+        assert_eq!(unescape_udev("odd\\x2"), "odd\\x2");
+    }
+
     #[test]
     fn test_drive_id_generation() {
         let drive = DriveInfo {
-            name: "TestDrive".to_string(),
+            device_name: "/dev/sdb1".to_string(),
+            volume_label: Some("TestDrive".to_string()),
             mount_point: PathBuf::from("/mnt/test"),
             total_space: 1000000000,
             available_space: 500000000,
             file_system: "NTFS".to_string(),
             is_removable: true,
+            is_network: false,
+            volume_uuid: None,
+            marker_id: None,
         };
 
         let id = DriveDetector::generate_drive_id(&drive);
         assert!(id.starts_with("drive-"));
         assert!(id.len() > 6);
     }
+
+    #[test]
+    fn test_network_file_system_detection() {
+        assert!(is_network_file_system("cifs"));
+        assert!(is_network_file_system("NFS4"));
+        assert!(!is_network_file_system("ext4"));
+        assert!(!is_network_file_system("vfat"));
+    }
 }
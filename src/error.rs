@@ -1,5 +1,6 @@
 use thiserror::Error;
 
+/// The error type of every fallible call in this crate
 #[derive(Error, Debug)]
 pub enum OrchestratorError {
     #[error("IO error: {0}")]
@@ -14,15 +15,29 @@ pub enum OrchestratorError {
     #[error("File classification error: {0}")]
     Classification(String),
 
+    /// The file is there but can't be read right now (denied, locked, an
+    /// I/O error), as opposed to being read and not recognised
+    #[error("File unreadable: {0}")]
+    Unreadable(String),
+
     #[error("Sync error: {0}")]
     Sync(String),
 
     #[error("State management error: {0}")]
     State(String),
 
+    #[error("Timed out: {0}")]
+    Timeout(String),
+
     #[error("Watch error: {0}")]
     Watch(String),
 
+    #[error("Another instance is already running: {0}")]
+    InstanceLocked(String),
+
+    #[error("Database appears corrupt: {0}")]
+    DatabaseCorrupt(String),
+
     #[error("Database error: {0}")]
     Database(#[from] sled::Error),
 
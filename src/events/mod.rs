@@ -0,0 +1,167 @@
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+
+use crate::error::Result;
+use crate::state::current_timestamp;
+
+/// Events buffered per client; a client further behind than this misses the
+/// oldest ones instead of holding up syncing
+const CLIENT_BUFFER: usize = 256;
+
+/// Something that happened while syncing, as sent to event stream clients
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event")]
+pub enum Event {
+    FileSynced {
+        source: PathBuf,
+        target: PathBuf,
+        category: Option<String>,
+        drive: Option<String>,
+    },
+    Pending {
+        source: PathBuf,
+        drive: String,
+    },
+    DriveConnected {
+        drive: String,
+    },
+    Error {
+        source: Option<PathBuf>,
+        message: String,
+    },
+}
+
+#[derive(Serialize)]
+struct EventLine<'a> {
+    time: u64,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+/// Fan-out of sync events to every connected client. Sending never waits:
+/// with no clients an event is dropped, and a client that falls behind
+/// skips ahead.
+#[derive(Clone)]
+pub struct EventBus {
+    tx: broadcast::Sender<String>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self { tx: broadcast::channel(CLIENT_BUFFER).0 }
+    }
+}
+
+impl EventBus {
+    pub fn send(&self, event: Event) {
+        let line = EventLine { time: current_timestamp(), event: &event };
+        if let Ok(json) = serde_json::to_string(&line) {
+            let _ = self.tx.send(json + "\n");
+        }
+    }
+
+    /// Forward every event from now on to `client` as a line of JSON, until
+    /// it disconnects
+    async fn stream_to<W: AsyncWrite + Unpin>(&self, mut client: W) {
+        let mut rx = self.tx.subscribe();
+        loop {
+            let line = match rx.recv().await {
+                Ok(line) => line,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    format!("{{\"event\":\"Lagged\",\"skipped\":{}}}\n", skipped)
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            };
+            if client.write_all(line.as_bytes()).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Accept event stream clients on a Unix domain socket at `path` until the
+/// task is dropped. A socket left over from an earlier run is replaced.
+#[cfg(unix)]
+pub async fn serve(path: &Path, bus: EventBus) -> Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    if std::fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+        std::fs::remove_file(path)?;
+    }
+    let listener = tokio::net::UnixListener::bind(path)?;
+    info!("Streaming sync events on {}", path.display());
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let bus = bus.clone();
+        tokio::spawn(async move { bus.stream_to(stream).await });
+    }
+}
+
+/// Accept event stream clients on a named pipe like `\\.\pipe\fo-events`
+/// until the task is dropped
+#[cfg(windows)]
+pub async fn serve(path: &Path, bus: EventBus) -> Result<()> {
+    use tokio::net::windows::named_pipe::ServerOptions;
+
+    let mut server = ServerOptions::new().first_pipe_instance(true).create(path)?;
+    info!("Streaming sync events on {}", path.display());
+
+    loop {
+        server.connect().await?;
+        let client = std::mem::replace(&mut server, ServerOptions::new().create(path)?);
+        let bus = bus.clone();
+        tokio::spawn(async move { bus.stream_to(client).await });
+    }
+}
+
+/// Run [`serve`] in the background, logging why it stopped
+pub fn spawn_server(path: PathBuf, bus: EventBus) {
+    tokio::spawn(async move {
+        if let Err(e) = serve(&path, bus).await {
+            warn!("Event stream on {} stopped: {}", path.display(), e);
+        }
+    });
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, BufReader};
+
+    #[tokio::test]
+    async fn test_events_reach_every_client() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let socket = dir.path().join("events.sock");
+        let bus = EventBus::default();
+        spawn_server(socket.clone(), bus.clone());
+
+        let mut clients = Vec::new();
+        for _ in 0..2 {
+            let stream = loop {
+                match tokio::net::UnixStream::connect(&socket).await {
+                    Ok(stream) => break stream,
+                    Err(_) => tokio::time::sleep(std::time::Duration::from_millis(10)).await,
+                }
+            };
+            clients.push(BufReader::new(stream));
+        }
+        // Let both subscriptions register before sending
+        while bus.tx.receiver_count() < 2 {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        bus.send(Event::Pending { source: PathBuf::from("/src/a.jpg"), drive: "USB".to_string() });
+        for client in &mut clients {
+            let mut line = String::new();
+            client.read_line(&mut line).await.unwrap();
+            let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+            assert_eq!(json["event"], "Pending");
+            assert_eq!(json["drive"], "USB");
+        }
+    }
+}
@@ -3,8 +3,11 @@ use std::sync::{Arc, Mutex};
 use std::path::PathBuf;
 use crate::config::Config;
 use crate::state::StateManager;
-use crate::drive::DriveDetector;
+use crate::sync::SyncManager;
+use crate::drive::{DriveDetector, DriveProvider};
 use crate::error::Result;
+use crate::lock::InstanceLock;
+use crate::control::{self, ControlCommand};
 
 pub struct FileOrchestratorApp {
     config: Arc<Mutex<Config>>,
@@ -31,6 +34,7 @@ pub struct FileOrchestratorApp {
     // Watcher control
     watcher_running: Arc<Mutex<bool>>,
     watcher_handle: Arc<Mutex<Option<std::process::Child>>>,
+    watcher_paused: bool,
     config_path: String,
     db_path: String,
 }
@@ -66,6 +70,7 @@ impl FileOrchestratorApp {
             drive_to_remove: None,
             watcher_running: Arc::new(Mutex::new(false)),
             watcher_handle: Arc::new(Mutex::new(None)),
+            watcher_paused: false,
             config_path,
             db_path,
         }
@@ -80,7 +85,11 @@ impl FileOrchestratorApp {
         self.drives_status.clear();
         for (uuid, drive_config) in &config.drives {
             let connected = if let Some(ref path) = drive_config.path {
-                detector.is_drive_connected(path)
+                if drive_config.network {
+                    DriveDetector::is_path_reachable(path)
+                } else {
+                    detector.is_drive_connected(path)
+                }
             } else {
                 false
             };
@@ -100,6 +109,8 @@ impl FileOrchestratorApp {
             }
         }
         self.pending_count = total_pending;
+        self.watcher_paused = *self.watcher_running.lock().unwrap()
+            && control::is_paused(std::path::Path::new(&self.db_path));
     }
     
     fn show_dashboard(&mut self, ui: &mut egui::Ui) {
@@ -156,14 +167,25 @@ impl FileOrchestratorApp {
         let is_running = *self.watcher_running.lock().unwrap();
         
         ui.horizontal(|ui| {
-            let status_color = if is_running { egui::Color32::GREEN } else { egui::Color32::RED };
-            let status_text = if is_running { "[RUNNING]" } else { "[STOPPED]" };
+            let (status_text, status_color) = match (is_running, self.watcher_paused) {
+                (true, true) => ("[PAUSED]", egui::Color32::YELLOW),
+                (true, false) => ("[RUNNING]", egui::Color32::GREEN),
+                _ => ("[STOPPED]", egui::Color32::RED),
+            };
             ui.label(egui::RichText::new(status_text).color(status_color).strong());
             
             if is_running {
                 if ui.button("Stop Watcher").clicked() {
                     self.stop_watcher();
                 }
+
+                if self.watcher_paused {
+                    if ui.button("Resume Syncing").clicked() {
+                        self.control_watcher(ControlCommand::Resume);
+                    }
+                } else if ui.button("Pause Syncing").clicked() {
+                    self.control_watcher(ControlCommand::Pause);
+                }
             } else {
                 if ui.button("Start Watcher").clicked() {
                     self.start_watcher();
@@ -250,6 +272,14 @@ impl FileOrchestratorApp {
                 
                 if let Some(ref path) = self.selected_path {
                     ui.label(format!("Selected: {}", path.display()));
+                    let drive = self.drive_detector.lock().unwrap().get_drive_for_path(path);
+                    if let Some(drive) = drive {
+                        ui.label(format!(
+                            "Volume label: {}, device: {}",
+                            drive.volume_label.as_deref().unwrap_or("(none)"),
+                            drive.device_name
+                        ));
+                    }
                 }
             });
             
@@ -268,6 +298,7 @@ impl FileOrchestratorApp {
                         target: self.new_drive_category.clone(),
                         path: self.selected_path.clone(),
                         last_seen: Some(chrono::Utc::now().to_rfc3339()),
+                        ..Default::default()
                     };
                     
                     let save_result = {
@@ -326,11 +357,22 @@ impl FileOrchestratorApp {
     fn start_watcher(&mut self) {
         use std::process::Command;
         
+        // The watcher takes the database lock itself; check first so we can
+        // say why instead of spawning a process that exits immediately
+        if InstanceLock::is_held(&self.db_path) {
+            self.error_message = Some("Another orchestrator instance is already using this database".to_string());
+            return;
+        }
+        
         // Get the binary path (assume it's in the same directory as config)
         let binary_path = std::env::current_exe()
             .unwrap_or_else(|_| PathBuf::from("./target/release/fo"));
         
         match Command::new(&binary_path)
+            .arg("--config")
+            .arg(&self.config_path)
+            .arg("--db")
+            .arg(&self.db_path)
             .arg("run")
             .arg("--interval")
             .arg("5")
@@ -357,14 +399,68 @@ impl FileOrchestratorApp {
                 self.error_message = Some(format!("Failed to stop watcher: {}", e));
             } else {
                 *self.watcher_running.lock().unwrap() = false;
+                self.watcher_paused = false;
+                let _ = control::set_paused(std::path::Path::new(&self.db_path), false);
                 self.status_message = Some("File watcher stopped".to_string());
             }
         }
     }
     
+    fn control_watcher(&mut self, command: ControlCommand) {
+        let pid = match self.watcher_handle.lock().unwrap().as_ref() {
+            Some(child) => child.id(),
+            None => return,
+        };
+
+        match control::send(pid, command) {
+            Ok(()) => {
+                self.watcher_paused = command == ControlCommand::Pause;
+                self.status_message = Some(match command {
+                    ControlCommand::Pause => "Syncing paused".to_string(),
+                    ControlCommand::Resume => "Syncing resumed".to_string(),
+                });
+            }
+            Err(e) => {
+                self.error_message = Some(format!("Failed to control watcher: {}", e));
+            }
+        }
+    }
+    
+    /// Re-read the config file and check it the way `fo validate` does
+    fn validate_config_cmd(&mut self) {
+        let config = match Config::load(&self.config_path) {
+            Ok(config) => config,
+            Err(e) => {
+                self.error_message = Some(e.to_string());
+                return;
+            }
+        };
+
+        let unrouted = StateManager::temporary()
+            .and_then(|state| SyncManager::new(config, state).unrouted_categories());
+        match unrouted {
+            Ok(unrouted) if unrouted.is_empty() => {
+                self.status_message = Some("Configuration is valid".to_string());
+            }
+            Ok(unrouted) => {
+                let missing: Vec<String> = unrouted
+                    .iter()
+                    .map(|(category, count)| format!("{} {} files", count, category))
+                    .collect();
+                self.error_message = Some(format!("Valid, but no drive for: {}", missing.join(", ")));
+            }
+            Err(e) => self.error_message = Some(format!("Couldn't scan the source: {}", e)),
+        }
+    }
+
     fn show_settings(&mut self, ui: &mut egui::Ui) {
         ui.heading("Settings");
         ui.add_space(10.0);
+
+        if ui.button("Validate Config").clicked() {
+            self.validate_config_cmd();
+        }
+        ui.add_space(10.0);
         
         let config = self.config.lock().unwrap();
         
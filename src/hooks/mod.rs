@@ -0,0 +1,131 @@
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::Command;
+use tracing::{info, warn};
+
+/// How a hook run ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HookOutcome {
+    Succeeded,
+    /// Exited non-zero (or was killed by a signal)
+    Failed(String),
+    /// Didn't finish within the configured timeout and was killed
+    TimedOut,
+}
+
+impl HookOutcome {
+    pub fn succeeded(&self) -> bool {
+        *self == HookOutcome::Succeeded
+    }
+}
+
+/// Substitute `{name}` tokens in a hook command template. Values are quoted
+/// for the shell so file names with spaces or quotes stay one argument.
+/// The template is read once, left to right, so a value that itself looks
+/// like a token is never expanded.
+pub fn render(template: &str, tokens: &[(&str, &str)]) -> String {
+    let mut command = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        command.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let value = after.find('}').and_then(|close| {
+            let name = &after[..close];
+            tokens.iter().find(|(token, _)| *token == name).map(|(_, value)| (close, value))
+        });
+        match value {
+            Some((close, value)) => {
+                command.push_str(&shell_quote(value));
+                rest = &after[close + 1..];
+            }
+            None => {
+                command.push('{');
+                rest = after;
+            }
+        }
+    }
+    command.push_str(rest);
+    command
+}
+
+#[cfg(not(windows))]
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+#[cfg(windows)]
+fn shell_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "\"\""))
+}
+
+/// Run a rendered hook command through the platform shell, killing it if it
+/// runs longer than `timeout`
+pub async fn run(name: &str, command: &str, timeout: Duration) -> HookOutcome {
+    #[cfg(not(windows))]
+    let mut child = Command::new("sh");
+    #[cfg(not(windows))]
+    child.arg("-c").arg(command);
+    #[cfg(windows)]
+    let mut child = Command::new("cmd");
+    #[cfg(windows)]
+    child.arg("/C").arg(command);
+
+    let child = child
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .spawn();
+    let child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            warn!("Failed to start {} hook: {}", name, e);
+            return HookOutcome::Failed(e.to_string());
+        }
+    };
+
+    // Dropping the future on timeout kills the child
+    match tokio::time::timeout(timeout, child.wait_with_output()).await {
+        Ok(Ok(output)) if output.status.success() => {
+            info!("{} hook succeeded: {}", name, command);
+            HookOutcome::Succeeded
+        }
+        Ok(Ok(output)) => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            warn!("{} hook failed ({}): {} {}", name, output.status, command, stderr.trim());
+            HookOutcome::Failed(output.status.to_string())
+        }
+        Ok(Err(e)) => {
+            warn!("{} hook failed: {}", name, e);
+            HookOutcome::Failed(e.to_string())
+        }
+        Err(_) => {
+            warn!("{} hook timed out after {}s: {}", name, timeout.as_secs(), command);
+            HookOutcome::TimedOut
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_quotes_values() {
+        let command = render("echo {source} {category}", &[("source", "/src/it's here.jpg"), ("category", "images")]);
+        assert_eq!(command, "echo '/src/it'\\''s here.jpg' 'images'");
+    }
+
+    #[test]
+    fn test_render_does_not_expand_tokens_in_values() {
+        let source = "/src/a{target}b$(touch pwned).jpg";
+        let command = render("cp {source} {target} {unknown}", &[("source", source), ("target", "/drive/x.jpg")]);
+        assert_eq!(command, "cp '/src/a{target}b$(touch pwned).jpg' '/drive/x.jpg' {unknown}");
+    }
+
+    #[tokio::test]
+    async fn test_run_outcomes() {
+        let timeout = Duration::from_secs(5);
+        assert_eq!(run("test", "true", timeout).await, HookOutcome::Succeeded);
+        assert!(matches!(run("test", "exit 3", timeout).await, HookOutcome::Failed(_)));
+        assert_eq!(run("test", "sleep 5", Duration::from_millis(100)).await, HookOutcome::TimedOut);
+    }
+}
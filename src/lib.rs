@@ -0,0 +1,66 @@
+//! File Orchestrator: watch a folder and sync its files to removable
+//! drives by category.
+//!
+//! The `fo` binary is a thin command-line layer over this library; the
+//! same pieces can be embedded in another program:
+//!
+//! - [`config::Config`] loads and validates `config.toml`
+//! - [`classifier::FileClassifier`] decides a file's category
+//! - [`state::StateManager`] records what was synced where, in a sled database
+//! - [`drive::DriveProvider`] reports connected drives; [`drive::DriveDetector`]
+//!   asks the OS
+//! - [`sync::SyncManager`] ties them together and copies files
+//! - [`clock::Clock`] is where both get the time; [`clock::MockClock`]
+//!   stands in for the system clock in tests
+//! - [`error::OrchestratorError`] is the error type everything returns
+//!
+//! ```no_run
+//! use file_orchestrator::config::Config;
+//! use file_orchestrator::state::StateManager;
+//! use file_orchestrator::sync::SyncManager;
+//!
+//! # async fn run() -> file_orchestrator::error::Result<()> {
+//! let config = Config::load("config.toml")?;
+//! let state = StateManager::new("state.db")?;
+//! let mut sync_manager = SyncManager::new(config, state);
+//! let summary = sync_manager.sync_all().await?;
+//! println!("{} synced, {} pending", summary.synced, summary.pending);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The modules above are the stable interface. The others exist for the
+//! binary's commands and may change between releases.
+
+pub mod error;
+pub mod config;
+pub mod classifier;
+pub mod state;
+pub mod drive;
+pub mod sync;
+pub mod clock;
+
+#[doc(hidden)]
+pub mod watcher;
+#[doc(hidden)]
+pub mod lock;
+#[doc(hidden)]
+pub mod control;
+#[doc(hidden)]
+pub mod notifications;
+#[doc(hidden)]
+pub mod sanitize;
+#[doc(hidden)]
+pub mod report;
+#[doc(hidden)]
+pub mod hooks;
+#[doc(hidden)]
+pub mod manifest;
+#[doc(hidden)]
+pub mod events;
+#[doc(hidden)]
+pub mod schema;
+
+#[cfg(feature = "metrics")]
+#[doc(hidden)]
+pub mod metrics;
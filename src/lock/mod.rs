@@ -0,0 +1,73 @@
+use fs2::FileExt;
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+use crate::error::{OrchestratorError, Result};
+
+/// An exclusive advisory lock on `<db>.lock`, held for as long as a command
+/// that writes to the state database is running. The OS drops the lock if
+/// the process dies, so a crash or Ctrl+C never leaves it stuck. The file
+/// itself is left in place; deleting it could let two processes lock
+/// different files under the same name.
+pub struct InstanceLock {
+    file: File,
+}
+
+impl InstanceLock {
+    /// Take the lock for a database, failing if another instance holds it
+    pub fn acquire<P: AsRef<Path>>(db_path: P) -> Result<Self> {
+        let path = Self::lock_path(db_path.as_ref());
+
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .map_err(|e| OrchestratorError::State(format!("Failed to open lock file {}: {}", path.display(), e)))?;
+
+        file.try_lock_exclusive().map_err(|_| {
+            OrchestratorError::InstanceLocked(format!(
+                "{} is in use by another orchestrator process (lock: {})",
+                db_path.as_ref().display(),
+                path.display()
+            ))
+        })?;
+
+        Ok(Self { file })
+    }
+
+    /// Whether another process currently holds the lock for a database
+    pub fn is_held<P: AsRef<Path>>(db_path: P) -> bool {
+        matches!(Self::acquire(db_path), Err(OrchestratorError::InstanceLocked(_)))
+    }
+
+    fn lock_path(db_path: &Path) -> PathBuf {
+        let mut name = db_path.file_name().unwrap_or_default().to_os_string();
+        name.push(".lock");
+        db_path.with_file_name(name)
+    }
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        let _ = FileExt::unlock(&self.file);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_second_lock_is_refused() {
+        let dir = TempDir::new().unwrap();
+        let db = dir.path().join("state.db");
+
+        let lock = InstanceLock::acquire(&db).unwrap();
+        assert!(dir.path().join("state.db.lock").exists());
+        assert!(matches!(InstanceLock::acquire(&db), Err(OrchestratorError::InstanceLocked(_))));
+
+        drop(lock);
+        assert!(InstanceLock::acquire(&db).is_ok());
+    }
+}
@@ -1,36 +1,55 @@
-mod error;
-mod config;
-mod classifier;
-mod state;
-mod drive;
-mod sync;
-mod watcher;
+use file_orchestrator::{
+    config, control, drive, error, events, lock, notifications, report, schema, state, sync,
+    watcher,
+};
+
+#[cfg(feature = "metrics")]
+use file_orchestrator::metrics;
+
 mod cli;
 
 #[cfg(feature = "gui")]
 mod gui;
 
+#[cfg(feature = "tui")]
+mod tui;
+
 use cli::{Cli, Commands};
 use config::Config;
 use state::StateManager;
 use sync::SyncManager;
-use drive::DriveDetector;
+use drive::{DriveDetector, DriveProvider};
 use watcher::{AsyncFileWatcher, FileEvent};
 use error::Result;
+use lock::InstanceLock;
+use control::ControlCommand;
+use notifications::{Notification, Notifier};
+use config::NotifyEvent;
+use report::{ReportFormat, ReportPeriod};
 
-use tracing::{info, error, Level};
-use tracing_subscriber;
-use std::path::Path;
+use tracing::{info, warn, error};
+use std::path::{Path, PathBuf};
 use tokio::time::{sleep, Duration};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use tokio::sync::Mutex;
 
+/// How far before the last recorded run the startup scan looks, to allow
+/// for events that were still queued when fo stopped
+const STARTUP_SCAN_SLACK_SECS: u64 = 5 * 60;
+
+/// How often a long pending drain republishes its totals for `status --watch`
+const STATS_PUBLISH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// State database `sync-once --target-root` keeps inside the target folder
+const TARGET_ROOT_DB: &str = ".orchestrator-target-root.db";
+
 fn main() -> Result<()> {
     // Check for --gui flag before CLI parsing (for backward compatibility)
-    let args: Vec<String> = std::env::args().collect();
-    
     #[cfg(feature = "gui")]
     {
+        let args: Vec<String> = std::env::args().collect();
+        
         if args.len() > 1 && args[1] == "--gui" {
             // Run GUI mode with old-style flag
             let config_path = args.iter()
@@ -45,7 +64,8 @@ fn main() -> Result<()> {
                 .map(|s| s.to_string())
                 .unwrap_or_else(|| "orchestrator.db".to_string());
             
-            return gui::run_gui(config_path, db_path);
+            let db_path = resolve_db_path(Path::new(&config_path), Path::new(&db_path));
+            return gui::run_gui(config_path, db_path.to_string_lossy().into_owned());
         }
     }
     
@@ -56,45 +76,126 @@ fn main() -> Result<()> {
 }
 
 async fn run_cli() -> Result<()> {
-    // Initialize logging
+    // Parse command line arguments
+    let mut cli = Cli::parse_args();
+
+    // Initialize logging; RUST_LOG, when set, replaces what -v/-q pick
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new(cli.log_filter()));
     tracing_subscriber::fmt()
-        .with_max_level(Level::INFO)
+        .with_env_filter(filter)
         .with_target(false)
         .init();
 
-    // Parse command line arguments
-    let cli = Cli::parse_args();
+    cli.db = resolve_db_path(&cli.config, &cli.db);
 
     match cli.command {
-        Commands::Init { output, force } => {
-            cmd_init(&output, force)?;
+        Commands::Init { output, force, with_comments } => {
+            cmd_init(&output, force, with_comments)?;
         }
-        Commands::RegisterDrive { label, category, path } => {
-            cmd_register_drive(&cli.config, &label, &category, path)?;
+        Commands::Schema { json } => {
+            if json {
+                println!("{}", serde_json::to_string_pretty(&schema::json_schema())?);
+            } else {
+                print!("{}", schema::describe());
+            }
+        }
+        Commands::RegisterDrive { label, category, path, auto_map } => {
+            if auto_map {
+                cmd_register_auto_map(&cli.config)?;
+            } else {
+                // clap requires both unless --auto-map is given
+                let (label, category) = (label.unwrap_or_default(), category.unwrap_or_default());
+                cmd_register_drive(&cli.config, &label, &category, path)?;
+            }
         }
         Commands::ListDrives => {
-            cmd_list_drives(&cli.config)?;
+            cmd_list_drives(&cli.config, &cli.db)?;
         }
         Commands::ListConnected => {
             cmd_list_connected()?;
         }
-        Commands::SyncOnce { file } => {
-            cmd_sync_once(&cli.config, &cli.db, file).await?;
+        Commands::SyncOnce { file, since, limit, resume, no_cache, target_root } => {
+            let (config, db) = sync_target(&cli.config, &cli.db, target_root.as_deref())?;
+            cmd_sync_once(config, &db, file, since, limit, resume, no_cache).await?;
         }
-        Commands::Run { interval } => {
-            cmd_run(&cli.config, &cli.db, interval).await?;
+        Commands::Run { interval, no_startup_scan, events_socket } => {
+            cmd_run(&cli.config, &cli.db, interval, no_startup_scan, events_socket).await?;
         }
-        Commands::Status => {
+        Commands::Status { watch: false, .. } => {
             cmd_status(&cli.config, &cli.db)?;
         }
+        Commands::Status { watch: true, interval } => {
+            cmd_status_watch(&cli.config, &cli.db, interval).await?;
+        }
         Commands::ProcessPending => {
             cmd_process_pending(&cli.config, &cli.db).await?;
         }
+        Commands::ReassignPending { from, to, category } => {
+            cmd_reassign_pending(&cli.config, &cli.db, &from, &to, category.as_deref())?;
+        }
+        Commands::Fsck { fix } => {
+            cmd_fsck(&cli.config, &cli.db, fix)?;
+        }
+        Commands::Commit { drive } => {
+            cmd_commit(&cli.config, &cli.db, drive.as_deref()).await?;
+        }
+        Commands::Discard { drive } => {
+            cmd_discard(&cli.config, &cli.db, drive.as_deref()).await?;
+        }
+        Commands::ListPending { drive, category, format } => {
+            cmd_list_pending(&cli.config, &cli.db, drive.as_deref(), category.as_deref(), format)?;
+        }
         Commands::Clear { confirm } => {
             cmd_clear(&cli.db, confirm)?;
         }
+        Commands::Pull { drive } => {
+            cmd_pull(&cli.config, &cli.db, &drive).await?;
+        }
+        Commands::ListSnapshots { drive } => {
+            cmd_list_snapshots(&cli.config, &cli.db, &drive)?;
+        }
         Commands::Validate => {
             cmd_validate(&cli.config)?;
+        }
+        Commands::Doctor => {
+            if !cmd_doctor(&cli.config, &cli.db) {
+                std::process::exit(1);
+            }
+        }
+        Commands::Verify { drive, rehash, requeue, manifest } => {
+            cmd_verify(&cli.config, &cli.db, drive.as_deref(), rehash, requeue, manifest)?;
+        }
+        Commands::Reroute { dry_run } => {
+            cmd_reroute(&cli.config, &cli.db, dry_run).await?;
+        }
+        Commands::Undo { batch } => {
+            cmd_undo(&cli.config, &cli.db, batch)?;
+        }
+        Commands::Report { by, since, until, format } => {
+            cmd_report(&cli.db, by, since, until, format)?;
+        }
+        Commands::Repair { rescan } => {
+            cmd_repair(&cli.config, &cli.db, rescan)?;
+        }
+        Commands::Compact => {
+            cmd_compact(&cli.db)?;
+        }
+        Commands::Tag { file, tags, remove, note } => {
+            cmd_tag(&cli.db, &file, &tags, remove, note)?;
+        }
+        Commands::Find { tag, format } => {
+            cmd_find(&cli.db, &tag, format)?;
+        }
+        Commands::LastRun { format } => {
+            cmd_runs(&cli.db, 1, format, true)?;
+        }
+        Commands::Runs { limit, format } => {
+            cmd_runs(&cli.db, limit, format, false)?;
+        }
+        #[cfg(feature = "tui")]
+        Commands::Tui => {
+            tui::run_tui(&cli.config, &cli.db).await?;
         }        #[cfg(feature = "gui")]
         Commands::Gui => {
             let config_path = cli.config.to_string_lossy().to_string();
@@ -106,14 +207,18 @@ async fn run_cli() -> Result<()> {
 }
 
 /// Initialize a new configuration file
-fn cmd_init(output: &Path, force: bool) -> Result<()> {
+fn cmd_init(output: &Path, force: bool, with_comments: bool) -> Result<()> {
     if output.exists() && !force {
         error!("Configuration file already exists. Use --force to overwrite.");
         return Ok(());
     }
 
     let config = Config::default_config();
-    config.save(output)?;
+    if with_comments {
+        std::fs::write(output, schema::commented(&config)?)?;
+    } else {
+        config.save(output)?;
+    }
 
     println!("✓ Created configuration file: {}", output.display());
     println!("\nNext steps:");
@@ -134,9 +239,24 @@ fn cmd_register_drive(
     let mut config = Config::load(config_path)?;
 
     // Validate category
-    let valid_categories = ["images", "videos", "music", "documents", "archives"];
-    if !valid_categories.contains(&category) {
-        error!("Invalid category. Must be one of: {:?}", valid_categories);
+    let known = config.known_categories();
+    if !known.iter().any(|known| known == category) {
+        error!("Invalid category. Must be one of: {:?}", known);
+        return Ok(());
+    }
+
+    // Generate a simple UUID
+    let drive_uuid = uuid::Uuid::new_v4().to_string();
+
+    // A second drive for the category needs to say how the drives share it
+    let mut with_drive = config.clone();
+    with_drive.drives.insert(drive_uuid.clone(), config::DriveConfig {
+        label: label.to_string(),
+        target: category.to_string(),
+        ..Default::default()
+    });
+    if let Err(e) = with_drive.check_shared_targets() {
+        error!("{}", e);
         return Ok(());
     }
 
@@ -155,9 +275,10 @@ fn cmd_register_drive(
 
         println!("\n=== Available Drives ===");
         for (idx, drive) in drives.iter().enumerate() {
-            println!("{}. {} - {} ({} available)", 
+            println!("{}. {} ({}) - {} ({} available)", 
                 idx + 1, 
-                drive.name,
+                drive.volume_label.as_deref().unwrap_or("no label"),
+                drive.device_name,
                 drive.mount_point.display(),
                 format_size(drive.available_space)
             );
@@ -190,8 +311,13 @@ fn cmd_register_drive(
         }
     };
 
-    // Generate a simple UUID
-    let drive_uuid = uuid::Uuid::new_v4().to_string();
+    // Remember the file system UUID so the drive can be followed if it remounts elsewhere
+    let mounted = drive_path
+        .as_ref()
+        .and_then(|p| DriveDetector::new().get_drive_by_mount_point(p));
+    let volume_uuid = mounted.as_ref().and_then(|drive| drive.volume_uuid.clone());
+
+    let marker_id = mounted.and_then(|drive| write_drive_marker(&drive.mount_point, &drive_uuid));
 
     // Add drive to config
     config.drives.insert(
@@ -201,6 +327,9 @@ fn cmd_register_drive(
             target: category.to_string(),
             path: drive_path.clone(),
             last_seen: None,
+            volume_uuid,
+            marker_id,
+            ..Default::default()
         },
     );
 
@@ -215,10 +344,121 @@ fn cmd_register_drive(
     } else {
         println!("  Path: Not set (will be detected when connected)");
     }
+    if let Some(ref marker_id) = config.drives[&drive_uuid].marker_id {
+        println!("  Marker: {}", marker_id);
+    }
+
+    Ok(())
+}
+
+/// Give the drive at `root` a marker file so it is recognised on any OS and
+/// mount point, keeping the id of one it already has. Read-only drives get
+/// none and are matched by path.
+fn write_drive_marker(root: &Path, id: &str) -> Option<String> {
+    match drive::ensure_marker(root, id) {
+        Ok(marker_id) => Some(marker_id),
+        Err(e) => {
+            warn!("Couldn't write {} to {} ({}); it will be matched by path", drive::MARKER_FILE_NAME, root.display(), e);
+            None
+        }
+    }
+}
+
+/// Register all connected removable drives in one go, targeting the
+/// category folder each one already contains
+fn cmd_register_auto_map(config_path: &Path) -> Result<()> {
+    let mut config = Config::load(config_path)?;
+    let detector = DriveDetector::new();
+
+    let mut registered = Vec::new();
+
+    for drive in detector.get_removable_drives() {
+        let already_registered = config.drives.values().any(|d| {
+            d.path.as_ref() == Some(&drive.mount_point)
+                || (d.volume_uuid.is_some() && d.volume_uuid == drive.volume_uuid)
+                || (d.marker_id.is_some() && d.marker_id == drive.marker_id)
+        });
+        if already_registered {
+            info!("Already registered, skipping: {}", drive.mount_point.display());
+            continue;
+        }
+
+        let found: Vec<&str> = config::BUILTIN_CATEGORIES
+            .iter()
+            .copied()
+            .filter(|category| drive.mount_point.join(config.folder_for(category)).is_dir())
+            .collect();
+
+        let category = if found.len() == 1 {
+            found[0].to_string()
+        } else {
+            // Zero or several category folders: let the user decide once
+            let choices = if found.is_empty() { config::BUILTIN_CATEGORIES.to_vec() } else { found };
+            println!("\nDrive {} ({}, {})", drive.display_name(), drive.device_name, drive.mount_point.display());
+            let answer = prompt(&format!("Category for this drive {:?} (Enter to skip): ", choices))?;
+            if answer.is_empty() {
+                continue;
+            }
+            if !choices.contains(&answer.as_str()) {
+                error!("Invalid category '{}', skipping drive", answer);
+                continue;
+            }
+            answer
+        };
+
+        // Unlabelled volumes are usually mounted at a directory named
+        // after something more telling than the device
+        let label = drive.volume_label.clone().unwrap_or_else(|| {
+            drive.mount_point
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_else(|| drive.device_name.clone())
+        });
+
+        let drive_uuid = uuid::Uuid::new_v4().to_string();
+        let marker_id = write_drive_marker(&drive.mount_point, &drive_uuid);
+        config.drives.insert(
+            drive_uuid.clone(),
+            config::DriveConfig {
+                label: label.clone(),
+                target: category.clone(),
+                path: Some(drive.mount_point.clone()),
+                last_seen: Some(chrono::Utc::now().to_rfc3339()),
+                volume_uuid: drive.volume_uuid.clone(),
+                marker_id,
+                ..Default::default()
+            },
+        );
+        registered.push((drive_uuid, label, category, drive.mount_point));
+    }
+
+    if registered.is_empty() {
+        println!("No new drives to register");
+        return Ok(());
+    }
+
+    config.save(config_path)?;
+
+    println!("✓ Registered {} drive(s):", registered.len());
+    for (uuid, label, category, path) in registered {
+        println!("  {} -> {} ({}) [{}]", label, category, path.display(), uuid);
+    }
 
     Ok(())
 }
 
+/// Print a question and read one trimmed line from stdin
+fn prompt(question: &str) -> Result<String> {
+    use std::io::{self, Write};
+
+    print!("{}", question);
+    io::stdout().flush()?;
+
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    Ok(input.trim().to_string())
+}
+
 fn format_size(bytes: u64) -> String {
     const GB: u64 = 1024 * 1024 * 1024;
     const MB: u64 = 1024 * 1024;
@@ -232,9 +472,21 @@ fn format_size(bytes: u64) -> String {
     }
 }
 
+/// A duration in seconds in its largest whole unit, e.g. "3d"
+fn format_age(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86_399 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86_400),
+    }
+}
+
 /// List all registered drives
-fn cmd_list_drives(config_path: &Path) -> Result<()> {
+fn cmd_list_drives(config_path: &Path, db_path: &Path) -> Result<()> {
     let config = Config::load(config_path)?;
+    // Only for when each drive was last seen; a running watcher holds the lock
+    let state = StateManager::new(db_path).ok();
 
     println!("\n=== Registered Drives ===");
     for (uuid, drive) in &config.drives {
@@ -244,7 +496,11 @@ fn cmd_list_drives(config_path: &Path) -> Result<()> {
         if let Some(ref path) = drive.path {
             println!("  Path: {}", path.display());
         }
-        if let Some(ref last_seen) = drive.last_seen {
+        if let Some(ref marker_id) = drive.marker_id {
+            println!("  Marker: {}", marker_id);
+        }
+        let seen = state.as_ref().and_then(|state| state.get_drive_last_seen(uuid).ok().flatten());
+        if let Some(last_seen) = seen.or_else(|| drive.last_seen.clone()) {
             println!("  Last Seen: {}", last_seen);
         }
     }
@@ -260,81 +516,308 @@ fn cmd_list_connected() -> Result<()> {
     Ok(())
 }
 
+/// A relative `--db` is next to the config file, wherever fo is started
+/// from. A database that only exists in the working directory, where
+/// earlier versions put it, is still used there.
+fn resolve_db_path(config_path: &Path, db_path: &Path) -> PathBuf {
+    let resolved = Config::resolve_path(config_path, db_path);
+    if !resolved.exists() && db_path.exists() && std::path::absolute(db_path).ok().as_ref() != Some(&resolved) {
+        warn!(
+            "Using the database in the working directory ({}); move it next to the config file, \
+             where a relative --db now points ({})",
+            db_path.display(),
+            resolved.display()
+        );
+        return db_path.to_path_buf();
+    }
+    resolved
+}
+
+/// The config and database a one-time sync uses: the usual ones, or with
+/// `--target-root` everything redirected to that folder and recorded in a
+/// database of its own there, so the real sync records aren't touched
+fn sync_target(config_path: &Path, db_path: &Path, target_root: Option<&Path>) -> Result<(Config, PathBuf)> {
+    let config = Config::load(config_path)?;
+    let Some(root) = target_root else {
+        return Ok((config, db_path.to_path_buf()));
+    };
+
+    let root = std::path::absolute(root)?;
+    let config = config.with_target_root(&root)?;
+    std::fs::create_dir_all(&root)?;
+    println!("Sending every file to {} instead of the registered drives", root.display());
+    Ok((config, root.join(TARGET_ROOT_DB)))
+}
+
 /// Perform a one-time sync
 async fn cmd_sync_once(
-    config_path: &Path,
+    config: Config,
     db_path: &Path,
     file: Option<std::path::PathBuf>,
+    since: Option<std::time::Duration>,
+    limit: Option<usize>,
+    resume: bool,
+    no_cache: bool,
 ) -> Result<()> {
-    let config = Config::load(config_path)?;
+    let _lock = InstanceLock::acquire(db_path)?;
     let state = StateManager::new(db_path)?;
-    let mut sync_manager = SyncManager::new(config, state);
+    let mut sync_manager = SyncManager::new(config, state).with_command("sync-once");
+    if no_cache {
+        sync_manager = sync_manager.with_full_walk();
+    }
 
     if let Some(file_path) = file {
         // Sync a single file
         info!("Syncing single file: {}", file_path.display());
         match sync_manager.sync_file(&file_path).await {
             Ok(result) => {
+                sync_manager.write_manifests();
                 println!("✓ Sync result: {:?}", result);
             }
             Err(e) => {
                 error!("Failed to sync file: {}", e);
             }
         }
+    } else if let Some(since) = since {
+        // Sync only recently modified files
+        let cutoff = std::time::SystemTime::now()
+            .checked_sub(since)
+            .unwrap_or(std::time::UNIX_EPOCH);
+        let (summary, filtered) = sync_manager.sync_modified_since(cutoff).await?;
+        println!(
+            "Considered {} files, skipped {} not modified since the cutoff",
+            summary.total() + filtered,
+            filtered
+        );
+        summary.print();
+    } else if limit.is_some() || resume {
+        // Sync part of the tree, picking up where the last part stopped
+        let (summary, remaining) = sync_manager.sync_all_from_cursor(limit, resume).await?;
+        summary.print();
+        if remaining > 0 {
+            println!("{} files not reached yet; `fo sync-once --resume` continues from here", remaining);
+        } else {
+            println!("Reached the end of the source tree");
+        }
+        if let Some(batch) = sync_manager.last_batch() {
+            println!("Recorded as batch {}; `fo undo` reverts it", batch);
+        }
     } else {
         // Sync all files
         info!("Starting full sync...");
         let summary = sync_manager.sync_all().await?;
         summary.print();
+        if let Some(batch) = sync_manager.last_batch() {
+            println!("Recorded as batch {}; `fo undo` reverts it", batch);
+        }
     }
 
     Ok(())
 }
 
 /// Run the orchestrator in watch mode
-async fn cmd_run(config_path: &Path, db_path: &Path, interval: u64) -> Result<()> {
+async fn cmd_run(
+    config_path: &Path,
+    db_path: &Path,
+    interval: u64,
+    no_startup_scan: bool,
+    events_socket: Option<PathBuf>,
+) -> Result<()> {
     let config = Config::load(config_path)?;
+    let _lock = InstanceLock::acquire(db_path)?;
     let state = StateManager::new(db_path)?;
     
+    #[cfg(feature = "metrics")]
+    let metrics = Arc::new(metrics::Metrics::default());
+    #[cfg(feature = "metrics")]
+    if let Some(ref addr) = config.metrics_addr {
+        let (addr, metrics, state, registered) = (addr.clone(), Arc::clone(&metrics), state.clone(), config.drives.len());
+        tokio::spawn(async move {
+            if let Err(e) = metrics::serve(&addr, metrics, state, registered).await {
+                error!("Metrics endpoint stopped: {}", e);
+            }
+        });
+    }
+    #[cfg(not(feature = "metrics"))]
+    if config.metrics_addr.is_some() {
+        warn!("metrics_addr is set but fo was built without the metrics feature");
+    }
+
+    let run_state = state.clone();
+    let sync_manager = SyncManager::new(config.clone(), state)
+        .with_config_path(config_path)
+        .with_interleaved_pending()
+        .with_command("run");
+    #[cfg(feature = "metrics")]
+    let sync_manager = sync_manager.with_metrics(Arc::clone(&metrics));
+    let sync_manager = match events_socket {
+        Some(path) => {
+            let bus = events::EventBus::default();
+            events::spawn_server(path, bus.clone());
+            sync_manager.with_events(bus)
+        }
+        None => sync_manager,
+    };
+
     // Wrap sync_manager in Arc<Mutex<>> for thread-safe sharing
-    let sync_manager = Arc::new(Mutex::new(SyncManager::new(config.clone(), state)));
+    let sync_manager = Arc::new(Mutex::new(sync_manager));
 
     info!("Starting File Orchestrator...");
     info!("Watching: {}", config.source.path.display());
 
-    // Perform initial sync of existing files
-    info!("Performing initial sync of existing files...");
-    {
+    // Sync what arrived while we weren't running: everything on the first
+    // run, afterwards only what changed since the last recorded watch or
+    // failed last time
+    let scan_started = state::current_timestamp();
+    // Files still being written, and when to look at them again
+    let mut settling: Vec<(PathBuf, tokio::time::Instant)> = Vec::new();
+    if no_startup_scan {
+        info!("Skipping the startup scan");
+    } else {
         let mut sm = sync_manager.lock().await;
-        match sm.sync_all().await {
+        let result = match run_state.get_last_run()? {
+            Some(last_run) => {
+                let cutoff = std::time::UNIX_EPOCH
+                    + Duration::from_secs(last_run.saturating_sub(STARTUP_SCAN_SLACK_SECS));
+                info!("Syncing changes since the last run...");
+                sm.catch_up_since(cutoff).await.map(|(summary, _)| summary)
+            }
+            None => {
+                info!("Performing initial sync of existing files...");
+                sm.sync_all().await
+            }
+        };
+        match result {
             Ok(summary) => {
                 info!("Initial sync complete: {} synced, {} pending, {} already synced, {} skipped", 
                       summary.synced, summary.pending, summary.already_synced, summary.skipped);
+                settling.extend(summary.unsettled.into_iter().map(|path| (path, tokio::time::Instant::now())));
             }
             Err(e) => {
                 error!("Initial sync failed: {}", e);
             }
         }
     }
+    // Changes that arrived while an earlier run was paused
+    match run_state.take_deferred() {
+        Ok(deferred) if !deferred.is_empty() => {
+            info!("Syncing {} change(s) deferred by an earlier paused run", deferred.len());
+            let mut sm = sync_manager.lock().await;
+            for path in sync_deferred(&mut sm, deferred).await {
+                settling.push((path, tokio::time::Instant::now()));
+            }
+        }
+        Ok(_) => {}
+        Err(e) => error!("Failed to read the deferred changes: {}", e),
+    }
+    // Only as far as the scan has covered; the drive check keeps this current
+    if let Err(e) = run_state.set_last_run(scan_started) {
+        error!("Failed to record the run time: {}", e);
+    }
 
     // Start file watcher
-    let mut file_watcher = AsyncFileWatcher::watch(&config.source.path)?;
+    let mut file_watcher = AsyncFileWatcher::watch(&config.source.path, config.source.watch_queue_capacity)?;
+
+    // Paused state, shared with the drive check so it holds off too
+    let paused = Arc::new(AtomicBool::new(false));
+    control::set_paused(db_path, false)?;
+    let mut control_rx = control::listen();
+
+    let notifier = Notifier::new(config.notifications.clone());
 
     // Spawn a task to check for connected drives periodically
     let sync_manager_clone = Arc::clone(&sync_manager);
+    let paused_clone = Arc::clone(&paused);
+    let drive_notifier = notifier.clone();
+    let drive_labels: std::collections::HashMap<String, String> = config.drives
+        .iter()
+        .map(|(uuid, drive)| (uuid.clone(), drive.label.clone()))
+        .collect();
+    let tick_state = run_state.clone();
+    let tick_db_path = db_path.to_path_buf();
+    let jitter = config.sync.interval_jitter;
+    let mut known_connected = sync_manager.lock().await.connected_drives();
+    #[cfg(feature = "metrics")]
+    let drive_metrics = {
+        metrics.set_connected_drives(known_connected.len());
+        Arc::clone(&metrics)
+    };
     
-    tokio::spawn(async move {
+    let drive_check = tokio::spawn(async move {
         loop {
-            sleep(Duration::from_secs(interval)).await;
+            sleep(drive_check_delay(interval, jitter)).await;
+
+            // Deferred files aren't synced yet, so a restart must still see them
+            if paused_clone.load(Ordering::SeqCst) {
+                continue;
+            }
+
+            // Also bounds how long batched sync records sit unflushed when idle
+            if let Err(e) = tick_state.set_last_run(state::current_timestamp()).and_then(|_| tick_state.flush()) {
+                error!("Failed to record the run time: {}", e);
+            }
+            publish_stats(&tick_state, &tick_db_path);
             
+            // Don't queue up behind a copy that is stuck on a slow drive;
+            // try again next interval
+            let Ok(mut sm) = sync_manager_clone.try_lock() else {
+                info!("A sync is still in progress, checking drives next time");
+                continue;
+            };
+
+            // Files synced as they appeared since the last tick
+            sm.write_manifests();
+
             info!("Checking for connected drives...");
-            
-            // Use the shared sync_manager
-            let mut sm = sync_manager_clone.lock().await;
-            
+            let connected = sm.connected_drives();
+            for uuid in connected.iter().filter(|uuid| !known_connected.contains(uuid)) {
+                let label = drive_labels.get(uuid).cloned().unwrap_or_else(|| uuid.clone());
+                drive_notifier.notify(Notification {
+                    event: NotifyEvent::DriveConnected,
+                    file: None,
+                    category: None,
+                    drive: Some(label),
+                    result: "Processing pending syncs".to_string(),
+                });
+            }
+            #[cfg(feature = "metrics")]
+            drive_metrics.set_connected_drives(connected.len());
+            known_connected = connected;
+
+            // Nothing queued: leave the disks alone to save power
+            match sm.drive_check_needed() {
+                Ok(false) => continue,
+                Ok(true) => {}
+                Err(e) => error!("Failed to read the pending queue: {}", e),
+            }
+
             if let Err(e) = sm.check_and_sync_connected_drives().await {
                 error!("Error checking connected drives: {}", e);
             }
+            drop(sm);
+
+            // Long queues drain a few files at a time, letting file events
+            // (waiting on the same lock) through in between
+            let mut published = tokio::time::Instant::now();
+            loop {
+                tokio::task::yield_now().await;
+                if paused_clone.load(Ordering::SeqCst) {
+                    break;
+                }
+                let more = sync_manager_clone.lock().await.continue_pending().await;
+                if published.elapsed() >= STATS_PUBLISH_INTERVAL || !matches!(more, Ok(true)) {
+                    publish_stats(&tick_state, &tick_db_path);
+                    published = tokio::time::Instant::now();
+                }
+                match more {
+                    Ok(true) => {}
+                    Ok(false) => break,
+                    Err(e) => {
+                        error!("Error processing pending syncs: {}", e);
+                        break;
+                    }
+                }
+            }
         }
     });
 
@@ -342,58 +825,689 @@ async fn cmd_run(config_path: &Path, db_path: &Path, interval: u64) -> Result<()
     println!("✓ File Orchestrator is running. Press Ctrl+C to stop.");
     println!("  Watching for file changes in: {}", config.source.path.display());
 
-    while let Some(event) = file_watcher.next_event().await {
+    #[cfg(unix)]
+    println!("  Send SIGUSR1 to pause syncing and SIGUSR2 to resume (pid {}).", std::process::id());
+
+    // A file being synced when the signal arrives is finished first
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+
+    loop {
+        let next_settled = settling.iter().map(|(_, due)| *due).min();
+        let event = tokio::select! {
+            _ = &mut shutdown => {
+                println!("Shutting down...");
+                break;
+            }
+            _ = tokio::time::sleep_until(next_settled.unwrap_or_else(tokio::time::Instant::now)), if next_settled.is_some() => {
+                let index = settling.iter().position(|(_, due)| Some(*due) == next_settled).unwrap_or(0);
+                FileEvent::Modified(settling.swap_remove(index).0)
+            }
+            event = file_watcher.next_event() => match event {
+                Some(event) => event,
+                None => break,
+            },
+            Some(command) = control_rx.recv() => {
+                match command {
+                    ControlCommand::Pause => {
+                        paused.store(true, Ordering::SeqCst);
+                        if let Err(e) = control::set_paused(db_path, true) {
+                            error!("Failed to record paused state: {}", e);
+                        }
+                        // The drive check, which also flushes, holds off while paused
+                        if let Err(e) = run_state.flush() {
+                            error!("Failed to flush sync state: {}", e);
+                        }
+                        println!("⏸ Syncing paused; changes will be synced on resume");
+                    }
+                    ControlCommand::Resume if paused.load(Ordering::SeqCst) => {
+                        paused.store(false, Ordering::SeqCst);
+                        if let Err(e) = control::set_paused(db_path, false) {
+                            error!("Failed to record paused state: {}", e);
+                        }
+                        let deferred = run_state.take_deferred().unwrap_or_else(|e| {
+                            error!("Failed to read the deferred changes: {}", e);
+                            Vec::new()
+                        });
+                        println!("▶ Syncing resumed; {} deferred change(s)", deferred.len());
+
+                        let mut sm = sync_manager.lock().await;
+                        for path in sync_deferred(&mut sm, deferred).await {
+                            settling.push((path, tokio::time::Instant::now()));
+                        }
+                    }
+                    ControlCommand::Resume => {}
+                }
+                continue;
+            }
+        };
+
+        // Entries came or went, so cached listings of the folders involved
+        // are out of date even if a coarse folder time didn't move
+        if config.source.dir_cache {
+            if let FileEvent::Created(path) | FileEvent::Removed(path) | FileEvent::DirectoryCreated(path) = &event {
+                sync_manager.lock().await.forget_dir_listing(path);
+            }
+        }
+
+        // Ignore anything outside the configured include_dirs / scan depth
+        let in_scope = match &event {
+            FileEvent::Created(path) | FileEvent::Modified(path) => config.source.in_scope(path),
+            FileEvent::DirectoryCreated(path) => config.source.should_descend(path),
+            FileEvent::Removed(_) => true,
+        };
+        if !in_scope {
+            continue;
+        }
+
+        if paused.load(Ordering::SeqCst) {
+            match event {
+                FileEvent::Created(path)
+                | FileEvent::Modified(path)
+                | FileEvent::DirectoryCreated(path) => {
+                    if let Err(e) = run_state.add_deferred(&path) {
+                        error!("Failed to defer {}: {}", path.display(), e);
+                    }
+                }
+                FileEvent::Removed(_) => {}
+            }
+            continue;
+        }
+
         match event {
             FileEvent::Created(path) | FileEvent::Modified(path) => {
                 info!("Detected file change: {}", path.display());
                 
                 let mut sm = sync_manager.lock().await;
-                if let Err(e) = sm.sync_file(&path).await {
-                    error!("Failed to sync file: {}", e);
+                match sm.sync_file(&path).await {
+                    Ok(sync::SyncResult::Synced(target)) => {
+                        let (category, drive) = sm.synced_destination(&path).unzip();
+                        notifier.notify(Notification {
+                            event: NotifyEvent::Synced,
+                            file: Some(path.clone()),
+                            category,
+                            drive,
+                            result: format!("Copied to {}", target.display()),
+                        });
+                    }
+                    Ok(sync::SyncResult::Unsettled(wait)) => {
+                        // Later writes push the check back rather than adding another
+                        settling.retain(|(settling_path, _)| *settling_path != path);
+                        settling.push((path, tokio::time::Instant::now() + wait));
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        error!("Failed to sync file: {}", e);
+                        notifier.notify(Notification {
+                            event: NotifyEvent::Failed,
+                            file: Some(path.clone()),
+                            category: None,
+                            drive: None,
+                            result: e.to_string(),
+                        });
+                    }
                 }
             }
             FileEvent::Removed(path) => {
                 info!("File removed: {}", path.display());
                 // Optionally handle file removals
             }
+            FileEvent::DirectoryCreated(path) => {
+                // Catch files that landed before the new directory was watched
+                let mut sm = sync_manager.lock().await;
+                match sm.sync_directory(&path).await {
+                    Ok(summary) if summary.total() > 0 => {
+                        info!("Scanned new directory {}: {} synced, {} pending",
+                              path.display(), summary.synced, summary.pending);
+                        let now = tokio::time::Instant::now();
+                        settling.extend(summary.unsettled.into_iter().map(|path| (path, now)));
+                    }
+                    Ok(_) => {}
+                    Err(e) => error!("Failed to scan new directory: {}", e),
+                }
+            }
+        }
+    }
+
+    // Waits out a drive check that is part-way through, and keeps the next
+    // one from starting
+    let mut sm = sync_manager.lock().await;
+    drive_check.abort();
+
+    // Changes that were seen but not synced yet go in the pending queue so
+    // the next run doesn't depend on the startup scan finding them
+    let mut unsynced = run_state.take_deferred().unwrap_or_else(|e| {
+        error!("Failed to read the deferred changes: {}", e);
+        Vec::new()
+    });
+    unsynced.extend(settling.into_iter().map(|(path, _)| path));
+    for event in file_watcher.close() {
+        match event {
+            FileEvent::Created(path) | FileEvent::Modified(path) if config.source.in_scope(&path) => {
+                unsynced.push(path)
+            }
+            FileEvent::DirectoryCreated(path) if config.source.should_descend(&path) => unsynced.push(path),
+            _ => {}
+        }
+    }
+    let mut seen = std::collections::HashSet::new();
+    unsynced.retain(|path| seen.insert(path.clone()));
+
+    let mut queued = 0;
+    for path in &unsynced {
+        match sm.queue_pending(path).await {
+            Ok(count) => queued += count,
+            Err(e) => error!("Failed to queue {}: {}", path.display(), e),
         }
     }
+    sm.write_manifests();
+    run_state.flush()?;
 
+    let pending = run_state.get_all_pending_syncs()?.len();
+    println!("✓ Stopped. Queued {} unsynced change(s); {} file(s) pending in total.", queued, pending);
     Ok(())
 }
 
+/// How long the drive check waits: `interval` seconds plus a random share
+/// of up to `jitter` more
+fn drive_check_delay(interval: u64, jitter: u64) -> Duration {
+    use std::hash::{BuildHasher, Hasher};
+
+    // A freshly keyed hasher is random enough to spread out pollers
+    let random = std::collections::hash_map::RandomState::new().build_hasher().finish();
+    let jitter_ms = match jitter {
+        0 => 0,
+        secs => random % secs.saturating_mul(1000).saturating_add(1),
+    };
+    Duration::from_secs(interval) + Duration::from_millis(jitter_ms)
+}
+
+/// Resolves on Ctrl+C, or on SIGTERM on Unix
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        if let Ok(mut terminate) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+            return;
+        }
+    }
+
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        error!("Failed to listen for Ctrl+C: {}", e);
+        // Never resolve rather than shut down straight away
+        std::future::pending::<()>().await;
+    }
+}
+
+/// Sync the changes that arrived while the watcher was paused; returns the
+/// files that were still being written
+async fn sync_deferred(sm: &mut SyncManager, paths: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut unsettled = Vec::new();
+    for path in paths {
+        let result = if path.is_dir() {
+            sm.sync_directory(&path).await.map(|summary| unsettled.extend(summary.unsettled))
+        } else if path.is_file() {
+            sm.sync_file(&path).await.map(|result| {
+                if let sync::SyncResult::Unsettled(_) = result {
+                    unsettled.push(path.clone());
+                }
+            })
+        } else {
+            // Removed again before the resume
+            Ok(())
+        };
+
+        if let Err(e) = result {
+            error!("Failed to sync {}: {}", path.display(), e);
+        }
+    }
+    unsettled
+}
+
 /// Show current status and statistics
 fn cmd_status(config_path: &Path, db_path: &Path) -> Result<()> {
     let config = Config::load(config_path)?;
     let state = StateManager::new(db_path)?;
-    let sync_manager = SyncManager::new(config, state);
+    let staged = state.get_all_staged()?.len();
+    let mut sync_manager = SyncManager::new(config.clone(), state);
 
     let stats = sync_manager.get_stats()?;
+    let by_drive = sync_manager.get_stats_by_drive()?;
+    let connected = sync_manager.connected_drives();
+    let read_only = sync_manager.read_only_drives();
+    let full: std::collections::HashMap<_, _> = sync_manager.full_drives().into_iter().collect();
+    let moved: std::collections::HashMap<_, _> = sync_manager.moved_drives().into_iter().collect();
+    let replication = sync_manager.replication_completeness()?;
+    let orphans = sync_manager.find_orphans()?;
 
     println!("\n=== File Orchestrator Status ===");
     println!("Total files synced: {}", stats.total_files);
     println!("Total size: {} MB", stats.total_size / 1_000_000);
     println!("Pending syncs: {}", stats.pending_syncs);
+    if staged > 0 {
+        println!("Staged, waiting for `fo commit`: {}", staged);
+    }
+    if !orphans.is_empty() {
+        println!(
+            "For drives no longer configured: {} queued, {} synced record(s) (see `fo fsck`)",
+            orphans.pending.len(),
+            orphans.records.len()
+        );
+    }
+    if stats.quarantined > 0 {
+        println!("Quarantined (unknown type): {}", stats.quarantined);
+    }
+    if InstanceLock::is_held(db_path) {
+        let watcher = if control::is_paused(db_path) { "paused" } else { "running" };
+        println!("Watcher: {}", watcher);
+    }
     
     println!("\nBy category:");
     for (category, count) in &stats.by_category {
         println!("  {}: {}", category, count);
     }
+
+    let quarantined = sync_manager.quarantined_files()?;
+    if !quarantined.is_empty() {
+        println!("\nQuarantined files (rename them with the right extension to sync):");
+        for file in &quarantined {
+            println!("  {} (from {})", file.quarantine_path.display(), file.source_path.display());
+        }
+    }
+
+    println!("\nBy drive:");
+    let mut drives: Vec<_> = config.drives.iter().collect();
+    drives.sort_by(|a, b| a.1.label.cmp(&b.1.label));
+    for (uuid, drive) in drives {
+        let drive_stats = by_drive.get(uuid).cloned().unwrap_or_default();
+        let status = if let Some(mount) = moved.get(uuid) {
+            format!(
+                "MOVED - mounted at {} but configured at {}",
+                mount.display(), drive.path.as_deref().unwrap_or(Path::new("")).display()
+            )
+        } else if read_only.contains(uuid) {
+            "connected, READ-ONLY - check the write-protect switch".to_string()
+        } else if let Some(percent) = full.get(uuid) {
+            format!("connected, {:.0}% FULL - syncing paused until space is freed", percent)
+        } else if connected.contains(uuid) {
+            "connected".to_string()
+        } else {
+            "disconnected".to_string()
+        };
+
+        println!("  {} ({}, {})", drive.label, drive.target, status);
+        println!("    Files: {} ({})", drive_stats.file_count, format_size(drive_stats.total_size));
+        println!("    Pending: {} ({})", drive_stats.pending_count, format_size(drive_stats.pending_size));
+        if let Some((copies, files)) = replication.get(uuid) {
+            println!("    Replicated: {} of {} {} file(s) are current here", copies, files, drive.target);
+        }
+        if let Some(speed) = drive_stats.write_speed {
+            println!("    Write speed: {}/s (over {} copies)", format_size(speed.bytes_per_sec as u64), speed.samples);
+        }
+    }
     println!("\n================================\n");
 
     Ok(())
 }
 
+/// Reprint the totals and connected drives every `interval` seconds until
+/// Ctrl+C. While `fo run` has the database open, the totals it last
+/// published are shown instead.
+async fn cmd_status_watch(config_path: &Path, db_path: &Path, interval: u64) -> Result<()> {
+    let config = Config::load(config_path)?;
+    let mut detector = DriveDetector::new();
+
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+
+    loop {
+        // Opened only for the read, so a watcher starting meanwhile isn't locked out
+        let (stats, published) = match StateManager::new(db_path) {
+            Ok(state) => (Some(state.get_sync_stats()?), None),
+            Err(error::OrchestratorError::InstanceLocked(_)) => match control::read_stats(db_path) {
+                Some((at, stats)) => (Some(stats), Some(at)),
+                None => (None, None),
+            },
+            Err(e) => return Err(e),
+        };
+        detector.refresh();
+        let drives = detector.get_all_drives();
+
+        // Clear the screen and go to the top left
+        print!("\x1b[2J\x1b[H");
+        println!("=== File Orchestrator Status ({}) ===", chrono::Local::now().format("%H:%M:%S"));
+        match stats {
+            Some(stats) => {
+                if let Some(at) = published {
+                    let at: chrono::DateTime<chrono::Local> = at.into();
+                    println!("(from the running watcher, as of {})", at.format("%H:%M:%S"));
+                }
+                println!("Total files synced: {}", stats.total_files);
+                println!("Total size: {}", format_size(stats.total_size));
+                println!("Pending syncs: {}", stats.pending_syncs);
+                if stats.quarantined > 0 {
+                    println!("Quarantined (unknown type): {}", stats.quarantined);
+                }
+                let mut categories: Vec<_> = stats.by_category.iter().collect();
+                categories.sort();
+                for (category, count) in categories {
+                    println!("  {}: {}", category, count);
+                }
+            }
+            None => println!("The database is in use and the watcher hasn't published totals yet"),
+        }
+
+        println!("\nConnected drives:");
+        if drives.is_empty() {
+            println!("  (none)");
+        }
+        for drive in &drives {
+            let registered = config.drives.values().find(|configured| configured.path.as_deref() == Some(drive.mount_point.as_path()));
+            let label = registered.map(|configured| format!(" [{} -> {}]", configured.label, configured.target)).unwrap_or_default();
+            println!(
+                "  {} at {}{}: {} free of {}",
+                drive.display_name(),
+                drive.mount_point.display(),
+                label,
+                format_size(drive.available_space),
+                format_size(drive.total_space)
+            );
+        }
+        println!("\nRefreshing every {}s, Ctrl+C to stop", interval);
+
+        tokio::select! {
+            _ = &mut shutdown => break,
+            _ = sleep(Duration::from_secs(interval)) => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Publish the totals for `status --watch` to read while the watcher has
+/// the database
+fn publish_stats(state: &StateManager, db_path: &Path) {
+    if let Err(e) = state.get_sync_stats().and_then(|stats| control::write_stats(db_path, &stats)) {
+        warn!("Failed to publish sync totals: {}", e);
+    }
+}
+
+/// Print synced file counts and sizes per period from the sync history
+fn cmd_report(
+    db_path: &Path,
+    by: ReportPeriod,
+    since: Option<u64>,
+    until: Option<u64>,
+    format: ReportFormat,
+) -> Result<()> {
+    let state = StateManager::new(db_path)?;
+    let buckets = report::build_report(&state.get_history(since, until)?, by);
+
+    if format == ReportFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&buckets)?);
+        return Ok(());
+    }
+
+    if buckets.is_empty() {
+        println!("No syncs recorded in this range");
+        return Ok(());
+    }
+
+    println!("\n{:<12} {:>8} {:>12}", "Period", "Files", "Size");
+    for bucket in &buckets {
+        println!("{:<12} {:>8} {:>12}", bucket.period, bucket.totals.files, format_size(bucket.totals.bytes));
+        for (category, totals) in &bucket.by_category {
+            println!("  {:<10} {:>8} {:>12}", category, totals.files, format_size(totals.bytes));
+        }
+    }
+    println!();
+
+    Ok(())
+}
+
 /// Process pending syncs
 async fn cmd_process_pending(config_path: &Path, db_path: &Path) -> Result<()> {
     let config = Config::load(config_path)?;
+    let _lock = InstanceLock::acquire(db_path)?;
     let state = StateManager::new(db_path)?;
-    let mut sync_manager = SyncManager::new(config, state);
+    let mut sync_manager = SyncManager::new(config, state).with_config_path(config_path);
 
     info!("Checking for connected drives and processing pending syncs...");
     sync_manager.check_and_sync_connected_drives().await?;
 
     println!("✓ Finished processing pending syncs");
+    if let Some(batch) = sync_manager.last_batch() {
+        println!("Recorded as batch {}; `fo undo` reverts it", batch);
+    }
+
+    Ok(())
+}
+
+/// Queue another drive's pending files for a different drive
+fn cmd_reassign_pending(config_path: &Path, db_path: &Path, from: &str, to: &str, category: Option<&str>) -> Result<()> {
+    let config = Config::load(config_path)?;
+    let _lock = InstanceLock::acquire(db_path)?;
+    let state = StateManager::new(db_path)?;
+    let sync_manager = SyncManager::new(config, state);
+
+    let report = sync_manager.reassign_pending(from, to, category)?;
+    println!("✓ Reassigned {} pending file(s) to {}", report.moved, to);
+    if !report.incompatible.is_empty() {
+        println!("{} file(s) stayed queued for {}; the new drive doesn't take them:", report.incompatible.len(), from);
+        for path in &report.incompatible {
+            println!("  {}", path.display());
+        }
+    }
+
+    Ok(())
+}
+
+/// Report (and with `fix`, clear up) queue entries and records for drives
+/// that are no longer configured
+fn cmd_fsck(config_path: &Path, db_path: &Path, fix: bool) -> Result<()> {
+    let config = Config::load(config_path)?;
+    let _lock = if fix { Some(InstanceLock::acquire(db_path)?) } else { None };
+    let state = StateManager::new(db_path)?;
+    let sync_manager = SyncManager::new(config.clone(), state.clone());
+
+    let orphans = sync_manager.find_orphans()?;
+    if orphans.is_empty() {
+        println!("✓ Every queued file and sync record is for a configured drive");
+        return Ok(());
+    }
+
+    let mut by_drive: std::collections::BTreeMap<String, (usize, usize)> = std::collections::BTreeMap::new();
+    for pending in &orphans.pending {
+        by_drive.entry(pending.target_drive.clone()).or_default().0 += 1;
+    }
+    for copy in orphans.records.iter().flat_map(|record| record.copies()) {
+        if !config.drives.contains_key(&copy.target_drive) {
+            by_drive.entry(copy.target_drive).or_default().1 += 1;
+        }
+    }
+    println!("Drives no longer in the config:");
+    for (drive, (queued, records)) in &by_drive {
+        println!("  {}: {} queued file(s), {} synced record(s)", drive, queued, records);
+    }
+
+    if !fix {
+        println!("\n`fo fsck --fix` queues those files for the drive now taking their category and forgets the copies");
+        return Ok(());
+    }
+    let done = sync_manager.fix_orphans(&orphans)?;
+    state.flush()?;
+    println!(
+        "\n✓ Queued {} file(s) for a configured drive, dropped {} with none; kept {} record(s) without their \
+         removed copies and removed {} (they are copied again on the next sync)",
+        done.requeued, done.unqueued, done.trimmed, done.forgotten
+    );
+    Ok(())
+}
+
+/// Move staged copies into place on their drives and record them
+async fn cmd_commit(config_path: &Path, db_path: &Path, drive: Option<&str>) -> Result<()> {
+    let config = Config::load(config_path)?;
+    if let Some(uuid) = drive.filter(|uuid| !config.drives.contains_key(*uuid)) {
+        return Err(error::OrchestratorError::DriveNotFound(uuid.to_string()));
+    }
+    let _lock = InstanceLock::acquire(db_path)?;
+    let state = StateManager::new(db_path)?;
+    let mut sync_manager = SyncManager::new(config, state.clone());
+
+    let commit = sync_manager.commit_staged(drive).await?;
+    state.flush()?;
+    println!("✓ Committed {} staged file(s)", commit.committed.len());
+    if commit.waiting > 0 {
+        println!("{} staged file(s) are on drives that aren't connected", commit.waiting);
+    }
+    if !commit.failed.is_empty() {
+        println!("Left staged:");
+        for (path, reason) in &commit.failed {
+            println!("  {}: {}", path.display(), reason);
+        }
+    }
+    Ok(())
+}
+
+/// Delete staged copies so their files are copied again on the next sync
+async fn cmd_discard(config_path: &Path, db_path: &Path, drive: Option<&str>) -> Result<()> {
+    let config = Config::load(config_path)?;
+    if let Some(uuid) = drive.filter(|uuid| !config.drives.contains_key(*uuid)) {
+        return Err(error::OrchestratorError::DriveNotFound(uuid.to_string()));
+    }
+    let _lock = InstanceLock::acquire(db_path)?;
+    let state = StateManager::new(db_path)?;
+    let mut sync_manager = SyncManager::new(config, state.clone());
+
+    let discarded = sync_manager.discard_staged(drive).await?;
+    state.flush()?;
+    println!("✓ Discarded {} staged file(s)", discarded);
+    Ok(())
+}
+
+/// List pending syncs, oldest first, with what each drive needs room for
+fn cmd_list_pending(
+    config_path: &Path,
+    db_path: &Path,
+    drive: Option<&str>,
+    category: Option<&str>,
+    format: ReportFormat,
+) -> Result<()> {
+    let config = Config::load(config_path)?;
+
+    if let Some(uuid) = drive {
+        if !config.drives.contains_key(uuid) {
+            error!("Unknown drive UUID: {}", uuid);
+            return Ok(());
+        }
+    }
+
+    let state = StateManager::new(db_path)?;
+    let mut pending = match drive {
+        Some(uuid) => state.get_pending_syncs(uuid)?,
+        None => state.get_all_pending_syncs()?,
+    };
+    pending.retain(|entry| category.is_none_or(|category| entry.file_category == category));
+    pending.sort_by_key(|entry| entry.created_at);
+
+    let label = |uuid: &str| config.drives.get(uuid).map(|drive| drive.label.clone()).unwrap_or_else(|| uuid.to_string());
+    let now = state::current_timestamp();
+    let total_bytes: u64 = pending.iter().map(|entry| entry.size).sum();
+
+    if format == ReportFormat::Json {
+        let files: Vec<_> = pending
+            .iter()
+            .map(|entry| serde_json::json!({
+                "source_path": entry.source_path,
+                "category": entry.file_category,
+                "drive": entry.target_drive,
+                "drive_label": label(&entry.target_drive),
+                "size": entry.size,
+                "queued_at": entry.created_at,
+                "age_secs": now.saturating_sub(entry.created_at),
+            }))
+            .collect();
+        let listing = serde_json::json!({ "files": files, "total_files": pending.len(), "total_bytes": total_bytes });
+        println!("{}", serde_json::to_string_pretty(&listing)?);
+        return Ok(());
+    }
+
+    if pending.is_empty() {
+        println!("No files pending");
+        return Ok(());
+    }
+
+    println!("\n{:<12} {:<16} {:>12} {:>6}  File", "Category", "Drive", "Size", "Age");
+    let mut by_drive: std::collections::BTreeMap<&str, (usize, u64)> = std::collections::BTreeMap::new();
+    for entry in &pending {
+        println!(
+            "{:<12} {:<16} {:>12} {:>6}  {}",
+            entry.file_category,
+            label(&entry.target_drive),
+            format_size(entry.size),
+            format_age(now.saturating_sub(entry.created_at)),
+            entry.source_path.display()
+        );
+        let totals = by_drive.entry(&entry.target_drive).or_default();
+        totals.0 += 1;
+        totals.1 += entry.size;
+    }
+
+    println!("\nTotal: {} file(s), {}", pending.len(), format_size(total_bytes));
+    for (uuid, (files, bytes)) in &by_drive {
+        let over = match state.get_drive_capacity(uuid)? {
+            Some(capacity) if *bytes > capacity => format!(" (more than the {} it holds)", format_size(capacity)),
+            _ => String::new(),
+        };
+        println!("  {} needs {} for {} file(s){}", label(uuid), format_size(*bytes), files, over);
+    }
+    println!();
+
+    Ok(())
+}
+
+/// Pull new files from a drive into the source directory
+async fn cmd_pull(config_path: &Path, db_path: &Path, drive: &str) -> Result<()> {
+    let config = Config::load(config_path)?;
+    let _lock = InstanceLock::acquire(db_path)?;
+    let state = StateManager::new(db_path)?;
+    let mut sync_manager = SyncManager::new(config, state);
+
+    let summary = sync_manager.pull_from_drive(drive).await?;
+    sync_manager.write_manifests();
+    summary.print();
+
+    Ok(())
+}
+
+/// List a drive's snapshots, oldest first
+fn cmd_list_snapshots(config_path: &Path, db_path: &Path, drive: &str) -> Result<()> {
+    let config = Config::load(config_path)?;
+    let state = StateManager::new(db_path)?;
+    let sync_manager = SyncManager::new(config.clone(), state);
+
+    let snapshots = sync_manager.list_snapshots(drive)?;
+    let Some(drive_config) = config.drives.get(drive) else {
+        return Ok(());
+    };
+    if snapshots.is_empty() {
+        println!("No snapshots on {}", drive_config.label);
+        return Ok(());
+    }
+
+    println!("\n{:<20} {:>8} {:>8}", "Snapshot", "Files", "Latest");
+    for snapshot in &snapshots {
+        println!("{:<20} {:>8} {:>8}", snapshot.id, snapshot.files, snapshot.current);
+    }
+    if let Some(keep) = drive_config.snapshot_retention {
+        println!("\nKeeping the newest {}; older ones are deleted after each full sync", keep);
+    }
+    println!("Latest: files whose most recent copy on {} is in that snapshot\n", drive_config.label);
 
     Ok(())
 }
@@ -413,6 +1527,207 @@ fn cmd_clear(db_path: &Path, confirm: bool) -> Result<()> {
     Ok(())
 }
 
+/// Re-route synced files under the current rules
+async fn cmd_reroute(config_path: &Path, db_path: &Path, dry_run: bool) -> Result<()> {
+    let config = Config::load(config_path)?;
+    let _lock = InstanceLock::acquire(db_path)?;
+    let state = StateManager::new(db_path)?;
+    let mut sync_manager = SyncManager::new(config, state).with_config_path(config_path);
+
+    let report = sync_manager.reroute(dry_run).await?;
+    report.print(dry_run);
+
+    Ok(())
+}
+
+/// Undo a recorded sync batch
+fn cmd_undo(config_path: &Path, db_path: &Path, batch: Option<u64>) -> Result<()> {
+    let config = Config::load(config_path)?;
+    let _lock = InstanceLock::acquire(db_path)?;
+    let state = StateManager::new(db_path)?;
+    let mut sync_manager = SyncManager::new(config, state);
+
+    let report = sync_manager.undo_batch(batch)?;
+    report.print();
+
+    Ok(())
+}
+
+/// Repair the state database
+fn cmd_repair(config_path: &Path, db_path: &Path, rescan: bool) -> Result<()> {
+    let config = Config::load(config_path)?;
+    let _lock = InstanceLock::acquire(db_path)?;
+    let (state, outcome) = StateManager::repair(db_path)?;
+
+    let rebuilt = match outcome {
+        state::RepairOutcome::Cleaned { removed } => {
+            println!("✓ Database opened; removed {} unreadable entries", removed);
+            false
+        }
+        state::RepairOutcome::Rebuilt { backup } => {
+            println!("✓ Replaced the corrupt database (kept at {})", backup.display());
+            true
+        }
+    };
+
+    if rebuilt || rescan {
+        let mut sync_manager = SyncManager::new(config, state);
+        let restored = sync_manager.rebuild_from_targets()?;
+        println!("✓ Restored {} sync records from connected drives", restored);
+        println!("  Files on disconnected drives will be re-checked on the next sync.");
+    }
+
+    Ok(())
+}
+
+/// Add or remove a synced file's tags, or set its note
+fn cmd_tag(db_path: &Path, file: &Path, tags: &[String], remove: bool, note: Option<String>) -> Result<()> {
+    let _lock = InstanceLock::acquire(db_path)?;
+    let state = StateManager::new(db_path)?;
+    let file = std::path::absolute(file)?;
+
+    let Some(record) = state.get_file_state(&file)? else {
+        return Err(error::OrchestratorError::State(format!("{} has no sync record to tag", file.display())));
+    };
+    let tags: Vec<&str> = tags.iter().map(|tag| tag.trim()).filter(|tag| !tag.is_empty()).collect();
+    if remove {
+        let kept = record.tags.iter().filter(|tag| !tags.contains(&tag.as_str())).cloned().collect();
+        state.set_tags(&file, kept)?;
+    } else {
+        for tag in &tags {
+            state.add_tag(&file, tag)?;
+        }
+    }
+    if let Some(note) = note {
+        state.set_note(&file, Some(note).filter(|note| !note.is_empty()))?;
+    }
+    state.flush()?;
+
+    let record = state.get_file_state(&file)?.unwrap_or(record);
+    println!("✓ {}", file.display());
+    println!("  Tags: {}", if record.tags.is_empty() { "(none)".to_string() } else { record.tags.join(", ") });
+    if let Some(note) = record.note {
+        println!("  Note: {}", note);
+    }
+    Ok(())
+}
+
+/// List the synced files carrying a tag
+fn cmd_find(db_path: &Path, tag: &str, format: ReportFormat) -> Result<()> {
+    let state = StateManager::new(db_path)?;
+    let mut files = state.get_by_tag(tag)?;
+    files.sort_by(|a, b| a.source_path.cmp(&b.source_path));
+
+    if format == ReportFormat::Json {
+        let found: Vec<_> = files
+            .iter()
+            .map(|file| serde_json::json!({
+                "source_path": file.source_path,
+                "target_drive": file.target_drive,
+                "target_path": file.target_path,
+                "tags": file.tags,
+                "note": file.note,
+            }))
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&found)?);
+        return Ok(());
+    }
+
+    if files.is_empty() {
+        println!("No synced files tagged '{}'", tag);
+        return Ok(());
+    }
+    for file in &files {
+        println!("{} -> {}", file.source_path.display(), file.target_path.display());
+        if let Some(ref note) = file.note {
+            println!("    {}", note);
+        }
+    }
+    println!("\n{} file(s) tagged '{}'", files.len(), tag);
+    Ok(())
+}
+
+/// Print the most recent sync runs; `last` shows the newest one in full
+fn cmd_runs(db_path: &Path, limit: usize, format: ReportFormat, last: bool) -> Result<()> {
+    let state = StateManager::new(db_path)?;
+    let runs = state.get_runs(limit)?;
+
+    if format == ReportFormat::Json {
+        match (last, runs.first()) {
+            (true, Some(run)) => println!("{}", serde_json::to_string_pretty(run)?),
+            (true, None) => println!("null"),
+            (false, _) => println!("{}", serde_json::to_string_pretty(&runs)?),
+        }
+        return Ok(());
+    }
+
+    let local = |timestamp: u64| {
+        chrono::DateTime::from_timestamp(timestamp as i64, 0)
+            .map(|time| time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default()
+    };
+    let command = |run: &state::RunRecord| match &run.command {
+        Some(command) => format!("{} ({})", command, run.kind),
+        None => run.kind.clone(),
+    };
+
+    let Some(latest) = runs.first() else {
+        println!("No sync runs recorded yet");
+        return Ok(());
+    };
+    if last {
+        let run = latest;
+        println!("\n=== Last Run ===");
+        println!("Command: {}", command(run));
+        println!("Finished: {} (took {}s)", local(run.finished_at), run.finished_at.saturating_sub(run.started_at));
+        println!("Synced: {}", run.synced);
+        println!("Already synced: {}", run.already_synced);
+        println!("Pending: {}", run.pending);
+        println!("Skipped: {}", run.skipped);
+        if run.quarantined > 0 {
+            println!("Quarantined (unknown type): {}", run.quarantined);
+        }
+        println!("Conflicts: {}", run.conflicts);
+        if run.unsettled > 0 {
+            println!("Still being written or unreadable (not synced yet): {}", run.unsettled);
+        }
+        println!("Failed: {}", run.failed);
+        if !run.failures.is_empty() {
+            println!("\nFailed files:");
+            for (path, reason) in &run.failures {
+                println!("  {}: {}", path.display(), reason);
+            }
+            if run.failed > run.failures.len() {
+                println!("  ... and {} more", run.failed - run.failures.len());
+            }
+        }
+        println!("================\n");
+        return Ok(());
+    }
+
+    println!("\n{:<20} {:<24} {:>8} {:>8} {:>8}", "Finished", "Command", "Synced", "Pending", "Failed");
+    for run in &runs {
+        println!("{:<20} {:<24} {:>8} {:>8} {:>8}", local(run.finished_at), command(run), run.synced, run.pending, run.failed);
+    }
+    println!();
+    Ok(())
+}
+
+/// Rewrite the state database without the space its removed records held
+fn cmd_compact(db_path: &Path) -> Result<()> {
+    let _lock = InstanceLock::acquire(db_path)?;
+    let outcome = StateManager::compact(db_path)?;
+
+    println!(
+        "✓ Compacted {} ({} records): {} -> {}",
+        db_path.display(),
+        outcome.entries,
+        format_size(outcome.before),
+        format_size(outcome.after)
+    );
+    Ok(())
+}
+
 /// Validate configuration
 fn cmd_validate(config_path: &Path) -> Result<()> {
     let config = Config::load(config_path)?;
@@ -421,5 +1736,212 @@ fn cmd_validate(config_path: &Path) -> Result<()> {
     println!("\nSource directory: {}", config.source.path.display());
     println!("Registered drives: {}", config.drives.len());
 
+    let sync_manager = SyncManager::new(config, StateManager::temporary()?);
+    for (category, count) in sync_manager.unrouted_categories()? {
+        println!(
+            "⚠ {} {} files in the source have no drive (register one with --category {})",
+            count, category, category
+        );
+    }
+
+    Ok(())
+}
+
+/// Lines printed by `fo doctor`
+#[derive(Default)]
+struct Checklist {
+    failures: usize,
+    warnings: usize,
+}
+
+impl Checklist {
+    fn pass(&mut self, check: &str) {
+        println!("[PASS] {}", check);
+    }
+
+    fn warn(&mut self, check: &str, hint: &str) {
+        self.warnings += 1;
+        println!("[WARN] {}\n       -> {}", check, hint);
+    }
+
+    fn fail(&mut self, check: &str, hint: &str) {
+        self.failures += 1;
+        println!("[FAIL] {}\n       -> {}", check, hint);
+    }
+}
+
+/// Check the setup end to end and print a PASS/WARN/FAIL checklist.
+/// Returns false if anything failed.
+fn cmd_doctor(config_path: &Path, db_path: &Path) -> bool {
+    let mut checks = Checklist::default();
+    println!("\n=== File Orchestrator Doctor ===");
+
+    let config = match Config::load(config_path) {
+        Ok(config) => {
+            checks.pass(&format!("Config {} loads and validates", config_path.display()));
+            config
+        }
+        Err(e) => {
+            checks.fail(
+                &format!("Config {} could not be loaded: {}", config_path.display(), e),
+                "fix the error above, or create a config with `fo init`",
+            );
+            println!("\n1 failure; remaining checks need a valid config\n");
+            return false;
+        }
+    };
+
+    match std::fs::read_dir(&config.source.path) {
+        Ok(_) => checks.pass(&format!("Source {} is readable", config.source.path.display())),
+        Err(e) => checks.fail(
+            &format!("Source {} can't be read: {}", config.source.path.display(), e),
+            "check [source] path in the config and the folder's permissions",
+        ),
+    }
+
+    let state = match StateManager::new(db_path) {
+        Ok(state) => {
+            checks.pass(&format!("Database {} opens", db_path.display()));
+            Some(state)
+        }
+        Err(e @ error::OrchestratorError::InstanceLocked(_)) => {
+            checks.warn(&format!("Database is in use: {}", e), "stop `fo run` or the GUI to run the database checks");
+            None
+        }
+        Err(e @ error::OrchestratorError::DatabaseCorrupt(_)) => {
+            checks.fail(&format!("Database is unreadable: {}", e), "run `fo repair`");
+            None
+        }
+        Err(e) => {
+            checks.fail(&format!("Database can't be opened: {}", e), "check --db points at a writable location");
+            None
+        }
+    };
+
+    let mut detector = DriveDetector::new();
+    detector.refresh();
+    let mut connected = Vec::new();
+    let mut drives: Vec<_> = config.drives.iter().collect();
+    drives.sort_by(|a, b| a.1.label.cmp(&b.1.label));
+    for (uuid, drive) in &drives {
+        let found = match drive.path {
+            Some(ref path) if path.is_dir() => detector
+                .get_all_drives()
+                .into_iter()
+                .filter(|info| path.starts_with(&info.mount_point))
+                .max_by_key(|info| info.mount_point.components().count())
+                .or(Some(drive::DriveInfo {
+                    mount_point: path.clone(),
+                    ..Default::default()
+                })),
+            Some(_) => None,
+            None => detector.find_registered_drive(drive.marker_id.as_deref(), drive.volume_uuid.as_deref(), &drive.label),
+        };
+
+        match found {
+            Some(info) => {
+                let location = drive.path.as_ref().unwrap_or(&info.mount_point);
+                checks.pass(&format!("Drive {} ({}) found at {}", drive.label, drive.target, location.display()));
+                connected.push((uuid.to_string(), drive.label.clone(), info));
+            }
+            None => checks.warn(
+                &format!("Drive {} ({}) is not connected", drive.label, drive.target),
+                "plug it in; files for it are queued until then (check `path` if it is connected)",
+            ),
+        }
+    }
+
+    if let Some(state) = state {
+        let by_drive = state.get_stats_by_drive().unwrap_or_default();
+        let sync_manager = SyncManager::new(config.clone(), state);
+
+        match sync_manager.source_category_counts() {
+            Ok(counts) => {
+                for (category, count) in counts {
+                    if category == "unknown" {
+                        checks.warn(
+                            &format!("{} source files have an unknown type", count),
+                            "add extensions or patterns to [rules], or set sync.quarantine_dir",
+                        );
+                    } else if !config.drives.values().any(|drive| drive.target == category) {
+                        checks.fail(
+                            &format!("{} {} files in the source but no drive for {}", count, category, category),
+                            &format!("register one with `fo register-drive --label <name> --category {}`", category),
+                        );
+                    } else {
+                        checks.pass(&format!("{} {} files have a drive", count, category));
+                    }
+                }
+            }
+            Err(e) => checks.warn(&format!("Couldn't scan the source: {}", e), "check the source is readable"),
+        }
+
+        for (uuid, label, info) in &connected {
+            if info.total_space == 0 {
+                continue;
+            }
+            let pending = by_drive.get(uuid).map(|stats| stats.pending_size).unwrap_or(0);
+            if pending > info.available_space {
+                checks.fail(
+                    &format!(
+                        "Drive {} has {} free but {} is waiting to be copied",
+                        label,
+                        format_size(info.available_space),
+                        format_size(pending)
+                    ),
+                    "free up space on the drive or register another drive for the category",
+                );
+            } else if let Some(limit) = config.sync.pause_at_percent_full.filter(|limit| {
+                info.total_space.saturating_sub(info.available_space) as f64 * 100.0 >= f64::from(*limit) * info.total_space as f64
+            }) {
+                checks.fail(
+                    &format!("Drive {} is at least {}% full ({} free); syncing to it is paused", label, limit, format_size(info.available_space)),
+                    "free up space on the drive, raise sync.pause_at_percent_full or add another drive for the category",
+                );
+            } else if info.available_space < info.total_space / 10 {
+                checks.warn(
+                    &format!("Drive {} is over 90% full ({} free)", label, format_size(info.available_space)),
+                    "free up space soon or add another drive for the category",
+                );
+            } else {
+                checks.pass(&format!("Drive {} has {} free", label, format_size(info.available_space)));
+            }
+        }
+    }
+
+    println!("\n{} failure(s), {} warning(s)\n", checks.failures, checks.warnings);
+    checks.failures == 0
+}
+
+/// Audit synced files on connected target drives
+fn cmd_verify(
+    config_path: &Path,
+    db_path: &Path,
+    drive: Option<&str>,
+    rehash: bool,
+    requeue: bool,
+    manifest: bool,
+) -> Result<()> {
+    let config = Config::load(config_path)?;
+
+    if let Some(uuid) = drive {
+        if !config.drives.contains_key(uuid) {
+            error!("Unknown drive UUID: {}", uuid);
+            return Ok(());
+        }
+    }
+
+    // The manifests stand in for the database, which may be lost or elsewhere
+    let state = if manifest { StateManager::temporary()? } else { StateManager::new(db_path)? };
+    let mut sync_manager = SyncManager::new(config, state);
+
+    info!("Verifying synced files...");
+    let report = if manifest {
+        sync_manager.verify_manifests(drive, rehash)?
+    } else {
+        sync_manager.verify(drive, rehash, requeue)?
+    };
+    report.print();
+
     Ok(())
 }
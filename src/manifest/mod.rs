@@ -0,0 +1,151 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+
+use crate::error::Result;
+use crate::state::{FileState, HashAlgorithm};
+
+/// Name of the manifest kept at the root of a category folder on a drive
+pub const MANIFEST_FILE_NAME: &str = ".orchestrator-manifest.json";
+
+/// What fo put in one category folder of a drive, so the drive can be
+/// checked without the state database
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DriveManifest {
+    pub drive: String,
+    pub category: String,
+    /// Unix time the manifest was written
+    pub generated_at: u64,
+    pub files: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Path of the copy relative to the category folder, `/`-separated
+    pub path: String,
+    /// Size of the source file
+    pub size: u64,
+    /// Hash of the source file's content (before any compression)
+    pub hash: String,
+    #[serde(default)]
+    pub hash_algorithm: HashAlgorithm,
+    /// The copy is stored zstd-compressed
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub compressed: bool,
+    /// Unix time the file was synced
+    pub synced_at: u64,
+}
+
+impl ManifestEntry {
+    /// Entry for a sync record whose copy is inside `folder`, if it is
+    pub fn from_state(folder: &Path, state: &FileState) -> Option<Self> {
+        let relative = state.target_path.strip_prefix(folder).ok()?;
+        let path = relative
+            .components()
+            .map(|component| match component {
+                Component::Normal(name) => Some(name.to_string_lossy()),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()?
+            .join("/");
+
+        Some(Self {
+            path,
+            size: state.size,
+            hash: state.hash.clone(),
+            hash_algorithm: state.hash_algorithm,
+            compressed: state.is_compressed(),
+            synced_at: state.last_synced,
+        })
+    }
+
+    /// Where this entry's copy is, given the folder holding the manifest
+    pub fn target_path(&self, folder: &Path) -> PathBuf {
+        self.path.split('/').fold(folder.to_path_buf(), |path, part| path.join(part))
+    }
+}
+
+/// Whether `path` is a manifest rather than a synced file
+pub fn is_manifest(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name == MANIFEST_FILE_NAME)
+}
+
+/// Replace the manifest in `folder`. It is written next to the old one and
+/// renamed over it, so a reader (or an unplugged drive) never sees half a
+/// manifest.
+pub fn write(folder: &Path, manifest: &DriveManifest) -> Result<()> {
+    fs::create_dir_all(folder)?;
+    let path = folder.join(MANIFEST_FILE_NAME);
+    let temp = folder.join(format!("{}.tmp", MANIFEST_FILE_NAME));
+
+    let mut file = fs::File::create(&temp)?;
+    file.write_all(&serde_json::to_vec_pretty(manifest)?)?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(&temp, &path)?;
+    Ok(())
+}
+
+/// The manifest in `folder`, if there is one (and `folder` is a folder)
+pub fn read(folder: &Path) -> Result<Option<DriveManifest>> {
+    match fs::read(folder.join(MANIFEST_FILE_NAME)) {
+        Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        Err(e) if matches!(e.kind(), std::io::ErrorKind::NotFound | std::io::ErrorKind::NotADirectory) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_manifest_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let folder = dir.path().join("images");
+        let state = FileState {
+            source_path: PathBuf::from("/src/trip/a.jpg"),
+            hash: "abc".to_string(),
+            size: 4,
+            last_synced: 1_700_000_000,
+            target_drive: "drive".to_string(),
+            target_path: folder.join("trip").join("a.jpg"),
+            file_category: "images".to_string(),
+            compressed_size: None,
+            direction: Default::default(),
+            reflinked: false,
+            sparse: false,
+            hash_algorithm: HashAlgorithm::Blake3,
+            link: None,
+            snapshot: None,
+            tags: Vec::new(),
+            note: None,
+            replicas: Vec::new(),
+        };
+        let entry = ManifestEntry::from_state(&folder, &state).unwrap();
+        assert_eq!(entry.path, "trip/a.jpg");
+        assert_eq!(entry.target_path(&folder), state.target_path);
+
+        let elsewhere = FileState { target_path: dir.path().join("videos/b.mp4"), ..state };
+        assert!(ManifestEntry::from_state(&folder, &elsewhere).is_none());
+
+        assert!(read(&folder).unwrap().is_none());
+        let manifest = DriveManifest {
+            drive: "drive".to_string(),
+            category: "images".to_string(),
+            generated_at: 1_700_000_100,
+            files: vec![entry],
+        };
+        write(&folder, &manifest).unwrap();
+        assert!(is_manifest(&folder.join(MANIFEST_FILE_NAME)));
+        assert!(!folder.join(format!("{}.tmp", MANIFEST_FILE_NAME)).exists());
+
+        let read_back = read(&folder).unwrap().unwrap();
+        assert_eq!(read_back.files.len(), 1);
+        assert_eq!(read_back.files[0].hash, "abc");
+        assert!(!read_back.files[0].compressed);
+    }
+}
@@ -0,0 +1,205 @@
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{info, warn};
+
+use crate::error::{OrchestratorError, Result};
+use crate::state::{StateManager, SyncStats};
+use crate::sync::SyncResult;
+
+/// Upper bounds (seconds) of the copy duration histogram buckets
+const DURATION_BUCKETS: [f64; 9] = [0.05, 0.1, 0.5, 1.0, 5.0, 10.0, 30.0, 60.0, 300.0];
+
+/// Largest request head we bother reading; `/metrics` needs only the first line
+const MAX_REQUEST: usize = 8 * 1024;
+
+/// Counters kept by the running process, shared between the sync manager
+/// and the `/metrics` server
+#[derive(Default)]
+pub struct Metrics {
+    synced: AtomicU64,
+    failed: AtomicU64,
+    skipped: AtomicU64,
+    pending: AtomicU64,
+    connected_drives: AtomicUsize,
+    /// Per bucket, not cumulative; summed up when rendering
+    duration_buckets: [AtomicU64; DURATION_BUCKETS.len()],
+    duration_count: AtomicU64,
+    duration_micros: AtomicU64,
+}
+
+impl Metrics {
+    /// Count the outcome of one `sync_file` call, and its duration when a
+    /// file was actually copied
+    pub fn record(&self, result: &Result<SyncResult>, elapsed: Duration) {
+        let copied = match result {
+            Ok(SyncResult::Synced(_)) | Ok(SyncResult::Staged(_)) => {
+                self.synced.fetch_add(1, Ordering::Relaxed);
+                true
+            }
+            Ok(SyncResult::Conflict(policy, _)) if *policy != crate::config::ConflictPolicy::Skip => {
+                self.synced.fetch_add(1, Ordering::Relaxed);
+                true
+            }
+            Ok(SyncResult::Pending(_)) | Ok(SyncResult::DriveReadOnly(_)) | Ok(SyncResult::DriveFull(_)) => {
+                self.pending.fetch_add(1, Ordering::Relaxed);
+                false
+            }
+            Ok(SyncResult::Skipped(_)) | Ok(SyncResult::Conflict(..)) | Ok(SyncResult::WouldExceedCapacity(_)) => {
+                self.skipped.fetch_add(1, Ordering::Relaxed);
+                false
+            }
+            Ok(SyncResult::AlreadySynced) | Ok(SyncResult::Quarantined(_)) | Ok(SyncResult::Unsettled(_)) => false,
+            Err(_) => {
+                self.failed.fetch_add(1, Ordering::Relaxed);
+                false
+            }
+        };
+
+        if copied {
+            let seconds = elapsed.as_secs_f64();
+            if let Some(bucket) = DURATION_BUCKETS.iter().position(|bound| seconds <= *bound) {
+                self.duration_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+            }
+            self.duration_count.fetch_add(1, Ordering::Relaxed);
+            self.duration_micros.fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+        }
+    }
+
+    /// Remember how many registered drives are connected, as of the last
+    /// drive check
+    pub fn set_connected_drives(&self, count: usize) {
+        self.connected_drives.store(count, Ordering::Relaxed);
+    }
+
+    /// Prometheus text exposition of the counters plus the state database figures
+    pub fn render(&self, stats: &SyncStats, registered_drives: usize) -> String {
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: String| {
+            let _ = writeln!(out, "# HELP {} {}\n# TYPE {} {}\n{} {}", name, help, name, kind, name, value);
+        };
+
+        metric("fo_files_synced_total", "counter", "Files copied to a drive", self.synced.load(Ordering::Relaxed).to_string());
+        metric("fo_files_failed_total", "counter", "Files that failed to sync", self.failed.load(Ordering::Relaxed).to_string());
+        metric("fo_files_skipped_total", "counter", "Files skipped by filters, hooks or conflicts", self.skipped.load(Ordering::Relaxed).to_string());
+        metric("fo_files_queued_total", "counter", "Files queued because their drive was unavailable", self.pending.load(Ordering::Relaxed).to_string());
+        metric("fo_pending_syncs", "gauge", "Files currently waiting for their drive", stats.pending_syncs.to_string());
+        metric("fo_drives_registered", "gauge", "Drives in the config", registered_drives.to_string());
+        metric("fo_drives_connected", "gauge", "Registered drives connected at the last check", self.connected_drives.load(Ordering::Relaxed).to_string());
+
+        let mut categories: Vec<_> = stats.by_category.keys().collect();
+        categories.sort();
+        let _ = writeln!(out, "# HELP fo_category_files Synced files per category\n# TYPE fo_category_files gauge");
+        for category in &categories {
+            let _ = writeln!(out, "fo_category_files{{category=\"{}\"}} {}", escape_label(category), stats.by_category[*category]);
+        }
+        let _ = writeln!(out, "# HELP fo_category_bytes Synced bytes per category\n# TYPE fo_category_bytes gauge");
+        for category in &categories {
+            let bytes = stats.size_by_category.get(*category).copied().unwrap_or(0);
+            let _ = writeln!(out, "fo_category_bytes{{category=\"{}\"}} {}", escape_label(category), bytes);
+        }
+
+        let _ = writeln!(out, "# HELP fo_copy_duration_seconds Time taken to sync a copied file\n# TYPE fo_copy_duration_seconds histogram");
+        let mut cumulative = 0;
+        for (bound, count) in DURATION_BUCKETS.iter().zip(&self.duration_buckets) {
+            cumulative += count.load(Ordering::Relaxed);
+            let _ = writeln!(out, "fo_copy_duration_seconds_bucket{{le=\"{}\"}} {}", bound, cumulative);
+        }
+        let count = self.duration_count.load(Ordering::Relaxed);
+        let _ = writeln!(out, "fo_copy_duration_seconds_bucket{{le=\"+Inf\"}} {}", count);
+        let _ = writeln!(out, "fo_copy_duration_seconds_sum {}", self.duration_micros.load(Ordering::Relaxed) as f64 / 1e6);
+        let _ = writeln!(out, "fo_copy_duration_seconds_count {}", count);
+
+        out
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Serve `/metrics` on `addr` until the task is dropped. Figures come from
+/// a handle on the state database, so scrapes never wait for a running sync.
+pub async fn serve(addr: &str, metrics: Arc<Metrics>, state: StateManager, registered_drives: usize) -> Result<()> {
+    let addr: SocketAddr = addr.parse()
+        .map_err(|e| OrchestratorError::Config(format!("Invalid metrics_addr '{}': {}", addr, e)))?;
+    let listener = TcpListener::bind(addr).await?;
+    info!("Serving metrics on http://{}/metrics", addr);
+
+    let state = Arc::new(state);
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let metrics = Arc::clone(&metrics);
+        let state = Arc::clone(&state);
+        tokio::spawn(async move {
+            if let Err(e) = respond(stream, &metrics, &state, registered_drives).await {
+                warn!("Metrics request failed: {}", e);
+            }
+        });
+    }
+}
+
+async fn respond(mut stream: TcpStream, metrics: &Metrics, state: &StateManager, registered_drives: usize) -> Result<()> {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < MAX_REQUEST {
+        let read = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut buf)).await
+            .map_err(|_| OrchestratorError::Timeout("metrics request".to_string()))??;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..read]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let mut parts = request.lines().next().unwrap_or_default().split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", metrics.render(&state.get_sync_stats()?, registered_drives)),
+        _ => ("404 Not Found", "Not found\n".to_string()),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, body.len(), body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_render_counts_and_histogram() {
+        let metrics = Metrics::default();
+        metrics.record(&Ok(SyncResult::Synced(PathBuf::from("a"))), Duration::from_millis(80));
+        metrics.record(&Ok(SyncResult::Synced(PathBuf::from("b"))), Duration::from_secs(2));
+        metrics.record(&Ok(SyncResult::Pending("USB".to_string())), Duration::ZERO);
+        metrics.record(&Err(OrchestratorError::Sync("boom".to_string())), Duration::ZERO);
+        metrics.set_connected_drives(1);
+
+        let mut stats = SyncStats { pending_syncs: 1, ..Default::default() };
+        stats.by_category.insert("images".to_string(), 2);
+        stats.size_by_category.insert("images".to_string(), 2048);
+
+        let text = metrics.render(&stats, 3);
+        assert!(text.contains("fo_files_synced_total 2\n"));
+        assert!(text.contains("fo_files_failed_total 1\n"));
+        assert!(text.contains("fo_files_queued_total 1\n"));
+        assert!(text.contains("fo_drives_registered 3\n"));
+        assert!(text.contains("fo_drives_connected 1\n"));
+        assert!(text.contains("fo_category_bytes{category=\"images\"} 2048\n"));
+        assert!(text.contains("fo_copy_duration_seconds_bucket{le=\"0.05\"} 0\n"));
+        assert!(text.contains("fo_copy_duration_seconds_bucket{le=\"0.1\"} 1\n"));
+        assert!(text.contains("fo_copy_duration_seconds_bucket{le=\"5\"} 2\n"));
+        assert!(text.contains("fo_copy_duration_seconds_count 2\n"));
+    }
+}
@@ -0,0 +1,105 @@
+use serde::Serialize;
+use std::path::PathBuf;
+use tracing::warn;
+use crate::config::{NotificationsConfig, NotifyEvent};
+
+/// One thing worth telling the user about. Serialized as the webhook body.
+#[derive(Debug, Clone, Serialize)]
+pub struct Notification {
+    pub event: NotifyEvent,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub drive: Option<String>,
+    /// Short human-readable outcome, e.g. the target path or the error
+    pub result: String,
+}
+
+impl Notification {
+    fn summary(&self) -> String {
+        match self.event {
+            NotifyEvent::Synced => "File synced".to_string(),
+            NotifyEvent::Failed => "Sync failed".to_string(),
+            NotifyEvent::DriveConnected => format!("Drive connected: {}", self.drive.as_deref().unwrap_or("unknown")),
+        }
+    }
+
+    fn body(&self) -> String {
+        match &self.file {
+            Some(file) => format!("{}\n{}", file.display(), self.result),
+            None => self.result.clone(),
+        }
+    }
+}
+
+/// Sends notifications to the desktop and/or a webhook, as configured in
+/// `[notifications]`. Delivery happens in the background and failures are
+/// only logged, so a broken notifier never holds up or aborts a sync.
+#[derive(Clone)]
+pub struct Notifier {
+    config: NotificationsConfig,
+}
+
+impl Notifier {
+    pub fn new(config: NotificationsConfig) -> Self {
+        Self { config }
+    }
+
+    /// Whether `event` would be sent anywhere
+    pub fn wants(&self, event: NotifyEvent) -> bool {
+        self.config.enabled && self.config.on_events.contains(&event)
+    }
+
+    pub fn notify(&self, notification: Notification) {
+        if !self.wants(notification.event) {
+            return;
+        }
+
+        let config = self.config.clone();
+        tokio::task::spawn_blocking(move || {
+            if config.desktop {
+                if let Err(e) = notify_rust::Notification::new()
+                    .appname("File Orchestrator")
+                    .summary(&notification.summary())
+                    .body(&notification.body())
+                    .show()
+                {
+                    warn!("Failed to show desktop notification: {}", e);
+                }
+            }
+
+            if let Some(ref url) = config.webhook_url {
+                if let Err(e) = ureq::post(url)
+                    .timeout(std::time::Duration::from_secs(10))
+                    .send_json(&notification)
+                {
+                    warn!("Failed to call notification webhook {}: {}", url, e);
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_webhook_payload() {
+        let notification = Notification {
+            event: NotifyEvent::Synced,
+            file: Some(PathBuf::from("/src/photo.jpg")),
+            category: Some("images".to_string()),
+            drive: Some("ImageUSB".to_string()),
+            result: "Copied to /mnt/usb/images/photo.jpg".to_string(),
+        };
+
+        let payload: serde_json::Value = serde_json::to_value(&notification).unwrap();
+        assert_eq!(payload["event"], "synced");
+        assert_eq!(payload["file"], "/src/photo.jpg");
+        assert_eq!(payload["category"], "images");
+        assert_eq!(payload["drive"], "ImageUSB");
+    }
+}
@@ -0,0 +1,115 @@
+use chrono::{DateTime, Datelike, Local};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use crate::state::HistoryEntry;
+
+/// How history entries are grouped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReportPeriod {
+    Day,
+    Week,
+    Month,
+}
+
+impl ReportPeriod {
+    /// Label of the bucket a sync time falls in, in local time
+    fn bucket(self, synced_at: u64) -> String {
+        let time: DateTime<Local> = DateTime::from_timestamp(synced_at as i64, 0)
+            .unwrap_or_default()
+            .with_timezone(&Local);
+
+        match self {
+            ReportPeriod::Day => time.format("%Y-%m-%d").to_string(),
+            ReportPeriod::Week => {
+                let week = time.iso_week();
+                format!("{}-W{:02}", week.year(), week.week())
+            }
+            ReportPeriod::Month => time.format("%Y-%m").to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct Totals {
+    pub files: usize,
+    pub bytes: u64,
+}
+
+impl Totals {
+    fn add(&mut self, size: u64) {
+        self.files += 1;
+        self.bytes += size;
+    }
+}
+
+/// Syncs within one day, week or month
+#[derive(Debug, Clone, Serialize)]
+pub struct ReportBucket {
+    pub period: String,
+    #[serde(flatten)]
+    pub totals: Totals,
+    pub by_category: BTreeMap<String, Totals>,
+}
+
+/// Group history entries into buckets, oldest first
+pub fn build_report(entries: &[HistoryEntry], period: ReportPeriod) -> Vec<ReportBucket> {
+    let mut buckets: BTreeMap<String, ReportBucket> = BTreeMap::new();
+
+    for entry in entries {
+        let label = period.bucket(entry.synced_at);
+        let bucket = buckets.entry(label.clone()).or_insert_with(|| ReportBucket {
+            period: label,
+            totals: Totals::default(),
+            by_category: BTreeMap::new(),
+        });
+        bucket.totals.add(entry.size);
+        bucket.by_category.entry(entry.file_category.clone()).or_default().add(entry.size);
+    }
+
+    buckets.into_values().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::path::PathBuf;
+
+    fn entry(category: &str, size: u64, day: u32) -> HistoryEntry {
+        HistoryEntry {
+            source_path: PathBuf::from("/src/file"),
+            file_category: category.to_string(),
+            target_drive: "drive".to_string(),
+            size,
+            synced_at: Local.with_ymd_and_hms(2024, 3, day, 12, 0, 0).unwrap().timestamp() as u64,
+            snapshot: None,
+        }
+    }
+
+    #[test]
+    fn test_build_report() {
+        // 2024-03-04 is a Monday, so the 10th closes the same ISO week
+        let entries = [entry("images", 10, 4), entry("videos", 100, 4), entry("images", 5, 10), entry("images", 1, 11)];
+
+        let days = build_report(&entries, ReportPeriod::Day);
+        assert_eq!(days.len(), 3);
+        assert_eq!(days[0].period, "2024-03-04");
+        assert_eq!(days[0].totals, Totals { files: 2, bytes: 110 });
+        assert_eq!(days[0].by_category["videos"], Totals { files: 1, bytes: 100 });
+
+        let weeks = build_report(&entries, ReportPeriod::Week);
+        assert_eq!(weeks.iter().map(|b| b.period.as_str()).collect::<Vec<_>>(), ["2024-W10", "2024-W11"]);
+        assert_eq!(weeks[0].by_category["images"], Totals { files: 2, bytes: 15 });
+
+        let months = build_report(&entries, ReportPeriod::Month);
+        assert_eq!(months.len(), 1);
+        assert_eq!(months[0].totals.files, 4);
+    }
+}
@@ -0,0 +1,178 @@
+use std::borrow::Cow;
+use std::path::{Component, Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+
+/// Characters FAT and exFAT refuse in file names
+const FAT_RESERVED_CHARS: &[char] = &['"', '*', '/', ':', '<', '>', '?', '\\', '|'];
+
+/// Device names Windows won't open as files, whatever the extension
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Whether files on this file system need FAT-safe names
+pub fn needs_fat_names(file_system: &str) -> bool {
+    matches!(
+        file_system.to_lowercase().as_str(),
+        "vfat" | "fat" | "fat12" | "fat16" | "fat32" | "msdos" | "exfat"
+    )
+}
+
+/// Whether names on this file system ignore case, when that is known from
+/// its type alone (APFS and HFS+ can go either way)
+pub fn is_case_insensitive_fs(file_system: &str) -> bool {
+    needs_fat_names(file_system) || matches!(file_system.to_lowercase().as_str(), "ntfs" | "ntfs3" | "refs")
+}
+
+/// How source names are rewritten before they become state keys and
+/// target paths, so one file keeps one identity whatever form its name
+/// arrives in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NameNormalization {
+    /// Unicode NFC, so macOS-style decomposed (NFD) names match composed ones
+    pub nfc: bool,
+    pub lowercase: bool,
+}
+
+impl NameNormalization {
+    pub fn is_enabled(&self) -> bool {
+        self.nfc || self.lowercase
+    }
+
+    /// Normalize every component of `path`. Names that aren't valid UTF-8
+    /// are left as they are.
+    pub fn apply<'a>(&self, path: &'a Path) -> Cow<'a, Path> {
+        if !self.is_enabled() {
+            return Cow::Borrowed(path);
+        }
+        let Some(text) = path.to_str() else {
+            return Cow::Borrowed(path);
+        };
+
+        let composed = if self.nfc && !is_nfc(text) { Cow::Owned(text.nfc().collect()) } else { Cow::Borrowed(text) };
+        let normalized = if self.lowercase && composed.chars().any(char::is_uppercase) {
+            Cow::Owned(composed.to_lowercase())
+        } else {
+            composed
+        };
+        match normalized {
+            Cow::Borrowed(_) => Cow::Borrowed(path),
+            Cow::Owned(name) => Cow::Owned(PathBuf::from(name)),
+        }
+    }
+}
+
+/// Make a single file or directory name valid on FAT/exFAT. Reserved
+/// characters become `_`, trailing dots and spaces become `_`, and device
+/// names get a `_` suffix. Already-valid names are returned unchanged, so
+/// sanitizing twice gives the same result as sanitizing once.
+pub fn sanitize_name(name: &str) -> Cow<'_, str> {
+    let is_bad = |c: char| c.is_control() || FAT_RESERVED_CHARS.contains(&c);
+    let trimmed = name.trim_end_matches(['.', ' ']);
+    let stem = trimmed.split('.').next().unwrap_or(trimmed);
+    let reserved = RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(stem.trim_end()));
+
+    if !name.contains(is_bad) && trimmed.len() == name.len() && !reserved {
+        return Cow::Borrowed(name);
+    }
+
+    let mut sanitized: String = trimmed.chars().map(|c| if is_bad(c) { '_' } else { c }).collect();
+    if reserved {
+        // CON.txt -> CON_.txt. Found in `sanitized`, since a multibyte
+        // control character in the stem is now a one-byte `_`.
+        let end = sanitized.find('.').unwrap_or(sanitized.len());
+        sanitized.insert(end, '_');
+    }
+    sanitized.extend(std::iter::repeat_n('_', name.len() - trimmed.len()));
+    Cow::Owned(sanitized)
+}
+
+/// Sanitize every component of a relative path. Returns `None` when the
+/// path is already valid.
+pub fn sanitize_relative_path(path: &Path) -> Option<PathBuf> {
+    let mut changed = false;
+    let sanitized = path
+        .components()
+        .map(|component| match component {
+            Component::Normal(name) => {
+                let name = name.to_string_lossy();
+                let clean = sanitize_name(&name);
+                if let Cow::Owned(clean) = clean {
+                    changed = true;
+                    PathBuf::from(clean)
+                } else {
+                    PathBuf::from(name.as_ref())
+                }
+            }
+            other => PathBuf::from(other.as_os_str()),
+        })
+        .collect();
+
+    changed.then_some(sanitized)
+}
+
+/// A name for a sanitized file that collides with a different file. The
+/// suffix is derived from the original name, so the same file always gets
+/// the same replacement.
+pub fn disambiguate(sanitized: &Path, original: &Path) -> PathBuf {
+    let original_name = original.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+    let tag = &blake3::hash(original_name.as_bytes()).to_hex()[..8];
+
+    let stem = sanitized.file_stem().map(|s| s.to_string_lossy()).unwrap_or_default();
+    let name = match sanitized.extension() {
+        Some(ext) => format!("{}~{}.{}", stem, tag, ext.to_string_lossy()),
+        None => format!("{}~{}", stem, tag),
+    };
+    sanitized.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_name() {
+        assert_eq!(sanitize_name("holiday.jpg"), "holiday.jpg");
+        assert_eq!(sanitize_name("12:30 \"party\"?.jpg"), "12_30 _party__.jpg");
+        assert_eq!(sanitize_name("notes. "), "notes__");
+        assert_eq!(sanitize_name("con.txt"), "con_.txt");
+        assert_eq!(sanitize_name("CON\u{85}\u{85}.txt"), "CON___.txt");
+
+        for name in ["a:b?.jpg", "trailing...", "AUX", "x|y"] {
+            let once = sanitize_name(name).into_owned();
+            assert_eq!(sanitize_name(&once), once.as_str());
+        }
+    }
+
+    #[test]
+    fn test_sanitize_relative_path() {
+        assert_eq!(sanitize_relative_path(Path::new("2024/trip/a.jpg")), None);
+        assert_eq!(
+            sanitize_relative_path(Path::new("2024: trip/a?.jpg")),
+            Some(PathBuf::from("2024_ trip/a_.jpg"))
+        );
+
+        let a = disambiguate(Path::new("x/a_.jpg"), Path::new("a?.jpg"));
+        let b = disambiguate(Path::new("x/a_.jpg"), Path::new("a*.jpg"));
+        assert_ne!(a, b);
+        assert_eq!(a, disambiguate(Path::new("x/a_.jpg"), Path::new("a?.jpg")));
+    }
+
+    #[test]
+    fn test_name_normalization() {
+        let decomposed = Path::new("Cafe\u{301}/Re\u{301}sume\u{301}.JPG");
+        let composed = Path::new("Caf\u{e9}/R\u{e9}sum\u{e9}.JPG");
+
+        assert_eq!(NameNormalization::default().apply(decomposed), decomposed);
+        let nfc = NameNormalization { nfc: true, lowercase: false };
+        assert_eq!(nfc.apply(decomposed), composed);
+        assert!(matches!(nfc.apply(composed), Cow::Borrowed(_)));
+
+        let both = NameNormalization { nfc: true, lowercase: true };
+        assert_eq!(both.apply(decomposed), Path::new("caf\u{e9}/r\u{e9}sum\u{e9}.jpg"));
+        assert_eq!(both.apply(decomposed), both.apply(composed));
+    }
+}
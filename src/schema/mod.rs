@@ -0,0 +1,369 @@
+use std::fmt::Write as _;
+
+use crate::config::Config;
+use crate::error::Result;
+
+/// One documented key of `config.toml`
+pub struct Field {
+    pub key: &'static str,
+    /// TOML type, with the accepted values for enums
+    pub kind: &'static str,
+    pub doc: &'static str,
+    /// Written commented-out when the key isn't set, so it can be enabled
+    pub example: Option<&'static str>,
+}
+
+/// A table of `config.toml`. `drives` and `folder_names` are maps keyed by
+/// drive UUID and category name respectively.
+pub struct Section {
+    pub name: &'static str,
+    pub doc: &'static str,
+    pub fields: &'static [Field],
+    /// Sample entry written commented-out when a map section is empty
+    pub example: Option<&'static str>,
+}
+
+const fn field(key: &'static str, kind: &'static str, doc: &'static str, example: Option<&'static str>) -> Field {
+    Field { key, kind, doc, example }
+}
+
+/// Keys outside any table
+pub const TOP_LEVEL: &[Field] = &[
+    field("version", "integer", "Config layout version; managed by fo, don't edit", None),
+    field("metrics_addr", "string", "Serve Prometheus metrics at http://<addr>/metrics during `fo run` (needs the metrics feature)", Some("\"127.0.0.1:9898\"")),
+];
+
+pub const SECTIONS: &[Section] = &[
+    Section {
+        name: "source",
+        doc: "The folder that is watched and synced",
+        fields: &[
+            field("path", "path", "Directory to watch", None),
+            field("include_dirs", "array of paths", "Only scan and watch these subfolders (relative to path)", Some("[\"Photos\", \"Music/Albums\"]")),
+            field("scan_max_depth", "integer", "How many folder levels below path to scan; 0 is only files directly in path", Some("4")),
+            field("skip_hidden", "boolean", "Leave out hidden files and folders (dot-names on Unix, the hidden attribute on Windows)", None),
+            field("skip_dirs", "array of strings", "Folder names never scanned or watched, case-insensitive, a trailing * matches any suffix; replaces the built-in list of trash, recycle bin and other system folders", Some("[\".Trash-*\", \"node_modules\"]")),
+            field("dir_cache", "boolean", "Cache folder listings in the state database and only re-read folders whose modification time changed; `sync-once --no-cache` walks everything", None),
+            field("watch_queue_capacity", "integer", "File events `fo run` holds before merging repeated events for the same file, and past that making the watcher wait", None),
+        ],
+        example: None,
+    },
+    Section {
+        name: "rules",
+        doc: "File extensions for each category (without the dot). `include = \"rules.toml\"` reads them from another file.",
+        fields: &[
+            field("images", "array of strings", "Extensions synced as images", None),
+            field("videos", "array of strings", "Extensions synced as videos", None),
+            field("music", "array of strings", "Extensions synced as music", None),
+            field("documents", "array of strings", "Extensions synced as documents", None),
+            field("archives", "array of strings", "Extensions synced as archives", None),
+            field("pattern_syntax", "\"glob\" | \"regex\"", "Syntax of the pattern strings in patterns", None),
+            field("classify_strategy", "\"extension-first\" | \"content\"", "\"content\" (default) always checks the magic bytes; \"extension-first\" trusts an extension that is in exactly one list without reading the file, which also keeps category_priority from overruling it", Some("\"extension-first\"")),
+            field("category_priority", "array of strings", "Which category wins when an extension is in several lists or its list disagrees with the detected content, earliest first", Some("[\"documents\", \"archives\"]")),
+            field("mime", "table of category = { allow, deny }", "MIME types (like image/jpeg or image/*) a category accepts or refuses, checked against the detected content", Some("{ images = { allow = [\"image/jpeg\", \"image/png\"] } }")),
+            field("text_sniffing", "table { sample_bytes, max_control_ratio }", "Sync extensionless files whose first sample_bytes are text (valid UTF-8, at most max_control_ratio control bytes) as documents", Some("{ sample_bytes = 8192, max_control_ratio = 0.01 }")),
+            field("patterns", "array of { pattern, category }", "Filename patterns checked in order before the extension lists; the category may be a custom one", Some("[{ pattern = \"Screenshot_*.png\", category = \"screenshots\" }]")),
+            field("subfolders", "table of category = array of { pattern, folder }", "Subfolders of a category's folder for files matching a pattern (in pattern_syntax), first match wins; other files stay in the category folder", Some("{ images = [{ pattern = \"Screenshot_*\", folder = \"Screenshots\" }] }")),
+        ],
+        example: None,
+    },
+    Section {
+        name: "sync",
+        doc: "How files are copied",
+        fields: &[
+            field("conflict_policy", "\"overwrite\" | \"skip\" | \"rename\" | \"fail\"", "What to do when a different file already exists at the target path", None),
+            field("auto_bind_path", "boolean", "Record a drive's mount point the first time it is found, and follow it when it moves", None),
+            field("pending_order", "\"fifo\" | \"smallest-first\" | \"largest-first\"", "Order in which queued files are copied when their drive reconnects", None),
+            field("drive_selection", "\"first\" | \"fastest\"", "Which drive gets a file when several take its category; fastest picks the connected drive with room that has written quickest", None),
+            field("allow_multiple_per_category", "boolean", "Allow several drives to take the same category with drive_selection \"first\" when the category isn't replicated or split by accept_extensions; the first by UUID gets each file", None),
+            field("sync_empty_files", "boolean", "Sync zero-byte files; false skips them", None),
+            field("min_file_size", "integer or size string", "Skip files smaller than this, in bytes or like \"10KB\"", Some("\"1KB\"")),
+            field("max_file_size", "integer or size string", "Skip files larger than this, in bytes or like \"4GB\"", Some("\"4GB\"")),
+            field("hash_algorithm", "\"blake3\" | \"sha256\" | \"md5\"", "Hash recorded for newly synced files", None),
+            field("hash_workers", "integer", "Threads hashing files ahead of the copies during a batch sync or pending drain; 0 hashes each file as it is synced; copies still run one at a time", None),
+            field("progress_every", "integer", "Log progress every this many files during a batch sync; 0 turns it off", None),
+            field("target_file_mode", "string", "Octal permission bits for synced files on Unix; unset leaves them to the umask", Some("\"0644\"")),
+            field("target_dir_mode", "string", "Octal permission bits for folders created on the drives on Unix", Some("\"0755\"")),
+            field("settle_seconds", "integer", "Wait until a file hasn't been modified for this many seconds before syncing it; 0 syncs straight away", Some("30")),
+            field("per_file_timeout_secs", "integer", "Give up on copying a single file after this many seconds and queue it for the drive's next check", Some("600")),
+            field("quarantine_dir", "path", "Put files of unknown type here instead of skipping them; relative to the source path", Some("\"_unsorted\"")),
+            field("quarantine_mode", "\"move\" | \"copy\"", "Whether quarantined files are moved out of the source or copied", None),
+            field("normalize_unicode", "boolean", "Unicode-normalize (NFC) source names in sync records and target paths so NFD and NFC spellings match", None),
+            field("lowercase_names", "boolean", "Lowercase source names in sync records and target paths", None),
+            field("drive_manifests", "boolean", "Keep a .orchestrator-manifest.json of path, size, hash and sync time in each category folder on the drives", None),
+            field("reject_over_capacity", "boolean", "Don't queue files for an unplugged drive beyond its last-seen size (by default this only warns)", None),
+            field("pause_at_percent_full", "integer", "Stop copying to a drive that is at least this full (percent), keeping its files queued until space is freed", Some("98")),
+            field("same_device_strategy", "\"copy\" | \"hardlink\" | \"symlink\" | \"reflink\"", "What to put on a drive on the source's own device; links fall back to a copy where the file system has none", None),
+            field("transient_retries", "integer", "Times to retry a copy or hash read that failed because the file was briefly busy (sharing violation, EBUSY, EAGAIN); other errors fail at once", None),
+            field("transient_retry_delay_ms", "integer", "Milliseconds to wait between those retries", None),
+            field("copy_xattrs", "boolean", "Copy extended attributes (Finder tags, quarantine flags, resource forks) with each file on Linux and macOS; ones the drive can't store are logged and left out", None),
+            field("group_sidecars", "boolean", "Send subtitles and .nfo files to the drive and category of the file in the same folder whose name they extend (movie.en.srt with movie.mkv)", None),
+            field("sidecar_extensions", "array of strings", "Extensions group_sidecars treats as sidecars; defaults to srt, ass, ssa, sub, idx, vtt and nfo. Adding jpg makes posters follow their video, and Live Photo stills too", Some("[\"srt\", \"nfo\"]")),
+            field("staging", "boolean", "Copy files into .staging on the drive and record them only when `fo commit` moves them into place; `fo discard` drops them. Replicated categories are staged on each of their drives", None),
+            field("chunk_hash", "integer or size string", "Hash copies in pieces of this size so a file that only grew since its last sync has just the new part written to the drive and checked", Some("\"64MB\"")),
+            field("replicate", "array of strings", "Categories copied to every drive that takes them instead of one; drives that are away are queued until they return", Some("[\"images\"]")),
+            field("state_flush_interval_ms", "integer", "Flush sync records to disk at most this often; a crash loses at most this window. The pending queue, quarantine, staged files and undo batches are always flushed at once. 0 flushes every write", None),
+            field("interval_jitter", "integer", "Add up to this many random seconds to each wait between fo run's drive checks", None),
+        ],
+        example: None,
+    },
+    Section {
+        name: "notifications",
+        doc: "Desktop and webhook notifications",
+        fields: &[
+            field("enabled", "boolean", "Send notifications at all", None),
+            field("on_events", "array of \"synced\" | \"failed\" | \"drive-connected\"", "Which events to notify about", None),
+            field("desktop", "boolean", "Show desktop notifications", None),
+            field("webhook_url", "string", "POST a JSON description of each event to this URL", Some("\"https://example.com/hook\"")),
+        ],
+        example: None,
+    },
+    Section {
+        name: "hooks",
+        doc: "Commands run around each copy. Templates may use {source}, {target}, {category}, {drive} and {hash}.",
+        fields: &[
+            field("pre_sync", "string", "Run before copying; a non-zero exit skips the file", Some("\"test -r {source}\"")),
+            field("post_sync", "string", "Run after a file is synced; its exit status is only logged", Some("\"echo synced {source} >> ~/fo.log\"")),
+            field("timeout_secs", "integer", "Kill a hook that runs longer than this", None),
+        ],
+        example: None,
+    },
+    Section {
+        name: "folder_names",
+        doc: "Folder on the drive for each category, e.g. images = \"Photos\". Unlisted categories use the category name.",
+        fields: &[],
+        example: Some("images = \"Photos\""),
+    },
+    Section {
+        name: "drives",
+        doc: "Registered drives, one [drives.<uuid>] table each (see `fo register-drive`)",
+        fields: &[
+            field("label", "string", "Volume label used to recognise the drive", None),
+            field("target", "string", "Category this drive receives", None),
+            field("path", "path", "Mount point or folder to copy into; found from the label when unset", Some("\"/media/usb\"")),
+            field("last_seen", "string", "When the drive was registered; fo records later connections in its database", None),
+            field("compress", "boolean", "Store zstd-compressed copies (name.ext.zst) of compressible files", None),
+            field("network", "boolean", "path is a network share that counts as connected whenever it is reachable", None),
+            field("accept_extensions", "array of strings", "Only take these extensions of the category; others go to the next drive", Some("[\"jpg\", \"png\"]")),
+            field("bidirectional", "boolean", "Also copy files added directly on the drive back into the source", None),
+            field("flatten", "boolean", "Put every file directly in the category folder instead of mirroring the source tree", None),
+            field("sparse_copy", "boolean", "Keep holes in sparse files on the drive instead of writing zeros; ignored on FAT/exFAT", None),
+            field("snapshots", "boolean", "Put each full sync in a new snapshots/<date>/ folder, hard-linking files unchanged since the previous snapshot", None),
+            field("snapshot_retention", "integer", "Keep this many snapshots, deleting older ones after each full sync (ones holding a file's current copy stay)", Some("10")),
+            field("volume_uuid", "string", "File system UUID recorded when the drive was bound; managed by fo", None),
+            field("marker_id", "string", "Id in the .orchestrator-id file register-drive put at the drive's root; the drive is recognised by it at any mount point", None),
+        ],
+        example: None,
+    },
+];
+
+/// Render `config` as TOML with a comment above every key, and unset
+/// optional keys written commented-out
+pub fn commented(config: &Config) -> Result<String> {
+    let mut table = toml::Table::try_from(config)?;
+    let mut out = String::from("# File Orchestrator configuration. `fo schema` lists every key.\n\n");
+
+    // Tables are pulled out first; TOML needs the top-level keys before them
+    let sections: Vec<_> = SECTIONS
+        .iter()
+        .map(|section| match table.remove(section.name) {
+            Some(toml::Value::Table(values)) => (section, values),
+            _ => (section, toml::Table::new()),
+        })
+        .collect();
+
+    render_fields(&mut out, TOP_LEVEL, &mut table);
+    for (section, values) in sections {
+        out.push('\n');
+        comment(&mut out, section.doc);
+
+        match section.name {
+            "drives" => {
+                let mut drives: Vec<_> = values.into_iter().collect();
+                drives.sort_by(|a, b| a.0.cmp(&b.0));
+                for (uuid, drive) in drives {
+                    let toml::Value::Table(mut drive) = drive else { continue };
+                    let _ = writeln!(out, "[drives.{}]", key(&uuid));
+                    render_fields(&mut out, section.fields, &mut drive);
+                    out.push('\n');
+                }
+            }
+            _ => {
+                let mut values = values;
+                let _ = writeln!(out, "[{}]", section.name);
+                if let (true, Some(example)) = (values.is_empty(), section.example) {
+                    let _ = writeln!(out, "# {}", example);
+                }
+                render_fields(&mut out, section.fields, &mut values);
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+/// Write `fields` in order, taking their values out of `values`. Whatever is
+/// left in `values` afterwards (e.g. folder_names entries) is written as-is.
+fn render_fields(out: &mut String, fields: &[Field], values: &mut toml::Table) {
+    for field in fields {
+        let line = match (values.remove(field.key), field.example) {
+            (Some(value), _) => format!("{} = {}", field.key, value),
+            (None, Some(example)) => format!("# {} = {}", field.key, example),
+            // Nothing to show, e.g. keys fo fills in itself
+            (None, None) => continue,
+        };
+        comment(out, &format!("{} ({})", field.doc, field.kind));
+        let _ = writeln!(out, "{}", line);
+    }
+    for (name, value) in std::mem::take(values) {
+        let _ = writeln!(out, "{} = {}", key(&name), value);
+    }
+}
+
+fn comment(out: &mut String, text: &str) {
+    let _ = writeln!(out, "# {}", text);
+}
+
+/// A bare key where TOML allows it, quoted otherwise
+fn key(name: &str) -> String {
+    if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        name.to_string()
+    } else {
+        toml::Value::String(name.to_string()).to_string()
+    }
+}
+
+/// Every key with its type and meaning, for `fo schema`
+pub fn describe() -> String {
+    let mut out = String::new();
+    let list = |out: &mut String, fields: &[Field]| {
+        let width = fields.iter().map(|f| f.key.len()).max().unwrap_or(0);
+        for field in fields {
+            let _ = writeln!(out, "  {:width$}  {}\n  {:width$}  {}", field.key, field.kind, "", field.doc, width = width);
+        }
+    };
+
+    let _ = writeln!(out, "(top level)");
+    list(&mut out, TOP_LEVEL);
+    for section in SECTIONS {
+        let name = match section.name {
+            "drives" => "drives.<uuid>",
+            "folder_names" => "folder_names  (<category> = string)",
+            name => name,
+        };
+        let _ = writeln!(out, "\n[{}]\n  {}", name, section.doc);
+        list(&mut out, section.fields);
+    }
+    out
+}
+
+/// A JSON Schema (draft 2020-12) of `config.toml`, built from the same
+/// tables as `fo schema`, for `fo schema --json`. Editors with TOML support
+/// (Taplo, Even Better TOML) can check a config against it.
+pub fn json_schema() -> serde_json::Value {
+    let properties = |fields: &[Field]| -> serde_json::Map<String, serde_json::Value> {
+        fields
+            .iter()
+            .map(|field| {
+                let mut schema = json_type(field.kind);
+                schema["description"] = field.doc.into();
+                (field.key.to_string(), schema)
+            })
+            .collect()
+    };
+
+    let mut root = properties(TOP_LEVEL);
+    for section in SECTIONS {
+        let table = serde_json::json!({ "type": "object", "properties": properties(section.fields) });
+        let mut schema = match section.name {
+            "drives" => serde_json::json!({ "type": "object", "additionalProperties": table }),
+            "folder_names" => serde_json::json!({ "type": "object", "additionalProperties": { "type": "string" } }),
+            _ => table,
+        };
+        schema["description"] = section.doc.into();
+        root.insert(section.name.to_string(), schema);
+    }
+
+    serde_json::json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "File Orchestrator config.toml",
+        "type": "object",
+        "properties": root,
+    })
+}
+
+/// JSON Schema for one of the `kind` strings above
+fn json_type(kind: &str) -> serde_json::Value {
+    if let Some(items) = kind.strip_prefix("array of ") {
+        let items = match items {
+            "strings" | "paths" => "string",
+            items => items,
+        };
+        return serde_json::json!({ "type": "array", "items": json_type(items) });
+    }
+    if kind.starts_with('"') {
+        let values: Vec<&str> = kind.split(" | ").map(|value| value.trim_matches('"')).collect();
+        return serde_json::json!({ "enum": values });
+    }
+    match kind {
+        "boolean" => serde_json::json!({ "type": "boolean" }),
+        "integer" => serde_json::json!({ "type": "integer", "minimum": 0 }),
+        "string" | "path" => serde_json::json!({ "type": "string" }),
+        "integer or size string" => serde_json::json!({ "type": ["integer", "string"] }),
+        // "table { ... }", "table of ...", "{ pattern, category }"
+        _ => serde_json::json!({ "type": "object" }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commented_config_round_trips() {
+        let mut config = Config::default_config();
+        config.folder_names.insert("images".to_string(), "Photos".to_string());
+        config.sync.hash_workers = 2;
+
+        let text = commented(&config).unwrap();
+        assert!(text.contains("# What to do when a different file already exists"));
+        assert!(text.contains("# metrics_addr = \"127.0.0.1:9898\""));
+
+        // Same TOML as the plain writer, just with comments
+        let parsed: toml::Table = toml::from_str(&text).unwrap();
+        assert_eq!(parsed, toml::Table::try_from(&config).unwrap());
+    }
+
+    #[test]
+    fn test_json_schema_types() {
+        let schema = json_schema();
+        let sync = &schema["properties"]["sync"]["properties"];
+        assert_eq!(sync["hash_algorithm"]["enum"], serde_json::json!(["blake3", "sha256", "md5"]));
+        assert_eq!(sync["replicate"]["items"]["type"], "string");
+        assert_eq!(sync["max_file_size"]["type"], serde_json::json!(["integer", "string"]));
+        assert_eq!(schema["properties"]["notifications"]["properties"]["on_events"]["items"]["enum"][2], "drive-connected");
+
+        let drive = &schema["properties"]["drives"]["additionalProperties"]["properties"];
+        assert_eq!(drive["label"]["type"], "string");
+        assert!(drive["label"]["description"].is_string());
+    }
+
+    #[test]
+    fn test_every_written_key_is_documented() {
+        let table = toml::Table::try_from(Config::default_config()).unwrap();
+        for (name, value) in &table {
+            let Some(section) = SECTIONS.iter().find(|s| s.name == name) else {
+                assert!(TOP_LEVEL.iter().any(|f| f.key == name), "undocumented top-level key {}", name);
+                continue;
+            };
+            let toml::Value::Table(values) = value else { continue };
+            let keys: Vec<_> = match section.name {
+                "drives" => values.values().filter_map(|d| d.as_table()).flat_map(|d| d.keys()).collect(),
+                _ => values.keys().collect(),
+            };
+            for key in keys {
+                assert!(section.fields.iter().any(|f| f.key == key), "undocumented key {}.{}", name, key);
+            }
+        }
+    }
+}
@@ -1,9 +1,21 @@
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use md5::Md5;
 use sled::Db;
+use std::collections::HashMap;
+use std::fs;
 use std::path::{Path, PathBuf};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use crate::clock::{Clock, SystemClock};
+use crate::config::ConflictPolicy;
 use crate::error::{OrchestratorError, Result};
+use crate::sanitize::NameNormalization;
+use tracing::warn;
 
+/// What was recorded when a source file was synced: where it went and the
+/// hash it had
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileState {
     pub source_path: PathBuf,
@@ -13,6 +25,167 @@ pub struct FileState {
     pub target_drive: String,
     pub target_path: PathBuf,
     pub file_category: String,
+    /// Size on the target when stored zstd-compressed (`target_path` ends in `.zst`)
+    #[serde(default)]
+    pub compressed_size: Option<u64>,
+    /// Whether the file was pushed to the drive or pulled from it into source
+    #[serde(default)]
+    pub direction: SyncDirection,
+    /// The target shares the source's blocks (copy-on-write clone) rather
+    /// than being a full copy
+    #[serde(default)]
+    pub reflinked: bool,
+    /// The copy was made hole-aware, so the source's sparse ranges stay
+    /// unallocated on the target
+    #[serde(default)]
+    pub sparse: bool,
+    /// Algorithm `hash` was computed with; records from before this was
+    /// configurable are BLAKE3
+    #[serde(default)]
+    pub hash_algorithm: HashAlgorithm,
+    /// The target is a link to the source rather than a copy (the
+    /// `same_device_strategy` for a drive on the source's device)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link: Option<LinkKind>,
+    /// The snapshot the copy is in, for drives with `snapshots`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snapshot: Option<String>,
+    /// Labels attached with `fo tag`, e.g. "favorite"; kept when the file
+    /// is synced again
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// A note attached with `fo tag --note`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// Copies on further drives, for categories in `sync.replicate`. Each
+    /// is a record of its own (with no replicas); the copy above is
+    /// whichever drive was copied to first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub replicas: Vec<FileState>,
+}
+
+/// How a target links to its source
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkKind {
+    Hardlink,
+    Symlink,
+}
+
+/// Content hash used to detect changes and verify copies
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    #[default]
+    Blake3,
+    Sha256,
+    Md5,
+}
+
+impl HashAlgorithm {
+    fn hasher(self) -> Hasher {
+        match self {
+            HashAlgorithm::Blake3 => Hasher::Blake3(Box::default()),
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            HashAlgorithm::Md5 => Hasher::Md5(Md5::new()),
+        }
+    }
+}
+
+/// Incremental hasher for any [`HashAlgorithm`]
+enum Hasher {
+    Blake3(Box<blake3::Hasher>),
+    Sha256(Sha256),
+    Md5(Md5),
+}
+
+impl Hasher {
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Blake3(hasher) => {
+                hasher.update(data);
+            }
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Md5(hasher) => hasher.update(data),
+        }
+    }
+
+    /// Lowercase hex digest
+    fn finalize(self) -> String {
+        match self {
+            Hasher::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+            Hasher::Sha256(hasher) => format!("{:x}", hasher.finalize()),
+            Hasher::Md5(hasher) => format!("{:x}", hasher.finalize()),
+        }
+    }
+}
+
+impl std::io::Write for Hasher {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncDirection {
+    #[default]
+    Push,
+    Pull,
+}
+
+impl FileState {
+    pub fn is_compressed(&self) -> bool {
+        self.compressed_size.is_some()
+    }
+
+    /// Every copy of the file, each as a record of its own
+    pub fn copies(&self) -> Vec<FileState> {
+        let mut copies = vec![FileState { replicas: Vec::new(), ..self.clone() }];
+        copies.extend(self.replicas.iter().cloned());
+        copies
+    }
+
+    /// The copy on `drive`, if there is one
+    pub fn copy_on(&self, drive: &str) -> Option<FileState> {
+        self.copies().into_iter().find(|copy| copy.target_drive == drive)
+    }
+
+    /// This record with `copy` added, replacing any copy on the same drive
+    pub fn with_copy(mut self, copy: FileState) -> FileState {
+        if copy.target_drive == self.target_drive {
+            return FileState { replicas: self.replicas, tags: self.tags, note: self.note, ..copy };
+        }
+        self.replicas.retain(|replica| replica.target_drive != copy.target_drive);
+        self.replicas.push(FileState { replicas: Vec::new(), ..copy });
+        self
+    }
+
+    /// This record without its copy on `drive`, or `None` if that was the
+    /// only one. Tags and notes stay with whichever copy becomes the first.
+    pub fn without_copy(self, drive: &str) -> Option<FileState> {
+        if self.target_drive != drive {
+            let replicas = self.replicas.iter().filter(|replica| replica.target_drive != drive).cloned().collect();
+            return Some(FileState { replicas, ..self });
+        }
+        let mut replicas = self.replicas.into_iter();
+        let first = replicas.next()?;
+        Some(FileState { replicas: replicas.collect(), tags: self.tags, note: self.note, ..first })
+    }
+
+    /// Hash of the most recently synced copy, which the others should match
+    pub fn latest_hash(&self) -> &str {
+        self.replicas
+            .iter()
+            .chain(std::iter::once(self))
+            .max_by_key(|copy| copy.last_synced)
+            .map_or(&self.hash, |copy| &copy.hash)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -23,19 +196,523 @@ pub struct PendingSync {
     pub hash: String,
     pub size: u64,
     pub created_at: u64,
+    /// Waiting for one drive of a replicated category. Such entries are
+    /// queued per drive, so a file can wait for several at once.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub replica: bool,
+}
+
+/// An unfinished copy into `<target>.partial`, kept so an interrupted copy
+/// of a large file can resume instead of starting over
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartialCopy {
+    pub source_path: PathBuf,
+    pub target_path: PathBuf,
+    pub expected_size: u64,
+    pub source_hash: String,
+}
+
+/// A copy written under a drive's staging folder with `sync.staging`,
+/// waiting for `fo commit` to move it to `state.target_path` and record it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StagedFile {
+    pub staged_path: PathBuf,
+    /// The record saved once the copy is committed
+    pub state: FileState,
+    pub staged_at: u64,
+    /// One copy of a replicated category; those are staged per drive
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub replica: bool,
+    /// How a file already at `state.target_path` was dealt with when the
+    /// copy was staged; with `overwrite`, the commit replaces it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conflict: Option<ConflictPolicy>,
+}
+
+/// BLAKE3 hashes of a plain copy's `chunk_size` pieces, kept with
+/// `sync.chunk_hash` so a source that only grew can keep the copy's
+/// unchanged pieces
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkHashes {
+    pub target_path: PathBuf,
+    /// Whole-file hash and size of the content these pieces are from
+    pub hash: String,
+    pub size: u64,
+    pub chunk_size: u64,
+    /// One per full piece from the start; a shorter last piece isn't kept
+    pub chunks: Vec<String>,
+}
+
+/// One completed sync, kept after the file's current state moves on so
+/// intake over time can be reported
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub source_path: PathBuf,
+    pub file_category: String,
+    pub target_drive: String,
+    pub size: u64,
+    pub synced_at: u64,
+    /// The drive snapshot the copy went into, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snapshot: Option<String>,
+}
+
+/// The copies one full sync or pending-queue run made, kept so the run can
+/// be undone
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncBatch {
+    pub id: u64,
+    /// What ran: `sync-all`, `process-pending`, `commit` or `discard`
+    pub kind: String,
+    pub started_at: u64,
+    pub entries: Vec<BatchEntry>,
+}
+
+/// A record a batch wrote, and the one it replaced
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchEntry {
+    pub previous: Option<FileState>,
+    pub written: FileState,
+    /// `written` is one copy of a replicated file, and `previous` the copy
+    /// on the same drive before it, rather than whole records
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub replica: bool,
+    /// Set when the batch discarded this staged copy instead of writing
+    /// `written`; undoing it stages the source again
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub discarded: Option<StagedFile>,
+}
+
+/// The outcome of one sync run, for `fo last-run` and `fo runs`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub id: u64,
+    /// The `fo` command that ran it (`sync-once`, `run`, ...), if known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// What it synced: `sync-all`, `sync-part`, `sync-since` or `catch-up`
+    pub kind: String,
+    pub started_at: u64,
+    pub finished_at: u64,
+    pub synced: usize,
+    pub pending: usize,
+    pub already_synced: usize,
+    pub skipped: usize,
+    pub quarantined: usize,
+    pub conflicts: usize,
+    pub failed: usize,
+    pub unsettled: usize,
+    /// The first [`RUN_FAILURES_KEPT`] failed files, with the error
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failures: Vec<(PathBuf, String)>,
+}
+
+/// Failed files kept in a [`RunRecord`]; `failed` counts them all
+pub const RUN_FAILURES_KEPT: usize = 100;
+
+/// Runs kept in the database; the oldest go as new ones are recorded
+const RUNS_KEPT: usize = 1000;
+
+/// A file of unknown type that was put in the quarantine directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuarantinedFile {
+    pub source_path: PathBuf,
+    pub quarantine_path: PathBuf,
+    pub size: u64,
+    pub quarantined_at: u64,
 }
 
+const LAST_RUN_KEY: &[u8] = b"meta:last_run";
+/// Last file a `sync-once --limit` run got to
+const SYNC_CURSOR_KEY: &[u8] = b"meta:sync_cursor";
+/// The snapshot that run was copying into
+const SYNC_CURSOR_SNAPSHOT_KEY: &[u8] = b"meta:sync_cursor_snapshot";
+/// The [`NameNormalization`] the `file:` and `pending:` keys were made with
+const NAME_NORMALIZATION_KEY: &[u8] = b"meta:name_normalization";
+/// Prefix of each drive's running total of queued bytes
+const QUEUED_SIZE_PREFIX: &[u8] = b"queuedsize:";
+
+/// Clones share the same open database and flush batch.
+///
+/// By default every write is flushed to disk before it returns. With a
+/// flush interval (see [`set_flush_interval`](Self::set_flush_interval))
+/// sync records, chunk hashes, folder listings and drive statistics are
+/// only flushed once the interval has passed since the last flush, or on
+/// an explicit [`flush`](Self::flush); a crash then loses at most those
+/// writes since the last flush. A lost sync record costs a re-hash: the
+/// file is found identical on the drive and recorded again. Queue entries,
+/// quarantine, partial-copy and staged records and undo batches can't be
+/// rebuilt that way, so they are always flushed at once. `fo run` skips its
+/// periodic flush while paused, and so flushes when it pauses.
+#[derive(Clone)]
 pub struct StateManager {
     db: Db,
+    batch: Arc<FlushBatch>,
+    /// Applied to source paths before they become `file:`/`pending:` keys
+    names: Arc<RwLock<NameNormalization>>,
+    /// Times records and speed samples; shared by all clones
+    clock: Arc<RwLock<Arc<dyn Clock>>>,
+}
+
+/// The last handle to go writes out what is left of the batch
+impl Drop for StateManager {
+    fn drop(&mut self) {
+        if Arc::strong_count(&self.batch) == 1 && self.batch.unflushed.load(Ordering::Acquire) > 0 {
+            if let Err(e) = self.db.flush() {
+                warn!("Failed to flush sync state on close: {}", e);
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct FlushBatch {
+    /// 0 flushes every write
+    interval_ms: AtomicU64,
+    unflushed: AtomicUsize,
+    last_flush: Mutex<Option<Instant>>,
 }
 
 impl StateManager {
-    /// Create a new state manager
+    /// Create a new state manager, first finishing a `fo compact` that was
+    /// interrupted (see [`Self::compact`])
     pub fn new<P: AsRef<Path>>(db_path: P) -> Result<Self> {
-        let db = sled::open(db_path)
-            .map_err(|e| OrchestratorError::State(format!("Failed to open database: {}", e)))?;
+        let db_path = db_path.as_ref();
+        recover_compaction(db_path)?;
+        let db = sled::open(db_path).map_err(|e| open_error(db_path, e))?;
         
-        Ok(Self { db })
+        Ok(Self { db, batch: Arc::default(), names: Arc::default(), clock: system_clock() })
+    }
+
+    /// A throwaway in-memory database, for commands that only need to
+    /// classify files and must not touch (or wait for) the real one
+    pub fn temporary() -> Result<Self> {
+        let db = sled::Config::new().temporary(true).open()?;
+        Ok(Self { db, batch: Arc::default(), names: Arc::default(), clock: system_clock() })
+    }
+
+    /// Take the time from `clock` instead of the system clock, in this
+    /// handle and every clone of it
+    pub fn set_clock(&self, clock: Arc<dyn Clock>) {
+        *self.clock.write().unwrap() = clock;
+    }
+
+    /// The clock records are timed by
+    pub fn clock(&self) -> Arc<dyn Clock> {
+        self.clock.read().unwrap().clone()
+    }
+
+    /// Flush writes in batches at most `interval` apart instead of one by
+    /// one; `Duration::ZERO` goes back to flushing every write
+    pub fn set_flush_interval(&self, interval: Duration) {
+        self.batch.interval_ms.store(interval.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// Normalize source paths this way in `file:` and `pending:` keys. If
+    /// the database was keyed differently, its keys are rewritten to match;
+    /// returns how many moved.
+    pub fn set_name_normalization(&self, names: NameNormalization) -> Result<usize> {
+        *self.names.write().unwrap() = names;
+
+        let recorded: NameNormalization = match self.db.get(NAME_NORMALIZATION_KEY)? {
+            Some(value) => serde_json::from_slice(&value)?,
+            None => NameNormalization::default(),
+        };
+        if recorded == names {
+            return Ok(0);
+        }
+
+        let mut moved = 0;
+        for prefix in ["file:", "pending:"] {
+            for item in self.db.scan_prefix(prefix.as_bytes()) {
+                let (key, value) = item?;
+                let source_path = if prefix == "file:" {
+                    serde_json::from_slice::<FileState>(&value).map(|state| state.source_path)
+                } else {
+                    serde_json::from_slice::<PendingSync>(&value).map(|pending| pending.source_path)
+                };
+                // Unreadable entries are for `fo repair` to deal with
+                let Ok(source_path) = source_path else {
+                    continue;
+                };
+                let new_key = if prefix == "file:" {
+                    self.file_key(&source_path)
+                } else {
+                    let pending: PendingSync = serde_json::from_slice(&value)?;
+                    self.queue_key(&pending)
+                };
+                if new_key == key.as_ref() {
+                    continue;
+                }
+
+                // Two spellings of one name: the later sync wins
+                let keep = match self.db.get(&new_key)? {
+                    Some(existing) if prefix == "file:" => {
+                        let existing: Option<FileState> = serde_json::from_slice(&existing).ok();
+                        let candidate: FileState = serde_json::from_slice(&value)?;
+                        existing.is_none_or(|existing| candidate.last_synced > existing.last_synced)
+                    }
+                    Some(_) => false,
+                    None => true,
+                };
+                if keep {
+                    self.db.insert(new_key, value)?;
+                }
+                self.db.remove(key)?;
+                moved += 1;
+            }
+        }
+
+        if moved > 0 {
+            self.forget_queued_sizes()?;
+        }
+        self.db.insert(NAME_NORMALIZATION_KEY, serde_json::to_vec(&names)?)?;
+        self.db.flush()?;
+        Ok(moved)
+    }
+
+    /// Write everything not yet on disk. Returns how many writes that covered.
+    pub fn flush(&self) -> Result<usize> {
+        let unflushed = self.batch.unflushed.swap(0, Ordering::AcqRel);
+        if unflushed > 0 {
+            self.db.flush()?;
+        }
+        *self.batch.last_flush.lock().unwrap() = Some(Instant::now());
+        Ok(unflushed)
+    }
+
+    /// [`flush`](Self::flush) on a blocking thread
+    pub async fn flush_async(&self) -> Result<usize> {
+        self.blocking(|state| state.flush()).await
+    }
+
+    /// Make a write durable now, whatever the flush interval. For the
+    /// queue, quarantine, partial, staged and batch records, which nothing
+    /// rebuilds if a crash loses them.
+    fn written_now(&self) -> Result<()> {
+        self.db.flush()?;
+        Ok(())
+    }
+
+    /// Make a write durable now, or as part of the current batch
+    fn written(&self) -> Result<()> {
+        let interval = self.batch.interval_ms.load(Ordering::Relaxed);
+        if interval == 0 {
+            self.db.flush()?;
+            return Ok(());
+        }
+
+        self.batch.unflushed.fetch_add(1, Ordering::AcqRel);
+        let due = self.batch.last_flush
+            .lock()
+            .unwrap()
+            .is_none_or(|last| last.elapsed() >= Duration::from_millis(interval));
+        if due {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Run a state call on tokio's blocking pool so a slow disk doesn't
+    /// stall the async runtime
+    async fn blocking<T, F>(&self, call: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&StateManager) -> Result<T> + Send + 'static,
+    {
+        let state = self.clone();
+        tokio::task::spawn_blocking(move || call(&state))
+            .await
+            .map_err(|e| OrchestratorError::State(format!("State task failed: {}", e)))?
+    }
+
+    /// [`get_file_state`](Self::get_file_state) on a blocking thread
+    pub async fn get_file_state_async(&self, source_path: &Path) -> Result<Option<FileState>> {
+        let source_path = source_path.to_path_buf();
+        self.blocking(move |state| state.get_file_state(&source_path)).await
+    }
+
+    /// [`save_file_state`](Self::save_file_state) on a blocking thread
+    pub async fn save_file_state_async(&self, file_state: FileState) -> Result<()> {
+        self.blocking(move |state| state.save_file_state(&file_state)).await
+    }
+
+    /// [`add_pending_sync`](Self::add_pending_sync) on a blocking thread
+    pub async fn add_pending_sync_async(&self, pending: PendingSync) -> Result<()> {
+        self.blocking(move |state| state.add_pending_sync(&pending)).await
+    }
+
+    /// [`record_history`](Self::record_history) on a blocking thread
+    pub async fn record_history_async(&self, file_state: FileState) -> Result<()> {
+        self.blocking(move |state| state.record_history(&file_state)).await
+    }
+
+    /// Open a database for `fo repair`. A database that opens has entries
+    /// that no longer decode removed; one that is corrupt is moved aside to
+    /// `<db>.corrupt-<timestamp>` and replaced with an empty one, to be
+    /// refilled by rescanning the drives.
+    pub fn repair<P: AsRef<Path>>(db_path: P) -> Result<(Self, RepairOutcome)> {
+        let db_path = db_path.as_ref();
+
+        match Self::new(db_path) {
+            Ok(state) => {
+                let removed = state.remove_undecodable()?;
+                Ok((state, RepairOutcome::Cleaned { removed }))
+            }
+            Err(OrchestratorError::DatabaseCorrupt(reason)) => {
+                warn!("Database is corrupt ({}), rebuilding", reason);
+
+                let mut name = db_path.file_name().unwrap_or_default().to_os_string();
+                name.push(format!(".corrupt-{}", current_timestamp()));
+                let backup = db_path.with_file_name(name);
+                fs::rename(db_path, &backup)?;
+
+                let state = Self::new(db_path)?;
+                Ok((state, RepairOutcome::Rebuilt { backup }))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Shrink the database at `db_path` for `fo compact`. sled reuses the
+    /// space of removed records but never gives it back, so the live
+    /// records are copied into a fresh database next to it, checked, and
+    /// swapped in: the old one is renamed to `<db>.pre-compact`, the new one
+    /// into its place, and only then is the old one deleted. If that is
+    /// interrupted, `<db>.pre-compact` is the complete old database, and
+    /// the next open or compact puts it back or deletes it.
+    pub fn compact<P: AsRef<Path>>(db_path: P) -> Result<CompactOutcome> {
+        let db_path = db_path.as_ref();
+        let fresh_path = sibling(db_path, ".compacting");
+        let old_path = sibling(db_path, ".pre-compact");
+        recover_compaction(db_path)?;
+
+        let old = sled::open(db_path).map_err(|e| open_error(db_path, e))?;
+        old.flush()?;
+        let before = disk_usage(db_path)?;
+
+        if fresh_path.exists() {
+            fs::remove_dir_all(&fresh_path)?;
+        }
+        let fresh = sled::open(&fresh_path)?;
+        fresh.import(old.export());
+        fresh.flush()?;
+        if fresh.checksum()? != old.checksum()? {
+            drop(fresh);
+            fs::remove_dir_all(&fresh_path)?;
+            return Err(OrchestratorError::State("The compacted copy doesn't match the database; left it as it was".to_string()));
+        }
+        let entries = fresh.len();
+        drop(fresh);
+        drop(old);
+
+        fs::rename(db_path, &old_path)?;
+        fs::rename(&fresh_path, db_path)?;
+        fs::remove_dir_all(&old_path)?;
+
+        Ok(CompactOutcome { before, after: disk_usage(db_path)?, entries })
+    }
+
+    /// Drop entries that fail to deserialize, returning how many
+    fn remove_undecodable(&self) -> Result<usize> {
+        let mut bad_keys = Vec::new();
+
+        for item in self.db.iter() {
+            let (key, value) = item?;
+            let decodes = if key.starts_with(b"file:") {
+                serde_json::from_slice::<FileState>(&value).is_ok()
+            } else if key.starts_with(b"pending:") {
+                serde_json::from_slice::<PendingSync>(&value).is_ok()
+            } else if key.starts_with(b"partial:") {
+                serde_json::from_slice::<PartialCopy>(&value).is_ok()
+            } else if key.starts_with(b"staged:") {
+                serde_json::from_slice::<StagedFile>(&value).is_ok()
+            } else if key.starts_with(b"chunks:") {
+                serde_json::from_slice::<ChunkHashes>(&value).is_ok()
+            } else if key.starts_with(b"quarantine:") {
+                serde_json::from_slice::<QuarantinedFile>(&value).is_ok()
+            } else if key.starts_with(b"history:") {
+                serde_json::from_slice::<HistoryEntry>(&value).is_ok()
+            } else if key.starts_with(b"batch:") {
+                serde_json::from_slice::<SyncBatch>(&value).is_ok()
+            } else if key.starts_with(b"drivestat:") {
+                serde_json::from_slice::<DriveSpeed>(&value).is_ok()
+            } else if key.starts_with(b"dirlist:") {
+                serde_json::from_slice::<DirListing>(&value).is_ok()
+            } else if key.starts_with(b"runs:") {
+                serde_json::from_slice::<RunRecord>(&value).is_ok()
+            } else if key.starts_with(b"deferred:") {
+                serde_json::from_slice::<PathBuf>(&value).is_ok()
+            } else {
+                true
+            };
+
+            if !decodes {
+                warn!("Removing unreadable entry {}", String::from_utf8_lossy(&key));
+                bad_keys.push(key);
+            }
+        }
+
+        for key in &bad_keys {
+            self.db.remove(key)?;
+        }
+        if !bad_keys.is_empty() {
+            self.forget_queued_sizes()?;
+        }
+        self.db.flush()?;
+
+        Ok(bad_keys.len())
+    }
+
+    /// When `fo run` was last known to be watching, in seconds since the
+    /// Unix epoch
+    pub fn get_last_run(&self) -> Result<Option<u64>> {
+        match self.db.get(LAST_RUN_KEY)? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Record that `fo run` was watching at `timestamp`
+    pub fn set_last_run(&self, timestamp: u64) -> Result<()> {
+        self.db.insert(LAST_RUN_KEY, serde_json::to_vec(&timestamp)?)?;
+        self.written()?;
+        Ok(())
+    }
+
+    /// Where a limited full sync stopped, for `sync-once --resume`
+    pub fn get_sync_cursor(&self) -> Result<Option<PathBuf>> {
+        match self.db.get(SYNC_CURSOR_KEY)? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Record where a limited full sync stopped; `None` once it has finished
+    pub fn set_sync_cursor(&self, cursor: Option<&Path>) -> Result<()> {
+        match cursor {
+            Some(path) => self.db.insert(SYNC_CURSOR_KEY, serde_json::to_vec(path)?)?,
+            None => self.db.remove(SYNC_CURSOR_KEY)?,
+        };
+        self.written()?;
+        Ok(())
+    }
+
+    /// The snapshot a limited full sync was copying into when it stopped
+    pub fn get_sync_cursor_snapshot(&self) -> Result<Option<String>> {
+        match self.db.get(SYNC_CURSOR_SNAPSHOT_KEY)? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_sync_cursor_snapshot(&self, snapshot: Option<&str>) -> Result<()> {
+        match snapshot {
+            Some(id) => self.db.insert(SYNC_CURSOR_SNAPSHOT_KEY, serde_json::to_vec(id)?)?,
+            None => self.db.remove(SYNC_CURSOR_SNAPSHOT_KEY)?,
+        };
+        self.written()?;
+        Ok(())
     }
 
     /// Save file state after successful sync
@@ -44,7 +721,7 @@ impl StateManager {
         let value = serde_json::to_vec(state)?;
         
         self.db.insert(key, value)?;
-        self.db.flush()?;
+        self.written()?;
         
         Ok(())
     }
@@ -61,30 +738,72 @@ impl StateManager {
         Ok(None)
     }
 
-    /// Check if file has been synced (and hasn't changed)
-    #[allow(dead_code)]
-    pub fn is_file_synced(&self, source_path: &Path, current_hash: &str) -> Result<bool> {
+    /// Replace a synced file's tags. Returns false if the file has no
+    /// sync record to attach them to.
+    pub fn set_tags(&self, source_path: &Path, tags: Vec<String>) -> Result<bool> {
+        self.annotate(source_path, |record| record.tags = tags)
+    }
+
+    /// Add one tag to a synced file, unless it has it already
+    pub fn add_tag(&self, source_path: &Path, tag: &str) -> Result<bool> {
+        self.annotate(source_path, |record| {
+            if !record.tags.iter().any(|existing| existing == tag) {
+                record.tags.push(tag.to_string());
+            }
+        })
+    }
+
+    /// Set or clear a synced file's note
+    pub fn set_note(&self, source_path: &Path, note: Option<String>) -> Result<bool> {
+        self.annotate(source_path, |record| record.note = note)
+    }
+
+    fn annotate(&self, source_path: &Path, change: impl FnOnce(&mut FileState)) -> Result<bool> {
+        let Some(mut record) = self.get_file_state(source_path)? else {
+            return Ok(false);
+        };
+        change(&mut record);
+        self.save_file_state(&record)?;
+        Ok(true)
+    }
+
+    /// Every synced file carrying `tag`
+    pub fn get_by_tag(&self, tag: &str) -> Result<Vec<FileState>> {
+        Ok(self
+            .get_all_file_states()?
+            .into_iter()
+            .filter(|record| record.tags.iter().any(|existing| existing == tag))
+            .collect())
+    }
+
+    /// Check if file has been synced (and hasn't changed), hashing it with
+    /// whichever algorithm its record was made with. A file whose size
+    /// differs from the record's has changed, so it isn't read.
+    pub fn is_file_synced(&self, source_path: &Path) -> Result<bool> {
         if let Some(state) = self.get_file_state(source_path)? {
-            return Ok(state.hash == current_hash);
+            if fs::metadata(source_path)?.len() != state.size {
+                return Ok(false);
+            }
+            return Ok(calculate_file_hash(source_path, state.hash_algorithm)? == state.hash);
         }
         Ok(false)
     }
 
     /// Add a file to pending sync queue
     pub fn add_pending_sync(&self, pending: &PendingSync) -> Result<()> {
-        let key = self.pending_key(&pending.source_path);
+        let key = self.queue_key(pending);
         let value = serde_json::to_vec(pending)?;
         
-        self.db.insert(key, value)?;
-        self.db.flush()?;
+        let replaced = self.db.insert(key, value)?;
+        self.track_queued(replaced, Some(pending))?;
+        self.written_now()?;
         
         Ok(())
     }
 
     /// Remove all pending syncs for a specific drive
-    #[allow(dead_code)]
     pub fn cleanup_drive_data(&self, drive_uuid: &str) -> Result<()> {
-        let prefix = format!("pending:");
+        let prefix = "pending:";
         let mut keys_to_remove = Vec::new();
 
         for item in self.db.scan_prefix(prefix.as_bytes()) {
@@ -100,6 +819,7 @@ impl StateManager {
         for key in keys_to_remove {
             self.db.remove(key)?;
         }
+        self.db.remove(queued_size_key(drive_uuid))?;
         
         self.db.flush()?;
         Ok(())
@@ -107,7 +827,7 @@ impl StateManager {
 
     /// Get all pending syncs for a specific drive
     pub fn get_pending_syncs(&self, drive_uuid: &str) -> Result<Vec<PendingSync>> {
-        let prefix = format!("pending:");
+        let prefix = "pending:";
         let mut pending_syncs = Vec::new();
 
         for item in self.db.scan_prefix(prefix.as_bytes()) {
@@ -122,17 +842,97 @@ impl StateManager {
         Ok(pending_syncs)
     }
 
+    /// The queue entry for a file, if it is waiting for a drive
+    pub fn get_pending_sync(&self, source_path: &Path) -> Result<Option<PendingSync>> {
+        match self.db.get(self.pending_key(source_path))? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
     /// Remove a file from pending sync queue
     pub fn remove_pending_sync(&self, source_path: &Path) -> Result<()> {
         let key = self.pending_key(source_path);
-        self.db.remove(key)?;
-        self.db.flush()?;
+        let removed = self.db.remove(key)?;
+        self.track_queued(removed, None)?;
+        self.written_now()?;
+        Ok(())
+    }
+
+    /// Remove the queue entry `pending` was stored under
+    pub fn remove_queued(&self, pending: &PendingSync) -> Result<()> {
+        let removed = self.db.remove(self.queue_key(pending))?;
+        self.track_queued(removed, None)?;
+        self.written_now()?;
+        Ok(())
+    }
+
+    /// Remove a replicated file's entry for one drive
+    pub fn remove_replica_pending(&self, source_path: &Path, drive_uuid: &str) -> Result<()> {
+        let removed = self.db.remove(self.replica_key(source_path, drive_uuid))?;
+        self.track_queued(removed, None)?;
+        self.written_now()?;
+        Ok(())
+    }
+
+    /// The entry stored under the key `pending` would be queued under
+    pub fn get_queued(&self, pending: &PendingSync) -> Result<Option<PendingSync>> {
+        match self.db.get(self.queue_key(pending))? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Total size of the files queued for a drive. The total is kept up to
+    /// date as entries come and go, and counted from the queue the first
+    /// time it is asked for.
+    pub fn get_queued_size(&self, drive_uuid: &str) -> Result<u64> {
+        let key = queued_size_key(drive_uuid);
+        if let Some(value) = self.db.get(&key)? {
+            return Ok(serde_json::from_slice(&value)?);
+        }
+        let total: u64 = self.get_pending_syncs(drive_uuid)?.iter().map(|pending| pending.size).sum();
+        self.db.insert(key, serde_json::to_vec(&total)?)?;
+        Ok(total)
+    }
+
+    /// Move the queued totals for an entry that was replaced or removed and
+    /// one that was added. Totals not counted yet are left to be counted.
+    fn track_queued(&self, previous: Option<sled::IVec>, added: Option<&PendingSync>) -> Result<()> {
+        if let Some(previous) = previous.and_then(|value| serde_json::from_slice::<PendingSync>(&value).ok()) {
+            self.adjust_queued_size(&previous.target_drive, |total| total.saturating_sub(previous.size))?;
+        }
+        if let Some(added) = added {
+            self.adjust_queued_size(&added.target_drive, |total| total + added.size)?;
+        }
+        Ok(())
+    }
+
+    fn adjust_queued_size(&self, drive_uuid: &str, adjust: impl Fn(u64) -> u64) -> Result<()> {
+        self.db.update_and_fetch(queued_size_key(drive_uuid), |value| {
+            // An unreadable total is dropped and counted afresh
+            let total = serde_json::from_slice::<u64>(value?).ok()?;
+            serde_json::to_vec(&adjust(total)).ok()
+        })?;
         Ok(())
     }
 
+    /// Drop every queued total, for when entries moved or went in bulk
+    fn forget_queued_sizes(&self) -> Result<()> {
+        for item in self.db.scan_prefix(QUEUED_SIZE_PREFIX) {
+            self.db.remove(item?.0)?;
+        }
+        Ok(())
+    }
+
+    /// Whether any file is queued for any drive
+    pub fn has_pending_syncs(&self) -> Result<bool> {
+        Ok(self.db.scan_prefix(b"pending:").next().transpose()?.is_some())
+    }
+
     /// Get all pending syncs (for all drives)
     pub fn get_all_pending_syncs(&self) -> Result<Vec<PendingSync>> {
-        let prefix = format!("pending:");
+        let prefix = "pending:";
         let mut pending_syncs = Vec::new();
 
         for item in self.db.scan_prefix(prefix.as_bytes()) {
@@ -144,71 +944,651 @@ impl StateManager {
         Ok(pending_syncs)
     }
 
-    /// Get statistics about synced files
-    pub fn get_sync_stats(&self) -> Result<SyncStats> {
-        let mut stats = SyncStats::default();
-        let prefix = "file:";
+    /// Record that a copy into a `.partial` file has started
+    pub fn save_partial_copy(&self, partial: &PartialCopy) -> Result<()> {
+        let key = self.partial_key(&partial.target_path);
+        self.db.insert(key, serde_json::to_vec(partial)?)?;
+        self.written_now()?;
+        Ok(())
+    }
 
-        for item in self.db.scan_prefix(prefix.as_bytes()) {
-            let (_, value) = item?;
-            let state: FileState = serde_json::from_slice(&value)?;
-            
-            stats.total_files += 1;
-            stats.total_size += state.size;
-            
-            *stats.by_category.entry(state.file_category.clone()).or_insert(0) += 1;
+    /// The unfinished copy for a target path, if any
+    pub fn get_partial_copy(&self, target_path: &Path) -> Result<Option<PartialCopy>> {
+        match self.db.get(self.partial_key(target_path))? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
         }
-
-        stats.pending_syncs = self.get_all_pending_syncs()?.len();
-
-        Ok(stats)
     }
 
-    /// Clear all state (use with caution!)
-    pub fn clear_all(&self) -> Result<()> {
-        self.db.clear()?;
-        self.db.flush()?;
+    /// Forget an unfinished copy once it completed or was discarded
+    pub fn remove_partial_copy(&self, target_path: &Path) -> Result<()> {
+        self.db.remove(self.partial_key(target_path))?;
+        self.written_now()?;
         Ok(())
     }
 
-    /// Get all synced file states
-    pub fn get_all_file_states(&self) -> Result<Vec<FileState>> {
-        let prefix = "file:";
-        let mut files = Vec::new();
+    /// Record a staged copy, replacing an earlier one of the same source
+    /// (on the same drive, for replicas)
+    pub fn add_staged(&self, staged: &StagedFile) -> Result<()> {
+        let key = self.staged_entry_key(staged);
+        self.db.insert(key, serde_json::to_vec(staged)?)?;
+        self.written_now()?;
+        Ok(())
+    }
 
-        for item in self.db.scan_prefix(prefix.as_bytes()) {
-            let (_, value) = item?;
-            let file_state: FileState = serde_json::from_slice(&value)?;
-            files.push(file_state);
+    /// The staged copy of a source file, if any
+    pub fn get_staged(&self, source_path: &Path) -> Result<Option<StagedFile>> {
+        match self.db.get(self.staged_key(source_path))? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
         }
-
-        Ok(files)
     }
 
-    /// Remove a file state (for deleted files)
+    /// The staged copy of a replicated source file on `drive_uuid`, if any
+    pub fn get_staged_replica(&self, source_path: &Path, drive_uuid: &str) -> Result<Option<StagedFile>> {
+        match self.db.get(self.staged_replica_key(source_path, drive_uuid))? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Every staged copy, ordered by source path
+    pub fn get_all_staged(&self) -> Result<Vec<StagedFile>> {
+        let mut staged = Vec::new();
+        for item in self.db.scan_prefix(b"staged:") {
+            let (_, value) = item?;
+            staged.push(serde_json::from_slice(&value)?);
+        }
+        Ok(staged)
+    }
+
+    /// Forget a staged copy once it was committed or discarded
+    pub fn remove_staged(&self, staged: &StagedFile) -> Result<()> {
+        self.db.remove(self.staged_entry_key(staged))?;
+        self.written_now()?;
+        Ok(())
+    }
+
+    /// Keep the piece hashes of the copy at `chunks.target_path`
+    pub fn save_chunk_hashes(&self, chunks: &ChunkHashes) -> Result<()> {
+        let key = self.chunks_key(&chunks.target_path);
+        self.db.insert(key, serde_json::to_vec(chunks)?)?;
+        self.written()?;
+        Ok(())
+    }
+
+    /// The piece hashes kept for a copy, if any
+    pub fn get_chunk_hashes(&self, target_path: &Path) -> Result<Option<ChunkHashes>> {
+        match self.db.get(self.chunks_key(target_path))? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Forget the piece hashes of a copy that was deleted or moved
+    pub fn remove_chunk_hashes(&self, target_path: &Path) -> Result<()> {
+        self.db.remove(self.chunks_key(target_path))?;
+        self.written()?;
+        Ok(())
+    }
+
+    /// Record a file moved or copied into quarantine
+    pub fn add_quarantined(&self, file: &QuarantinedFile) -> Result<()> {
+        let key = self.quarantine_key(&file.source_path);
+        self.db.insert(key, serde_json::to_vec(file)?)?;
+        self.written_now()?;
+        Ok(())
+    }
+
+    /// Get the quarantine record for a source path
+    pub fn get_quarantined(&self, source_path: &Path) -> Result<Option<QuarantinedFile>> {
+        match self.db.get(self.quarantine_key(source_path))? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Quarantine records whose file is still in the quarantine directory
+    pub fn get_all_quarantined(&self) -> Result<Vec<QuarantinedFile>> {
+        let mut files = Vec::new();
+        for item in self.db.scan_prefix(b"quarantine:") {
+            let (_, value) = item?;
+            let file: QuarantinedFile = serde_json::from_slice(&value)?;
+            if file.quarantine_path.exists() {
+                files.push(file);
+            }
+        }
+        Ok(files)
+    }
+
+    /// Remember a path that changed while the watcher was paused, so it is
+    /// still synced if the watcher stops before it resumes. Written to disk
+    /// at once, since the watcher holds off flushing while paused.
+    pub fn add_deferred(&self, path: &Path) -> Result<()> {
+        let key = format!("deferred:{}", path.display());
+        self.db.insert(key.into_bytes(), serde_json::to_vec(path)?)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    /// Take every deferred path, in path order, leaving none recorded
+    pub fn take_deferred(&self) -> Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        for item in self.db.scan_prefix(b"deferred:") {
+            let (key, value) = item?;
+            paths.push(serde_json::from_slice(&value)?);
+            self.db.remove(key)?;
+        }
+        if !paths.is_empty() {
+            self.db.flush()?;
+        }
+        Ok(paths)
+    }
+
+    /// Append a completed sync to the history
+    pub fn record_history(&self, state: &FileState) -> Result<()> {
+        let entry = HistoryEntry {
+            source_path: state.source_path.clone(),
+            file_category: state.file_category.clone(),
+            target_drive: state.target_drive.clone(),
+            size: state.size,
+            synced_at: state.last_synced,
+            snapshot: state.snapshot.clone(),
+        };
+        // Zero-padded so keys sort by time; the id keeps same-second syncs apart
+        let key = format!("history:{:020}:{:020}", entry.synced_at, self.db.generate_id()?);
+
+        self.db.insert(key.into_bytes(), serde_json::to_vec(&entry)?)?;
+        self.written()?;
+        Ok(())
+    }
+
+    /// History entries synced in `[since, until)`, oldest first
+    pub fn get_history(&self, since: Option<u64>, until: Option<u64>) -> Result<Vec<HistoryEntry>> {
+        let start = format!("history:{:020}", since.unwrap_or(0));
+        // ';' sorts right after ':', so this bounds every history key
+        let end = match until {
+            Some(until) => format!("history:{:020}", until),
+            None => "history;".to_string(),
+        };
+
+        let mut entries = Vec::new();
+        for item in self.db.range(start.into_bytes()..end.into_bytes()) {
+            let (_, value) = item?;
+            entries.push(serde_json::from_slice(&value)?);
+        }
+        Ok(entries)
+    }
+
+    /// An id for a new batch, larger than every earlier one
+    pub fn next_batch_id(&self) -> Result<u64> {
+        Ok(self.db.generate_id()?)
+    }
+
+    /// Store (or replace) a batch
+    pub fn save_batch(&self, batch: &SyncBatch) -> Result<()> {
+        self.db.insert(Self::batch_key(batch.id), serde_json::to_vec(batch)?)?;
+        self.written_now()?;
+        Ok(())
+    }
+
+    pub fn get_batch(&self, id: u64) -> Result<Option<SyncBatch>> {
+        match self.db.get(Self::batch_key(id))? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// The most recently recorded batch
+    pub fn latest_batch(&self) -> Result<Option<SyncBatch>> {
+        match self.db.scan_prefix(b"batch:").next_back() {
+            Some(item) => Ok(Some(serde_json::from_slice(&item?.1)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn remove_batch(&self, id: u64) -> Result<()> {
+        self.db.remove(Self::batch_key(id))?;
+        self.written_now()?;
+        Ok(())
+    }
+
+    /// Zero-padded so the latest batch sorts last
+    fn batch_key(id: u64) -> Vec<u8> {
+        format!("batch:{:020}", id).into_bytes()
+    }
+
+    /// An id for the next [`RunRecord`], later than every earlier one's
+    pub fn next_run_id(&self) -> Result<u64> {
+        Ok(self.db.generate_id()?)
+    }
+
+    /// Store a finished run, dropping the oldest beyond the ones kept
+    pub fn record_run(&self, run: &RunRecord) -> Result<()> {
+        self.db.insert(format!("runs:{:020}", run.id).into_bytes(), serde_json::to_vec(run)?)?;
+        let excess = self.db.scan_prefix(b"runs:").count().saturating_sub(RUNS_KEPT);
+        for item in self.db.scan_prefix(b"runs:").take(excess) {
+            self.db.remove(item?.0)?;
+        }
+        self.written()?;
+        Ok(())
+    }
+
+    /// The `limit` most recent runs, newest first
+    pub fn get_runs(&self, limit: usize) -> Result<Vec<RunRecord>> {
+        self.db
+            .scan_prefix(b"runs:")
+            .rev()
+            .take(limit)
+            .map(|item| Ok(serde_json::from_slice(&item?.1)?))
+            .collect()
+    }
+
+    /// Get statistics about synced files
+    pub fn get_sync_stats(&self) -> Result<SyncStats> {
+        let mut stats = SyncStats::default();
+        let prefix = "file:";
+
+        for item in self.db.scan_prefix(prefix.as_bytes()) {
+            let (_, value) = item?;
+            let state: FileState = serde_json::from_slice(&value)?;
+            
+            stats.total_files += 1;
+            stats.total_size += state.size;
+            
+            *stats.by_category.entry(state.file_category.clone()).or_insert(0) += 1;
+            *stats.size_by_category.entry(state.file_category.clone()).or_insert(0) += state.size;
+        }
+
+        stats.pending_syncs = self.get_all_pending_syncs()?.len();
+        stats.quarantined = self.get_all_quarantined()?.len();
+
+        Ok(stats)
+    }
+
+    /// Get synced and pending totals for each target drive UUID
+    pub fn get_stats_by_drive(&self) -> Result<HashMap<String, DriveStats>> {
+        let mut by_drive: HashMap<String, DriveStats> = HashMap::new();
+
+        for state in self.get_all_file_states()?.iter().flat_map(FileState::copies) {
+            let stats = by_drive.entry(state.target_drive.clone()).or_default();
+            stats.file_count += 1;
+            // What the drive actually stores, which is less for compressed copies
+            stats.total_size += state.compressed_size.unwrap_or(state.size);
+        }
+
+        for pending in self.get_all_pending_syncs()? {
+            let stats = by_drive.entry(pending.target_drive.clone()).or_default();
+            stats.pending_count += 1;
+            stats.pending_size += pending.size;
+        }
+
+        for (drive, speed) in self.get_drive_speeds()? {
+            by_drive.entry(drive).or_default().write_speed = Some(speed);
+        }
+
+        Ok(by_drive)
+    }
+
+    /// Fold a measured copy into the drive's rolling write speed
+    pub fn record_copy_speed(&self, drive_uuid: &str, bytes: u64, elapsed: Duration) -> Result<()> {
+        let key = format!("drivestat:{}", drive_uuid);
+        let speed: DriveSpeed = match self.db.get(&key)? {
+            Some(value) => serde_json::from_slice(&value)?,
+            None => DriveSpeed::default(),
+        };
+        let speed = speed.update(bytes, elapsed, self.clock().timestamp());
+
+        self.db.insert(key.into_bytes(), serde_json::to_vec(&speed)?)?;
+        self.written()?;
+        Ok(())
+    }
+
+    /// Remember a drive's total size, so its queue can be checked against it
+    /// while the drive is unplugged
+    pub fn record_drive_capacity(&self, drive_uuid: &str, total_space: u64) -> Result<()> {
+        let key = format!("drivecap:{}", drive_uuid);
+        self.db.insert(key.into_bytes(), serde_json::to_vec(&total_space)?)?;
+        self.written()?;
+        Ok(())
+    }
+
+    /// Total size of the drive when it was last seen, if it has been
+    pub fn get_drive_capacity(&self, drive_uuid: &str) -> Result<Option<u64>> {
+        match self.db.get(format!("drivecap:{}", drive_uuid))? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Remember when a drive was last connected, as an RFC 3339 time. Kept
+    /// here rather than in the config so connecting a drive never rewrites
+    /// the config file.
+    pub fn record_drive_seen(&self, drive_uuid: &str, at: &str) -> Result<()> {
+        let key = format!("driveseen:{}", drive_uuid);
+        self.db.insert(key.into_bytes(), at.as_bytes())?;
+        self.written()?;
+        Ok(())
+    }
+
+    /// When the drive was last connected, if it has been
+    pub fn get_drive_last_seen(&self, drive_uuid: &str) -> Result<Option<String>> {
+        Ok(self
+            .db
+            .get(format!("driveseen:{}", drive_uuid))?
+            .map(|value| String::from_utf8_lossy(&value).into_owned()))
+    }
+
+    /// Rolling write speed of every drive that has one, by drive UUID
+    pub fn get_drive_speeds(&self) -> Result<HashMap<String, DriveSpeed>> {
+        let prefix = "drivestat:";
+        let mut speeds = HashMap::new();
+
+        for item in self.db.scan_prefix(prefix.as_bytes()) {
+            let (key, value) = item?;
+            let drive = String::from_utf8_lossy(&key[prefix.len()..]).to_string();
+            speeds.insert(drive, serde_json::from_slice(&value)?);
+        }
+
+        Ok(speeds)
+    }
+
+    /// The cached listing of a source directory, if there is one
+    pub fn get_dir_listing(&self, dir: &Path) -> Result<Option<DirListing>> {
+        match self.db.get(dir_listing_key(dir))? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn save_dir_listing(&self, dir: &Path, listing: &DirListing) -> Result<()> {
+        self.db.insert(dir_listing_key(dir), serde_json::to_vec(listing)?)?;
+        self.written()?;
+        Ok(())
+    }
+
+    pub fn remove_dir_listing(&self, dir: &Path) -> Result<()> {
+        if self.db.remove(dir_listing_key(dir))?.is_some() {
+            self.written()?;
+        }
+        Ok(())
+    }
+
+    /// Forget the cached listings of `dir` and every directory below it
+    pub fn remove_dir_listings(&self, dir: &Path) -> Result<usize> {
+        let key = dir_listing_key(dir);
+        let mut below = key.clone();
+        below.push(std::path::MAIN_SEPARATOR as u8);
+
+        let mut keys: Vec<_> = self.db.scan_prefix(&below).keys().collect::<std::result::Result<_, _>>()?;
+        keys.push(key.into());
+        let mut removed = 0;
+        for key in keys {
+            if self.db.remove(key)?.is_some() {
+                removed += 1;
+            }
+        }
+
+        if removed > 0 {
+            self.written()?;
+        }
+        Ok(removed)
+    }
+
+    /// Clear all state (use with caution!)
+    pub fn clear_all(&self) -> Result<()> {
+        self.db.clear()?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    /// Get all synced file states
+    pub fn get_all_file_states(&self) -> Result<Vec<FileState>> {
+        let prefix = "file:";
+        let mut files = Vec::new();
+
+        for item in self.db.scan_prefix(prefix.as_bytes()) {
+            let (_, value) = item?;
+            let file_state: FileState = serde_json::from_slice(&value)?;
+            files.push(file_state);
+        }
+
+        Ok(files)
+    }
+
+    /// Remove a file state (for deleted files), along with the piece hashes
+    /// of its copies
     pub fn remove_file_state(&self, source_path: &Path) -> Result<()> {
         let key = self.file_key(source_path);
-        self.db.remove(key)?;
-        self.db.flush()?;
+        if let Some(value) = self.db.remove(key)? {
+            let state: FileState = serde_json::from_slice(&value)?;
+            for copy in std::iter::once(&state).chain(&state.replicas) {
+                self.db.remove(self.chunks_key(&copy.target_path))?;
+            }
+        }
+        self.written()?;
         Ok(())
     }
 
     // Helper methods
     fn file_key(&self, path: &Path) -> Vec<u8> {
-        format!("file:{}", path.display()).into_bytes()
+        let names = *self.names.read().unwrap();
+        format!("file:{}", names.apply(path).display()).into_bytes()
     }
 
     fn pending_key(&self, path: &Path) -> Vec<u8> {
-        format!("pending:{}", path.display()).into_bytes()
+        let names = *self.names.read().unwrap();
+        format!("pending:{}", names.apply(path).display()).into_bytes()
+    }
+
+    /// Key of a queue entry: the source path, plus the drive for replicas
+    fn queue_key(&self, pending: &PendingSync) -> Vec<u8> {
+        if pending.replica {
+            self.replica_key(&pending.source_path, &pending.target_drive)
+        } else {
+            self.pending_key(&pending.source_path)
+        }
+    }
+
+    fn replica_key(&self, path: &Path, drive_uuid: &str) -> Vec<u8> {
+        let mut key = self.pending_key(path);
+        key.push(0);
+        key.extend_from_slice(drive_uuid.as_bytes());
+        key
+    }
+
+    fn partial_key(&self, path: &Path) -> Vec<u8> {
+        format!("partial:{}", path.display()).into_bytes()
+    }
+
+    fn staged_key(&self, path: &Path) -> Vec<u8> {
+        format!("staged:{}", path.display()).into_bytes()
+    }
+
+    fn staged_replica_key(&self, path: &Path, drive_uuid: &str) -> Vec<u8> {
+        let mut key = self.staged_key(path);
+        key.push(0);
+        key.extend_from_slice(drive_uuid.as_bytes());
+        key
+    }
+
+    /// Key of a staged copy: the source path, plus the drive for replicas
+    fn staged_entry_key(&self, staged: &StagedFile) -> Vec<u8> {
+        if staged.replica {
+            self.staged_replica_key(&staged.state.source_path, &staged.state.target_drive)
+        } else {
+            self.staged_key(&staged.state.source_path)
+        }
+    }
+
+    fn chunks_key(&self, path: &Path) -> Vec<u8> {
+        format!("chunks:{}", path.display()).into_bytes()
+    }
+
+    fn quarantine_key(&self, path: &Path) -> Vec<u8> {
+        format!("quarantine:{}", path.display()).into_bytes()
+    }
+}
+
+/// What `StateManager::repair` had to do
+#[derive(Debug)]
+pub enum RepairOutcome {
+    /// The database opened; this many unreadable entries were dropped
+    Cleaned { removed: usize },
+    /// The database was corrupt and has been replaced; the old one was kept here
+    Rebuilt { backup: PathBuf },
+}
+
+/// What `StateManager::compact` did, in bytes on disk
+#[derive(Debug)]
+pub struct CompactOutcome {
+    pub before: u64,
+    pub after: u64,
+    /// Records in the compacted database
+    pub entries: usize,
+}
+
+fn queued_size_key(drive_uuid: &str) -> Vec<u8> {
+    let mut key = QUEUED_SIZE_PREFIX.to_vec();
+    key.extend_from_slice(drive_uuid.as_bytes());
+    key
+}
+
+/// `<db_path><suffix>`, next to the database
+fn sibling(db_path: &Path, suffix: &str) -> PathBuf {
+    let mut name = db_path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    db_path.with_file_name(name)
+}
+
+/// Clean up after a compaction that stopped partway. Between its two
+/// renames the database is missing and `<db>.pre-compact` is all of it, so
+/// that is put back; once the compacted copy is in place, the old one is
+/// only waiting to be deleted.
+fn recover_compaction(db_path: &Path) -> Result<()> {
+    let old_path = sibling(db_path, ".pre-compact");
+    if !old_path.exists() {
+        return Ok(());
     }
+    if db_path.exists() {
+        fs::remove_dir_all(&old_path)?;
+    } else {
+        warn!("Restoring {} from an interrupted compaction", db_path.display());
+        fs::rename(&old_path, db_path)?;
+    }
+    Ok(())
 }
 
-#[derive(Debug, Default)]
+/// Total size of the files under `path`
+fn disk_usage(path: &Path) -> Result<u64> {
+    let mut total = 0;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        total += if metadata.is_dir() { disk_usage(&entry.path())? } else { metadata.len() };
+    }
+    Ok(total)
+}
+
+/// Turn a sled open failure into an error that says what to do about it
+fn open_error(db_path: &Path, error: sled::Error) -> OrchestratorError {
+    match error {
+        // sled reports its own file lock failing as a plain I/O error
+        sled::Error::Io(ref e) if e.to_string().contains("could not acquire lock") => {
+            OrchestratorError::InstanceLocked(format!(
+                "the database {} is open in another orchestrator process; stop it (or close the GUI) and retry",
+                db_path.display()
+            ))
+        }
+        sled::Error::Corruption { .. } | sled::Error::ReportableBug(_) => {
+            OrchestratorError::DatabaseCorrupt(format!(
+                "{} could not be read ({}); run `fo repair` to rebuild it",
+                db_path.display(),
+                error
+            ))
+        }
+        e => OrchestratorError::State(format!("Failed to open database {}: {}", db_path.display(), e)),
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct SyncStats {
     pub total_files: usize,
     pub total_size: u64,
     pub pending_syncs: usize,
-    pub by_category: std::collections::HashMap<String, usize>,
+    /// Unknown-type files sitting in the quarantine directory
+    pub quarantined: usize,
+    pub by_category: HashMap<String, usize>,
+    /// Source bytes synced per category
+    pub size_by_category: HashMap<String, u64>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct DriveStats {
+    pub file_count: usize,
+    /// Bytes stored on the drive
+    pub total_size: u64,
+    pub pending_count: usize,
+    /// Bytes waiting to be copied to the drive
+    pub pending_size: u64,
+    /// Observed write throughput, once a copy has been measured
+    pub write_speed: Option<DriveSpeed>,
+}
+
+/// Rolling average of how fast copies to a drive have gone
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct DriveSpeed {
+    pub bytes_per_sec: f64,
+    pub samples: u64,
+    pub updated_at: u64,
+}
+
+/// Copies smaller than this are mostly per-file overhead, so they aren't
+/// used to measure a drive's speed
+pub const MIN_SPEED_SAMPLE_BYTES: u64 = 1024 * 1024;
+
+/// Weight of the newest copy in the rolling average
+const SPEED_SAMPLE_WEIGHT: f64 = 0.3;
+
+/// The average counts half as much for every this many seconds it went
+/// without an update, so an old slow run fades out
+const SPEED_HALF_LIFE_SECS: f64 = 7.0 * 24.0 * 3600.0;
+
+impl DriveSpeed {
+    /// Fold in a copy of `bytes` that took `elapsed`, made at `now`
+    pub fn update(self, bytes: u64, elapsed: Duration, now: u64) -> Self {
+        let sample = bytes as f64 / elapsed.as_secs_f64().max(1e-3);
+        if self.samples == 0 {
+            return Self { bytes_per_sec: sample, samples: 1, updated_at: now };
+        }
+
+        let age = now.saturating_sub(self.updated_at) as f64;
+        let old_weight = (1.0 - SPEED_SAMPLE_WEIGHT) * 0.5f64.powf(age / SPEED_HALF_LIFE_SECS);
+        Self {
+            bytes_per_sec: self.bytes_per_sec * old_weight + sample * (1.0 - old_weight),
+            samples: self.samples + 1,
+            updated_at: now,
+        }
+    }
+}
+
+/// A source directory's entries as last read, reused while the directory's
+/// modification time is unchanged
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DirListing {
+    pub modified: SystemTime,
+    /// Names of the regular files directly in the directory
+    pub files: Vec<PathBuf>,
+    /// Names of its subdirectories
+    pub dirs: Vec<PathBuf>,
+}
+
+fn dir_listing_key(dir: &Path) -> Vec<u8> {
+    format!("dirlist:{}", dir.display()).into_bytes()
+}
+
+fn system_clock() -> Arc<RwLock<Arc<dyn Clock>>> {
+    Arc::new(RwLock::new(Arc::new(SystemClock)))
 }
 
 /// Get current timestamp in seconds
@@ -219,11 +1599,501 @@ pub fn current_timestamp() -> u64 {
         .as_secs()
 }
 
-/// Calculate file hash using BLAKE3
-pub fn calculate_file_hash<P: AsRef<Path>>(path: P) -> Result<String> {
-    let data = std::fs::read(path.as_ref())
+/// Read buffer size used when hashing files
+pub const HASH_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// BLAKE3 hash of only the first `len` bytes of a file, e.g. to check that
+/// a partial copy still matches the start of its source
+pub fn calculate_prefix_hash<P: AsRef<Path>>(path: P, len: u64) -> Result<String> {
+    use std::io::Read;
+
+    let file = std::fs::File::open(path.as_ref())
+        .map_err(|e| OrchestratorError::State(format!("Failed to open file for hashing: {}", e)))?;
+
+    let mut hasher = blake3::Hasher::new();
+    let hashed = std::io::copy(&mut file.take(len), &mut hasher)
         .map_err(|e| OrchestratorError::State(format!("Failed to read file for hashing: {}", e)))?;
-    
-    let hash = blake3::hash(&data);
-    Ok(hash.to_hex().to_string())
+    if hashed < len {
+        return Err(OrchestratorError::State(format!(
+            "{} is shorter than {} bytes",
+            path.as_ref().display(),
+            len
+        )));
+    }
+
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Calculate a file's hash with the given algorithm
+pub fn calculate_file_hash<P: AsRef<Path>>(path: P, algorithm: HashAlgorithm) -> Result<String> {
+    calculate_file_hash_chunked(path, algorithm, HASH_CHUNK_SIZE)
+}
+
+/// Calculate a file's hash, reading `chunk_size` bytes at a time so memory
+/// use doesn't grow with the file
+pub fn calculate_file_hash_chunked<P: AsRef<Path>>(
+    path: P,
+    algorithm: HashAlgorithm,
+    chunk_size: usize,
+) -> Result<String> {
+    let file = std::fs::File::open(path.as_ref())
+        .map_err(|e| OrchestratorError::State(format!("Failed to open file for hashing: {}", e)))?;
+
+    calculate_reader_hash(file, algorithm, chunk_size)
+        .map_err(|e| OrchestratorError::State(format!("Failed to read file for hashing: {}", e)))
+}
+
+/// Hash everything `reader` yields, `chunk_size` bytes at a time, keeping
+/// the I/O error for callers that act on its kind
+pub fn calculate_reader_hash<R: std::io::Read>(
+    mut reader: R,
+    algorithm: HashAlgorithm,
+    chunk_size: usize,
+) -> std::io::Result<String> {
+    let mut hasher = algorithm.hasher();
+    let mut buffer = vec![0u8; chunk_size.max(1)];
+
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+
+    Ok(hasher.finalize())
+}
+
+/// BLAKE3 hash of each `chunk_size` piece of a file from piece `first` on,
+/// including a shorter last piece
+pub fn calculate_chunk_hashes<P: AsRef<Path>>(path: P, chunk_size: u64, first: u64) -> std::io::Result<Vec<String>> {
+    use std::io::{Seek, SeekFrom};
+
+    let mut file = std::fs::File::open(path.as_ref())?;
+    file.seek(SeekFrom::Start(first * chunk_size))?;
+    let mut reader = ChunkingReader::new(std::io::BufReader::with_capacity(HASH_CHUNK_SIZE, file), chunk_size);
+    std::io::copy(&mut reader, &mut std::io::sink())?;
+    Ok(reader.finish())
+}
+
+/// A file's hash with the given algorithm and its [`calculate_chunk_hashes`],
+/// from one read
+pub fn calculate_file_hash_with_chunks<P: AsRef<Path>>(
+    path: P,
+    algorithm: HashAlgorithm,
+    chunk_size: u64,
+) -> std::io::Result<(String, Vec<String>)> {
+    let file = std::fs::File::open(path.as_ref())?;
+    let mut reader = ChunkingReader::new(file, chunk_size);
+    let hash = calculate_reader_hash(&mut reader, algorithm, HASH_CHUNK_SIZE)?;
+    Ok((hash, reader.finish()))
+}
+
+/// Passes reads through, hashing what went by in `chunk_size` pieces
+struct ChunkingReader<R> {
+    inner: R,
+    chunk_size: u64,
+    filled: u64,
+    hasher: blake3::Hasher,
+    chunks: Vec<String>,
+}
+
+impl<R> ChunkingReader<R> {
+    fn new(inner: R, chunk_size: u64) -> Self {
+        Self { inner, chunk_size: chunk_size.max(1), filled: 0, hasher: blake3::Hasher::new(), chunks: Vec::new() }
+    }
+
+    /// The piece hashes, the last one for whatever was left over
+    fn finish(mut self) -> Vec<String> {
+        if self.filled > 0 {
+            self.chunks.push(self.hasher.finalize().to_hex().to_string());
+        }
+        self.chunks
+    }
+}
+
+impl<R: std::io::Read> std::io::Read for ChunkingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        let mut rest = &buf[..read];
+        while !rest.is_empty() {
+            let take = (self.chunk_size - self.filled).min(rest.len() as u64) as usize;
+            self.hasher.update(&rest[..take]);
+            self.filled += take as u64;
+            rest = &rest[take..];
+            if self.filled == self.chunk_size {
+                self.chunks.push(self.hasher.finalize().to_hex().to_string());
+                self.hasher.reset();
+                self.filled = 0;
+            }
+        }
+        Ok(read)
+    }
+}
+
+/// Hash a file on the blocking thread pool so the async runtime keeps running
+pub async fn calculate_file_hash_async<P: AsRef<Path>>(path: P, algorithm: HashAlgorithm) -> Result<String> {
+    let path = path.as_ref().to_path_buf();
+
+    tokio::task::spawn_blocking(move || calculate_file_hash(path, algorithm))
+        .await
+        .map_err(|e| OrchestratorError::State(format!("Hashing task failed: {}", e)))?
+}
+
+/// Calculate the hash of the original content of a zstd-compressed file
+pub fn calculate_compressed_file_hash<P: AsRef<Path>>(path: P, algorithm: HashAlgorithm) -> Result<String> {
+    let file = std::fs::File::open(path.as_ref())
+        .map_err(|e| OrchestratorError::State(format!("Failed to open file for hashing: {}", e)))?;
+    let mut decoder = zstd::stream::Decoder::new(file)
+        .map_err(|e| OrchestratorError::State(format!("Failed to read compressed file: {}", e)))?;
+
+    let mut hasher = algorithm.hasher();
+    std::io::copy(&mut decoder, &mut hasher)
+        .map_err(|e| OrchestratorError::State(format!("Failed to decompress file for hashing: {}", e)))?;
+
+    Ok(hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_open_while_in_use_reports_lock() {
+        let dir = TempDir::new().unwrap();
+        let db = dir.path().join("state.db");
+
+        let _first = StateManager::new(&db).unwrap();
+        let err = StateManager::new(&db).err().unwrap();
+        assert!(matches!(err, OrchestratorError::InstanceLocked(_)), "{}", err);
+    }
+
+    #[test]
+    fn test_repair_drops_unreadable_entries() {
+        let dir = TempDir::new().unwrap();
+        let db = dir.path().join("state.db");
+
+        {
+            let state = StateManager::new(&db).unwrap();
+            state.db.insert(b"file:/src/broken.jpg", &b"not json"[..]).unwrap();
+            state.db.insert(b"pending:/src/broken.jpg", &b"{"[..]).unwrap();
+            state.db.flush().unwrap();
+        }
+
+        let (state, outcome) = StateManager::repair(&db).unwrap();
+        assert!(matches!(outcome, RepairOutcome::Cleaned { removed: 2 }));
+        assert_eq!(state.get_sync_stats().unwrap().total_files, 0);
+    }
+
+    #[test]
+    fn test_compact_keeps_live_records() {
+        let dir = TempDir::new().unwrap();
+        let db = dir.path().join("state.db");
+
+        {
+            let state = StateManager::new(&db).unwrap();
+            for i in 0..2000 {
+                state.db.insert(format!("history:{:08}", i), vec![b'x'; 1024]).unwrap();
+            }
+            state.db.flush().unwrap();
+            for i in 0..2000 {
+                state.db.remove(format!("history:{:08}", i)).unwrap();
+            }
+            state.set_last_run(1_700_000_000).unwrap();
+            state.db.flush().unwrap();
+        }
+
+        let outcome = StateManager::compact(&db).unwrap();
+        assert!(outcome.after < outcome.before, "{:?}", outcome);
+        assert_eq!(outcome.entries, 1);
+        assert!(!dir.path().join("state.db.pre-compact").exists());
+        assert!(!dir.path().join("state.db.compacting").exists());
+
+        let state = StateManager::new(&db).unwrap();
+        assert_eq!(state.get_last_run().unwrap(), Some(1_700_000_000));
+    }
+
+    #[test]
+    fn test_interrupted_compact_is_recovered() {
+        let dir = TempDir::new().unwrap();
+        let db = dir.path().join("state.db");
+        let old = dir.path().join("state.db.pre-compact");
+        StateManager::new(&db).unwrap().set_last_run(1_700_000_000).unwrap();
+
+        // Stopped between the renames: the database is only under its old name
+        fs::rename(&db, &old).unwrap();
+        let state = StateManager::new(&db).unwrap();
+        assert_eq!(state.get_last_run().unwrap(), Some(1_700_000_000));
+        assert!(!old.exists());
+        drop(state);
+
+        // Stopped before the old copy was deleted
+        fs::create_dir(&old).unwrap();
+        StateManager::compact(&db).unwrap();
+        assert!(!old.exists());
+        assert_eq!(StateManager::new(&db).unwrap().get_last_run().unwrap(), Some(1_700_000_000));
+    }
+
+    #[tokio::test]
+    async fn test_batched_flush() {
+        let dir = TempDir::new().unwrap();
+        let state = StateManager::new(dir.path().join("state.db")).unwrap();
+        state.set_flush_interval(Duration::from_secs(3600));
+
+        let record = |name: &str| FileState {
+            source_path: PathBuf::from("/src").join(name),
+            hash: "hash".to_string(),
+            size: 1,
+            last_synced: 100,
+            target_drive: "drive".to_string(),
+            target_path: PathBuf::from("/usb/images").join(name),
+            file_category: "images".to_string(),
+            compressed_size: None,
+            direction: SyncDirection::Push,
+            reflinked: false,
+            sparse: false,
+            hash_algorithm: HashAlgorithm::Blake3,
+            link: None,
+            snapshot: None,
+            tags: Vec::new(),
+            note: None,
+            replicas: Vec::new(),
+        };
+        for name in ["a.jpg", "b.jpg", "c.jpg"] {
+            state.save_file_state_async(record(name)).await.unwrap();
+        }
+
+        // The first write starts the batch, the other two wait for it
+        assert_eq!(state.get_all_file_states().unwrap().len(), 3);
+        assert_eq!(state.flush_async().await.unwrap(), 2);
+        assert_eq!(state.flush().unwrap(), 0);
+
+        // Queue entries don't wait
+        state.add_pending_sync(&PendingSync {
+            source_path: PathBuf::from("/src/d.jpg"),
+            file_category: "images".to_string(),
+            target_drive: "drive".to_string(),
+            hash: "hash".to_string(),
+            size: 1,
+            created_at: 0,
+            replica: false,
+        }).unwrap();
+        state.save_file_state(&record("d.jpg")).unwrap();
+        assert_eq!(state.flush().unwrap(), 1);
+
+        state.set_flush_interval(Duration::ZERO);
+        state.remove_file_state(Path::new("/src/a.jpg")).unwrap();
+        assert_eq!(state.flush().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_replica_entries_are_queued_per_drive() {
+        let dir = TempDir::new().unwrap();
+        let state = StateManager::new(dir.path().join("state.db")).unwrap();
+        let queued = |drive: &str| PendingSync {
+            source_path: PathBuf::from("/src/a.jpg"),
+            file_category: "images".to_string(),
+            target_drive: drive.to_string(),
+            hash: "hash".to_string(),
+            size: 1,
+            created_at: 0,
+            replica: true,
+        };
+
+        state.add_pending_sync(&queued("one")).unwrap();
+        state.add_pending_sync(&queued("two")).unwrap();
+        assert_eq!(state.get_all_pending_syncs().unwrap().len(), 2);
+        assert!(state.get_pending_sync(Path::new("/src/a.jpg")).unwrap().is_none());
+
+        state.remove_replica_pending(Path::new("/src/a.jpg"), "one").unwrap();
+        assert_eq!(state.get_pending_syncs("two").unwrap().len(), 1);
+        state.remove_queued(&queued("two")).unwrap();
+        assert!(!state.has_pending_syncs().unwrap());
+    }
+
+    #[test]
+    fn test_queued_size_follows_the_queue() {
+        let dir = TempDir::new().unwrap();
+        let state = StateManager::new(dir.path().join("state.db")).unwrap();
+        let queued = |name: &str, drive: &str, size: u64| PendingSync {
+            source_path: PathBuf::from("/src").join(name),
+            file_category: "images".to_string(),
+            target_drive: drive.to_string(),
+            hash: "hash".to_string(),
+            size,
+            created_at: 0,
+            replica: false,
+        };
+
+        // Counted from the queue the first time, then kept up to date
+        state.add_pending_sync(&queued("a.jpg", "one", 10)).unwrap();
+        assert_eq!(state.get_queued_size("one").unwrap(), 10);
+        state.add_pending_sync(&queued("b.jpg", "one", 5)).unwrap();
+        state.add_pending_sync(&queued("a.jpg", "one", 7)).unwrap();
+        assert_eq!(state.get_queued_size("one").unwrap(), 12);
+
+        // Re-queued for another drive, it moves between the totals
+        assert_eq!(state.get_queued_size("two").unwrap(), 0);
+        state.add_pending_sync(&queued("b.jpg", "two", 5)).unwrap();
+        assert_eq!((state.get_queued_size("one").unwrap(), state.get_queued_size("two").unwrap()), (7, 5));
+
+        state.remove_pending_sync(Path::new("/src/a.jpg")).unwrap();
+        state.remove_queued(&queued("b.jpg", "two", 5)).unwrap();
+        assert_eq!((state.get_queued_size("one").unwrap(), state.get_queued_size("two").unwrap()), (0, 0));
+    }
+
+    #[test]
+    fn test_deferred_paths_survive_reopen() {
+        let dir = TempDir::new().unwrap();
+        let db_path = dir.path().join("state.db");
+        let state = StateManager::new(&db_path).unwrap();
+        state.add_deferred(Path::new("/src/b.jpg")).unwrap();
+        state.add_deferred(Path::new("/src/a.jpg")).unwrap();
+        state.add_deferred(Path::new("/src/b.jpg")).unwrap();
+        drop(state);
+
+        let state = StateManager::new(&db_path).unwrap();
+        assert_eq!(state.take_deferred().unwrap(), [PathBuf::from("/src/a.jpg"), PathBuf::from("/src/b.jpg")]);
+        assert!(state.take_deferred().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_name_normalization_rekeys_records() {
+        let dir = TempDir::new().unwrap();
+        let state = StateManager::new(dir.path().join("state.db")).unwrap();
+        let decomposed = PathBuf::from("/src/Cafe\u{301}.jpg");
+        let composed = PathBuf::from("/src/Caf\u{e9}.jpg");
+
+        state.save_file_state(&FileState {
+            source_path: decomposed.clone(),
+            hash: "hash".to_string(),
+            size: 1,
+            last_synced: 100,
+            target_drive: "drive".to_string(),
+            target_path: PathBuf::from("/usb/images/Cafe\u{301}.jpg"),
+            file_category: "images".to_string(),
+            compressed_size: None,
+            direction: SyncDirection::Push,
+            reflinked: false,
+            sparse: false,
+            hash_algorithm: HashAlgorithm::Blake3,
+            link: None,
+            snapshot: None,
+            tags: Vec::new(),
+            note: None,
+            replicas: Vec::new(),
+        }).unwrap();
+        assert!(state.get_file_state(&composed).unwrap().is_none());
+
+        let nfc = NameNormalization { nfc: true, lowercase: false };
+        assert_eq!(state.set_name_normalization(nfc).unwrap(), 1);
+        // Either spelling now finds the record, and nothing moves a second time
+        assert_eq!(state.get_file_state(&composed).unwrap().unwrap().source_path, decomposed);
+        assert!(state.get_file_state(&decomposed).unwrap().is_some());
+        assert_eq!(state.set_name_normalization(nfc).unwrap(), 0);
+        assert_eq!(state.get_all_file_states().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_drive_speed_decays_old_samples() {
+        let mb = 1024 * 1024;
+        let speed = DriveSpeed::default().update(10 * mb, Duration::from_secs(1), 1000);
+        assert_eq!(speed.bytes_per_sec, (10 * mb) as f64);
+
+        // A slow copy right away only pulls the average part of the way
+        let slowed = speed.update(mb, Duration::from_secs(1), 1000);
+        assert!(slowed.bytes_per_sec > (6 * mb) as f64);
+
+        // A month later the old average has mostly faded
+        let later = speed.update(mb, Duration::from_secs(1), 1000 + 30 * 24 * 3600);
+        assert!(later.bytes_per_sec < (2 * mb) as f64);
+        assert_eq!(later.samples, 2);
+    }
+
+    #[test]
+    fn test_history_range() {
+        let dir = TempDir::new().unwrap();
+        let state = StateManager::new(dir.path().join("state.db")).unwrap();
+
+        for synced_at in [100, 200, 200, 300] {
+            state.record_history(&FileState {
+                source_path: PathBuf::from("/src/a.jpg"),
+                hash: "hash".to_string(),
+                size: 1,
+                last_synced: synced_at,
+                target_drive: "drive".to_string(),
+                target_path: PathBuf::from("/usb/images/a.jpg"),
+                file_category: "images".to_string(),
+                compressed_size: None,
+                direction: SyncDirection::Push,
+                reflinked: false,
+                sparse: false,
+                hash_algorithm: HashAlgorithm::Blake3,
+                link: None,
+                snapshot: None,
+                tags: Vec::new(),
+                note: None,
+                replicas: Vec::new(),
+            }).unwrap();
+        }
+
+        assert_eq!(state.get_history(None, None).unwrap().len(), 4);
+        let middle = state.get_history(Some(200), Some(300)).unwrap();
+        assert_eq!(middle.iter().map(|e| e.synced_at).collect::<Vec<_>>(), [200, 200]);
+    }
+
+    #[test]
+    fn test_hash_algorithms() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("abc.txt");
+        std::fs::write(&path, b"abc").unwrap();
+
+        assert_eq!(
+            calculate_file_hash(&path, HashAlgorithm::Sha256).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(calculate_file_hash(&path, HashAlgorithm::Md5).unwrap(), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(
+            calculate_file_hash(&path, HashAlgorithm::Blake3).unwrap(),
+            blake3::hash(b"abc").to_hex().to_string()
+        );
+    }
+
+    #[test]
+    fn test_chunked_hash_of_large_sparse_file() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("sparse.img");
+
+        // A sparse file takes no disk space but reads back as zeros
+        let size = 64 * 1024 * 1024;
+        std::fs::File::create(&path).unwrap().set_len(size as u64).unwrap();
+
+        // Built a buffer at a time too, so the test doesn't hold the file either
+        let mut hasher = blake3::Hasher::new();
+        let zeros = [0u8; 64 * 1024];
+        for _ in 0..size / zeros.len() {
+            hasher.update(&zeros);
+        }
+        let expected = hasher.finalize().to_hex().to_string();
+        assert_eq!(calculate_file_hash(&path, HashAlgorithm::Blake3).unwrap(), expected);
+
+        // Chunk boundaries must not affect the result
+        assert_eq!(calculate_file_hash_chunked(&path, HashAlgorithm::Blake3, 4096 + 7).unwrap(), expected);
+
+        // No read asks for more than a chunk, however large the file
+        struct LargestRead<R> {
+            inner: R,
+            largest: usize,
+        }
+        impl<R: std::io::Read> std::io::Read for LargestRead<R> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                self.largest = self.largest.max(buf.len());
+                self.inner.read(buf)
+            }
+        }
+        let mut reader = LargestRead { inner: std::fs::File::open(&path).unwrap(), largest: 0 };
+        assert_eq!(calculate_reader_hash(&mut reader, HashAlgorithm::Blake3, HASH_CHUNK_SIZE).unwrap(), expected);
+        assert_eq!(reader.largest, HASH_CHUNK_SIZE);
+    }
 }
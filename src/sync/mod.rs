@@ -1,33 +1,326 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::path::{Path, PathBuf};
 use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use futures::StreamExt;
 use tokio::fs as async_fs;
-use crate::config::Config;
-use crate::classifier::{FileClassifier, FileType};
-use crate::state::{StateManager, FileState, PendingSync, calculate_file_hash, current_timestamp};
-use crate::drive::DriveDetector;
+use crate::config::{Config, ConflictPolicy, DriveConfig, DriveSelection, PendingOrder, QuarantineMode, SameDeviceStrategy, STAGING_DIR};
+use crate::clock::Clock;
+use crate::classifier::{FileClassifier, FileInfo, FileType, PatternClassifier};
+use crate::state::{
+    StateManager, BatchEntry, ChunkHashes, DirListing, DriveSpeed, FileState, LinkKind, PartialCopy, PendingSync, QuarantinedFile, RunRecord, StagedFile, SyncBatch,
+    SyncDirection, calculate_file_hash,
+    calculate_file_hash_async, calculate_compressed_file_hash, calculate_chunk_hashes, calculate_file_hash_with_chunks, calculate_prefix_hash, calculate_reader_hash,
+    HashAlgorithm, HASH_CHUNK_SIZE, MIN_SPEED_SAMPLE_BYTES, RUN_FAILURES_KEPT,
+};
+use crate::drive::{DriveDetector, DriveInfo, DriveProvider};
 use crate::error::{OrchestratorError, Result};
-use tracing::{info, warn, error};
+use crate::events::{Event, EventBus};
+use crate::hooks;
+use crate::manifest::{self, DriveManifest, ManifestEntry};
+#[cfg(feature = "metrics")]
+use crate::metrics::Metrics;
+use crate::sanitize;
+use tracing::{debug, info, warn, error};
 
+/// Classifies source files, picks their drive and copies them, recording
+/// each copy in the [`StateManager`]. Files whose drive isn't connected are
+/// queued and copied when it comes back.
 pub struct SyncManager {
     config: Config,
+    patterns: PatternClassifier,
+    /// `rules.subfolders`, by category
+    subfolders: HashMap<String, PatternClassifier>,
     state: StateManager,
-    drive_detector: DriveDetector,
+    /// The state's clock, see [`SyncManager::with_clock`]
+    clock: Arc<dyn Clock>,
+    drive_detector: Box<dyn DriveProvider>,
+    /// Where `config` was loaded from, so drive bindings can be saved back
+    config_path: Option<PathBuf>,
+    /// Receives a [`BatchProgress`] every `progress_every` files of a batch
+    progress_tx: Option<tokio::sync::mpsc::UnboundedSender<BatchProgress>>,
+    /// Hashes computed ahead of time by `hash_workers` during a batch
+    hash_cache: HashCache,
+    /// Drive roots already probed for case-insensitive names
+    case_insensitive_roots: HashMap<PathBuf, bool>,
+    /// Where sync events are streamed, for `fo run --events-socket`
+    events: Option<EventBus>,
+    /// Drives that were online at the last drive check
+    online_drives: HashSet<String>,
+    /// Drives already warned about being mounted away from their configured path
+    reported_moves: HashSet<String>,
+    /// The full sync or pending run in progress, collecting what it copies
+    batch: Option<SyncBatch>,
+    /// The drive list was refreshed since the open batch started
+    drives_refreshed: bool,
+    /// Drive speeds read for `select_drive` since the open batch started
+    drive_speeds: Option<HashMap<String, DriveSpeed>>,
+    /// Id of the last batch that copied anything
+    last_batch: Option<u64>,
+    /// Drives whose manifests are out of date, with `drive_manifests` on
+    manifest_dirty: HashSet<String>,
+    /// Last total size saved for each drive, so it's only written when it changes
+    drive_capacities: HashMap<String, u64>,
+    /// Read every directory even where a cached listing is current
+    full_walk: bool,
+    /// Drain pending queues a step at a time, see `with_interleaved_pending`
+    interleave_pending: bool,
+    /// During a full sync with `group_sidecars`, the files of each walked
+    /// folder that can have sidecars, so `sidecar_route` needn't list the
+    /// folder again for every sidecar
+    sidecar_primaries: HashMap<PathBuf, Vec<PathBuf>>,
+    /// Whether each drive root took a test file, as found since the last
+    /// drive check
+    writable: HashMap<PathBuf, bool>,
+    /// Files that couldn't be read to classify them, with how many times
+    unreadable: HashMap<PathBuf, u32>,
+    /// Drives whose queue has files left for `continue_pending`, with the
+    /// files still to go; each queue is read once per drive check
+    draining: Vec<(String, VecDeque<PendingSync>)>,
+    /// The batch of a drain in progress while it is set aside
+    drain_batch: Option<SyncBatch>,
+    /// The snapshot this run copies into, once one is needed
+    snapshot_id: Option<String>,
+    /// The `fo` command runs are recorded under
+    command: Option<String>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Arc<Metrics>>,
+}
+
+/// Hashes of source files keyed by path, each valid only while the file's
+/// size and modification time are unchanged
+#[derive(Clone, Default)]
+struct HashCache(Arc<Mutex<HashMap<PathBuf, CachedHash>>>);
+
+struct CachedHash {
+    fingerprint: (u64, SystemTime),
+    algorithm: HashAlgorithm,
+    hash: String,
+    chunks: Option<Vec<String>>,
+}
+
+impl HashCache {
+    /// Hash `path`, and its pieces with `chunk_hash`, and remember the result
+    fn fill(&self, path: &Path, algorithm: HashAlgorithm, chunk_hash: Option<u64>) {
+        // Fingerprint first: a change during hashing then shows up as a mismatch
+        let Some(fingerprint) = file_fingerprint(path) else {
+            return;
+        };
+        if let Ok((hash, chunks)) = hash_with_chunks(path, algorithm, chunk_hash) {
+            let entry = CachedHash { fingerprint, algorithm, hash, chunks };
+            self.0.lock().unwrap().insert(path.to_path_buf(), entry);
+        }
+    }
+
+    /// Take the cached hash and piece hashes for `path` if the file hasn't
+    /// changed since
+    fn take(&self, path: &Path, algorithm: HashAlgorithm) -> Option<(String, Option<Vec<String>>)> {
+        let entry = self.0.lock().unwrap().remove(path)?;
+        (entry.algorithm == algorithm && file_fingerprint(path) == Some(entry.fingerprint))
+            .then_some((entry.hash, entry.chunks))
+    }
+
+    fn clear(&self) {
+        self.0.lock().unwrap().clear();
+    }
+}
+
+/// Folder at a drive's root holding one folder per snapshot
+pub const SNAPSHOTS_DIR: &str = "snapshots";
+
+/// Snapshot folder names: the run's local start time, sorting by date and
+/// valid on FAT
+const SNAPSHOT_ID_FORMAT: &str = "%Y-%m-%d_%H%M%S";
+
+/// How long a directory must go unmodified before its listing is cached
+const DIR_LISTING_SETTLE: std::time::Duration = std::time::Duration::from_secs(3);
+
+/// How long to wait before trying a file again that couldn't be read to
+/// classify it
+const UNREADABLE_RETRY: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Reads of an unreadable file before it is reported as failed rather than
+/// tried again, about five minutes at [`UNREADABLE_RETRY`]
+const UNREADABLE_ATTEMPTS: u32 = 10;
+
+/// Hash a source file, along with its piece hashes from the same read when
+/// it is at least `chunk_hash` bytes
+fn hash_with_chunks(
+    path: &Path,
+    algorithm: HashAlgorithm,
+    chunk_hash: Option<u64>,
+) -> std::io::Result<(String, Option<Vec<String>>)> {
+    let file = fs::File::open(path)?;
+    match chunk_hash.filter(|chunk| file.metadata().is_ok_and(|meta| meta.len() >= *chunk)) {
+        Some(chunk_size) => {
+            let (hash, chunks) = calculate_file_hash_with_chunks(path, algorithm, chunk_size)?;
+            Ok((hash, Some(chunks)))
+        }
+        None => Ok((calculate_reader_hash(file, algorithm, HASH_CHUNK_SIZE)?, None)),
+    }
+}
+
+fn file_fingerprint(path: &Path) -> Option<(u64, SystemTime)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.len(), metadata.modified().ok()?))
 }
 
 impl SyncManager {
     /// Create a new sync manager
     pub fn new(config: Config, state: StateManager) -> Self {
+        // Config::load has already rejected invalid patterns
+        let patterns = PatternClassifier::new(&config.rules.patterns, config.rules.pattern_syntax)
+            .unwrap_or_else(|e| {
+                error!("Ignoring pattern rules: {}", e);
+                PatternClassifier::default()
+            });
+        let subfolders = config.rules.subfolder_classifiers().unwrap_or_else(|e| {
+            error!("Ignoring subfolder rules: {}", e);
+            HashMap::new()
+        });
+
+        state.set_flush_interval(std::time::Duration::from_millis(config.sync.state_flush_interval_ms));
+        match state.set_name_normalization(config.sync.name_normalization()) {
+            Ok(0) => {}
+            Ok(moved) => info!("Re-keyed {} sync records for the new filename normalization", moved),
+            Err(e) => error!("Failed to apply filename normalization to sync records: {}", e),
+        }
+
         Self {
             config,
+            patterns,
+            subfolders,
+            clock: state.clock(),
             state,
-            drive_detector: DriveDetector::new(),
+            drive_detector: Box::new(DriveDetector::new()),
+            config_path: None,
+            progress_tx: None,
+            hash_cache: HashCache::default(),
+            case_insensitive_roots: HashMap::new(),
+            events: None,
+            online_drives: HashSet::new(),
+            reported_moves: HashSet::new(),
+            batch: None,
+            drives_refreshed: false,
+            drive_speeds: None,
+            last_batch: None,
+            manifest_dirty: HashSet::new(),
+            drive_capacities: HashMap::new(),
+            full_walk: false,
+            interleave_pending: false,
+            sidecar_primaries: HashMap::new(),
+            writable: HashMap::new(),
+            unreadable: HashMap::new(),
+            draining: Vec::new(),
+            drain_batch: None,
+            snapshot_id: None,
+            command: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
         }
     }
 
+    /// Record the runs this manager makes as started by `command`
+    pub fn with_command(mut self, command: &str) -> Self {
+        self.command = Some(command.to_string());
+        self
+    }
+
+    /// Ignore `dir_cache` listings and read every directory, saving fresh
+    /// listings as it goes
+    pub fn with_full_walk(mut self) -> Self {
+        self.full_walk = true;
+        self
+    }
+
+    /// Have drive checks sync only the first `hash_workers` (at least one)
+    /// files of each pending queue, hashed concurrently but copied one
+    /// after another, and leave the rest
+    /// to repeated `continue_pending` calls. `fo run` releases the manager
+    /// between those, so watcher events don't wait for a big catch-up.
+    pub fn with_interleaved_pending(mut self) -> Self {
+        self.interleave_pending = true;
+        self
+    }
+
+    /// Remember the config file so discovered drive paths can be persisted
+    pub fn with_config_path<P: AsRef<Path>>(mut self, config_path: P) -> Self {
+        self.config_path = Some(config_path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Also send batch progress updates to `tx`, e.g. for a progress bar
+    pub fn with_progress_channel(mut self, tx: tokio::sync::mpsc::UnboundedSender<BatchProgress>) -> Self {
+        self.progress_tx = Some(tx);
+        self
+    }
+
+    /// Count every sync outcome in `metrics` for the `/metrics` endpoint
+    #[cfg(feature = "metrics")]
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Publish what happens to each file, and drive connections, on `events`
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Look drives up through `provider` instead of the OS, e.g. a
+    /// `MockDriveProvider` in tests
+    pub fn with_drive_provider(mut self, provider: impl DriveProvider + 'static) -> Self {
+        self.drive_detector = Box::new(provider);
+        self
+    }
+
+    /// Take the time from `clock` instead of the system clock, for sync
+    /// records, queue entries and settle checks alike, e.g. a `MockClock`
+    /// in tests
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.state.set_clock(clock.clone());
+        self.clock = clock;
+        self
+    }
+
     /// Sync a single file
     pub async fn sync_file<P: AsRef<Path>>(&mut self, source_path: P) -> Result<SyncResult> {
-        let source_path = source_path.as_ref();
-        
+        #[cfg(feature = "metrics")]
+        let started = std::time::Instant::now();
+
+        let result = self.sync_one(source_path.as_ref()).await;
+
+        #[cfg(feature = "metrics")]
+        if let Some(ref metrics) = self.metrics {
+            metrics.record(&result, started.elapsed());
+        }
+        if let Some(ref events) = self.events {
+            if let Some(event) = self.event_for(source_path.as_ref(), &result) {
+                events.send(event);
+            }
+        }
+        result
+    }
+
+    /// The event to publish for a `sync_file` outcome, if any
+    fn event_for(&self, source_path: &Path, result: &Result<SyncResult>) -> Option<Event> {
+        let source = source_path.to_path_buf();
+        match result {
+            Ok(SyncResult::Synced(target)) | Ok(SyncResult::Conflict(ConflictPolicy::Overwrite | ConflictPolicy::Rename, target)) => {
+                let (category, drive) = self.synced_destination(source_path).unzip();
+                Some(Event::FileSynced { source, target: target.clone(), category, drive })
+            }
+            Ok(SyncResult::Pending(drive)) | Ok(SyncResult::DriveReadOnly(drive)) | Ok(SyncResult::DriveFull(drive)) => {
+                Some(Event::Pending { source, drive: drive.clone() })
+            }
+            Err(e) => Some(Event::Error { source: Some(source), message: e.to_string() }),
+            Ok(_) => None,
+        }
+    }
+
+    async fn sync_one(&mut self, source_path: &Path) -> Result<SyncResult> {
         info!("Processing file: {}", source_path.display());
 
         // Check if file exists
@@ -37,33 +330,113 @@ impl SyncManager {
             ));
         }
 
-        // Classify the file
-        let file_info = FileClassifier::get_file_info(source_path)
-            .map_err(|e| OrchestratorError::Sync(format!("Failed to classify file: {}", e)))?;
+        // Sockets, FIFOs and devices (seen on network shares) can't be
+        // copied like a file; reading one may block forever
+        if !fs::metadata(source_path)?.is_file() {
+            info!("Skipping {}: not a regular file", source_path.display());
+            return Ok(SyncResult::Skipped("Not a regular file".to_string()));
+        }
 
-        if file_info.file_type == FileType::Unknown {
-            warn!("Unknown file type, skipping: {}", source_path.display());
-            return Ok(SyncResult::Skipped("Unknown file type".to_string()));
+        let quarantine_dir = self.quarantine_dir();
+        if quarantine_dir.as_ref().is_some_and(|dir| source_path.starts_with(dir)) {
+            return Ok(SyncResult::Skipped("In the quarantine directory".to_string()));
         }
 
-        let category = file_info.file_type.as_str();
+        if let Some(wait) = self.settle_wait(source_path)? {
+            info!("{} was modified too recently, checking again in {:?}", source_path.display(), wait);
+            return Ok(SyncResult::Unsettled(wait));
+        }
 
-        // Find target drive for this category
-        let (drive_uuid, drive_config) = self.config
-            .find_drive_for_category(category)
-            .ok_or_else(|| OrchestratorError::Sync(
-                format!("No drive configured for category: {}", category)
-            ))?;
+        // Classify the file. One that can't be read just now (locked,
+        // denied) is tried again later rather than skipped as unknown.
+        let file_info = match self.file_info(source_path) {
+            Ok(file_info) => {
+                self.unreadable.remove(source_path);
+                file_info
+            }
+            Err(OrchestratorError::Unreadable(reason)) => {
+                let attempts = self.unreadable.entry(source_path.to_path_buf()).or_default();
+                *attempts += 1;
+                if *attempts >= UNREADABLE_ATTEMPTS {
+                    self.unreadable.remove(source_path);
+                    return Err(OrchestratorError::Unreadable(format!("gave up after {} attempts: {}", UNREADABLE_ATTEMPTS, reason)));
+                }
+                warn!("Can't read {} yet, trying again in {:?}: {}", source_path.display(), UNREADABLE_RETRY, reason);
+                return Ok(SyncResult::Unsettled(UNREADABLE_RETRY));
+            }
+            Err(e) => return Err(OrchestratorError::Sync(format!("Failed to classify file: {}", e))),
+        };
+
+        if let Some(reason) = self.config.sync.size_rejection(file_info.size) {
+            info!("Skipping {}: {}", source_path.display(), reason);
+            return Ok(SyncResult::Skipped(reason));
+        }
+
+        let relative_path = source_path
+            .strip_prefix(&self.config.source.path)
+            .unwrap_or(source_path);
+
+        let sidecar = self.sidecar_route(source_path)?;
+        let category = match sidecar {
+            Some(ref route) => {
+                debug!("{} goes with {}", source_path.display(), route.primary.display());
+                route.category.clone()
+            }
+            None => {
+                let Some(category) = self.categorize(relative_path, &file_info) else {
+                    if let Some(dir) = quarantine_dir {
+                        return self.quarantine(source_path, relative_path, &dir, file_info.size).await;
+                    }
+                    warn!("Unknown file type, skipping: {}", source_path.display());
+                    return Ok(SyncResult::Skipped("Unknown file type".to_string()));
+                };
+                debug!("{} classified as {} (detected type {:?})", source_path.display(), category, file_info.mime);
+
+                if let Some(reason) = self.config.rules.mime_rejection(&category, file_info.mime) {
+                    info!("Skipping {}: {}", source_path.display(), reason);
+                    return Ok(SyncResult::Skipped(reason));
+                }
+                category
+            }
+        };
+        let category = category.as_str();
 
-        // Calculate file hash
-        let hash = calculate_file_hash(source_path)
-            .map_err(|e| OrchestratorError::Sync(format!("Failed to hash file: {}", e)))?;
+        // Calculate file hash, noting what the file looked like first so a
+        // write during the hash or copy is noticed
+        let fingerprint = file_fingerprint(source_path);
+        let algorithm = self.config.sync.hash_algorithm;
+        let (hash, chunks) = match self.hash_cache.take(source_path, algorithm) {
+            Some(hashed) => hashed,
+            None => self.hash_source(source_path, algorithm).await?,
+        };
 
         // Check if already synced and verify target file still exists
-        if let Some(file_state) = self.state.get_file_state(source_path)? {
-            if file_state.hash == hash {
+        let previous_state = self.state.get_file_state_async(source_path).await?;
+        if self.config.sync.replicates(category) {
+            let file = Outgoing { source_path, relative_path, file_info: &file_info, category, hash: &hash, chunks: chunks.as_deref(), fingerprint };
+            return self.sync_replicated(&file, previous_state).await;
+        }
+        if let Some(ref file_state) = previous_state {
+            // Records made before a hash_algorithm change are compared in their own algorithm
+            let unchanged = if file_state.hash_algorithm == algorithm {
+                file_state.hash == hash
+            } else {
+                calculate_file_hash_async(source_path, file_state.hash_algorithm).await? == file_state.hash
+            };
+
+            if unchanged {
                 // Verify the target file still exists
-                if file_state.target_path.exists() {
+                if file_state.target_path.exists() && self.snapshot_outdated(file_state) {
+                    debug!("{} is unchanged, adding it to the new snapshot", source_path.display());
+                } else if file_state.target_path.exists() {
+                    if file_state.hash_algorithm != algorithm {
+                        // Content is the same, so the new hash describes the target too
+                        self.state.save_file_state(&FileState {
+                            hash,
+                            hash_algorithm: algorithm,
+                            ..file_state.clone()
+                        })?;
+                    }
                     info!("File already synced: {}", source_path.display());
                     return Ok(SyncResult::AlreadySynced);
                 } else {
@@ -73,271 +446,6508 @@ impl SyncManager {
             }
         }
 
-        // Check if target drive is connected
-        self.drive_detector.refresh();
-        
-        let drive_connected = if let Some(ref path) = drive_config.path {
-            self.drive_detector.is_drive_connected(path)
-        } else {
-            // Try to find by label
-            self.drive_detector.find_drive_by_label(&drive_config.label).is_some()
-        };
-
-        if !drive_connected {
-            info!("Target drive not connected, adding to pending queue: {}", drive_config.label);
-            
-            let pending = PendingSync {
-                source_path: source_path.to_path_buf(),
-                file_category: category.to_string(),
-                target_drive: drive_uuid.clone(),
-                hash: hash.clone(),
-                size: file_info.size,
-                created_at: current_timestamp(),
-            };
-            
-            self.state.add_pending_sync(&pending)?;
-            return Ok(SyncResult::Pending(drive_config.label.clone()));
+        if self.config.sync.staging {
+            if let Some(staged) = self.state.get_staged(source_path)? {
+                if staged.state.hash == hash && staged.staged_path.exists() {
+                    info!("{} is staged already, waiting for `fo commit`", source_path.display());
+                    return Ok(SyncResult::AlreadySynced);
+                }
+            }
         }
 
-        // Get target path
-        let target_base = if let Some(ref path) = drive_config.path {
-            path.clone()
-        } else {
-            self.drive_detector
-                .find_drive_by_label(&drive_config.label)
-                .ok_or_else(|| OrchestratorError::DriveNotFound(drive_config.label.clone()))?
-                .mount_point
+        // Find target drive for this category; a sidecar goes where its
+        // primary went, or would go. Picked only now, as a file that is
+        // already synced needs no drive.
+        self.refresh_drives();
+        let extension = match sidecar {
+            Some(ref route) => route.extension.as_deref(),
+            None => file_info.extension.as_deref(),
         };
+        let primary_drive = sidecar
+            .as_ref()
+            .and_then(|route| route.drive.as_ref())
+            .and_then(|uuid| self.config.drives.get(uuid).map(|drive| (uuid.clone(), drive.clone())))
+            .filter(|(_, drive)| drive.target == category);
+        let (drive_uuid, drive_config) = primary_drive
+            .map_or_else(|| self.queued_drive(source_path, category, extension), |drive| Ok(Some(drive)))?
+            .or_else(|| self.select_drive(category, extension, file_info.size))
+            .ok_or_else(|| OrchestratorError::Sync(
+                format!("No drive configured for category: {}", category)
+            ))?;
+        let (drive_uuid, drive_config) = (&drive_uuid, &drive_config);
+        debug!("{} goes to drive {} ({})", source_path.display(), drive_config.label, drive_uuid);
 
-        // Create target directory structure (preserve relative path from source)
-        let relative_path = source_path
-            .strip_prefix(&self.config.source.path)
-            .unwrap_or(source_path);
-        
-        let target_path = target_base.join(category).join(relative_path);
+        let pending = PendingSync {
+            source_path: source_path.to_path_buf(),
+            file_category: category.to_string(),
+            target_drive: drive_uuid.clone(),
+            hash: hash.clone(),
+            size: file_info.size,
+            created_at: self.clock.timestamp(),
+            replica: false,
+        };
 
-        // Ensure target directory exists
-        if let Some(parent) = target_path.parent() {
-            async_fs::create_dir_all(parent).await
-                .map_err(|e| OrchestratorError::Sync(format!("Failed to create target directory: {}", e)))?;
+        if !self.is_drive_online(drive_config) {
+            if let Some((queued, capacity)) = self.queue_over_capacity(drive_uuid, &pending)? {
+                if self.config.sync.reject_over_capacity {
+                    warn!(
+                        "Not queueing {}: {} bytes already wait for {}, which holds {} bytes",
+                        source_path.display(), queued, drive_config.label, capacity
+                    );
+                    return Ok(SyncResult::WouldExceedCapacity(drive_config.label.clone()));
+                }
+                warn!(
+                    "Queue for {} ({} bytes with {}) is larger than the drive ({} bytes)",
+                    drive_config.label, queued + pending.size, source_path.display(), capacity
+                );
+            }
+            info!("Target drive not connected, adding to pending queue: {}", drive_config.label);
+            self.state.add_pending_sync_async(pending).await?;
+            return Ok(SyncResult::Pending(drive_config.label.clone()));
+        }
+        self.remember_capacity(drive_uuid, drive_config);
+        if let Some(percent) = self.paused_for_space(drive_config) {
+            warn!("Drive {} is {:.0}% full, adding to pending queue: {}", drive_config.label, percent, source_path.display());
+            self.state.add_pending_sync_async(pending).await?;
+            return Ok(SyncResult::DriveFull(drive_config.label.clone()));
         }
 
-        // Copy the file
-        info!("Copying {} -> {}", source_path.display(), target_path.display());
-        async_fs::copy(source_path, &target_path).await
-            .map_err(|e| OrchestratorError::Sync(format!("Failed to copy file: {}", e)))?;
+        let file = Outgoing { source_path, relative_path, file_info: &file_info, category, hash: &hash, chunks: chunks.as_deref(), fingerprint };
+        let placed = match self.place_on_drive(&file, drive_uuid, drive_config, previous_state.as_ref()).await {
+            // The abandoned copy may still be writing; queue the file so a
+            // later drive check tries it again rather than dropping it
+            Err(e @ OrchestratorError::Timeout(_)) => {
+                warn!("Copy to {} timed out, adding to pending queue: {}", drive_config.label, source_path.display());
+                self.state.add_pending_sync_async(pending).await?;
+                return Err(e);
+            }
+            placed => placed?,
+        };
+        let (target_path, conflict, compressed_size, reflinked, sparse, link, snapshot, commit_to) =
+            match placed {
+                Placement::Copied { target_path, conflict, compressed_size, reflinked, sparse, link, snapshot, commit_to, .. } => {
+                    (target_path, conflict, compressed_size, reflinked, sparse, link, snapshot, commit_to)
+                }
+                // Write-protected or mounted read-only; keep the file until that's fixed
+                Placement::ReadOnly => {
+                    warn!("Drive {} is read-only, adding to pending queue: {}", drive_config.label, source_path.display());
+                    self.state.add_pending_sync_async(pending).await?;
+                    return Ok(SyncResult::DriveReadOnly(drive_config.label.clone()));
+                }
+                // Queued, so the next drive check copies it again even if
+                // nothing else notices the write
+                Placement::SourceChanged => {
+                    self.state.add_pending_sync_async(pending).await?;
+                    return Ok(SyncResult::Unsettled(self.changed_source_wait(source_path)?));
+                }
+                Placement::NotCopied(result) => return Ok(result),
+            };
+        let (target_path, staged_path) = match commit_to {
+            Some(final_path) => (final_path, Some(target_path)),
+            None => (target_path, None),
+        };
+        let hook_tokens = file.hook_tokens(&target_path, &drive_config.label);
 
         // Save state
         let file_state = FileState {
             source_path: source_path.to_path_buf(),
             hash,
             size: file_info.size,
-            last_synced: current_timestamp(),
+            last_synced: self.clock.timestamp(),
             target_drive: drive_uuid.clone(),
             target_path: target_path.clone(),
             file_category: category.to_string(),
+            compressed_size,
+            direction: SyncDirection::Push,
+            reflinked,
+            sparse,
+            hash_algorithm: algorithm,
+            link,
+            snapshot,
+            tags: previous_state.as_ref().map(|previous| previous.tags.clone()).unwrap_or_default(),
+            note: previous_state.as_ref().and_then(|previous| previous.note.clone()),
+            replicas: Vec::new(),
         };
 
-        self.state.save_file_state(&file_state)?;
+        if let Some(staged_path) = staged_path {
+            self.state.add_staged(&StagedFile {
+                staged_path: staged_path.clone(),
+                state: file_state,
+                staged_at: self.clock.timestamp(),
+                replica: false,
+                conflict,
+            })?;
+            let _ = self.state.remove_pending_sync(source_path);
+            info!("Staged {} at {}", source_path.display(), staged_path.display());
+            return Ok(SyncResult::Staged(staged_path));
+        }
+        // Written straight to the library, so an older staged copy is moot
+        if let Some(staged) = self.state.get_staged(source_path)? {
+            let _ = async_fs::remove_file(&staged.staged_path).await;
+            self.state.remove_staged(&staged)?;
+        }
+
+        self.state.save_file_state_async(file_state.clone()).await?;
+        self.state.record_history_async(file_state.clone()).await?;
+        self.manifest_changed(drive_uuid);
+        if let Some(ref mut batch) = self.batch {
+            batch.entries.push(BatchEntry {
+                previous: previous_state.clone(),
+                written: file_state.clone(),
+                replica: false,
+                discarded: None,
+            });
+        }
 
         // Remove from pending if it was there
         let _ = self.state.remove_pending_sync(source_path);
 
+        if let Some(ref template) = self.config.hooks.post_sync {
+            // The file is synced either way; a failing hook is only logged
+            self.run_hook("post_sync", template, &hook_tokens).await;
+        }
+
         info!("Successfully synced: {}", source_path.display());
-        Ok(SyncResult::Synced(target_path))
+        match conflict {
+            Some(policy) => Ok(SyncResult::Conflict(policy, target_path)),
+            None => Ok(SyncResult::Synced(target_path)),
+        }
     }
 
-    /// Sync all files in the source directory
-    pub async fn sync_all(&mut self) -> Result<SyncSummary> {
-        let mut summary = SyncSummary::default();
-        
-        info!("Starting full sync from: {}", self.config.source.path.display());
+    /// Copy a file of a replicated category to every drive that takes it,
+    /// verifying each copy. Drives that are unplugged, read-only or fail
+    /// the copy get a queue entry of their own; the others are copied to
+    /// now, and each copy is recorded as soon as it is made.
+    async fn sync_replicated(&mut self, file: &Outgoing<'_>, mut record: Option<FileState>) -> Result<SyncResult> {
+        let algorithm = self.config.sync.hash_algorithm;
+        let drives: Vec<(String, DriveConfig)> = self.config
+            .drives_for_file(file.category, file.file_info.extension.as_deref())
+            .into_iter()
+            .map(|(uuid, drive)| (uuid.clone(), drive.clone()))
+            .collect();
+        self.refresh_drives();
 
-        let files = self.collect_files(&self.config.source.path)?;
-        
-        for file in files {
-            match self.sync_file(&file).await {
-                Ok(SyncResult::Synced(_)) => summary.synced += 1,
-                Ok(SyncResult::Pending(_)) => summary.pending += 1,
-                Ok(SyncResult::AlreadySynced) => summary.already_synced += 1,
-                Ok(SyncResult::Skipped(_)) => summary.skipped += 1,
+        let mut written = None;
+        let mut staged = None;
+        let mut waiting = Vec::new();
+        let mut failures = Vec::new();
+        let mut not_copied = None;
+        let mut source_changed = false;
+        for (drive_uuid, drive_config) in &drives {
+            let existing = record.as_ref().and_then(|record| record.copy_on(drive_uuid));
+            if let Some(ref copy) = existing {
+                // Copies made before a hash_algorithm change are compared in their own algorithm
+                let unchanged = if copy.hash_algorithm == algorithm {
+                    copy.hash == file.hash
+                } else {
+                    calculate_file_hash_async(file.source_path, copy.hash_algorithm).await? == copy.hash
+                };
+                if unchanged && copy.target_path.exists() && !self.snapshot_outdated(copy) {
+                    continue;
+                }
+            }
+            if self.config.sync.staging {
+                if let Some(waiting) = self.state.get_staged_replica(file.source_path, drive_uuid)? {
+                    if waiting.state.hash == file.hash && waiting.staged_path.exists() {
+                        debug!("{} is staged on {} already", file.source_path.display(), drive_config.label);
+                        continue;
+                    }
+                }
+            }
+
+            let queued = PendingSync {
+                source_path: file.source_path.to_path_buf(),
+                file_category: file.category.to_string(),
+                target_drive: drive_uuid.clone(),
+                hash: file.hash.to_string(),
+                size: file.file_info.size,
+                created_at: self.clock.timestamp(),
+                replica: true,
+            };
+            if !self.is_drive_online(drive_config) {
+                info!("{} not connected, queueing its copy of {}", drive_config.label, file.source_path.display());
+                self.state.add_pending_sync_async(queued).await?;
+                waiting.push(drive_config.label.clone());
+                continue;
+            }
+            self.remember_capacity(drive_uuid, drive_config);
+            if let Some(percent) = self.paused_for_space(drive_config) {
+                warn!("Drive {} is {:.0}% full, queueing its copy of {}", drive_config.label, percent, file.source_path.display());
+                self.state.add_pending_sync_async(queued).await?;
+                waiting.push(drive_config.label.clone());
+                continue;
+            }
+
+            let placed = self.place_on_drive(file, drive_uuid, drive_config, existing.as_ref()).await;
+            let (target_path, conflict, compressed_size, reflinked, sparse, link, verified, snapshot, commit_to) = match placed {
+                Ok(Placement::Copied { target_path, conflict, compressed_size, reflinked, sparse, link, verified, snapshot, commit_to }) => {
+                    (target_path, conflict, compressed_size, reflinked, sparse, link, verified, snapshot, commit_to)
+                }
+                Ok(Placement::ReadOnly) => {
+                    warn!("Drive {} is read-only, queueing its copy of {}", drive_config.label, file.source_path.display());
+                    self.state.add_pending_sync_async(queued).await?;
+                    waiting.push(drive_config.label.clone());
+                    continue;
+                }
+                Ok(Placement::NotCopied(result)) => {
+                    not_copied = Some(result);
+                    continue;
+                }
+                // Copies to the remaining drives would be just as suspect;
+                // the next pass copies the new content to all of them
+                Ok(Placement::SourceChanged) => {
+                    self.state.add_pending_sync_async(queued).await?;
+                    source_changed = true;
+                    break;
+                }
                 Err(e) => {
-                    error!("Failed to sync {}: {}", file.display(), e);
-                    summary.failed += 1;
+                    error!("Failed to copy {} to {}: {}", file.source_path.display(), drive_config.label, e);
+                    self.state.add_pending_sync_async(queued).await?;
+                    failures.push(format!("{}: {}", drive_config.label, e));
+                    continue;
                 }
+            };
+
+            if !verified && hash_stored_file(&target_path, compressed_size.is_some(), algorithm)? != file.hash {
+                error!("Copy of {} on {} does not match the source, discarded", file.source_path.display(), drive_config.label);
+                let _ = async_fs::remove_file(&target_path).await;
+                self.state.add_pending_sync_async(queued).await?;
+                failures.push(format!("{}: copy does not match the source", drive_config.label));
+                continue;
             }
-        }
 
-        Ok(summary)
-    }
+            let (target_path, staged_path) = match commit_to {
+                Some(final_path) => (final_path, Some(target_path)),
+                None => (target_path, None),
+            };
+            let copy = FileState {
+                source_path: file.source_path.to_path_buf(),
+                hash: file.hash.to_string(),
+                size: file.file_info.size,
+                last_synced: self.clock.timestamp(),
+                target_drive: drive_uuid.clone(),
+                target_path: target_path.clone(),
+                file_category: file.category.to_string(),
+                compressed_size,
+                direction: SyncDirection::Push,
+                reflinked,
+                sparse,
+                hash_algorithm: algorithm,
+                link,
+                snapshot,
+                tags: Vec::new(),
+                note: None,
+                replicas: Vec::new(),
+            };
+            if let Some(staged_path) = staged_path {
+                self.state.add_staged(&StagedFile {
+                    staged_path: staged_path.clone(),
+                    state: copy,
+                    staged_at: self.clock.timestamp(),
+                    replica: true,
+                    conflict,
+                })?;
+                let _ = self.state.remove_replica_pending(file.source_path, drive_uuid);
+                info!("Staged {} on {} at {}", file.source_path.display(), drive_config.label, staged_path.display());
+                staged.get_or_insert(staged_path);
+                continue;
+            }
+            // Written straight to the library, so an older staged copy is moot
+            if let Some(earlier) = self.state.get_staged_replica(file.source_path, drive_uuid)? {
+                let _ = async_fs::remove_file(&earlier.staged_path).await;
+                self.state.remove_staged(&earlier)?;
+            }
 
-    /// Process pending syncs for a specific drive
-    pub async fn process_pending_syncs(&mut self, drive_uuid: &str) -> Result<usize> {
-        let pending_syncs = self.state.get_pending_syncs(drive_uuid)?;
-        let count = pending_syncs.len();
+            self.state.record_history_async(copy.clone()).await?;
+            if let Some(ref mut batch) = self.batch {
+                batch.entries.push(BatchEntry { previous: existing.clone(), written: copy.clone(), replica: true, discarded: None });
+            }
+            let updated = match record.take() {
+                Some(record) => record.with_copy(copy),
+                None => copy,
+            };
+            self.state.save_file_state_async(updated.clone()).await?;
+            record = Some(updated);
+            self.manifest_changed(drive_uuid);
+            let _ = self.state.remove_replica_pending(file.source_path, drive_uuid);
 
-        info!("Processing {} pending syncs for drive {}", count, drive_uuid);
+            if let Some(ref template) = self.config.hooks.post_sync {
+                let hook_tokens = file.hook_tokens(&target_path, &drive_config.label);
+                self.run_hook("post_sync", template, &hook_tokens).await;
+            }
+            written.get_or_insert((target_path, conflict));
+        }
+        // Every drive now has its copy or an entry of its own, so an entry
+        // for the whole file (from queue-pending) is done with
+        let _ = self.state.remove_pending_sync(file.source_path);
+        if source_changed {
+            return Ok(SyncResult::Unsettled(self.changed_source_wait(file.source_path)?));
+        }
 
-        for pending in pending_syncs {
-            if pending.source_path.exists() {
-                match self.sync_file(&pending.source_path).await {
-                    Ok(_) => info!("Synced pending file: {}", pending.source_path.display()),
-                    Err(e) => error!("Failed to sync pending file: {}", e),
+        if (written.is_some() || staged.is_some()) && !failures.is_empty() {
+            warn!("{} still needs copying to {}", file.source_path.display(), failures.join("; "));
+        }
+        match (written, staged) {
+            (Some((target_path, conflict)), _) => {
+                info!("Successfully replicated: {}", file.source_path.display());
+                match conflict {
+                    Some(policy) => Ok(SyncResult::Conflict(policy, target_path)),
+                    None => Ok(SyncResult::Synced(target_path)),
                 }
-            } else {
-                warn!("Pending file no longer exists: {}", pending.source_path.display());
-                let _ = self.state.remove_pending_sync(&pending.source_path);
             }
+            (None, Some(staged_path)) => Ok(SyncResult::Staged(staged_path)),
+            (None, None) if !failures.is_empty() => Err(OrchestratorError::Sync(format!(
+                "Failed to replicate {}: {}",
+                file.source_path.display(), failures.join("; ")
+            ))),
+            (None, None) if !waiting.is_empty() => Ok(SyncResult::Pending(waiting.join(", "))),
+            (None, None) => Ok(not_copied.unwrap_or(SyncResult::AlreadySynced)),
         }
-
-        Ok(count)
     }
 
-    /// Collect all files from a directory recursively
-    fn collect_files(&self, dir: &Path) -> Result<Vec<PathBuf>> {
-        let mut files = Vec::new();
-        self.collect_files_recursive(dir, &mut files)?;
-        Ok(files)
-    }
+    /// Put a file on one drive: pick a target path that doesn't clobber a
+    /// file we didn't put there (`previous_target` is ours), run pre_sync,
+    /// copy and apply modes. Nothing is recorded.
+    async fn place_on_drive(
+        &mut self,
+        file: &Outgoing<'_>,
+        drive_uuid: &str,
+        drive_config: &DriveConfig,
+        previous: Option<&FileState>,
+    ) -> Result<Placement> {
+        let Outgoing { source_path, relative_path, file_info, category, hash, .. } = *file;
+        let algorithm = self.config.sync.hash_algorithm;
+        // Don't clobber a different file that we didn't put there
+        let previous_target = previous.map(|copy| copy.target_path.as_path());
 
-    fn collect_files_recursive(&self, dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
-        if !dir.is_dir() {
-            return Ok(());
+        // Get target path
+        let target_base = self.drive_root(drive_config)?;
+        if !self.drive_writable(&target_base) {
+            return Ok(Placement::ReadOnly);
         }
 
-        let entries = fs::read_dir(dir)
-            .map_err(|e| OrchestratorError::Sync(format!("Failed to read directory: {}", e)))?;
+        // Already-compressed formats are stored as-is even on compressing drives
+        let compress = drive_config.compress && file_info.file_type.is_compressible();
 
-        for entry in entries {
-            let entry = entry
-                .map_err(|e| OrchestratorError::Sync(format!("Failed to read entry: {}", e)))?;
-            let path = entry.path();
+        // Create target directory structure (preserve relative path from
+        // source, unless the drive keeps everything in one folder)
+        let snapshot = drive_config.snapshots.then(|| self.current_snapshot());
+        let category_root = self.file_folder(&target_base, snapshot.as_deref(), category, relative_path);
+        let normalized = self.config.sync.name_normalization().apply(relative_path);
+        let placed = match normalized.file_name() {
+            Some(name) if drive_config.flatten => Path::new(name),
+            _ => normalized.as_ref(),
+        };
+        let mut target_path = category_root.join(placed);
 
-            if path.is_dir() {
-                self.collect_files_recursive(&path, files)?;
-            } else if path.is_file() {
-                files.push(path);
+        // FAT/exFAT drives can't hold names like `12:30?.jpg`
+        let file_system = self.target_file_system(drive_config);
+        let fat_names = file_system.as_deref().is_some_and(sanitize::needs_fat_names);
+        if fat_names {
+            if let Some(clean) = sanitize::sanitize_relative_path(placed) {
+                let clean = category_root.join(clean);
+                let stored = if compress { compressed_path(&clean) } else { clean.clone() };
+                // Two source names can map to the same safe name
+                target_path = if Self::is_foreign_file(&stored, hash, algorithm, previous_target, compress)? {
+                    sanitize::disambiguate(&clean, relative_path)
+                } else {
+                    clean
+                };
+                info!("Using FAT-safe name {} for {}", target_path.display(), source_path.display());
             }
         }
 
-        Ok(())
-    }
+        // `Photo.JPG` and `photo.jpg` are the same file on exFAT, NTFS and
+        // default macOS volumes; types that can go either way are probed once
+        let case_insensitive = file_system.as_deref().is_some_and(sanitize::is_case_insensitive_fs)
+            || *self.case_insensitive_roots
+                .entry(target_base.clone())
+                .or_insert_with(|| probe_case_insensitive(&target_base));
+        let resolution = self.resolve_target(target_path, hash, previous_target, compress, case_insensitive)?;
+        let (target_path, conflict) = match resolution {
+            TargetResolution::Clear(path) => (path, None),
+            TargetResolution::Conflict(policy, path) => {
+                warn!("Target conflict for {} ({:?}): {}", source_path.display(), policy, path.display());
+                if policy == ConflictPolicy::Skip {
+                    return Ok(Placement::NotCopied(SyncResult::Conflict(policy, path)));
+                }
+                (path, Some(policy))
+            }
+        };
 
-    /// Get sync statistics
-    pub fn get_stats(&self) -> Result<crate::state::SyncStats> {
-        self.state.get_sync_stats()
-    }
+        let hook_tokens = file.hook_tokens(&target_path, &drive_config.label);
 
-    /// Verify that synced files still exist on target drives and re-queue if missing
-    async fn verify_synced_files(&mut self, drive_uuid: &str) -> Result<()> {
-        let all_states = self.state.get_all_file_states()?;
-        let mut re_synced = 0;
+        // With `staging`, copy beside the library; `fo commit` moves it in
+        let commit_to = self.config.sync.staging.then(|| target_path.clone());
+        let target_path = match commit_to {
+            Some(_) => staging_path(&target_base, &target_path),
+            None => target_path,
+        };
 
-        for file_state in all_states {
-            // Only check files synced to this drive
-            if file_state.target_drive != drive_uuid {
-                continue;
+        if let Some(ref template) = self.config.hooks.pre_sync {
+            let outcome = self.run_hook("pre_sync", template, &hook_tokens).await;
+            if !outcome.succeeded() {
+                return Ok(Placement::NotCopied(SyncResult::Skipped(format!("pre_sync hook did not succeed: {:?}", outcome))));
             }
+        }
 
-            // Check if target file still exists
-            if !file_state.target_path.exists() {
-                warn!(
-                    "Target file was deleted, re-syncing: {} -> {}",
-                    file_state.source_path.display(),
-                    file_state.target_path.display()
-                );
+        // Ensure target directory exists
+        if let Some(parent) = target_path.parent() {
+            async_fs::create_dir_all(parent).await
+                .map_err(|e| OrchestratorError::Sync(format!("Failed to create target directory: {}", e)))?;
 
-                // Check if source file still exists
-                if file_state.source_path.exists() {
-                    // Re-sync the file
-                    match self.sync_file(&file_state.source_path).await {
-                        Ok(_) => {
-                            re_synced += 1;
-                        }
-                        Err(e) => {
-                            error!("Failed to re-sync deleted file: {}", e);
-                        }
+            if let Some(mode) = self.config.sync.target_dir_mode.filter(|_| !fat_names) {
+                for dir in parent.ancestors().take_while(|dir| *dir != target_base && dir.starts_with(&target_base)) {
+                    apply_mode(dir, mode);
+                }
+            }
+        }
+
+        // Unchanged since an earlier snapshot: hard-link that copy rather
+        // than store the content again, where the drive can
+        let earlier = previous.filter(|copy| {
+            snapshot.is_some()
+                && copy.snapshot.is_some()
+                && copy.snapshot != snapshot
+                && copy.hash_algorithm == algorithm
+                && copy.hash == hash
+                && copy.is_compressed() == compress
+                && copy.target_path.exists()
+        });
+        if let Some(earlier) = earlier {
+            match link_file(&earlier.target_path, &target_path, LinkKind::Hardlink).await {
+                Ok(()) => {
+                    info!("Linking {} to its copy in {}", target_path.display(), earlier.target_path.display());
+                    return Ok(Placement::Copied {
+                        target_path,
+                        conflict,
+                        compressed_size: earlier.compressed_size,
+                        reflinked: false,
+                        sparse: earlier.sparse,
+                        link: None,
+                        verified: true,
+                        snapshot,
+                        commit_to,
+                    });
+                }
+                Err(e) => debug!("Can't hard-link {} ({}), copying instead", target_path.display(), e),
+            }
+        }
+
+        // Copy the file. FAT/exFAT can't store holes, so sparse files get a
+        // normal dense copy there.
+        let sparse = drive_config.sparse_copy && !fat_names;
+        let timeout = self.config.sync.per_file_timeout_secs.map(std::time::Duration::from_secs);
+        let same_device = same_file_system(source_path, &target_path);
+        let strategy = self.config.sync.same_device_strategy;
+        let link = match strategy {
+            SameDeviceStrategy::Hardlink => Some(LinkKind::Hardlink),
+            SameDeviceStrategy::Symlink => Some(LinkKind::Symlink),
+            SameDeviceStrategy::Copy | SameDeviceStrategy::Reflink => None,
+        }
+        .filter(|_| !compress && same_device && same_volume(source_path, &target_path));
+        let try_reflink = same_device && strategy == SameDeviceStrategy::Reflink;
+        // Copies through `.partial` are hash-checked on the way, unless they
+        // kept pieces of the previous copy
+        let resumable = !compress && !try_reflink && link.is_none();
+        let retry = self.transient_retry();
+        let copy = async {
+            if compress {
+                info!("Compressing {} -> {}", source_path.display(), target_path.display());
+                Ok((Some(compress_file(source_path, &target_path, retry).await?), false, false, None, false))
+            } else if let Some(kind) = link {
+                match link_file(source_path, &target_path, kind).await {
+                    Ok(()) => {
+                        info!("Linking ({:?}) {} -> {}", kind, target_path.display(), source_path.display());
+                        Ok((None, false, false, Some(kind), false))
+                    }
+                    Err(e) => {
+                        info!("Can't link {} ({}), copying instead", target_path.display(), e);
+                        let (reflinked, sparse) = copy_file(source_path, &target_path, sparse, retry).await?;
+                        Ok((None, reflinked, sparse, None, false))
                     }
-                } else {
-                    // Source file also deleted, remove from state
-                    info!("Source file also deleted, removing from state: {}", file_state.source_path.display());
-                    self.state.remove_file_state(&file_state.source_path)?;
                 }
+            } else if !resumable {
+                info!("Copying {} -> {}", source_path.display(), target_path.display());
+                let (reflinked, sparse) = copy_file(source_path, &target_path, sparse, retry).await?;
+                Ok((None, reflinked, sparse, None, false))
+            } else {
+                info!("Copying {} -> {}", source_path.display(), target_path.display());
+                let (sparse, read_back) = self.copy_resumable(file, &target_path, sparse, previous).await?;
+                Ok((None, false, sparse, None, read_back))
+            }
+        };
+        let copy_started = std::time::Instant::now();
+        let copied = with_copy_timeout(timeout, &target_path, resumable, copy).await;
+        if matches!(copied, Err(OrchestratorError::Timeout(_))) {
+            // with_copy_timeout removed the partial file, so don't offer it for resuming
+            let _ = self.state.remove_partial_copy(&target_path);
+        }
+        // The hash describes the source as it was before it was read; a
+        // write since then may have left the copy a mix of old and new. A
+        // resumable copy was checked against the hash before it replaced
+        // the target, and discarded there if the source had changed.
+        let checked = resumable && copied.is_ok();
+        if !checked && file.source_changed() {
+            warn!("{} changed while it was being copied, trying again later", source_path.display());
+            if copied.is_ok() {
+                let _ = async_fs::remove_file(&target_path).await;
             }
+            return Ok(Placement::SourceChanged);
+        }
+        let (compressed_size, reflinked, sparse, link, read_back) = copied?;
+        // A link's mode is the source's own
+        if let Some(mode) = self.config.sync.target_file_mode.filter(|_| !fat_names && link.is_none()) {
+            apply_mode(&target_path, mode);
         }
 
-        if re_synced > 0 {
-            info!("Re-synced {} files that were deleted from target", re_synced);
+        // A link has the source's attributes already
+        if self.config.sync.copy_xattrs && link.is_none() {
+            let (from, to) = (source_path.to_path_buf(), target_path.clone());
+            match tokio::task::spawn_blocking(move || copy_xattrs(&from, &to)).await {
+                Ok(Ok((_, failed))) if failed.is_empty() => {}
+                Ok(Ok((copied, failed))) => {
+                    let names: Vec<_> = failed.iter().map(|(name, _)| name.to_string_lossy()).collect();
+                    warn!(
+                        "{} kept {} of {} extended attributes of {}; not stored: {} ({})",
+                        drive_config.label,
+                        copied,
+                        copied + failed.len(),
+                        source_path.display(),
+                        names.join(", "),
+                        failed[0].1
+                    );
+                }
+                Ok(Err(e)) => warn!("Failed to read extended attributes of {}: {}", source_path.display(), e),
+                Err(e) => warn!("Extended attribute copy for {} failed: {}", source_path.display(), e),
+            }
         }
 
-        Ok(())
+        // A reflink or link doesn't write the data, so it says nothing about speed
+        let written = compressed_size.unwrap_or(file_info.size);
+        if !reflinked && link.is_none() && written >= MIN_SPEED_SAMPLE_BYTES {
+            if let Err(e) = self.state.record_copy_speed(drive_uuid, written, copy_started.elapsed()) {
+                warn!("Failed to record copy speed for {}: {}", drive_config.label, e);
+            }
+        }
+
+        // Kept pieces of a resumable copy weren't read back, so sync_replicated checks it
+        let verified = read_back || link.is_some();
+        Ok(Placement::Copied { target_path, conflict, compressed_size, reflinked, sparse, link, verified, snapshot, commit_to })
     }
 
-    /// Check for newly connected drives and process their pending syncs
-    pub async fn check_and_sync_connected_drives(&mut self) -> Result<()> {
+    /// Copy through `<target>.partial`, picking up where an interrupted
+    /// copy of the same source content left off. The finished file is
+    /// hash-checked before it is renamed into place. With `sparse`, holes
+    /// in the source are kept. With `chunk_hash`, a plain copy already at
+    /// `target` whose first pieces still match `source_chunks` keeps them
+    /// and only the rest is written (see [`Self::copy_tail`]). Returns
+    /// whether the copy has holes, and whether all of it was read back.
+    async fn copy_resumable(
+        &self,
+        file: &Outgoing<'_>,
+        target: &Path,
+        sparse: bool,
+        previous: Option<&FileState>,
+    ) -> Result<(bool, bool)> {
+        let (source, source_hash, size) = (file.source_path, file.hash, file.file_info.size);
+        let partial_path = partial_path(target);
+        let chunk_size = self.config.sync.chunk_hash.filter(|chunk| size >= *chunk);
+
+        let resume_from = match self.state.get_partial_copy(target)? {
+            Some(partial)
+                if partial.source_hash == source_hash
+                    && partial.expected_size == size
+                    && partial_path.exists() =>
+            {
+                let len = fs::metadata(&partial_path)?.len();
+                // Only trust the bytes already there if they match the source
+                let consistent = len <= size
+                    && calculate_prefix_hash(&partial_path, len)? == calculate_prefix_hash(source, len)?;
+                if consistent { len } else { 0 }
+            }
+            // A partial from different source content is stale
+            _ => 0,
+        };
+
+        if resume_from > 0 {
+            info!("Resuming copy of {} at {} of {} bytes", source.display(), resume_from, size);
+        } else {
+            if let (Some(chunk_size), Some(source_chunks)) = (chunk_size, file.chunks) {
+                if let Some(kept) = self.unchanged_prefix(target, chunk_size, previous, source_chunks)? {
+                    info!("Keeping the first {} bytes of {}, copying the other {}", kept, target.display(), size - kept);
+                    self.copy_tail(file, target, kept, chunk_size, source_chunks).await?;
+                    // The tail is written densely, so only the kept part can have holes
+                    return Ok((previous.is_some_and(|copy| copy.sparse), false));
+                }
+            }
+            self.state.save_partial_copy(&PartialCopy {
+                source_path: source.to_path_buf(),
+                target_path: target.to_path_buf(),
+                expected_size: size,
+                source_hash: source_hash.to_string(),
+            })?;
+        }
+
+        let (from, to, retry) = (source.to_path_buf(), partial_path.clone(), self.transient_retry());
+        let sparse = tokio::task::spawn_blocking(move || retry.run(&from, || copy_chunked(&from, &to, resume_from, sparse)))
+            .await
+            .map_err(|e| OrchestratorError::Sync(format!("Copy task failed: {}", e)))?
+            .map_err(|e| OrchestratorError::Sync(format!("Failed to copy file: {}", e)))?;
+
+        let algorithm = self.config.sync.hash_algorithm;
+        let (written_hash, chunks) = match chunk_size {
+            Some(chunk_size) => {
+                let partial = partial_path.clone();
+                let (hash, chunks) =
+                    tokio::task::spawn_blocking(move || calculate_file_hash_with_chunks(&partial, algorithm, chunk_size))
+                        .await
+                        .map_err(|e| OrchestratorError::State(format!("Hashing task failed: {}", e)))??;
+                (hash, Some(chunks))
+            }
+            None => (calculate_file_hash_async(&partial_path, algorithm).await?, None),
+        };
+
+        if written_hash != source_hash || file.source_changed() {
+            let _ = fs::remove_file(&partial_path);
+            self.state.remove_partial_copy(target)?;
+            return Err(OrchestratorError::Sync(format!(
+                "Copy of {} does not match the source, discarded",
+                source.display()
+            )));
+        }
+
+        // Windows won't rename over an existing file
+        if target.exists() {
+            async_fs::remove_file(target).await?;
+        }
+        async_fs::rename(&partial_path, target).await?;
+        self.state.remove_partial_copy(target)?;
+
+        if let (Some(chunk_size), Some(chunks)) = (chunk_size, chunks) {
+            self.save_whole_chunks(target, source_hash, size, chunk_size, &chunks)?;
+        }
+        Ok((sparse, true))
+    }
+
+    /// Keep the first `kept` bytes of the copy at `target` and replace the
+    /// rest with the source's. The rest is written to `<target>.tail` and
+    /// its pieces checked against `source_chunks` before the copy is cut
+    /// back and the tail appended, so a failed or mismatched copy leaves
+    /// `target` as it was. Kept pieces aren't read again: they were checked
+    /// when written and the copy is still the size it was synced at. If
+    /// the append itself fails, the copy no longer has that size and the
+    /// next sync copies the file in full.
+    async fn copy_tail(
+        &self,
+        file: &Outgoing<'_>,
+        target: &Path,
+        kept: u64,
+        chunk_size: u64,
+        source_chunks: &[String],
+    ) -> Result<()> {
+        let source = file.source_path;
+        let tail_path = tail_path(target);
+        let appended = async {
+            let (from, to, retry) = (source.to_path_buf(), tail_path.clone(), self.transient_retry());
+            tokio::task::spawn_blocking(move || retry.run(&from, || copy_from(&from, &to, kept)))
+                .await
+                .map_err(|e| OrchestratorError::Sync(format!("Copy task failed: {}", e)))?
+                .map_err(|e| OrchestratorError::Sync(format!("Failed to copy file: {}", e)))?;
+
+            let tail = tail_path.clone();
+            let written = tokio::task::spawn_blocking(move || calculate_chunk_hashes(&tail, chunk_size, 0))
+                .await
+                .map_err(|e| OrchestratorError::State(format!("Hashing task failed: {}", e)))??;
+            if written[..] != source_chunks[(kept / chunk_size) as usize..] || file.source_changed() {
+                return Err(OrchestratorError::Sync(format!(
+                    "Copy of {} does not match the source, discarded",
+                    source.display()
+                )));
+            }
+
+            let (tail, to) = (tail_path.clone(), target.to_path_buf());
+            tokio::task::spawn_blocking(move || append_at(&to, &tail, kept))
+                .await
+                .map_err(|e| OrchestratorError::Sync(format!("Copy task failed: {}", e)))?
+                .map_err(|e| OrchestratorError::Sync(format!("Failed to copy file: {}", e)))
+        }
+        .await;
+        let _ = async_fs::remove_file(&tail_path).await;
+        appended?;
+
+        self.save_whole_chunks(target, file.hash, file.file_info.size, chunk_size, source_chunks)
+    }
+
+    /// Keep the hashes of the whole pieces of the copy at `target`; a
+    /// shorter last piece can't match a later version's
+    fn save_whole_chunks(&self, target: &Path, hash: &str, size: u64, chunk_size: u64, chunks: &[String]) -> Result<()> {
+        self.state.save_chunk_hashes(&ChunkHashes {
+            target_path: target.to_path_buf(),
+            hash: hash.to_string(),
+            size,
+            chunk_size,
+            chunks: chunks[..(size / chunk_size) as usize].to_vec(),
+        })
+    }
+
+    /// With `chunk_hash`, how many bytes at the start of the plain copy
+    /// `previous` left at `target` still match the source, in whole pieces,
+    /// going by the piece hashes kept for the copy and `source_chunks`, the
+    /// source's from when it was hashed. The copy has to be the size it was
+    /// synced at; linked and snapshot copies share their data, so they are
+    /// never kept.
+    fn unchanged_prefix(
+        &self,
+        target: &Path,
+        chunk_size: u64,
+        previous: Option<&FileState>,
+        source_chunks: &[String],
+    ) -> Result<Option<u64>> {
+        let Some(previous) = previous.filter(|copy| {
+            copy.target_path == target
+                && copy.link.is_none()
+                && copy.snapshot.is_none()
+                && !copy.reflinked
+                && !copy.is_compressed()
+        }) else {
+            return Ok(None);
+        };
+        let Some(stored) = self.state.get_chunk_hashes(target)?.filter(|stored| {
+            stored.hash == previous.hash && stored.size == previous.size && stored.chunk_size == chunk_size
+        }) else {
+            return Ok(None);
+        };
+        if fs::metadata(target).map(|meta| meta.len()).ok() != Some(previous.size) {
+            return Ok(None);
+        }
+
+        let kept = stored.chunks.iter().zip(source_chunks).take_while(|(old, new)| old == new).count() as u64;
+        Ok((kept > 0).then_some(kept * chunk_size))
+    }
+
+    /// Classify a file, skipping the content read when its extension
+    /// settles the category (see [`crate::config::FileRules::confident_category`])
+    fn file_info(&self, path: &Path) -> Result<FileInfo> {
+        let known = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| self.config.rules.confident_category(&ext.to_lowercase()))
+            .and_then(FileType::from_category);
+
+        match known {
+            Some(file_type) => FileClassifier::get_file_info_as(path, file_type),
+            None => FileClassifier::get_file_info(path),
+        }
+    }
+
+    /// With `group_sidecars`, where a sidecar file follows its primary: the
+    /// file in the same folder (not itself a sidecar) whose name the
+    /// sidecar's extends, preferring the longest such name and then a file
+    /// that isn't an image, so subtitles pass a poster by. A primary that
+    /// is synced or queued already fixes the drive too; one that would be
+    /// skipped leaves the sidecar to its own rules.
+    fn sidecar_route(&self, source_path: &Path) -> Result<Option<SidecarRoute>> {
+        let sync = &self.config.sync;
+        let is_sidecar = |path: &Path| {
+            path.extension().is_some_and(|ext| sync.is_sidecar_extension(&ext.to_string_lossy().to_lowercase()))
+        };
+        if !sync.group_sidecars || !is_sidecar(source_path) {
+            return Ok(None);
+        }
+        let (Some(dir), Some(name)) = (source_path.parent(), source_path.file_name()) else {
+            return Ok(None);
+        };
+        let name = name.to_string_lossy();
+
+        let listed;
+        let candidates = match self.sidecar_primaries.get(dir) {
+            Some(walked) => walked,
+            None => {
+                let mut found = Vec::new();
+                for entry in fs::read_dir(dir)? {
+                    let path = entry?.path();
+                    if !is_sidecar(&path) && path.is_file() {
+                        found.push(path);
+                    }
+                }
+                listed = found;
+                &listed
+            }
+        };
+        let is_image = |path: &Path| {
+            path.extension()
+                .and_then(|ext| self.config.get_file_category(&ext.to_string_lossy()))
+                .is_some_and(|category| category == "images")
+        };
+        let mut primaries: Vec<(usize, bool, PathBuf)> = Vec::new();
+        for path in candidates {
+            let Some(stem) = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()) else {
+                continue;
+            };
+            if name.strip_prefix(stem.as_str()).is_some_and(|rest| rest.starts_with('.')) {
+                primaries.push((stem.len(), is_image(path), path.clone()));
+            }
+        }
+        primaries.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)).then_with(|| a.2.cmp(&b.2)));
+        let Some((_, _, primary)) = primaries.into_iter().next() else {
+            return Ok(None);
+        };
+        let extension = primary.extension().map(|ext| ext.to_string_lossy().to_lowercase());
+
+        if let Some(record) = self.state.get_file_state(&primary)? {
+            let drive = Some(record.target_drive);
+            return Ok(Some(SidecarRoute { primary, category: record.file_category, extension, drive }));
+        }
+        if let Some(pending) = self.state.get_pending_sync(&primary)? {
+            let drive = Some(pending.target_drive);
+            return Ok(Some(SidecarRoute { primary, category: pending.file_category, extension, drive }));
+        }
+
+        let relative = primary.strip_prefix(&self.config.source.path).unwrap_or(&primary);
+        let category = self
+            .file_info(&primary)
+            .ok()
+            .filter(|info| sync.size_rejection(info.size).is_none())
+            .and_then(|info| {
+                self.categorize(relative, &info)
+                    .filter(|category| self.config.rules.mime_rejection(category, info.mime).is_none())
+            });
+        match category {
+            Some(category) => Ok(Some(SidecarRoute { primary, category, extension, drive: None })),
+            None => {
+                info!("{} isn't synced, so {} is sorted by its own rules", primary.display(), source_path.display());
+                Ok(None)
+            }
+        }
+    }
+
+    /// The walked files that can have sidecars, by folder, when
+    /// `group_sidecars` is on
+    fn group_primaries(&self, files: &[PathBuf]) -> HashMap<PathBuf, Vec<PathBuf>> {
+        let sync = &self.config.sync;
+        let mut primaries: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+        if !sync.group_sidecars {
+            return primaries;
+        }
+        for file in files {
+            let Some(dir) = file.parent() else {
+                continue;
+            };
+            // A folder of nothing but sidecars still gets its (empty) entry
+            let folder = primaries.entry(dir.to_path_buf()).or_default();
+            if !file.extension().is_some_and(|ext| sync.is_sidecar_extension(&ext.to_string_lossy().to_lowercase())) {
+                folder.push(file.clone());
+            }
+        }
+        primaries
+    }
+
+    /// Category for a file: the first matching pattern rule, otherwise its
+    /// detected type. `None` for files nothing claims.
+    fn categorize(&self, relative_path: &Path, file_info: &FileInfo) -> Option<String> {
+        if let Some(category) = self.patterns.classify(relative_path) {
+            return Some(category.to_string());
+        }
+
+        // The content says one category and the extension lists another
+        let listed = file_info.extension.as_deref().and_then(|ext| self.config.get_file_category(ext));
+        let detected = file_info.file_type.as_str();
+        if let Some(listed) = listed.as_deref() {
+            if file_info.file_type != FileType::Unknown && listed != detected {
+                return self.config.rules.preferred(&[detected, listed]).map(str::to_string);
+            }
+        }
+
+        match file_info.file_type {
+            // Neither `infer` nor an extension said what this is
+            FileType::Unknown if file_info.mime.is_none() && file_info.extension.is_none() => {
+                let sniffing = self.config.rules.text_sniffing?;
+                FileClassifier::looks_like_text(&file_info.path, &sniffing)
+                    .ok()
+                    .filter(|&text| text)
+                    .map(|_| FileType::Document.as_str().to_string())
+            }
+            FileType::Unknown => None,
+            ref file_type => Some(file_type.as_str().to_string()),
+        }
+    }
+
+    /// Check what is already at `target_path` and apply the conflict policy.
+    /// Our own earlier copy (`previous_target`) or a file with identical content
+    /// is never treated as a conflict. With `compressed` the resolved path
+    /// carries the `.zst` suffix. With `case_insensitive` an existing file
+    /// whose name differs only in case counts as the same path, and its
+    /// on-disk name is the one returned.
+    fn resolve_target(
+        &self,
+        target_path: PathBuf,
+        hash: &str,
+        previous_target: Option<&Path>,
+        compressed: bool,
+        case_insensitive: bool,
+    ) -> Result<TargetResolution> {
+        let stored = |path: PathBuf| {
+            let path = if compressed { compressed_path(&path) } else { path };
+            if case_insensitive { on_disk_name(path) } else { path }
+        };
+
+        let algorithm = self.config.sync.hash_algorithm;
+        if !Self::is_foreign_file(&stored(target_path.clone()), hash, algorithm, previous_target, compressed)? {
+            return Ok(TargetResolution::Clear(stored(target_path)));
+        }
+
+        let policy = self.config.sync.conflict_policy;
+        match policy {
+            ConflictPolicy::Overwrite | ConflictPolicy::Skip => {
+                Ok(TargetResolution::Conflict(policy, stored(target_path)))
+            }
+            ConflictPolicy::Fail => Err(OrchestratorError::Sync(format!(
+                "Target already exists with different content: {}",
+                target_path.display()
+            ))),
+            ConflictPolicy::Rename => {
+                let stem = target_path.file_stem()
+                    .map(|s| s.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                let extension = target_path.extension()
+                    .map(|e| format!(".{}", e.to_string_lossy()))
+                    .unwrap_or_default();
+
+                for n in 1.. {
+                    let candidate = stored(target_path.with_file_name(format!("{}-{}{}", stem, n, extension)));
+                    if !Self::is_foreign_file(&candidate, hash, algorithm, previous_target, compressed)? {
+                        return Ok(TargetResolution::Conflict(policy, candidate));
+                    }
+                }
+                unreachable!()
+            }
+        }
+    }
+
+    /// Whether `path` exists and holds something other than this file's content
+    fn is_foreign_file(
+        path: &Path,
+        hash: &str,
+        algorithm: HashAlgorithm,
+        previous_target: Option<&Path>,
+        compressed: bool,
+    ) -> Result<bool> {
+        if !path.exists() || previous_target == Some(path) {
+            return Ok(false);
+        }
+        // An unreadable or non-zstd file under a .zst name is foreign too
+        let existing = hash_stored_file(path, compressed, algorithm).unwrap_or_default();
+        Ok(existing != hash)
+    }
+
+    /// Sync all files in the source directory
+    pub async fn sync_all(&mut self) -> Result<SyncSummary> {
+        info!("Starting full sync from: {}", self.config.source.path.display());
+
+        let started_at = self.clock.timestamp();
+        let files = self.collect_files(&self.config.source.path)?;
+        // Each full sync is a snapshot of its own on drives that keep them
+        self.snapshot_id = None;
+        self.sidecar_primaries = self.group_primaries(&files);
+        let opened = self.begin_batch("sync-all");
+        let summary = self.sync_files(files).await;
+        self.sidecar_primaries.clear();
+        if opened {
+            self.finish_batch();
+        }
+        self.prune_all_snapshots();
+        self.record_run("sync-all", started_at, &summary);
+        Ok(summary)
+    }
+
+    /// Keep a run's summary for `fo last-run`
+    fn record_run(&self, kind: &str, started_at: u64, summary: &SyncSummary) {
+        let run = self.state.next_run_id().map(|id| RunRecord {
+            id,
+            command: self.command.clone(),
+            kind: kind.to_string(),
+            started_at,
+            finished_at: self.clock.timestamp(),
+            synced: summary.synced,
+            pending: summary.pending,
+            already_synced: summary.already_synced,
+            skipped: summary.skipped,
+            quarantined: summary.quarantined,
+            conflicts: summary.conflicts,
+            failed: summary.failed,
+            unsettled: summary.unsettled.len(),
+            failures: summary.failures.iter().take(RUN_FAILURES_KEPT).cloned().collect(),
+        });
+        if let Err(e) = run.and_then(|run| self.state.record_run(&run)) {
+            error!("Failed to record the run's summary: {}", e);
+        }
+    }
+
+    /// Start collecting copies into a batch, unless one is already open.
+    /// Returns whether this call opened it.
+    fn begin_batch(&mut self, kind: &str) -> bool {
+        if self.batch.is_some() {
+            return false;
+        }
+        let id = match self.state.next_batch_id() {
+            Ok(id) => id,
+            Err(e) => {
+                error!("Failed to start a batch, this run can't be undone: {}", e);
+                return false;
+            }
+        };
+        self.batch = Some(SyncBatch {
+            id,
+            kind: kind.to_string(),
+            started_at: self.clock.timestamp(),
+            entries: Vec::new(),
+        });
+        self.drives_refreshed = false;
+        self.drive_speeds = None;
+        true
+    }
+
+    /// Refresh the drive list before copying a file. Within a batch that
+    /// happens once, for its first file; drive checks between runs notice
+    /// drives coming and going.
+    fn refresh_drives(&mut self) {
+        if self.batch.is_some() && self.drives_refreshed {
+            return;
+        }
         self.drive_detector.refresh();
+        self.drives_refreshed = self.batch.is_some();
+    }
 
-        // Collect drive info first to avoid borrowing issues
-        let drive_uuids: Vec<String> = self.config.drives.keys().cloned().collect();
+    /// Store the open batch if it copied anything
+    fn finish_batch(&mut self) {
+        self.drives_refreshed = false;
+        self.drive_speeds = None;
+        let Some(batch) = self.batch.take() else {
+            return;
+        };
+        if batch.entries.is_empty() {
+            return;
+        }
+        match self.state.save_batch(&batch) {
+            Ok(()) => {
+                info!("Recorded batch {} ({} files)", batch.id, batch.entries.len());
+                self.last_batch = Some(batch.id);
+            }
+            Err(e) => error!("Failed to record batch {}: {}", batch.id, e),
+        }
+    }
 
-        // Now process each drive
-        for drive_uuid in drive_uuids {
-            if let Some(drive_config) = self.config.drives.get(&drive_uuid).cloned() {
-                let is_connected = if let Some(ref path) = drive_config.path {
-                    self.drive_detector.is_drive_connected(path)
+    /// Copy a discarded staged file's source back to where it was staged,
+    /// if the source is still the content that was staged. Returns whether
+    /// it was.
+    fn restage(&self, staged: &StagedFile) -> Result<bool> {
+        let record = &staged.state;
+        let unchanged = record.source_path.is_file()
+            && calculate_file_hash(&record.source_path, record.hash_algorithm)? == record.hash;
+        // Staged again since, by a later sync
+        let restaged = if staged.replica {
+            self.state.get_staged_replica(&record.source_path, &record.target_drive)?
+        } else {
+            self.state.get_staged(&record.source_path)?
+        };
+        if !unchanged || restaged.is_some() {
+            return Ok(false);
+        }
+
+        if let Some(parent) = staged.staged_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if record.is_compressed() {
+            let output = fs::File::create(&staged.staged_path)?;
+            zstd::stream::copy_encode(fs::File::open(&record.source_path)?, &output, 0)?;
+        } else {
+            fs::copy(&record.source_path, &staged.staged_path)?;
+        }
+        self.state.add_staged(&StagedFile { staged_at: self.clock.timestamp(), ..staged.clone() })?;
+        Ok(true)
+    }
+
+    /// Id of the last full sync or pending run that copied anything
+    pub fn last_batch(&self) -> Option<u64> {
+        self.last_batch
+    }
+
+    /// Revert a batch (the latest one by default): delete the copies it made
+    /// and put back the records they replaced, where the earlier copy is
+    /// still on the drive. A file whose earlier copy was written over is
+    /// queued to sync again instead. Copies that are no longer the
+    /// batch's own, because the file was synced again since or the copy on
+    /// the drive was edited, are left alone. Copies on drives that aren't
+    /// connected stay in the batch for a later undo.
+    pub fn undo_batch(&mut self, id: Option<u64>) -> Result<UndoReport> {
+        let batch = match id {
+            Some(id) => self.state.get_batch(id)?,
+            None => self.state.latest_batch()?,
+        };
+        let Some(mut batch) = batch else {
+            return Err(OrchestratorError::State(match id {
+                Some(id) => format!("No batch {}", id),
+                None => "No batch to undo".to_string(),
+            }));
+        };
+
+        self.drive_detector.refresh();
+        let mut report = UndoReport { batch: batch.id, ..Default::default() };
+        let mut remaining = Vec::new();
+
+        // Newest first, so a file synced twice in the batch unwinds in order
+        for entry in batch.entries.drain(..).rev() {
+            let written = &entry.written;
+            let source = written.source_path.clone();
+
+            if let Some(ref staged) = entry.discarded {
+                let online = self.config.drives.get(&written.target_drive)
+                    .is_some_and(|drive| self.is_drive_online(drive));
+                if !online {
+                    report.offline.push(source);
+                    remaining.push(entry);
+                } else if self.restage(staged)? {
+                    report.restaged += 1;
                 } else {
-                    self.drive_detector.find_drive_by_label(&drive_config.label).is_some()
-                };
+                    report.superseded.push(source);
+                }
+                continue;
+            }
 
-                if is_connected {
-                    info!("Drive {} is connected, checking for pending syncs", drive_config.label);
-                    
-                    // Verify existing synced files still exist on target
-                    self.verify_synced_files(&drive_uuid).await?;
-                    
-                    let count = self.process_pending_syncs(&drive_uuid).await?;
-                    if count > 0 {
-                        info!("Processed {} pending syncs for {}", count, drive_config.label);
+            let current = self.state.get_file_state(&source)?;
+            let current_copy = match current {
+                Some(ref current) if entry.replica => current.copy_on(&written.target_drive),
+                ref current => current.clone(),
+            };
+            let still_ours = current_copy.as_ref().is_some_and(|current| {
+                current.target_path == written.target_path
+                    && current.hash == written.hash
+                    && current.last_synced == written.last_synced
+            });
+            if !still_ours {
+                report.superseded.push(source);
+                continue;
+            }
+
+            let online = self.config.drives.get(&written.target_drive)
+                .is_some_and(|drive| self.is_drive_online(drive));
+            if !online {
+                report.offline.push(source);
+                remaining.push(entry);
+                continue;
+            }
+
+            if written.target_path.exists() {
+                let on_drive = hash_stored_file(&written.target_path, written.compressed_size.is_some(), written.hash_algorithm)
+                    .unwrap_or_default();
+                if on_drive != written.hash {
+                    warn!("{} changed since batch {} wrote it, leaving it", written.target_path.display(), batch.id);
+                    report.changed.push(written.target_path.clone());
+                    continue;
+                }
+                fs::remove_file(&written.target_path)?;
+                self.state.remove_chunk_hashes(&written.target_path)?;
+                report.deleted += 1;
+            }
+
+            // A replica's entry covers its own drive; the file's other copies stay
+            let others = current.clone().filter(|_| entry.replica).and_then(|current| current.without_copy(&written.target_drive));
+            match entry.previous {
+                // A copy the batch wrote over is gone with it
+                Some(ref previous) if previous.target_path != written.target_path && previous.target_path.exists() => {
+                    let restored = match current {
+                        Some(current) if entry.replica => current.with_copy(previous.clone()),
+                        _ => previous.clone(),
+                    };
+                    self.state.save_file_state(&restored)?;
+                    self.manifest_changed(&previous.target_drive);
+                    report.restored += 1;
+                }
+                Some(_) => {
+                    match others {
+                        Some(ref others) => self.state.save_file_state(others)?,
+                        None => self.state.remove_file_state(&source)?,
+                    }
+                    if source.is_file() && written.direction == SyncDirection::Push {
+                        self.state.add_pending_sync(&PendingSync {
+                            source_path: source.clone(),
+                            file_category: written.file_category.clone(),
+                            target_drive: written.target_drive.clone(),
+                            hash: written.hash.clone(),
+                            size: written.size,
+                            created_at: self.clock.timestamp(),
+                            replica: entry.replica,
+                        })?;
+                        report.requeued.push(source);
                     }
                 }
+                None => match others {
+                    Some(ref others) => self.state.save_file_state(others)?,
+                    None => self.state.remove_file_state(&source)?,
+                },
             }
+            self.manifest_changed(&written.target_drive);
         }
 
-        Ok(())
+        if remaining.is_empty() {
+            self.state.remove_batch(batch.id)?;
+        } else {
+            // Keep the original order for the next attempt
+            remaining.reverse();
+            batch.entries = remaining;
+            self.state.save_batch(&batch)?;
+        }
+        self.write_manifests();
+        self.state.flush()?;
+        Ok(report)
     }
-}
 
-#[derive(Debug)]
-#[allow(dead_code)]
-pub enum SyncResult {
-    Synced(PathBuf),
-    Pending(String),
-    AlreadySynced,
-    Skipped(String),
-}
+    /// Sync all files in path order, stopping after `limit` files that
+    /// weren't already synced. With `resume`, start after where the last
+    /// limited run stopped. Where this run stopped is saved for the next
+    /// `resume`; also returns how many files it didn't get to.
+    pub async fn sync_all_from_cursor(&mut self, limit: Option<usize>, resume: bool) -> Result<(SyncSummary, usize)> {
+        let started_at = self.clock.timestamp();
+        let mut files = self.collect_files(&self.config.source.path)?;
+        if resume {
+            if let Some(cursor) = self.state.get_sync_cursor()? {
+                info!("Resuming full sync after {}", cursor.display());
+                let start = files.partition_point(|file| *file <= cursor);
+                files.drain(..start);
+            }
+        }
+        let total = files.len();
 
-#[derive(Debug, Default)]
-pub struct SyncSummary {
-    pub synced: usize,
-    pub pending: usize,
-    pub already_synced: usize,
-    pub skipped: usize,
-    pub failed: usize,
-}
+        // A resumed run adds to the snapshot the interrupted one started
+        if !resume {
+            self.snapshot_id = None;
+        } else if self.snapshot_id.is_none() {
+            self.snapshot_id = self.state.get_sync_cursor_snapshot()?;
+        }
+        let opened = self.begin_batch("sync-all");
+        let (summary, last) = self.sync_files_limited(files, limit).await;
+        if opened {
+            self.finish_batch();
+        }
 
-impl SyncSummary {
-    pub fn total(&self) -> usize {
-        self.synced + self.pending + self.already_synced + self.skipped + self.failed
+        let remaining = total - summary.total();
+        if remaining == 0 {
+            self.state.set_sync_cursor(None)?;
+            self.state.set_sync_cursor_snapshot(None)?;
+            self.prune_all_snapshots();
+        } else if let Some(ref last) = last {
+            self.state.set_sync_cursor(Some(last))?;
+            self.state.set_sync_cursor_snapshot(self.snapshot_id.as_deref())?;
+        }
+        self.record_run("sync-part", started_at, &summary);
+        self.write_manifests();
+        self.state.flush_async().await?;
+        Ok((summary, remaining))
     }
 
-    pub fn print(&self) {
-        println!("\n=== Sync Summary ===");
-        println!("Total files: {}", self.total());
-        println!("Synced: {}", self.synced);
-        println!("Already synced: {}", self.already_synced);
-        println!("Pending: {}", self.pending);
-        println!("Skipped: {}", self.skipped);
-        println!("Failed: {}", self.failed);
-        println!("====================\n");
+    /// Sync only source files modified after `cutoff`, skipping the rest
+    /// without hashing them. Also returns how many files the filter skipped.
+    pub async fn sync_modified_since(&mut self, cutoff: SystemTime) -> Result<(SyncSummary, usize)> {
+        info!("Starting incremental sync from: {}", self.config.source.path.display());
+
+        let started_at = self.clock.timestamp();
+        let files = self.collect_files(&self.config.source.path)?;
+        let total = files.len();
+        let recent: Vec<PathBuf> = files
+            .into_iter()
+            .filter(|file| {
+                fs::metadata(file)
+                    .and_then(|m| m.modified())
+                    .map(|modified| modified > cutoff)
+                    // Sync anything whose mtime can't be read rather than miss it
+                    .unwrap_or(true)
+            })
+            .collect();
+        let filtered = total - recent.len();
+
+        let summary = self.sync_files(recent).await;
+        self.record_run("sync-since", started_at, &summary);
+        Ok((summary, filtered))
+    }
+
+    /// Catch up on what changed while `fo run` wasn't watching: files
+    /// modified after `cutoff`, plus any file without a sync record (e.g.
+    /// copied in with its original modification time) or among the last
+    /// run's failures. Returns how many files were left alone.
+    pub async fn catch_up_since(&mut self, cutoff: SystemTime) -> Result<(SyncSummary, usize)> {
+        info!("Catching up on changes in: {}", self.config.source.path.display());
+
+        let started_at = self.clock.timestamp();
+        let files = self.collect_files(&self.config.source.path)?;
+        let total = files.len();
+        // A failed file may be older than the cutoff and still hold the
+        // record of an earlier copy
+        let failed: HashSet<PathBuf> = self.state.get_runs(1)?
+            .into_iter()
+            .flat_map(|run| run.failures.into_iter().map(|(path, _)| path))
+            .collect();
+        let mut changed = Vec::new();
+        for file in files {
+            let modified = fs::metadata(&file)
+                .and_then(|m| m.modified())
+                .map(|modified| modified > cutoff)
+                .unwrap_or(true);
+            if modified || failed.contains(&file) || self.state.get_file_state(&file)?.is_none() {
+                changed.push(file);
+            }
+        }
+        let unchanged = total - changed.len();
+
+        let summary = self.sync_files(changed).await;
+        self.record_run("catch-up", started_at, &summary);
+        Ok((summary, unchanged))
+    }
+
+    /// Record files that were seen but not synced (e.g. at shutdown) in the
+    /// pending queue, so they are synced when their drive is next checked.
+    /// Directories are expanded; returns how many files were queued.
+    pub async fn queue_pending(&mut self, path: &Path) -> Result<usize> {
+        let files = if path.is_dir() {
+            self.collect_files(path)?
+        } else if path.is_file() {
+            vec![path.to_path_buf()]
+        } else {
+            return Ok(0);
+        };
+
+        let quarantine_dir = self.quarantine_dir();
+        let algorithm = self.config.sync.hash_algorithm;
+        let mut queued = 0;
+        for file in files {
+            if quarantine_dir.as_ref().is_some_and(|dir| file.starts_with(dir)) {
+                continue;
+            }
+            let file_info = self.file_info(&file)
+                .map_err(|e| OrchestratorError::Sync(format!("Failed to classify file: {}", e)))?;
+            if self.config.sync.size_rejection(file_info.size).is_some() {
+                continue;
+            }
+            let relative_path = file.strip_prefix(&self.config.source.path).unwrap_or(&file);
+            // Files sync_file would skip anyway aren't worth a queue entry
+            let Some(category) = self.categorize(relative_path, &file_info) else {
+                continue;
+            };
+            if self.config.rules.mime_rejection(&category, file_info.mime).is_some() {
+                continue;
+            }
+            let Some(drive_uuid) = self.config
+                .find_drive_for_file(&category, file_info.extension.as_deref())
+                .map(|(uuid, _)| uuid.clone())
+            else {
+                continue;
+            };
+
+            let hash = calculate_file_hash_async(&file, algorithm).await
+                .map_err(|e| OrchestratorError::Sync(format!("Failed to hash file: {}", e)))?;
+            let synced = self.state.get_file_state_async(&file).await?
+                .is_some_and(|state| state.hash == hash && state.target_path.exists());
+            if synced {
+                continue;
+            }
+
+            self.state.add_pending_sync_async(PendingSync {
+                source_path: file,
+                file_category: category,
+                target_drive: drive_uuid,
+                hash,
+                size: file_info.size,
+                created_at: self.clock.timestamp(),
+                replica: false,
+            }).await?;
+            queued += 1;
+        }
+        Ok(queued)
+    }
+
+    /// Sync every file under `dir`, which need not be the source directory
+    pub async fn sync_directory(&mut self, dir: &Path) -> Result<SyncSummary> {
+        info!("Scanning directory: {}", dir.display());
+
+        let files = self.collect_files(dir)?;
+        Ok(self.sync_files(files).await)
+    }
+
+    async fn sync_files(&mut self, files: Vec<PathBuf>) -> SyncSummary {
+        self.sync_files_limited(files, None).await.0
+    }
+
+    /// Sync `files` in order until `limit` of them needed more than a
+    /// record check. Also returns the last file processed.
+    async fn sync_files_limited(&mut self, files: Vec<PathBuf>, limit: Option<usize>) -> (SyncSummary, Option<PathBuf>) {
+        let mut summary = SyncSummary::default();
+        let mut last = None;
+        if limit == Some(0) {
+            return (summary, last);
+        }
+        let total = files.len();
+        let started = std::time::Instant::now();
+        let every = self.config.sync.progress_every;
+        let prehash = self.spawn_prehash(&files);
+
+        for (index, file) in files.into_iter().enumerate() {
+            match self.sync_file(&file).await {
+                Ok(SyncResult::Synced(_)) => summary.synced += 1,
+                Ok(SyncResult::Pending(_)) | Ok(SyncResult::DriveReadOnly(_)) | Ok(SyncResult::DriveFull(_)) => summary.pending += 1,
+                Ok(SyncResult::AlreadySynced) => summary.already_synced += 1,
+                Ok(SyncResult::Skipped(_)) | Ok(SyncResult::WouldExceedCapacity(_)) => summary.skipped += 1,
+                Ok(SyncResult::Quarantined(_)) => summary.quarantined += 1,
+                Ok(SyncResult::Staged(_)) => summary.staged += 1,
+                Ok(SyncResult::Conflict(_, _)) => summary.conflicts += 1,
+                Ok(SyncResult::Unsettled(_)) => summary.unsettled.push(file.clone()),
+                Err(e) => {
+                    error!("Failed to sync {}: {}", file.display(), e);
+                    summary.failed += 1;
+                    summary.failures.push((file.clone(), e.to_string()));
+                }
+            }
+            last = Some(file);
+
+            let processed = index + 1;
+            if every > 0 && processed % every == 0 && processed < total {
+                let progress = BatchProgress::new(&summary, processed, total, started.elapsed());
+                progress.log();
+                if let Some(ref tx) = self.progress_tx {
+                    let _ = tx.send(progress);
+                }
+            }
+
+            if limit.is_some_and(|limit| summary.total() - summary.already_synced >= limit) {
+                info!("Stopping after {} files that weren't synced yet", summary.total() - summary.already_synced);
+                break;
+            }
+        }
+
+        if let Some(prehash) = prehash {
+            prehash.abort();
+            self.hash_cache.clear();
+        }
+
+        self.write_manifests();
+        if let Err(e) = self.state.flush_async().await {
+            error!("Failed to flush sync state: {}", e);
+        }
+        (summary, last)
+    }
+
+    /// Start hashing `files`, in order, on `hash_workers` blocking threads so
+    /// `sync_file` usually finds the hash ready while earlier files copy
+    fn spawn_prehash(&self, files: &[PathBuf]) -> Option<tokio::task::JoinHandle<()>> {
+        let workers = self.config.sync.hash_workers;
+        if workers == 0 || files.len() < 2 {
+            return None;
+        }
+
+        let cache = self.hash_cache.clone();
+        let (algorithm, chunk_hash) = (self.config.sync.hash_algorithm, self.config.sync.chunk_hash);
+        let files = files.to_vec();
+        Some(tokio::spawn(async move {
+            futures::stream::iter(files)
+                .for_each_concurrent(workers, |path| {
+                    let cache = cache.clone();
+                    async move {
+                        let _ = tokio::task::spawn_blocking(move || cache.fill(&path, algorithm, chunk_hash)).await;
+                    }
+                })
+                .await;
+        }))
+    }
+
+    /// Process pending syncs for a specific drive
+    pub async fn process_pending_syncs(&mut self, drive_uuid: &str) -> Result<usize> {
+        let mut queue = match self.draining.iter().position(|(uuid, _)| uuid == drive_uuid) {
+            Some(index) => self.draining.remove(index).1,
+            None => self.load_pending(drive_uuid)?.into(),
+        };
+
+        // Interleaved, only the next few go now and the rest of the queue is
+        // left in `draining` for `continue_pending`. Files that stay queued
+        // (a failed copy) aren't retried until the next drive check.
+        let pending_syncs: Vec<PendingSync> = if self.interleave_pending {
+            let step = self.config.sync.hash_workers.max(1);
+            let now: Vec<PendingSync> = queue.drain(..step.min(queue.len())).collect();
+            if !queue.is_empty() {
+                self.draining.push((drive_uuid.to_string(), queue));
+            }
+            now
+        } else {
+            queue.into()
+        };
+        let count = pending_syncs.len();
+
+        info!("Processing {} pending syncs for drive {}", count, drive_uuid);
+
+        let files: Vec<PathBuf> = pending_syncs.iter().map(|pending| pending.source_path.clone()).collect();
+        let prehash = self.spawn_prehash(&files);
+        let drive_config = self.config.drives.get(drive_uuid).cloned();
+
+        for (index, pending) in pending_syncs.into_iter().enumerate() {
+            // An unplugged drive keeps the rest of its queue for next time.
+            // The drive list isn't re-read within a batch, so look at the
+            // drive itself, as `drain_step` does.
+            if index > 0 && drive_config.as_ref().is_some_and(|drive| !self.drive_root(drive).is_ok_and(|root| root.is_dir())) {
+                warn!("Drive {} went away with {} pending syncs left", drive_uuid, count - index);
+                self.draining.retain(|(uuid, _)| uuid != drive_uuid);
+                break;
+            }
+            if let Some(percent) = drive_config.as_ref().and_then(|drive| self.paused_for_space(drive)) {
+                warn!("Drive {} is {:.0}% full, pausing with {} pending syncs left", drive_uuid, percent, count - index);
+                self.draining.retain(|(uuid, _)| uuid != drive_uuid);
+                break;
+            }
+            if pending.source_path.exists() {
+                match self.sync_file(&pending.source_path).await {
+                    Ok(_) => info!("Synced pending file: {}", pending.source_path.display()),
+                    Err(e) => error!("Failed to sync pending file: {}", e),
+                }
+            } else {
+                warn!("Pending file no longer exists: {}", pending.source_path.display());
+                let _ = self.state.remove_queued(&pending);
+            }
+        }
+
+        if let Some(prehash) = prehash {
+            prehash.abort();
+            self.hash_cache.clear();
+        }
+
+        Ok(count)
+    }
+
+    /// A drive's queue in the order it drains
+    fn load_pending(&self, drive_uuid: &str) -> Result<Vec<PendingSync>> {
+        let mut pending_syncs = self.state.get_pending_syncs(drive_uuid)?;
+        // With `fastest` any drive for the category will do, so files
+        // waiting for an unplugged sibling needn't keep waiting
+        if self.config.sync.drive_selection == DriveSelection::Fastest {
+            pending_syncs.extend(self.stranded_pending(drive_uuid)?);
+        }
+        sort_pending(&mut pending_syncs, self.config.sync.pending_order);
+        Ok(pending_syncs)
+    }
+
+    /// Drain the next few files of each drive whose queue a drive check
+    /// left part-done (see [`SyncManager::with_interleaved_pending`]).
+    /// Returns whether any queue still has files left.
+    pub async fn continue_pending(&mut self) -> Result<bool> {
+        if self.draining.is_empty() {
+            return Ok(false);
+        }
+
+        let owned = self.resume_drain_batch();
+        let result = self.drain_step().await;
+        self.end_drain_batch(owned);
+        if self.draining.is_empty() {
+            self.write_manifests();
+            self.state.flush_async().await?;
+        }
+        result.map(|()| !self.draining.is_empty())
+    }
+
+    /// One step of each draining queue. The drive check that started the
+    /// drain found the drives online and writable, so a step only makes sure
+    /// each drive is still mounted rather than asking the OS for every drive.
+    async fn drain_step(&mut self) -> Result<()> {
+        let uuids: Vec<String> = self.draining.iter().map(|(uuid, _)| uuid.clone()).collect();
+        for drive_uuid in uuids {
+            let Some(drive_config) = self.config.drives.get(&drive_uuid).cloned() else {
+                self.draining.retain(|(uuid, _)| *uuid != drive_uuid);
+                continue;
+            };
+            if !self.drive_root(&drive_config).is_ok_and(|root| root.is_dir()) {
+                warn!("Drive {} went away, its pending syncs wait for it to return", drive_config.label);
+                self.draining.retain(|(uuid, _)| *uuid != drive_uuid);
+                continue;
+            }
+            if let Some(percent) = self.paused_for_space(&drive_config) {
+                warn!("Drive {} is {:.0}% full, leaving its queue pending until space is freed", drive_config.label, percent);
+                self.draining.retain(|(uuid, _)| *uuid != drive_uuid);
+                continue;
+            }
+            self.process_pending_syncs(&drive_uuid).await?;
+        }
+        Ok(())
+    }
+
+    /// Take back the batch of a drain that is part-way through, or open a
+    /// new one. Returns whether this call owns it.
+    fn resume_drain_batch(&mut self) -> bool {
+        if self.batch.is_none() && self.drain_batch.is_some() {
+            self.batch = self.drain_batch.take();
+            return true;
+        }
+        self.begin_batch("process-pending")
+    }
+
+    /// Set the batch aside while queues are still draining, so files the
+    /// watcher syncs in between aren't undone with it; finish it otherwise
+    fn end_drain_batch(&mut self, owned: bool) {
+        if !owned {
+            return;
+        }
+        if self.draining.is_empty() {
+            self.finish_batch();
+        } else {
+            self.drain_batch = self.batch.take();
+        }
+    }
+
+    /// Collect all files from a directory recursively, sorted by path so
+    /// runs visit them in the same order
+    fn collect_files(&self, dir: &Path) -> Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        self.collect_files_recursive(dir, &mut files)?;
+        files.sort();
+        Ok(files)
+    }
+
+    fn collect_files_recursive(&self, dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+        if !dir.is_dir() {
+            return Ok(());
+        }
+
+        let listing = self.list_dir(dir)?;
+
+        for name in &listing.dirs {
+            let path = dir.join(name);
+            if self.config.source.should_descend(&path) && self.quarantine_dir().as_ref() != Some(&path) {
+                self.collect_files_recursive(&path, files)?;
+            } else {
+                debug!("Not scanning {}: excluded folder", path.display());
+            }
+        }
+
+        let has_size_bounds = self.config.sync.min_file_size.is_some()
+            || self.config.sync.max_file_size.is_some()
+            || !self.config.sync.sync_empty_files;
+        for name in &listing.files {
+            let path = dir.join(name);
+            if !self.config.source.in_scope(&path) {
+                debug!("Not scanning {}: outside the source scope", path.display());
+                continue;
+            }
+            // Leave files outside the size bounds out before anything hashes them
+            let size = if has_size_bounds { fs::metadata(&path).map(|m| m.len()).unwrap_or(0) } else { 0 };
+            match self.config.sync.size_rejection(size) {
+                None => files.push(path),
+                Some(reason) => debug!("Not scanning {}: {}", path.display(), reason),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The files and subdirectories directly in `dir`. With `dir_cache` the
+    /// listing saved in the state is used while the directory's modification
+    /// time matches it; otherwise the directory is read (and the listing saved).
+    fn list_dir(&self, dir: &Path) -> Result<DirListing> {
+        // Drive folders scanned for pulls are always read
+        let use_cache = self.config.source.dir_cache && dir.starts_with(&self.config.source.path);
+        let modified = fs::metadata(dir).and_then(|m| m.modified()).ok();
+        let cached = if use_cache { self.state.get_dir_listing(dir).unwrap_or(None) } else { None };
+
+        if let (Some(cached), Some(modified)) = (&cached, modified) {
+            if !self.full_walk && cached.modified == modified {
+                return Ok(cached.clone());
+            }
+        }
+
+        let entries = fs::read_dir(dir)
+            .map_err(|e| OrchestratorError::Sync(format!("Failed to read directory: {}", e)))?;
+        let mut listing = DirListing { modified: modified.unwrap_or(SystemTime::UNIX_EPOCH), files: Vec::new(), dirs: Vec::new() };
+        for entry in entries {
+            let entry = entry
+                .map_err(|e| OrchestratorError::Sync(format!("Failed to read entry: {}", e)))?;
+            let path = entry.path();
+            if path.is_dir() {
+                listing.dirs.push(entry.file_name().into());
+            } else if path.is_file() {
+                listing.files.push(entry.file_name().into());
+            } else {
+                debug!("Not scanning {}: not a regular file", path.display());
+            }
+        }
+
+        if !use_cache {
+            return Ok(listing);
+        }
+        // Subdirectories that are gone take their cached listings with them
+        for name in cached.iter().flat_map(|cached| &cached.dirs) {
+            if !listing.dirs.contains(name) {
+                let _ = self.state.remove_dir_listings(&dir.join(name));
+            }
+        }
+        // A directory changed within the last couple of seconds may change
+        // again without its time moving on (FAT keeps it to 2 s), so its
+        // listing is only trusted once it has been quiet for longer
+        let settled = modified
+            .and_then(|modified| self.clock.now().duration_since(modified).ok())
+            .is_some_and(|age| age >= DIR_LISTING_SETTLE);
+        if settled {
+            if let Err(e) = self.state.save_dir_listing(dir, &listing) {
+                warn!("Failed to cache the listing of {}: {}", dir.display(), e);
+            }
+        } else if cached.is_some() {
+            let _ = self.state.remove_dir_listing(dir);
+        }
+
+        Ok(listing)
+    }
+
+    /// Drop the cached listings affected by `path` being created or removed:
+    /// its directory's and, for a directory, its own and those below it
+    pub fn forget_dir_listing(&self, path: &Path) {
+        if !self.config.source.dir_cache {
+            return;
+        }
+        if let Some(parent) = path.parent() {
+            let _ = self.state.remove_dir_listing(parent);
+        }
+        let _ = self.state.remove_dir_listings(path);
+    }
+
+    /// Get sync statistics
+    pub fn get_stats(&self) -> Result<crate::state::SyncStats> {
+        self.state.get_sync_stats()
+    }
+
+    /// Get synced and pending totals per target drive UUID
+    pub fn get_stats_by_drive(&self) -> Result<std::collections::HashMap<String, crate::state::DriveStats>> {
+        self.state.get_stats_by_drive()
+    }
+
+    /// For each drive of a replicated category: how many of the category's
+    /// synced files (that the drive takes) have a current copy on it, and
+    /// how many there are
+    pub fn replication_completeness(&self) -> Result<HashMap<String, (usize, usize)>> {
+        let mut completeness: HashMap<String, (usize, usize)> = HashMap::new();
+        for record in self.state.get_all_file_states()? {
+            if !self.config.sync.replicates(&record.file_category) {
+                continue;
+            }
+            let extension = record.source_path
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase());
+            for (uuid, _) in self.config.drives_for_file(&record.file_category, extension.as_deref()) {
+                let current = record
+                    .copy_on(uuid)
+                    .is_some_and(|copy| copy.hash == record.latest_hash());
+                let (copies, files) = completeness.entry(uuid.clone()).or_default();
+                *copies += usize::from(current);
+                *files += 1;
+            }
+        }
+        Ok(completeness)
+    }
+
+    /// Category and drive label a source file was last synced to
+    pub fn synced_destination(&self, source_path: &Path) -> Option<(String, String)> {
+        let state = self.state.get_file_state(source_path).ok()??;
+        let label = self.config.drives
+            .get(&state.target_drive)
+            .map(|drive| drive.label.clone())
+            .unwrap_or(state.target_drive);
+        Some((state.file_category, label))
+    }
+
+    /// Most recently synced files, newest first
+    pub fn recent_syncs(&self, limit: usize) -> Result<Vec<FileState>> {
+        let mut states = self.state.get_all_file_states()?;
+        states.sort_by_key(|state| std::cmp::Reverse(state.last_synced));
+        states.truncate(limit);
+        Ok(states)
+    }
+
+    /// UUIDs of the registered drives that are connected right now
+    pub fn connected_drives(&mut self) -> Vec<String> {
+        self.drive_detector.refresh();
+
+        self.config.drives
+            .iter()
+            .filter(|(_, drive)| self.is_drive_online(drive))
+            .map(|(uuid, _)| uuid.clone())
+            .collect()
+    }
+
+    /// How many source files fall in each category; files nothing claims
+    /// are counted under "unknown"
+    pub fn source_category_counts(&self) -> Result<std::collections::BTreeMap<String, usize>> {
+        let mut counts = std::collections::BTreeMap::new();
+        for file in self.collect_files(&self.config.source.path)? {
+            let Ok(file_info) = self.file_info(&file) else {
+                continue;
+            };
+            let relative_path = file.strip_prefix(&self.config.source.path).unwrap_or(&file);
+            let category = self.categorize(relative_path, &file_info).unwrap_or_else(|| "unknown".to_string());
+            *counts.entry(category).or_insert(0) += 1;
+        }
+        Ok(counts)
+    }
+
+    /// Categories that have files in the source but no drive to go to, with
+    /// how many files each. Files of unknown type aren't included.
+    pub fn unrouted_categories(&self) -> Result<Vec<(String, usize)>> {
+        Ok(self.source_category_counts()?
+            .into_iter()
+            .filter(|(category, _)| category != "unknown" && !self.config.drives.values().any(|d| &d.target == category))
+            .collect())
+    }
+
+    /// [`is_writable`] for a drive root, probed once between drive checks
+    /// rather than for every file or status refresh
+    fn drive_writable(&mut self, root: &Path) -> bool {
+        *self.writable.entry(root.to_path_buf()).or_insert_with(|| is_writable(root))
+    }
+
+    /// UUIDs of connected drives that can't be written to
+    pub fn read_only_drives(&mut self) -> Vec<String> {
+        self.connected_drives()
+            .into_iter()
+            .filter(|uuid| {
+                let drive = &self.config.drives[uuid];
+                self.drive_root(drive).is_ok_and(|root| !self.drive_writable(&root))
+            })
+            .collect()
+    }
+
+    /// Connected drives past `pause_at_percent_full`, with how full they are
+    pub fn full_drives(&mut self) -> Vec<(String, f64)> {
+        self.connected_drives()
+            .into_iter()
+            .filter_map(|uuid| {
+                let percent = self.paused_for_space(&self.config.drives[&uuid])?;
+                Some((uuid, percent))
+            })
+            .collect()
+    }
+
+    /// Verify that synced files still exist on target drives and re-queue if missing
+    async fn verify_synced_files(&mut self, drive_uuid: &str) -> Result<()> {
+        let all_states = self.state.get_all_file_states()?;
+        let mut re_synced = 0;
+
+        for file_state in all_states.iter().flat_map(FileState::copies) {
+            // Only check files synced to this drive
+            if file_state.target_drive != drive_uuid {
+                continue;
+            }
+
+            // Check if target file still exists
+            if !file_state.target_path.exists() {
+                warn!(
+                    "Target file was deleted, re-syncing: {} -> {}",
+                    file_state.source_path.display(),
+                    file_state.target_path.display()
+                );
+
+                // Check if source file still exists
+                if file_state.source_path.exists() {
+                    // Re-sync the file
+                    match self.sync_file(&file_state.source_path).await {
+                        Ok(_) => {
+                            re_synced += 1;
+                        }
+                        Err(e) => {
+                            error!("Failed to re-sync deleted file: {}", e);
+                        }
+                    }
+                } else {
+                    // Source file also deleted, remove from state
+                    info!("Source file also deleted, removing from state: {}", file_state.source_path.display());
+                    self.state.remove_file_state(&file_state.source_path)?;
+                    self.manifest_changed(&file_state.target_drive);
+                }
+            }
+        }
+
+        if re_synced > 0 {
+            info!("Re-synced {} files that were deleted from target", re_synced);
+        }
+
+        Ok(())
+    }
+
+    /// How much longer a file must go unmodified to count as settled, if it
+    /// hasn't yet
+    fn settle_wait(&self, source_path: &Path) -> Result<Option<std::time::Duration>> {
+        let settle = std::time::Duration::from_secs(self.config.sync.settle_seconds);
+        if settle.is_zero() {
+            return Ok(None);
+        }
+
+        let modified = fs::metadata(source_path)?.modified()?;
+        // A modification time in the future can't be waited out
+        let Ok(age) = self.clock.now().duration_since(modified) else {
+            return Ok(None);
+        };
+        Ok(settle.checked_sub(age).filter(|wait| !wait.is_zero()))
+    }
+
+    /// How long to leave a file that changed during its copy before trying
+    /// again: until it has settled, or a few seconds without `settle_seconds`
+    fn changed_source_wait(&self, source_path: &Path) -> Result<std::time::Duration> {
+        Ok(self.settle_wait(source_path)?.unwrap_or(std::time::Duration::from_secs(5)))
+    }
+
+    /// Retries for copies and hash reads of busy files, from the config
+    fn transient_retry(&self) -> TransientRetry {
+        TransientRetry {
+            attempts: self.config.sync.transient_retries,
+            delay: std::time::Duration::from_millis(self.config.sync.transient_retry_delay_ms),
+        }
+    }
+
+    /// Hash a source file, reading it again while it is briefly busy
+    async fn hash_source(&self, path: &Path, algorithm: HashAlgorithm) -> Result<(String, Option<Vec<String>>)> {
+        let (path, retry, chunk_hash) = (path.to_path_buf(), self.transient_retry(), self.config.sync.chunk_hash);
+        tokio::task::spawn_blocking(move || retry.run(&path, || hash_with_chunks(&path, algorithm, chunk_hash)))
+        .await
+        .map_err(|e| OrchestratorError::Sync(format!("Hashing task failed: {}", e)))?
+        .map_err(|e| OrchestratorError::Sync(format!("Failed to hash file: {}", e)))
+    }
+
+    /// The snapshot this run's copies go into on drives with `snapshots`,
+    /// named after when the run first needed it
+    fn current_snapshot(&mut self) -> String {
+        self.snapshot_id
+            .get_or_insert_with(|| chrono::DateTime::<chrono::Local>::from(self.clock.now()).format(SNAPSHOT_ID_FORMAT).to_string())
+            .clone()
+    }
+
+    /// Whether `copy` is on a snapshot drive but not in this run's snapshot,
+    /// so even an unchanged file has to be put there
+    fn snapshot_outdated(&self, copy: &FileState) -> bool {
+        self.config.drives.get(&copy.target_drive).is_some_and(|drive| drive.snapshots)
+            && (self.snapshot_id.is_none() || copy.snapshot != self.snapshot_id)
+    }
+
+    /// The folder for `category` on a drive mounted at `root`, inside
+    /// `snapshot`'s folder for drives that keep snapshots
+    fn category_folder(&self, root: &Path, snapshot: Option<&str>, category: &str) -> PathBuf {
+        match snapshot {
+            Some(id) => root.join(SNAPSHOTS_DIR).join(id),
+            None => root.to_path_buf(),
+        }
+        .join(self.config.folder_for(category))
+    }
+
+    /// The folder a file at `relative_path` goes in: its category folder,
+    /// or the subfolder of it the first matching `rules.subfolders` rule gives
+    fn file_folder(&self, root: &Path, snapshot: Option<&str>, category: &str, relative_path: &Path) -> PathBuf {
+        let folder = self.category_folder(root, snapshot, category);
+        match self.subfolders.get(category).and_then(|rules| rules.classify(relative_path)) {
+            Some(subfolder) => folder.join(subfolder),
+            None => folder,
+        }
+    }
+
+    /// The snapshots on a connected drive, oldest first
+    pub fn list_snapshots(&self, drive_uuid: &str) -> Result<Vec<Snapshot>> {
+        let drive_config = self.config.drives.get(drive_uuid)
+            .ok_or_else(|| OrchestratorError::DriveNotFound(drive_uuid.to_string()))?;
+        if !self.is_drive_online(drive_config) {
+            return Err(OrchestratorError::DriveNotFound(format!("{} is not connected", drive_config.label)));
+        }
+
+        let dir = self.drive_root(drive_config)?.join(SNAPSHOTS_DIR);
+        let mut current: HashMap<String, usize> = HashMap::new();
+        // A deleted source's last copy doesn't hold its snapshot back
+        for copy in self.state.get_all_file_states()?.iter().flat_map(FileState::copies) {
+            if !copy.source_path.exists() {
+                continue;
+            }
+            if let (true, Some(id)) = (copy.target_drive == drive_uuid, copy.snapshot) {
+                *current.entry(id).or_default() += 1;
+            }
+        }
+
+        let mut snapshots = Vec::new();
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(snapshots),
+            Err(e) => return Err(e.into()),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if !path.is_dir() {
+                continue;
+            }
+            let id = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            snapshots.push(Snapshot {
+                files: count_files(&path)?,
+                current: current.get(&id).copied().unwrap_or(0),
+                id,
+                path,
+            });
+        }
+        // The names are dates, so this is oldest first
+        snapshots.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(snapshots)
+    }
+
+    /// Delete a drive's oldest snapshots beyond its `snapshot_retention`,
+    /// keeping any that still hold a file's latest copy. Returns the ids
+    /// deleted.
+    pub fn prune_snapshots(&self, drive_uuid: &str) -> Result<Vec<String>> {
+        let Some(keep) = self.config.drives.get(drive_uuid).and_then(|drive| drive.snapshot_retention) else {
+            return Ok(Vec::new());
+        };
+
+        let snapshots = self.list_snapshots(drive_uuid)?;
+        let excess = snapshots.len().saturating_sub(keep);
+        let mut pruned = Vec::new();
+        for snapshot in snapshots.into_iter().take(excess) {
+            if snapshot.current > 0 {
+                info!("Keeping snapshot {}: it has the latest copy of {} file(s)", snapshot.id, snapshot.current);
+                continue;
+            }
+            // Hard links elsewhere keep the content of files still in later snapshots
+            fs::remove_dir_all(&snapshot.path)?;
+            for record in self.state.get_all_file_states()? {
+                if record.target_drive == drive_uuid && record.snapshot.as_ref() == Some(&snapshot.id) && !record.source_path.exists() {
+                    self.state.remove_file_state(&record.source_path)?;
+                }
+            }
+            info!("Deleted snapshot {} from {}", snapshot.id, drive_uuid);
+            pruned.push(snapshot.id);
+        }
+        Ok(pruned)
+    }
+
+    /// Prune every connected drive that has a snapshot retention
+    fn prune_all_snapshots(&self) {
+        for (uuid, drive) in &self.config.drives {
+            if drive.snapshot_retention.is_none() || !self.is_drive_online(drive) {
+                continue;
+            }
+            if let Err(e) = self.prune_snapshots(uuid) {
+                error!("Failed to prune snapshots on {}: {}", drive.label, e);
+            }
+        }
+    }
+
+    /// Root directory of a connected drive
+    fn drive_root(&self, drive_config: &DriveConfig) -> Result<PathBuf> {
+        if let Some(ref path) = drive_config.path {
+            Ok(path.clone())
+        } else {
+            Ok(self.drive_detector
+                .find_registered_drive(drive_config.marker_id.as_deref(), drive_config.volume_uuid.as_deref(), &drive_config.label)
+                .ok_or_else(|| OrchestratorError::DriveNotFound(drive_config.label.clone()))?
+                .mount_point)
+        }
+    }
+
+    /// Run a hook command template with this file's tokens filled in
+    async fn run_hook(&self, name: &str, template: &str, tokens: &[(&str, String)]) -> hooks::HookOutcome {
+        let tokens: Vec<(&str, &str)> = tokens.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        let command = hooks::render(template, &tokens);
+        hooks::run(name, &command, std::time::Duration::from_secs(self.config.hooks.timeout_secs)).await
+    }
+
+    /// The configured quarantine directory, resolved against the source
+    fn quarantine_dir(&self) -> Option<PathBuf> {
+        self.config.sync.quarantine_dir.as_ref().map(|dir| self.config.source.path.join(dir))
+    }
+
+    /// Put an unknown-type file aside in the quarantine directory, keeping
+    /// its relative path, and record it so `status` can list it
+    async fn quarantine(&mut self, source_path: &Path, relative_path: &Path, dir: &Path, size: u64) -> Result<SyncResult> {
+        let mode = self.config.sync.quarantine_mode;
+
+        // A copy made on an earlier run is still there
+        if mode == QuarantineMode::Copy {
+            if let Some(existing) = self.state.get_quarantined(source_path)? {
+                if existing.quarantine_path.exists() {
+                    return Ok(SyncResult::Quarantined(existing.quarantine_path));
+                }
+            }
+        }
+
+        let mut target = dir.join(relative_path);
+        let stem = target.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        let extension = target.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+        for n in 1.. {
+            if !target.exists() {
+                break;
+            }
+            target = target.with_file_name(format!("{}-{}{}", stem, n, extension));
+        }
+
+        if let Some(parent) = target.parent() {
+            async_fs::create_dir_all(parent).await?;
+        }
+
+        match mode {
+            QuarantineMode::Copy => {
+                async_fs::copy(source_path, &target).await?;
+            }
+            // Across file systems a rename fails, so copy and remove instead
+            QuarantineMode::Move => {
+                if async_fs::rename(source_path, &target).await.is_err() {
+                    async_fs::copy(source_path, &target).await?;
+                    async_fs::remove_file(source_path).await?;
+                }
+            }
+        }
+        warn!("Unknown file type, quarantined {} -> {}", source_path.display(), target.display());
+
+        self.state.add_quarantined(&QuarantinedFile {
+            source_path: source_path.to_path_buf(),
+            quarantine_path: target.clone(),
+            size,
+            quarantined_at: self.clock.timestamp(),
+        })?;
+
+        Ok(SyncResult::Quarantined(target))
+    }
+
+    /// Unknown-type files currently in the quarantine directory
+    pub fn quarantined_files(&self) -> Result<Vec<QuarantinedFile>> {
+        self.state.get_all_quarantined()
+    }
+
+    /// The drive for a file of `category`, by `drive_selection`. With
+    /// `fastest`, that is the connected drive with room for `size` bytes
+    /// that has written quickest (unmeasured drives go first, to get measured);
+    /// when none is connected it is the first drive, for the pending queue.
+    /// Within a batch the speeds are read once, at its first pick.
+    fn select_drive(&mut self, category: &str, extension: Option<&str>, size: u64) -> Option<(String, DriveConfig)> {
+        let candidates: Vec<(String, DriveConfig)> = self.config
+            .drives_for_file(category, extension)
+            .into_iter()
+            .map(|(uuid, drive)| (uuid.clone(), drive.clone()))
+            .collect();
+        if self.config.sync.drive_selection == DriveSelection::First || candidates.len() < 2 {
+            return candidates.into_iter().next();
+        }
+
+        self.refresh_drives();
+        if self.drive_speeds.is_none() || self.batch.is_none() {
+            self.drive_speeds = Some(self.state.get_drive_speeds().unwrap_or_default());
+        }
+        let speeds = self.drive_speeds.as_ref().unwrap();
+        let speed = |uuid: &str| speeds.get(uuid).map_or(f64::INFINITY, |speed| speed.bytes_per_sec);
+        let usable = candidates
+            .iter()
+            .filter(|(_, drive)| self.is_drive_online(drive))
+            .filter(|(_, drive)| self.target_drive_info(drive).is_none_or(|info| info.available_space >= size))
+            .filter(|(_, drive)| self.paused_for_space(drive).is_none())
+            // Ties keep the `first` order, since max_by keeps the last maximum
+            .rev()
+            .max_by(|(a, _), (b, _)| speed(a).total_cmp(&speed(b)))
+            .cloned();
+        usable.or_else(|| candidates.into_iter().next())
+    }
+
+    /// The drive a queued file is waiting for, if that drive is connected
+    /// and still takes the file. A queued file goes there rather than to a
+    /// fresh pick, so `reassign-pending` sticks.
+    fn queued_drive(&self, source_path: &Path, category: &str, extension: Option<&str>) -> Result<Option<(String, DriveConfig)>> {
+        let Some(pending) = self.state.get_pending_sync(source_path)? else {
+            return Ok(None);
+        };
+        Ok(self.config
+            .drives_for_file(category, extension)
+            .into_iter()
+            .find(|(uuid, drive)| **uuid == pending.target_drive && self.is_drive_online(drive))
+            .map(|(uuid, drive)| (uuid.clone(), drive.clone())))
+    }
+
+    /// Files queued for unplugged drives with the same target as
+    /// `drive_uuid` that this drive would also take
+    fn stranded_pending(&self, drive_uuid: &str) -> Result<Vec<PendingSync>> {
+        let Some(drive) = self.config.drives.get(drive_uuid) else {
+            return Ok(Vec::new());
+        };
+
+        let mut stranded = Vec::new();
+        for (sibling_uuid, sibling) in &self.config.drives {
+            if sibling_uuid == drive_uuid || sibling.target != drive.target || self.is_drive_online(sibling) {
+                continue;
+            }
+            // A replica entry is for that drive's own copy
+            let taken: Vec<PendingSync> = self.state.get_pending_syncs(sibling_uuid)?
+                .into_iter()
+                .filter(|pending| !pending.replica && pending_compatible(drive, pending))
+                .collect();
+            if !taken.is_empty() {
+                info!("{} can take {} files queued for unplugged {}", drive.label, taken.len(), sibling.label);
+            }
+            stranded.extend(taken);
+        }
+        Ok(stranded)
+    }
+
+    /// Move files queued for one drive to another, optionally only those of
+    /// one category. Files the new drive wouldn't take (another category,
+    /// or an extension outside its `accept_extensions`) stay where they are.
+    pub fn reassign_pending(&self, from: &str, to: &str, category: Option<&str>) -> Result<ReassignReport> {
+        for uuid in [from, to] {
+            if !self.config.drives.contains_key(uuid) {
+                return Err(OrchestratorError::DriveNotFound(uuid.to_string()));
+            }
+        }
+        let target = &self.config.drives[to];
+
+        let mut report = ReassignReport::default();
+        for pending in self.state.get_pending_syncs(from)? {
+            // Replicated files wait for every drive they go to, not just one
+            if category.is_some_and(|category| pending.file_category != category) || from == to || pending.replica {
+                continue;
+            }
+            if !pending_compatible(target, &pending) {
+                report.incompatible.push(pending.source_path);
+                continue;
+            }
+            // Queue entries are keyed by source path, so this replaces the old one
+            self.state.add_pending_sync(&PendingSync { target_drive: to.to_string(), ..pending })?;
+            report.moved += 1;
+        }
+        Ok(report)
+    }
+
+    /// Queue entries and sync records for drives that are no longer in the
+    /// config, e.g. after a drive's table was deleted from config.toml by hand
+    pub fn find_orphans(&self) -> Result<OrphanReport> {
+        let known = |drive: &str| self.config.drives.contains_key(drive);
+        Ok(OrphanReport {
+            pending: self.state.get_all_pending_syncs()?.into_iter().filter(|entry| !known(&entry.target_drive)).collect(),
+            records: self
+                .state
+                .get_all_file_states()?
+                .into_iter()
+                .filter(|record| record.copies().iter().any(|copy| !known(&copy.target_drive)))
+                .collect(),
+        })
+    }
+
+    /// Clear up what [`Self::find_orphans`] found. A queued file moves to the
+    /// drive the config now picks for it, or leaves the queue if there is
+    /// none (replica entries always leave; the other drives have their own).
+    /// Records forget their copies on removed drives, and a record with no
+    /// copy left is removed, so the next sync copies the file again.
+    pub fn fix_orphans(&self, orphans: &OrphanReport) -> Result<OrphanFix> {
+        let mut fix = OrphanFix::default();
+        for pending in &orphans.pending {
+            let extension = pending.source_path.extension().map(|ext| ext.to_string_lossy().to_lowercase());
+            let drive = self
+                .config
+                .find_drive_for_file(&pending.file_category, extension.as_deref())
+                .filter(|_| !pending.replica);
+            self.state.remove_queued(pending)?;
+            match drive {
+                Some((uuid, _)) => {
+                    self.state.add_pending_sync(&PendingSync { target_drive: uuid.clone(), ..pending.clone() })?;
+                    fix.requeued += 1;
+                }
+                None => fix.unqueued += 1,
+            }
+        }
+
+        for record in &orphans.records {
+            let mut copies = record
+                .copies()
+                .into_iter()
+                .filter(|copy| self.config.drives.contains_key(&copy.target_drive));
+            let Some(first) = copies.next() else {
+                self.state.remove_file_state(&record.source_path)?;
+                fix.forgotten += 1;
+                continue;
+            };
+            let kept = FileState { tags: record.tags.clone(), note: record.note.clone(), ..first };
+            self.state.save_file_state(&copies.fold(kept, FileState::with_copy))?;
+            fix.trimmed += 1;
+        }
+        Ok(fix)
+    }
+
+    /// Move the staged copies on `drive` (or every drive) into their place
+    /// and record them, running post_sync for each, as a batch `fo undo`
+    /// can revert. Copies on unplugged drives wait; one whose place now
+    /// holds a file fo didn't put there stays staged, unless it was staged
+    /// with `on_conflict = "overwrite"`.
+    pub async fn commit_staged(&mut self, drive: Option<&str>) -> Result<StageCommit> {
+        let mut commit = StageCommit::default();
+        self.drive_detector.refresh();
+        let opened = self.begin_batch("commit");
+        let committed = self.commit_each(drive, &mut commit).await;
+        if opened {
+            self.finish_batch();
+        }
+        committed?;
+
+        self.write_manifests();
+        Ok(commit)
+    }
+
+    async fn commit_each(&mut self, drive: Option<&str>, commit: &mut StageCommit) -> Result<()> {
+        for staged in self.state.get_all_staged()? {
+            let StagedFile { ref staged_path, state: ref record, replica, .. } = staged;
+            let (staged_path, record) = (staged_path.clone(), record.clone());
+            if drive.is_some_and(|drive| drive != record.target_drive) {
+                continue;
+            }
+            let Some(drive_config) = self.config.drives.get(&record.target_drive).cloned() else {
+                commit.failed.push((record.source_path, format!("drive {} is not configured", record.target_drive)));
+                continue;
+            };
+            if !self.is_drive_online(&drive_config) {
+                commit.waiting += 1;
+                continue;
+            }
+
+            let previous = self.state.get_file_state(&record.source_path)?;
+            // A replica replaces only the copy on its own drive
+            let previous_copy = match previous {
+                Some(ref previous) if replica => previous.copy_on(&record.target_drive),
+                ref previous => previous.clone(),
+            };
+            let ours = previous_copy.as_ref().is_some_and(|previous| previous.target_path == record.target_path);
+            if !staged_path.exists() {
+                commit.failed.push((record.source_path, format!("staged copy {} is missing", staged_path.display())));
+                continue;
+            }
+            if record.target_path.exists() && !ours && staged.conflict != Some(ConflictPolicy::Overwrite) {
+                let reason = format!("{} holds a file fo didn't put there", record.target_path.display());
+                commit.failed.push((record.source_path, reason));
+                continue;
+            }
+            if let Err(e) = move_into_place(&staged_path, &record.target_path).await {
+                commit.failed.push((record.source_path, e.to_string()));
+                continue;
+            }
+            let root = self.drive_root(&drive_config)?.join(STAGING_DIR);
+            remove_empty_dirs(&staged_path, &root);
+
+            // Tags and notes set while it waited carry over
+            let record = FileState {
+                last_synced: self.clock.timestamp(),
+                tags: previous.as_ref().map(|previous| previous.tags.clone()).unwrap_or(record.tags),
+                note: previous.as_ref().and_then(|previous| previous.note.clone()).or(record.note),
+                ..record
+            };
+            let updated = match previous {
+                Some(previous) if replica => previous.with_copy(record.clone()),
+                _ => record.clone(),
+            };
+            self.state.save_file_state(&updated)?;
+            self.state.record_history(&record)?;
+            self.state.remove_staged(&staged)?;
+            self.manifest_changed(&record.target_drive);
+            if let Some(ref mut batch) = self.batch {
+                batch.entries.push(BatchEntry { previous: previous_copy, written: record.clone(), replica, discarded: None });
+            }
+
+            if let Some(ref template) = self.config.hooks.post_sync {
+                let hook_tokens = [
+                    ("source", record.source_path.to_string_lossy().to_string()),
+                    ("target", record.target_path.to_string_lossy().to_string()),
+                    ("category", record.file_category.clone()),
+                    ("drive", drive_config.label.clone()),
+                    ("hash", record.hash.clone()),
+                ];
+                self.run_hook("post_sync", template, &hook_tokens).await;
+            }
+            info!("Committed {} to {}", record.source_path.display(), record.target_path.display());
+            commit.committed.push(record.target_path);
+        }
+        Ok(())
+    }
+
+    /// Delete the staged copies on `drive` (or every drive); their sources
+    /// are copied again on the next sync, or staged again by `fo undo`.
+    /// Returns how many were dropped.
+    pub async fn discard_staged(&mut self, drive: Option<&str>) -> Result<usize> {
+        let opened = self.begin_batch("discard");
+        let discarded = self.discard_each(drive).await;
+        if opened {
+            self.finish_batch();
+        }
+        discarded
+    }
+
+    async fn discard_each(&mut self, drive: Option<&str>) -> Result<usize> {
+        let mut discarded = 0;
+        for staged in self.state.get_all_staged()? {
+            if drive.is_some_and(|drive| drive != staged.state.target_drive) {
+                continue;
+            }
+            match async_fs::remove_file(&staged.staged_path).await {
+                Ok(()) => {
+                    if let Some(drive_config) = self.config.drives.get(&staged.state.target_drive) {
+                        if let Ok(root) = self.drive_root(drive_config) {
+                            remove_empty_dirs(&staged.staged_path, &root.join(STAGING_DIR));
+                        }
+                    }
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    warn!("Failed to remove {}: {}", staged.staged_path.display(), e);
+                    continue;
+                }
+            }
+            self.state.remove_staged(&staged)?;
+            if let Some(ref mut batch) = self.batch {
+                let written = staged.state.clone();
+                batch.entries.push(BatchEntry { previous: None, written, replica: staged.replica, discarded: Some(staged) });
+            }
+            discarded += 1;
+        }
+        Ok(discarded)
+    }
+
+    /// File system type of the drive a config entry points at, if known
+    fn target_file_system(&self, drive_config: &DriveConfig) -> Option<String> {
+        self.target_drive_info(drive_config).map(|drive| drive.file_system)
+    }
+
+    /// Bytes already queued for a drive and its last-seen size, when adding
+    /// `pending` would take the queue past that size
+    fn queue_over_capacity(&self, drive_uuid: &str, pending: &PendingSync) -> Result<Option<(u64, u64)>> {
+        let Some(capacity) = self.state.get_drive_capacity(drive_uuid)? else {
+            return Ok(None);
+        };
+        // Re-queueing a file replaces its entry rather than adding to the queue
+        let mut queued = self.state.get_queued_size(drive_uuid)?;
+        if let Some(existing) = self.state.get_queued(pending)?.filter(|entry| entry.target_drive == drive_uuid) {
+            queued = queued.saturating_sub(existing.size);
+        }
+
+        Ok((queued + pending.size > capacity).then_some((queued, capacity)))
+    }
+
+    /// Save a connected drive's total size for [`Self::queue_over_capacity`]
+    fn remember_capacity(&mut self, drive_uuid: &str, drive_config: &DriveConfig) {
+        // Network shares and plain folders report the size of whatever
+        // filesystem holds the path
+        if drive_config.network || drive_config.always_connected {
+            return;
+        }
+        let Some(total_space) = self.target_drive_info(drive_config).map(|info| info.total_space) else {
+            return;
+        };
+        if total_space == 0 || self.drive_capacities.get(drive_uuid) == Some(&total_space) {
+            return;
+        }
+
+        match self.state.record_drive_capacity(drive_uuid, total_space) {
+            Ok(()) => {
+                self.drive_capacities.insert(drive_uuid.to_string(), total_space);
+            }
+            Err(e) => warn!("Failed to save the size of drive {}: {}", drive_config.label, e),
+        }
+    }
+
+    /// The mounted volume a config entry points at, if known
+    fn target_drive_info(&self, drive_config: &DriveConfig) -> Option<DriveInfo> {
+        match drive_config.path {
+            // The deepest mount containing the path, not `/`
+            Some(ref path) => self.drive_detector
+                .get_all_drives()
+                .into_iter()
+                .filter(|drive| path.starts_with(&drive.mount_point))
+                .max_by_key(|drive| drive.mount_point.components().count()),
+            None => self.drive_detector
+                .find_registered_drive(drive_config.marker_id.as_deref(), drive_config.volume_uuid.as_deref(), &drive_config.label),
+        }
+    }
+
+    /// How full (percent) a drive is, if that is past `pause_at_percent_full`
+    /// so copying to it is paused
+    fn paused_for_space(&self, drive_config: &DriveConfig) -> Option<f64> {
+        let limit = self.config.sync.pause_at_percent_full?;
+        let info = self.target_drive_info(drive_config).filter(|info| info.total_space > 0)?;
+        let used = info.total_space.saturating_sub(info.available_space);
+        let percent = used as f64 * 100.0 / info.total_space as f64;
+        (percent >= f64::from(limit)).then_some(percent)
+    }
+
+    /// Copy files that exist in a drive's category folder but not in the
+    /// source back into the source directory. Pulled files are recorded as
+    /// already synced to that drive so they aren't pushed straight back.
+    pub async fn pull_from_drive(&mut self, drive_uuid: &str) -> Result<SyncSummary> {
+        let drive_config = self.config.drives.get(drive_uuid).cloned()
+            .ok_or_else(|| OrchestratorError::DriveNotFound(drive_uuid.to_string()))?;
+
+        self.drive_detector.refresh();
+        if !self.is_drive_online(&drive_config) {
+            return Err(OrchestratorError::DriveNotFound(format!("{} is not connected", drive_config.label)));
+        }
+
+        let category_root = self.drive_root(&drive_config)?.join(self.config.folder_for(&drive_config.target));
+        info!("Pulling new files from {}", category_root.display());
+
+        // Anything we already track on this drive came from us (or a prior pull)
+        let known_targets: std::collections::HashSet<PathBuf> = self.state
+            .get_all_file_states()?
+            .iter()
+            .flat_map(FileState::copies)
+            .filter(|s| s.target_drive == drive_uuid)
+            .map(|s| s.target_path)
+            .collect();
+
+        let mut summary = SyncSummary::default();
+
+        for drive_file in self.collect_files(&category_root)? {
+            if manifest::is_manifest(&drive_file) {
+                continue;
+            }
+            if known_targets.contains(&drive_file) {
+                summary.already_synced += 1;
+                continue;
+            }
+
+            match self.pull_file(drive_uuid, &drive_config, &category_root, &drive_file).await {
+                Ok(SyncResult::Synced(_)) => summary.synced += 1,
+                Ok(SyncResult::AlreadySynced) => summary.already_synced += 1,
+                Ok(SyncResult::Conflict(_, _)) => summary.conflicts += 1,
+                Ok(_) => summary.skipped += 1,
+                Err(e) => {
+                    error!("Failed to pull {}: {}", drive_file.display(), e);
+                    summary.failed += 1;
+                    summary.failures.push((drive_file.clone(), e.to_string()));
+                }
+            }
+        }
+
+        Ok(summary)
+    }
+
+    async fn pull_file(
+        &mut self,
+        drive_uuid: &str,
+        drive_config: &DriveConfig,
+        category_root: &Path,
+        drive_file: &Path,
+    ) -> Result<SyncResult> {
+        let file_info = self.file_info(drive_file)?;
+        let relative_path = drive_file.strip_prefix(category_root).unwrap_or(drive_file);
+        let category = self.categorize(relative_path, &file_info).unwrap_or_else(|| "unknown".to_string());
+
+        // A file that would be routed elsewhere doesn't belong to this drive
+        if category != drive_config.target {
+            return Ok(SyncResult::Skipped(format!("Classified as {}", category)));
+        }
+
+        let source_path = self.config.source.path.join(relative_path);
+        let algorithm = self.config.sync.hash_algorithm;
+        let hash = calculate_file_hash(drive_file, algorithm)?;
+
+        if source_path.exists() {
+            if calculate_file_hash(&source_path, algorithm)? != hash {
+                // Never overwrite the user's library from a drive
+                warn!("Source already has a different {}, not pulling", source_path.display());
+                return Ok(SyncResult::Conflict(ConflictPolicy::Skip, source_path));
+            }
+        } else {
+            if let Some(parent) = source_path.parent() {
+                async_fs::create_dir_all(parent).await?;
+            }
+            info!("Pulling {} -> {}", drive_file.display(), source_path.display());
+            async_fs::copy(drive_file, &source_path).await
+                .map_err(|e| OrchestratorError::Sync(format!("Failed to copy file: {}", e)))?;
+        }
+
+        let copy = FileState {
+            source_path: source_path.clone(),
+            hash,
+            size: file_info.size,
+            last_synced: self.clock.timestamp(),
+            target_drive: drive_uuid.to_string(),
+            target_path: drive_file.to_path_buf(),
+            file_category: category.to_string(),
+            compressed_size: None,
+            direction: SyncDirection::Pull,
+            reflinked: false,
+            sparse: false,
+            hash_algorithm: algorithm,
+            link: None,
+            snapshot: None,
+            tags: Vec::new(),
+            note: None,
+            replicas: Vec::new(),
+        };
+        // A source that is synced elsewhere keeps its record, tags and
+        // copies; this drive's file joins them unless the drive has one
+        match self.state.get_file_state(&source_path)? {
+            Some(existing) if existing.copy_on(drive_uuid).is_some() => return Ok(SyncResult::AlreadySynced),
+            Some(existing) => self.state.save_file_state(&existing.with_copy(copy))?,
+            None => self.state.save_file_state(&copy)?,
+        }
+
+        Ok(SyncResult::Synced(source_path))
+    }
+
+    /// Recreate sync records from what is on the connected drives, after
+    /// the database had to be rebuilt. A copy is recorded when its source
+    /// file still exists with the same content. Returns how many were found.
+    pub fn rebuild_from_targets(&mut self) -> Result<usize> {
+        self.drive_detector.refresh();
+        let mut restored = 0;
+
+        let drives: Vec<(String, DriveConfig)> = self.config.drives
+            .iter()
+            .map(|(uuid, drive)| (uuid.clone(), drive.clone()))
+            .collect();
+
+        for (drive_uuid, drive_config) in drives {
+            if !self.is_drive_online(&drive_config) {
+                info!("Drive {} is not connected, skipping", drive_config.label);
+                continue;
+            }
+
+            if drive_config.flatten {
+                // Flattened copies don't say where in the source they came from
+                info!("Drive {} is flattened, skipping", drive_config.label);
+                continue;
+            }
+            if drive_config.snapshots {
+                // Which snapshot holds a file's latest copy isn't on the drive
+                info!("Drive {} keeps snapshots, skipping", drive_config.label);
+                continue;
+            }
+
+            let category_root = self.drive_root(&drive_config)?.join(self.config.folder_for(&drive_config.target));
+            info!("Rescanning {}", category_root.display());
+
+            for target_path in self.collect_files(&category_root)? {
+                let Ok(relative_path) = target_path.strip_prefix(&category_root) else {
+                    continue;
+                };
+
+                let compressed = drive_config.compress
+                    && target_path.extension().is_some_and(|ext| ext == "zst");
+                let relative_path = if compressed {
+                    relative_path.with_extension("")
+                } else {
+                    relative_path.to_path_buf()
+                };
+
+                let source_path = self.config.source.path.join(&relative_path);
+                if !source_path.is_file() || self.state.get_file_state(&source_path)?.is_some() {
+                    continue;
+                }
+
+                let algorithm = self.config.sync.hash_algorithm;
+                let hash = calculate_file_hash(&source_path, algorithm)?;
+                if hash_stored_file(&target_path, compressed, algorithm)? != hash {
+                    continue;
+                }
+
+                self.state.save_file_state(&FileState {
+                    source_path: source_path.clone(),
+                    hash,
+                    size: fs::metadata(&source_path)?.len(),
+                    last_synced: self.clock.timestamp(),
+                    target_drive: drive_uuid.clone(),
+                    target_path: target_path.clone(),
+                    file_category: drive_config.target.clone(),
+                    compressed_size: if compressed { Some(fs::metadata(&target_path)?.len()) } else { None },
+                    direction: SyncDirection::Push,
+                    reflinked: false,
+                    sparse: false,
+                    hash_algorithm: algorithm,
+                    link: None,
+                    snapshot: None,
+                    tags: Vec::new(),
+                    note: None,
+                    replicas: Vec::new(),
+                })?;
+                restored += 1;
+            }
+        }
+
+        Ok(restored)
+    }
+
+    /// Whether a registered drive is currently connected, by path or else by label
+    fn is_drive_online(&self, drive_config: &DriveConfig) -> bool {
+        if let Some(ref path) = drive_config.path {
+            if drive_config.network || drive_config.always_connected {
+                return DriveDetector::is_path_reachable(path);
+            }
+            self.drive_detector.is_registered_drive_connected(
+                path,
+                drive_config.marker_id.as_deref(),
+                drive_config.volume_uuid.as_deref(),
+            )
+        } else {
+            self.drive_detector
+                .find_registered_drive(drive_config.marker_id.as_deref(), drive_config.volume_uuid.as_deref(), &drive_config.label)
+                .is_some()
+        }
+    }
+
+    /// Move synced copies whose current routing differs from where they were
+    /// put, e.g. after the rules changed or a drive was added for a category.
+    /// A move re-copies from the source to the new drive and then deletes the
+    /// old copy, so both drives must be connected. With `dry_run` nothing is
+    /// touched and the report lists the proposed moves.
+    pub async fn reroute(&mut self, dry_run: bool) -> Result<RerouteReport> {
+        self.drive_detector.refresh();
+        let mut report = RerouteReport::default();
+
+        for old_state in self.state.get_all_file_states()? {
+            if old_state.direction == SyncDirection::Pull {
+                continue;
+            }
+
+            let source_path = old_state.source_path.clone();
+            if !source_path.is_file() {
+                report.missing_source += 1;
+                continue;
+            }
+
+            let file_info = self.file_info(&source_path)?;
+            let relative_path = source_path
+                .strip_prefix(&self.config.source.path)
+                .unwrap_or(&source_path);
+            let Some(category) = self.categorize(relative_path, &file_info) else {
+                continue;
+            };
+            // Replicated files belong on every drive for their category,
+            // and a sync puts them there
+            if self.config.sync.replicates(&category) || !old_state.replicas.is_empty() {
+                continue;
+            }
+            let candidates = self.config.drives_for_file(&category, file_info.extension.as_deref());
+            let Some((first, _)) = candidates.first() else {
+                continue;
+            };
+            // Under `fastest` any drive for the category is a right place
+            let new_drive = if self.config.sync.drive_selection == DriveSelection::Fastest
+                && candidates.iter().any(|(uuid, _)| **uuid == old_state.target_drive)
+            {
+                old_state.target_drive.clone()
+            } else {
+                (*first).clone()
+            };
+
+            // A renamed folder_names entry or a changed subfolder rule moves
+            // copies within the same drive. Renaming and sanitizing keep the
+            // depth below the folder, so a copy deeper than its source path
+            // is in a subfolder no rule sends it to any more.
+            let folder_changed = self.config.drives
+                .get(&new_drive)
+                .and_then(|drive| Some((drive, self.drive_root(drive).ok()?)))
+                .is_some_and(|(drive, root)| {
+                    let folder = self.file_folder(&root, old_state.snapshot.as_deref(), &category, relative_path);
+                    let depth = if drive.flatten { 1 } else { relative_path.components().count() };
+                    old_state.target_path
+                        .strip_prefix(folder)
+                        .map_or(true, |placed| placed.components().count() != depth)
+                });
+            if new_drive == old_state.target_drive && category == old_state.file_category && !folder_changed {
+                continue;
+            }
+
+            let planned = RerouteMove {
+                source_path: source_path.clone(),
+                from: format!("{} ({})", self.drive_label(&old_state.target_drive), old_state.file_category),
+                to: format!("{} ({})", self.drive_label(&new_drive), category),
+            };
+
+            let old_online = self.config.drives.get(&old_state.target_drive).is_some_and(|d| self.is_drive_online(d));
+            let new_online = self.config.drives.get(&new_drive).is_some_and(|d| self.is_drive_online(d));
+            if !old_online || !new_online {
+                report.offline.push(planned);
+                continue;
+            }
+            if dry_run {
+                report.moves.push(planned);
+                continue;
+            }
+
+            // Sync afresh under the current rules, then drop the old copy
+            self.state.remove_file_state(&source_path)?;
+            match self.sync_file(&source_path).await {
+                Ok(SyncResult::Synced(new_target)) | Ok(SyncResult::Conflict(ConflictPolicy::Rename, new_target))
+                | Ok(SyncResult::Conflict(ConflictPolicy::Overwrite, new_target)) => {
+                    if new_target != old_state.target_path && old_state.target_path.exists() {
+                        async_fs::remove_file(&old_state.target_path).await?;
+                    }
+                    self.manifest_changed(&old_state.target_drive);
+                    info!("Rerouted {} from {} to {}", source_path.display(), planned.from, planned.to);
+                    report.moves.push(planned);
+                }
+                other => {
+                    let reason = match other {
+                        Ok(result) => format!("{:?}", result),
+                        Err(e) => e.to_string(),
+                    };
+                    warn!("Could not reroute {}: {}", source_path.display(), reason);
+                    let _ = self.state.remove_pending_sync(&source_path);
+                    self.state.save_file_state(&old_state)?;
+                    report.failures.push((source_path, reason));
+                }
+            }
+        }
+
+        self.write_manifests();
+        Ok(report)
+    }
+
+    /// Note that a drive's manifest needs rewriting, if manifests are on
+    fn manifest_changed(&mut self, drive_uuid: &str) {
+        if self.config.sync.drive_manifests {
+            self.manifest_dirty.insert(drive_uuid.to_string());
+        }
+    }
+
+    /// Rewrite the manifests of drives whose files changed since they were
+    /// last written. Drives that are offline keep theirs marked for later.
+    pub fn write_manifests(&mut self) {
+        if self.manifest_dirty.is_empty() {
+            return;
+        }
+
+        let records = match self.state.get_all_file_states() {
+            Ok(records) => records,
+            Err(e) => {
+                error!("Failed to read sync records for drive manifests: {}", e);
+                return;
+            }
+        };
+
+        let dirty: Vec<String> = self.manifest_dirty.iter().cloned().collect();
+        for drive_uuid in dirty {
+            let Some(drive_config) = self.config.drives.get(&drive_uuid).cloned() else {
+                self.manifest_dirty.remove(&drive_uuid);
+                continue;
+            };
+            if !self.is_drive_online(&drive_config) {
+                continue;
+            }
+
+            match self.write_drive_manifests(&drive_uuid, &drive_config, &records) {
+                Ok(()) => {
+                    self.manifest_dirty.remove(&drive_uuid);
+                }
+                Err(e) => warn!("Failed to write the manifest on {}: {}", drive_config.label, e),
+            }
+        }
+    }
+
+    /// One manifest per category folder the drive has files in, plus its
+    /// own category's even when that is now empty. On drives with snapshots
+    /// each snapshot's folder lists the files whose latest copy it holds.
+    fn write_drive_manifests(&self, drive_uuid: &str, drive_config: &DriveConfig, records: &[FileState]) -> Result<()> {
+        let root = self.drive_root(drive_config)?;
+
+        let mut folders: BTreeMap<PathBuf, (String, Vec<ManifestEntry>)> = BTreeMap::new();
+        if !drive_config.snapshots {
+            let folder = self.category_folder(&root, None, &drive_config.target);
+            folders.entry(folder).or_insert_with(|| (drive_config.target.clone(), Vec::new()));
+        }
+        for record in records.iter().flat_map(FileState::copies).filter(|record| record.target_drive == drive_uuid) {
+            let folder = self.category_folder(&root, record.snapshot.as_deref(), &record.file_category);
+            if let Some(entry) = ManifestEntry::from_state(&folder, &record) {
+                folders.entry(folder).or_insert_with(|| (record.file_category.clone(), Vec::new())).1.push(entry);
+            }
+        }
+
+        for (folder, (category, mut files)) in folders {
+            files.sort_by(|a, b| a.path.cmp(&b.path));
+            manifest::write(&folder, &DriveManifest {
+                drive: drive_uuid.to_string(),
+                category,
+                generated_at: self.clock.timestamp(),
+                files,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Like [`verify`](Self::verify), but checks connected drives against
+    /// the manifests stored on them instead of the state database
+    pub fn verify_manifests(&mut self, drive_filter: Option<&str>, rehash: bool) -> Result<VerifyReport> {
+        self.drive_detector.refresh();
+
+        let mut report = VerifyReport::default();
+        let drives: Vec<(String, DriveConfig)> = self.config.drives
+            .iter()
+            .filter(|(uuid, _)| drive_filter.is_none_or(|filter| filter == uuid.as_str()))
+            .map(|(uuid, drive)| (uuid.clone(), drive.clone()))
+            .collect();
+
+        for (drive_uuid, drive_config) in drives {
+            if !self.is_drive_online(&drive_config) {
+                report.offline += 1;
+                continue;
+            }
+
+            // Manifests sit in category folders; files at the root, like
+            // the drive's ID file, aren't folders to look in
+            let root = self.drive_root(&drive_config)?;
+            let subfolders = |dir: &Path| -> Vec<PathBuf> {
+                fs::read_dir(dir).into_iter().flatten().flatten().map(|entry| entry.path()).filter(|path| path.is_dir()).collect()
+            };
+            let mut folders = subfolders(&root);
+            if drive_config.snapshots {
+                for snapshot in subfolders(&root.join(SNAPSHOTS_DIR)) {
+                    folders.extend(subfolders(&snapshot));
+                }
+            }
+            let mut found = false;
+            for folder in folders {
+                let Some(drive_manifest) = manifest::read(&folder)? else {
+                    continue;
+                };
+                if drive_manifest.drive != drive_uuid {
+                    continue;
+                }
+                found = true;
+
+                for file in &drive_manifest.files {
+                    let target_path = file.target_path(&folder);
+                    if !target_path.exists() {
+                        warn!("Missing on target: {}", target_path.display());
+                        report.missing.push(target_path);
+                    } else if rehash && hash_stored_file(&target_path, file.compressed, file.hash_algorithm)? != file.hash {
+                        warn!("Hash mismatch on target: {}", target_path.display());
+                        report.mismatched.push(target_path);
+                    } else {
+                        report.ok += 1;
+                    }
+                }
+            }
+
+            if !found {
+                warn!("Drive {} has no manifest to check against", drive_config.label);
+            }
+        }
+
+        Ok(report)
+    }
+
+    fn drive_label(&self, drive_uuid: &str) -> String {
+        self.config.drives
+            .get(drive_uuid)
+            .map(|drive| drive.label.clone())
+            .unwrap_or_else(|| drive_uuid.to_string())
+    }
+
+    /// Audit recorded file states against the target drives.
+    /// Only drives that are connected are checked; with `rehash` the target
+    /// content is compared against the stored hash, and with `requeue` any
+    /// missing or corrupt copies whose source still exists are queued as
+    /// pending for re-sync. A corrupt copy is never deleted: its record is
+    /// dropped, so the re-sync writes over it through the conflict policy.
+    pub fn verify(&mut self, drive_filter: Option<&str>, rehash: bool, requeue: bool) -> Result<VerifyReport> {
+        self.drive_detector.refresh();
+
+        let mut report = VerifyReport::default();
+
+        for file_state in self.state.get_all_file_states()?.iter().flat_map(FileState::copies) {
+            if let Some(uuid) = drive_filter {
+                if file_state.target_drive != uuid {
+                    continue;
+                }
+            }
+
+            let online = self.config.drives
+                .get(&file_state.target_drive)
+                .map(|drive| self.is_drive_online(drive))
+                .unwrap_or(false);
+
+            if !online {
+                report.offline += 1;
+                continue;
+            }
+
+            let mismatched = if !file_state.target_path.exists() {
+                warn!("Missing on target: {}", file_state.target_path.display());
+                report.missing.push(file_state.target_path.clone());
+                false
+            } else if rehash
+                && hash_stored_file(&file_state.target_path, file_state.is_compressed(), file_state.hash_algorithm)?
+                    != file_state.hash
+            {
+                warn!("Hash mismatch on target: {}", file_state.target_path.display());
+                report.mismatched.push(file_state.target_path.clone());
+                true
+            } else {
+                report.ok += 1;
+                continue;
+            };
+
+            if !requeue {
+                continue;
+            }
+            // The copy on the drive may be the only one left
+            if !file_state.source_path.exists() {
+                warn!("Source no longer exists, cannot re-queue: {}", file_state.source_path.display());
+                continue;
+            }
+            if mismatched {
+                // Otherwise the re-sync would take the copy for current
+                if let Some(current) = self.state.get_file_state(&file_state.source_path)? {
+                    match current.without_copy(&file_state.target_drive) {
+                        Some(others) => self.state.save_file_state(&others)?,
+                        None => self.state.remove_file_state(&file_state.source_path)?,
+                    }
+                }
+            }
+            self.state.add_pending_sync(&PendingSync {
+                source_path: file_state.source_path.clone(),
+                file_category: file_state.file_category.clone(),
+                target_drive: file_state.target_drive.clone(),
+                hash: file_state.hash.clone(),
+                size: file_state.size,
+                created_at: self.clock.timestamp(),
+                replica: self.config.sync.replicates(&file_state.file_category),
+            })?;
+            report.requeued += 1;
+        }
+
+        Ok(report)
+    }
+
+    /// Where a drive is mounted right now: found by its marker file or
+    /// recorded volume UUID, at its configured path, or else by label when exactly one connected
+    /// drive carries it
+    fn current_mount(&self, drive_config: &DriveConfig) -> Option<DriveInfo> {
+        if drive_config.network || drive_config.always_connected {
+            return None;
+        }
+
+        let stored_path_connected = drive_config.path
+            .as_ref()
+            .map(|p| self.drive_detector.is_drive_connected(p))
+            .unwrap_or(false);
+
+        let by_marker = drive_config.marker_id
+            .as_deref()
+            .and_then(|marker_id| self.drive_detector.find_drive_by_marker(marker_id));
+        if by_marker.is_some() {
+            by_marker
+        } else if let Some(ref volume_uuid) = drive_config.volume_uuid {
+            self.drive_detector.find_drive_by_volume_uuid(volume_uuid)
+        } else if stored_path_connected {
+            drive_config.path.as_ref().and_then(|p| self.drive_detector.get_drive_by_mount_point(p))
+        } else {
+            self.drive_detector.find_unique_drive_by_label(&drive_config.label)
+        }
+    }
+
+    /// Drives that are connected somewhere other than their configured path,
+    /// e.g. `/run/media/user/USB` after being `/media/user/USB`, with where
+    /// they are mounted now. Until the path is updated they count as
+    /// disconnected.
+    pub fn moved_drives(&mut self) -> Vec<(String, PathBuf)> {
+        self.drive_detector.refresh();
+        self.find_moved_drives()
+    }
+
+    fn find_moved_drives(&self) -> Vec<(String, PathBuf)> {
+        self.config.drives
+            .iter()
+            .filter_map(|(uuid, drive)| {
+                let stored = drive.path.as_ref()?;
+                let found = self.current_mount(drive)?;
+                (found.mount_point != *stored).then(|| (uuid.clone(), found.mount_point))
+            })
+            .collect()
+    }
+
+    /// Bind a drive to where it is mounted right now. Drives with a marker
+    /// or recorded volume UUID follow it to a new mount point; unbound drives are
+    /// bound only when exactly one connected drive matches their label.
+    /// Returns whether the drive's config changed.
+    fn bind_drive_path(&mut self, drive_uuid: &str) -> bool {
+        let Some(drive_config) = self.config.drives.get(drive_uuid) else {
+            return false;
+        };
+        let Some(found) = self.current_mount(drive_config) else {
+            return false;
+        };
+
+        let path_changed = drive_config.path.as_ref() != Some(&found.mount_point);
+        let uuid_learned = drive_config.volume_uuid.is_none() && found.volume_uuid.is_some();
+        if !path_changed && !uuid_learned {
+            return false;
+        }
+
+        let drive_config = self.config.drives.get_mut(drive_uuid).unwrap();
+        match drive_config.path {
+            Some(ref old) if path_changed => warn!(
+                "Drive {} is now mounted at {} instead of {}; updating its path in the config",
+                drive_config.label, found.mount_point.display(), old.display()
+            ),
+            None => info!("Bound drive {} to {}", drive_config.label, found.mount_point.display()),
+            _ => {}
+        }
+
+        drive_config.path = Some(found.mount_point);
+        if uuid_learned {
+            drive_config.volume_uuid = found.volume_uuid;
+        }
+        true
+    }
+
+    /// Write updated drive bindings into the config file on disk, leaving
+    /// every other setting and comment as the file has it
+    fn save_drive_bindings(&self, drive_uuids: &[String]) -> Result<()> {
+        let Some(ref config_path) = self.config_path else {
+            return Ok(());
+        };
+        self.config.save_drive_bindings(config_path, drive_uuids)
+    }
+
+    /// Whether a drive check has anything to do: files are queued, a
+    /// bidirectional drive may have new files, or event clients want to hear
+    /// about drives connecting
+    pub fn drive_check_needed(&self) -> Result<bool> {
+        Ok(self.events.is_some()
+            || self.config.drives.values().any(|drive| drive.bidirectional)
+            || self.state.has_pending_syncs()?)
+    }
+
+    /// Check for newly connected drives and process their pending syncs
+    pub async fn check_and_sync_connected_drives(&mut self) -> Result<()> {
+        // Queues are gathered afresh below, and drives probed again
+        self.draining.clear();
+        self.writable.clear();
+        let owned = self.resume_drain_batch();
+        let result = self.check_and_sync_drives().await;
+        self.end_drain_batch(owned);
+        result
+    }
+
+    async fn check_and_sync_drives(&mut self) -> Result<()> {
+        self.drive_detector.refresh();
+
+        // Collect drive info first to avoid borrowing issues
+        let drive_uuids: Vec<String> = self.config.drives.keys().cloned().collect();
+
+        let moved = self.find_moved_drives();
+        if !self.config.sync.auto_bind_path {
+            for (uuid, mount) in &moved {
+                if self.reported_moves.insert(uuid.clone()) {
+                    let drive = &self.config.drives[uuid];
+                    warn!(
+                        "Drive {} is mounted at {} but configured at {}; its files stay pending until drives.{}.path is updated",
+                        drive.label, mount.display(), drive.path.as_deref().unwrap_or(Path::new("")).display(), uuid
+                    );
+                }
+            }
+        }
+        self.reported_moves.retain(|uuid| moved.iter().any(|(moved_uuid, _)| moved_uuid == uuid));
+
+        let mut changed: Vec<String> = Vec::new();
+        if self.config.sync.auto_bind_path {
+            changed = drive_uuids
+                .iter()
+                .filter(|uuid| self.bind_drive_path(uuid))
+                .cloned()
+                .collect();
+        }
+        if !changed.is_empty() {
+            if let Err(e) = self.save_drive_bindings(&changed) {
+                error!("Failed to save drive paths to config: {}", e);
+            }
+        }
+
+        // Remember when each drive was last plugged in
+        let online: HashSet<String> = drive_uuids
+            .iter()
+            .filter(|uuid| self.is_drive_online(&self.config.drives[*uuid]))
+            .cloned()
+            .collect();
+        let now = chrono::DateTime::<chrono::Utc>::from(self.clock.now()).to_rfc3339();
+        for uuid in online.difference(&self.online_drives) {
+            if let Some(drive) = self.config.drives.get_mut(uuid) {
+                drive.last_seen = Some(now.clone());
+                if let Some(ref events) = self.events {
+                    events.send(Event::DriveConnected { drive: drive.label.clone() });
+                }
+            }
+            if let Err(e) = self.state.record_drive_seen(uuid, &now) {
+                warn!("Failed to record when drive {} was seen: {}", uuid, e);
+            }
+        }
+        self.online_drives = online;
+
+        // Now process each drive
+        for drive_uuid in drive_uuids {
+            if let Some(drive_config) = self.config.drives.get(&drive_uuid).cloned() {
+                if self.is_drive_online(&drive_config) {
+                    info!("Drive {} is connected, checking for pending syncs", drive_config.label);
+                    self.remember_capacity(&drive_uuid, &drive_config);
+                    
+                    // Verify existing synced files still exist on target
+                    self.verify_synced_files(&drive_uuid).await?;
+
+                    let root = self.drive_root(&drive_config)?;
+                    if !self.drive_writable(&root) {
+                        warn!("Drive {} is read-only (write-protect switch?), leaving its queue pending", drive_config.label);
+                        continue;
+                    }
+                    if let Some(percent) = self.paused_for_space(&drive_config) {
+                        warn!("Drive {} is {:.0}% full, leaving its queue pending until space is freed", drive_config.label, percent);
+                        continue;
+                    }
+
+                    let count = self.process_pending_syncs(&drive_uuid).await?;
+                    if count > 0 {
+                        info!("Processed {} pending syncs for {}", count, drive_config.label);
+                    }
+
+                    if drive_config.bidirectional {
+                        let pulled = self.pull_from_drive(&drive_uuid).await?;
+                        if pulled.synced > 0 {
+                            info!("Pulled {} new files from {}", pulled.synced, drive_config.label);
+                        }
+                    }
+                }
+            }
+        }
+
+        self.write_manifests();
+        self.state.flush_async().await?;
+        Ok(())
+    }
+}
+
+/// Whether `drive` would take a file queued for another drive
+fn pending_compatible(drive: &DriveConfig, pending: &PendingSync) -> bool {
+    let extension = pending.source_path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase());
+    drive.target == pending.file_category && drive.accepts_extension(extension.as_deref())
+}
+
+/// Order a drive's pending queue; queue time breaks ties so equal-sized
+/// files still drain oldest first
+fn sort_pending(pending: &mut [PendingSync], order: PendingOrder) {
+    match order {
+        PendingOrder::Fifo => pending.sort_by_key(|p| p.created_at),
+        PendingOrder::SmallestFirst => pending.sort_by_key(|p| (p.size, p.created_at)),
+        PendingOrder::LargestFirst => pending.sort_by_key(|p| (std::cmp::Reverse(p.size), p.created_at)),
+    }
+}
+
+/// Hash the original content of a file on a target, decompressing if needed
+fn hash_stored_file(path: &Path, compressed: bool, algorithm: HashAlgorithm) -> Result<String> {
+    if compressed {
+        calculate_compressed_file_hash(path, algorithm)
+    } else {
+        calculate_file_hash(path, algorithm)
+    }
+}
+
+/// Copy `source` to a `target` on the same file system, as a copy-on-write
+/// reflink where it supports them (Btrfs, XFS, APFS, ReFS) and as a normal
+/// copy otherwise (keeping holes with `sparse`). Returns whether a reflink
+/// was made and whether a sparse copy was.
+async fn copy_file(source: &Path, target: &Path, sparse: bool, retry: TransientRetry) -> Result<(bool, bool)> {
+    let source = source.to_path_buf();
+    let target = target.to_path_buf();
+
+    tokio::task::spawn_blocking(move || retry.run(&source, || -> std::io::Result<(bool, bool)> {
+        // An existing target would make the reflink fail
+        if target.exists() {
+            fs::remove_file(&target)?;
+        }
+        if !sparse {
+            // Falls back to a plain copy where reflinks are unsupported
+            return Ok((reflink_copy::reflink_or_copy(&source, &target)?.is_none(), false));
+        }
+        // A reflink shares the source's extents, holes included
+        match reflink_copy::reflink(&source, &target) {
+            Ok(()) => Ok((true, false)),
+            Err(_) => Ok((false, copy_chunked(&source, &target, 0, true)?)),
+        }
+    }))
+    .await
+    .map_err(|e| OrchestratorError::Sync(format!("Copy task failed: {}", e)))?
+    .map_err(|e| OrchestratorError::Sync(format!("Failed to copy file: {}", e)))
+}
+
+/// Regular files anywhere under `dir`, whatever the source's scan rules
+fn count_files(dir: &Path) -> Result<usize> {
+    let mut count = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            count += count_files(&entry.path())?;
+        } else if file_type.is_file() {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// Put a `kind` link to `source` at `target`, replacing what is there
+async fn link_file(source: &Path, target: &Path, kind: LinkKind) -> std::io::Result<()> {
+    let source = source.to_path_buf();
+    let target = target.to_path_buf();
+
+    tokio::task::spawn_blocking(move || {
+        // symlink_metadata, so a dangling link is replaced too
+        if fs::symlink_metadata(&target).is_ok() {
+            fs::remove_file(&target)?;
+        }
+        match kind {
+            LinkKind::Hardlink => fs::hard_link(&source, &target),
+            #[cfg(unix)]
+            LinkKind::Symlink => std::os::unix::fs::symlink(&source, &target),
+            #[cfg(windows)]
+            LinkKind::Symlink => std::os::windows::fs::symlink_file(&source, &target),
+        }
+    })
+    .await?
+}
+
+/// Copy the extended attributes of `source` to `target` one at a time, so
+/// those the target's file system refuses don't stop the rest. Returns how
+/// many were copied and the ones that weren't, with why.
+#[cfg(unix)]
+fn copy_xattrs(source: &Path, target: &Path) -> std::io::Result<(usize, Vec<(std::ffi::OsString, std::io::Error)>)> {
+    let mut copied = 0;
+    let mut failed = Vec::new();
+    for name in xattr::list(source)? {
+        // An attribute removed since the listing has nothing to copy
+        let copy = xattr::get(source, &name).and_then(|value| match value {
+            Some(value) => xattr::set(target, &name, &value),
+            None => Ok(()),
+        });
+        match copy {
+            Ok(()) => copied += 1,
+            Err(e) => failed.push((name, e)),
+        }
+    }
+    Ok((copied, failed))
+}
+
+/// No extended attributes are copied here
+#[cfg(not(unix))]
+fn copy_xattrs(_source: &Path, _target: &Path) -> std::io::Result<(usize, Vec<(std::ffi::OsString, std::io::Error)>)> {
+    Ok((0, Vec::new()))
+}
+
+/// Rename a staged copy over `target`, making its folders first
+async fn move_into_place(staged: &Path, target: &Path) -> std::io::Result<()> {
+    if let Some(parent) = target.parent() {
+        async_fs::create_dir_all(parent).await?;
+    }
+    // Windows won't rename over an existing file
+    if target.exists() {
+        async_fs::remove_file(target).await?;
+    }
+    async_fs::rename(staged, target).await
+}
+
+/// Remove the folders above `path` that are now empty, up to and
+/// including `root`
+fn remove_empty_dirs(path: &Path, root: &Path) {
+    for dir in path.ancestors().skip(1).take_while(|dir| dir.starts_with(root)) {
+        if fs::remove_dir(dir).is_err() {
+            break;
+        }
+    }
+}
+
+/// Where `sync.staging` puts a copy headed for `target` under `root`:
+/// `<root>/images/a.jpg` -> `<root>/.staging/images/a.jpg`
+fn staging_path(root: &Path, target: &Path) -> PathBuf {
+    root.join(STAGING_DIR).join(target.strip_prefix(root).unwrap_or(target))
+}
+
+/// Where an in-progress copy is written: `name.ext` -> `name.ext.partial`
+fn partial_path(target: &Path) -> PathBuf {
+    let mut name = target.file_name().unwrap_or_default().to_os_string();
+    name.push(".partial");
+    target.with_file_name(name)
+}
+
+/// Where the new end of a copy that keeps its first pieces is written:
+/// `name.ext` -> `name.ext.tail`
+fn tail_path(target: &Path) -> PathBuf {
+    let mut name = target.file_name().unwrap_or_default().to_os_string();
+    name.push(".tail");
+    target.with_file_name(name)
+}
+
+/// Write `source` from byte `from` on to a new file at `to`
+fn copy_from(source: &Path, to: &Path, from: u64) -> std::io::Result<()> {
+    use std::io::{Seek, SeekFrom};
+
+    let mut input = fs::File::open(source)?;
+    input.seek(SeekFrom::Start(from))?;
+    let mut output = fs::File::create(to)?;
+    std::io::copy(&mut input, &mut output)?;
+    output.sync_all()
+}
+
+/// Cut `target` back to `len` bytes and append `tail` to it
+fn append_at(target: &Path, tail: &Path, len: u64) -> std::io::Result<()> {
+    use std::io::{Seek, SeekFrom};
+
+    let mut output = fs::OpenOptions::new().write(true).open(target)?;
+    output.set_len(len)?;
+    output.seek(SeekFrom::Start(len))?;
+    std::io::copy(&mut fs::File::open(tail)?, &mut output)?;
+    output.sync_all()
+}
+
+/// Stream `source` into `partial` in fixed-size chunks, keeping the first
+/// `resume_from` bytes already there. With `sparse`, only the source's data
+/// ranges are written so its holes stay holes; returns whether it had any.
+fn copy_chunked(source: &Path, partial: &Path, resume_from: u64, sparse: bool) -> std::io::Result<bool> {
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    let mut input = fs::File::open(source)?;
+    let len = input.metadata()?.len();
+
+    let mut output = fs::OpenOptions::new().create(true).truncate(false).write(true).open(partial)?;
+    output.set_len(resume_from)?;
+
+    let holes = if sparse { data_ranges(&input, resume_from, len) } else { None };
+    let has_holes = holes.is_some();
+    let ranges = holes.unwrap_or_else(|| vec![(resume_from, u64::MAX)]);
+
+    let mut buffer = vec![0u8; HASH_CHUNK_SIZE];
+    for (start, end) in ranges {
+        input.seek(SeekFrom::Start(start))?;
+        output.seek(SeekFrom::Start(start))?;
+        let mut range = (&mut input).take(end - start);
+        loop {
+            let read = range.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            output.write_all(&buffer[..read])?;
+        }
+    }
+    if has_holes {
+        // A trailing hole has no data range to extend the file
+        output.set_len(len)?;
+    }
+
+    output.sync_all()?;
+    Ok(has_holes)
+}
+
+/// The data ranges of `file` from `from` up to `len`, if it has holes there
+/// and the file system reports them. `None` means copy it densely.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+fn data_ranges(file: &fs::File, from: u64, len: u64) -> Option<Vec<(u64, u64)>> {
+    use std::os::unix::io::AsRawFd;
+
+    // `Ok(None)` once there is no data past `offset`
+    let seek = |offset: u64, whence: libc::c_int| -> std::io::Result<Option<u64>> {
+        // SAFETY: lseek only moves the offset of a descriptor `file` keeps open
+        let result = unsafe { libc::lseek(file.as_raw_fd(), offset as libc::off_t, whence) };
+        if result >= 0 {
+            return Ok(Some(result as u64));
+        }
+        let error = std::io::Error::last_os_error();
+        if error.raw_os_error() == Some(libc::ENXIO) { Ok(None) } else { Err(error) }
+    };
+
+    let mut ranges = Vec::new();
+    let mut offset = from;
+    while offset < len {
+        // File systems without hole support fail here; copy those densely
+        let Some(start) = seek(offset, libc::SEEK_DATA).ok()? else { break };
+        let end = seek(start, libc::SEEK_HOLE).ok()?.unwrap_or(len).min(len);
+        if end <= start {
+            break;
+        }
+        ranges.push((start, end));
+        offset = end;
+    }
+
+    let data: u64 = ranges.iter().map(|(start, end)| end - start).sum();
+    (data < len.saturating_sub(from)).then_some(ranges)
+}
+
+/// Holes aren't reported here, so every copy is dense
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios", target_os = "freebsd")))]
+fn data_ranges(_file: &fs::File, _from: u64, _len: u64) -> Option<Vec<(u64, u64)>> {
+    None
+}
+
+/// Give `path` these permission bits unless it has them already. A file
+/// system that refuses them is only logged; the copy is still good.
+#[cfg(unix)]
+fn apply_mode(path: &Path, mode: u32) {
+    use std::os::unix::fs::PermissionsExt;
+
+    let result = fs::metadata(path).and_then(|meta| {
+        if meta.permissions().mode() & 0o7777 == mode {
+            return Ok(());
+        }
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))
+    });
+    if let Err(e) = result {
+        warn!("Could not set mode {:04o} on {}: {}", mode, path.display(), e);
+    }
+}
+
+#[cfg(not(unix))]
+fn apply_mode(_path: &Path, _mode: u32) {}
+
+/// Whether `source` and the directory `target` goes into are on one device
+#[cfg(unix)]
+fn same_file_system(source: &Path, target: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    let target_dir = target.parent().unwrap_or(target);
+    match (fs::metadata(source), fs::metadata(target_dir)) {
+        (Ok(source), Ok(target)) => source.dev() == target.dev(),
+        _ => false,
+    }
+}
+
+/// Device ids aren't exposed here, so compare the volumes the resolved paths
+/// sit on; anything that can't be resolved counts as a different device
+#[cfg(not(unix))]
+fn same_file_system(source: &Path, target: &Path) -> bool {
+    let target_dir = target.parent().unwrap_or(target);
+    match (fs::canonicalize(source), fs::canonicalize(target_dir)) {
+        (Ok(source), Ok(target)) => same_volume(&source, &target),
+        _ => false,
+    }
+}
+
+/// Whether two paths are on one volume, for links. A symlink would work
+/// across volumes, so unlike a reflink this needs checking up front.
+#[cfg(unix)]
+fn same_volume(source: &Path, target: &Path) -> bool {
+    same_file_system(source, target)
+}
+
+/// Same drive letter or network share
+#[cfg(not(unix))]
+fn same_volume(source: &Path, target: &Path) -> bool {
+    match (source.components().next(), target.components().next()) {
+        (Some(std::path::Component::Prefix(a)), Some(std::path::Component::Prefix(b))) => a == b,
+        _ => false,
+    }
+}
+
+/// The on-target name of a compressed copy: `name.ext` -> `name.ext.zst`
+fn compressed_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".zst");
+    path.with_file_name(name)
+}
+
+/// The name `path` actually has on disk if a file matches it apart from
+/// case (an exact match wins); `path` itself otherwise
+fn on_disk_name(path: PathBuf) -> PathBuf {
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+        return path;
+    };
+    if path.exists() && fs::read_dir(parent).is_ok_and(|mut entries| {
+        entries.any(|e| e.is_ok_and(|e| e.file_name() == name))
+    }) {
+        return path;
+    }
+
+    let wanted = name.to_string_lossy().to_lowercase();
+    let found = fs::read_dir(parent).ok().and_then(|entries| {
+        entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.file_name())
+            .find(|existing| existing.to_string_lossy().to_lowercase() == wanted)
+    });
+    match found {
+        Some(existing) => parent.join(existing),
+        None => path,
+    }
+}
+
+/// Check whether `dir` ignores case by creating a marker and looking it up
+/// under an upper-case name
+fn probe_case_insensitive(dir: &Path) -> bool {
+    let name = format!(".fo-case-test-{}", std::process::id());
+    let marker = dir.join(&name);
+    if fs::File::create(&marker).is_err() {
+        return false;
+    }
+    let insensitive = dir.join(name.to_uppercase()).exists();
+    let _ = fs::remove_file(&marker);
+    insensitive
+}
+
+/// Whether files can be created in `dir`, checked by creating and removing
+/// a marker file. Catches write-protect switches and read-only mounts
+/// before anything is copied.
+fn is_writable(dir: &Path) -> bool {
+    let marker = dir.join(format!(".fo-write-test-{}", std::process::id()));
+    match fs::File::create(&marker) {
+        Ok(_) => {
+            let _ = fs::remove_file(&marker);
+            true
+        }
+        Err(e) => {
+            warn!("Cannot write to {}: {}", dir.display(), e);
+            false
+        }
+    }
+}
+
+/// Run `copy`, which writes `target`, giving up after `timeout`. On timeout
+/// the target and any `.partial` file are removed; a `resumable` copy only
+/// replaces the target once it is complete, so the target is left alone and
+/// its `.tail` file removed instead. A copy already running on the blocking
+/// pool can't be stopped, but the caller no longer waits on it.
+async fn with_copy_timeout<T>(
+    timeout: Option<std::time::Duration>,
+    target: &Path,
+    resumable: bool,
+    copy: impl std::future::Future<Output = Result<T>>,
+) -> Result<T> {
+    let Some(timeout) = timeout else {
+        return copy.await;
+    };
+
+    match tokio::time::timeout(timeout, copy).await {
+        Ok(result) => result,
+        Err(_) => {
+            warn!("Copy to {} timed out after {}s, abandoning it", target.display(), timeout.as_secs());
+            // Removing from a hung drive may hang as well, so don't wait for it
+            let leftovers = if resumable {
+                [tail_path(target), partial_path(target)]
+            } else {
+                [target.to_path_buf(), partial_path(target)]
+            };
+            tokio::task::spawn_blocking(move || {
+                for path in leftovers {
+                    let _ = fs::remove_file(path);
+                }
+            });
+            Err(OrchestratorError::Timeout(format!(
+                "copying to {} took longer than {}s",
+                target.display(),
+                timeout.as_secs()
+            )))
+        }
+    }
+}
+
+/// Write a zstd-compressed copy of `source` to `target`, returning its size
+async fn compress_file(source: &Path, target: &Path, retry: TransientRetry) -> Result<u64> {
+    let source = source.to_path_buf();
+    let target = target.to_path_buf();
+
+    tokio::task::spawn_blocking(move || retry.run(&source, || -> std::io::Result<u64> {
+        let input = fs::File::open(&source)?;
+        // The old copy may be hard-linked into earlier snapshots; truncating
+        // it would rewrite theirs too
+        if fs::symlink_metadata(&target).is_ok() {
+            fs::remove_file(&target)?;
+        }
+        let output = fs::File::create(&target)?;
+        zstd::stream::copy_encode(input, &output, 0)?;
+        Ok(output.metadata()?.len())
+    }))
+    .await
+    .map_err(|e| OrchestratorError::Sync(format!("Compression task failed: {}", e)))?
+    .map_err(|e| OrchestratorError::Sync(format!("Failed to compress file: {}", e)))
+}
+
+/// How often to try again I/O that failed only because a file was busy
+#[derive(Debug, Clone, Copy, Default)]
+struct TransientRetry {
+    attempts: u32,
+    delay: std::time::Duration,
+}
+
+impl TransientRetry {
+    /// Run `op`, and again after `delay` each time it fails with a
+    /// transient error, up to `attempts` more times; it sleeps, so call it
+    /// on a blocking thread. Other
+    /// errors, and the last transient one, are returned as they are.
+    fn run<T>(self, path: &Path, mut op: impl FnMut() -> std::io::Result<T>) -> std::io::Result<T> {
+        let mut attempt = 0;
+        loop {
+            match op() {
+                Err(e) if attempt < self.attempts && is_transient(&e) => {
+                    attempt += 1;
+                    warn!("{} is busy ({}), trying again ({} of {})", path.display(), e, attempt, self.attempts);
+                    std::thread::sleep(self.delay);
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Whether `error` is a file being in use for a moment (another process
+/// holding it, a read that would block) rather than something a retry
+/// can't fix, like a full drive or denied access
+fn is_transient(error: &std::io::Error) -> bool {
+    // ERROR_SHARING_VIOLATION and ERROR_LOCK_VIOLATION
+    if cfg!(windows) && matches!(error.raw_os_error(), Some(32 | 33)) {
+        return true;
+    }
+    matches!(
+        error.kind(),
+        std::io::ErrorKind::ResourceBusy | std::io::ErrorKind::WouldBlock | std::io::ErrorKind::Interrupted
+    )
+}
+
+/// What happened to one file
+#[derive(Debug)]
+pub enum SyncResult {
+    Synced(PathBuf),
+    Pending(String),
+    /// The drive (label) is connected but can't be written to; queued as pending
+    DriveReadOnly(String),
+    /// The drive (label) is connected but past `pause_at_percent_full`;
+    /// queued as pending
+    DriveFull(String),
+    AlreadySynced,
+    Skipped(String),
+    /// Unknown file type, put in the quarantine directory at this path
+    Quarantined(PathBuf),
+    /// The target held a different file; the policy was applied and this is
+    /// the path that was written (or left alone, for `skip`)
+    Conflict(ConflictPolicy, PathBuf),
+    /// Copied into the drive's staging folder at this path, waiting for
+    /// `fo commit`
+    Staged(PathBuf),
+    /// Modified more recently than `settle_seconds` ago, or during its
+    /// copy, so possibly still being written; nothing was recorded and a
+    /// copy made meanwhile was removed. Also a file that couldn't be read
+    /// to classify it. Check again after this long.
+    Unsettled(std::time::Duration),
+    /// The drive (label) is unplugged and its queue would outgrow the
+    /// drive's last-seen size, with `reject_over_capacity` on; not queued
+    WouldExceedCapacity(String),
+}
+
+/// Where a file should be written after checking the existing target
+enum TargetResolution {
+    Clear(PathBuf),
+    Conflict(ConflictPolicy, PathBuf),
+}
+
+/// A source file on its way to a drive, as `sync_one` found it
+#[derive(Clone, Copy)]
+struct Outgoing<'a> {
+    source_path: &'a Path,
+    relative_path: &'a Path,
+    file_info: &'a FileInfo,
+    category: &'a str,
+    hash: &'a str,
+    /// The source's piece hashes from the same read as `hash`, with `chunk_hash`
+    chunks: Option<&'a [String]>,
+    /// Size and modification time from before `hash` was computed
+    fingerprint: Option<(u64, SystemTime)>,
+}
+
+impl Outgoing<'_> {
+    /// Whether the source was written to since `hash` was computed
+    fn source_changed(&self) -> bool {
+        self.fingerprint.is_some_and(|before| file_fingerprint(self.source_path) != Some(before))
+    }
+
+    fn hook_tokens(&self, target_path: &Path, drive_label: &str) -> [(&'static str, String); 5] {
+        [
+            ("source", self.source_path.to_string_lossy().to_string()),
+            ("target", target_path.to_string_lossy().to_string()),
+            ("category", self.category.to_string()),
+            ("drive", drive_label.to_string()),
+            ("hash", self.hash.to_string()),
+        ]
+    }
+}
+
+/// How putting a file on one drive went
+enum Placement {
+    /// Written to `target_path`; `conflict` is the policy applied if a
+    /// different file was there
+    Copied {
+        target_path: PathBuf,
+        conflict: Option<ConflictPolicy>,
+        compressed_size: Option<u64>,
+        reflinked: bool,
+        sparse: bool,
+        link: Option<LinkKind>,
+        /// The copy was checked against the source hash on the way (or is
+        /// the source itself, through a link, or an earlier snapshot's copy)
+        verified: bool,
+        snapshot: Option<String>,
+        /// Written under the staging folder, to be moved here by `fo commit`
+        commit_to: Option<PathBuf>,
+    },
+    /// The drive is mounted read-only or write-protected
+    ReadOnly,
+    /// The source was written to during the hash or copy; the copy was
+    /// removed and nothing should be recorded
+    SourceChanged,
+    /// Nothing was written, for this reason (a skipped conflict, pre_sync)
+    NotCopied(SyncResult),
+}
+
+/// Counts of [`SyncResult`]s over a batch of files
+#[derive(Debug, Default)]
+pub struct SyncSummary {
+    pub synced: usize,
+    pub pending: usize,
+    pub already_synced: usize,
+    pub skipped: usize,
+    pub quarantined: usize,
+    /// Copied into a staging folder, waiting for `fo commit`
+    pub staged: usize,
+    pub conflicts: usize,
+    pub failed: usize,
+    /// Each file that failed to sync, with the error message
+    pub failures: Vec<(PathBuf, String)>,
+    /// Files left alone because they were still being written
+    pub unsettled: Vec<PathBuf>,
+}
+
+/// Counts so far part-way through a batch sync
+#[derive(Debug, Clone)]
+pub struct BatchProgress {
+    pub processed: usize,
+    pub total: usize,
+    pub synced: usize,
+    pub pending: usize,
+    pub failed: usize,
+    pub elapsed: std::time::Duration,
+    /// Time left at the throughput so far
+    pub eta: std::time::Duration,
+}
+
+impl BatchProgress {
+    fn new(summary: &SyncSummary, processed: usize, total: usize, elapsed: std::time::Duration) -> Self {
+        let remaining = total.saturating_sub(processed) as u32;
+        Self {
+            processed,
+            total,
+            synced: summary.synced,
+            pending: summary.pending,
+            failed: summary.failed,
+            elapsed,
+            eta: elapsed / processed.max(1) as u32 * remaining,
+        }
+    }
+
+    fn log(&self) {
+        info!(
+            "Progress: {}/{} files ({} synced, {} pending, {} failed) in {}s, about {}s left",
+            self.processed,
+            self.total,
+            self.synced,
+            self.pending,
+            self.failed,
+            self.elapsed.as_secs(),
+            self.eta.as_secs()
+        );
+    }
+}
+
+/// One snapshot folder on a drive
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub id: String,
+    pub path: PathBuf,
+    /// Files in it, linked or copied
+    pub files: usize,
+    /// Files whose latest copy on the drive is this snapshot's
+    pub current: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct UndoReport {
+    pub batch: u64,
+    /// Copies removed from drives
+    pub deleted: usize,
+    /// Records put back to what they were before the batch
+    pub restored: usize,
+    /// Files whose earlier copy the batch wrote over, so there is none to
+    /// go back to; queued to sync again
+    pub requeued: Vec<PathBuf>,
+    /// Discarded staged copies put back
+    pub restaged: usize,
+    /// Files synced again since the batch, left as they are
+    pub superseded: Vec<PathBuf>,
+    /// Copies edited on the drive since the batch wrote them, left in place
+    pub changed: Vec<PathBuf>,
+    /// Files whose drive isn't connected; still in the batch
+    pub offline: Vec<PathBuf>,
+}
+
+impl UndoReport {
+    pub fn print(&self) {
+        println!("\n=== Undo Batch {} ===", self.batch);
+        println!("Deleted from drives: {}", self.deleted);
+        println!("Records restored: {}", self.restored);
+        if !self.requeued.is_empty() {
+            println!("Queued to sync again (the batch wrote over their earlier copy): {}", self.requeued.len());
+        }
+        if self.restaged > 0 {
+            println!("Staged again: {}", self.restaged);
+        }
+        if !self.superseded.is_empty() {
+            println!("Left alone (synced again since): {}", self.superseded.len());
+        }
+        if !self.changed.is_empty() {
+            println!("Left alone (changed on the drive): {}", self.changed.len());
+            for path in &self.changed {
+                println!("  {}", path.display());
+            }
+        }
+        if !self.offline.is_empty() {
+            println!("Waiting for a drive to connect (run undo again later): {}", self.offline.len());
+            for path in &self.offline {
+                println!("  {}", path.display());
+            }
+        }
+        println!("=====================\n");
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    pub ok: usize,
+    pub missing: Vec<PathBuf>,
+    pub mismatched: Vec<PathBuf>,
+    /// Records skipped because their drive isn't connected
+    pub offline: usize,
+    pub requeued: usize,
+}
+
+impl VerifyReport {
+    pub fn print(&self) {
+        println!("\n=== Verify Report ===");
+        println!("OK: {}", self.ok);
+        println!("Missing: {}", self.missing.len());
+        println!("Mismatched: {}", self.mismatched.len());
+        if self.offline > 0 {
+            println!("Not checked (drive offline): {}", self.offline);
+        }
+        if self.requeued > 0 {
+            println!("Re-queued for sync: {}", self.requeued);
+        }
+
+        for path in &self.missing {
+            println!("  missing: {}", path.display());
+        }
+        for path in &self.mismatched {
+            println!("  mismatched: {}", path.display());
+        }
+        println!("=====================\n");
+    }
+}
+
+#[derive(Debug)]
+pub struct RerouteMove {
+    pub source_path: PathBuf,
+    /// Drive label and category the copy is on now
+    pub from: String,
+    /// Drive label and category the current rules send it to
+    pub to: String,
+}
+
+/// What [`SyncManager::reassign_pending`] did
+#[derive(Debug, Default)]
+pub struct ReassignReport {
+    pub moved: usize,
+    /// Queued files the new drive doesn't take, left as they were
+    pub incompatible: Vec<PathBuf>,
+}
+
+/// Where [`SyncManager::sidecar_route`] sends a sidecar file
+struct SidecarRoute {
+    primary: PathBuf,
+    category: String,
+    /// The primary's extension, which the drive has to accept
+    extension: Option<String>,
+    /// The drive the primary was synced to or is queued for
+    drive: Option<String>,
+}
+
+/// What [`SyncManager::find_orphans`] found
+#[derive(Debug, Default)]
+pub struct OrphanReport {
+    /// Queue entries for a drive that isn't configured
+    pub pending: Vec<PendingSync>,
+    /// Sync records with a copy on a drive that isn't configured
+    pub records: Vec<FileState>,
+}
+
+impl OrphanReport {
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty() && self.records.is_empty()
+    }
+}
+
+/// What [`SyncManager::commit_staged`] did
+#[derive(Debug, Default)]
+pub struct StageCommit {
+    /// Where the committed copies now are
+    pub committed: Vec<PathBuf>,
+    /// Staged copies on drives that aren't connected
+    pub waiting: usize,
+    /// Sources whose staged copy couldn't be committed, and why
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+/// What [`SyncManager::fix_orphans`] did
+#[derive(Debug, Default)]
+pub struct OrphanFix {
+    /// Queued files moved to a configured drive
+    pub requeued: usize,
+    /// Queued files taken out of the queue, with no drive to go to
+    pub unqueued: usize,
+    /// Records that kept their copies on configured drives
+    pub trimmed: usize,
+    /// Records removed, having no copy on a configured drive
+    pub forgotten: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct RerouteReport {
+    /// Moves made, or proposed with `--dry-run`
+    pub moves: Vec<RerouteMove>,
+    /// Moves not made because a drive involved isn't connected
+    pub offline: Vec<RerouteMove>,
+    /// Records whose source file no longer exists
+    pub missing_source: usize,
+    pub failures: Vec<(PathBuf, String)>,
+}
+
+impl RerouteReport {
+    pub fn print(&self, dry_run: bool) {
+        println!("\n=== Reroute Report ===");
+        let verb = if dry_run { "Would move" } else { "Moved" };
+        println!("{}: {}", verb, self.moves.len());
+        for planned in &self.moves {
+            println!("  {}: {} -> {}", planned.source_path.display(), planned.from, planned.to);
+        }
+
+        if !self.offline.is_empty() {
+            println!("Waiting for a drive to connect: {}", self.offline.len());
+            for planned in &self.offline {
+                println!("  {}: {} -> {}", planned.source_path.display(), planned.from, planned.to);
+            }
+        }
+        if self.missing_source > 0 {
+            println!("Skipped (source missing): {}", self.missing_source);
+        }
+        if !self.failures.is_empty() {
+            println!("Failed: {}", self.failures.len());
+            for (path, reason) in &self.failures {
+                println!("  {}: {}", path.display(), reason);
+            }
+        }
+        println!("======================\n");
+    }
+}
+
+impl SyncSummary {
+    pub fn total(&self) -> usize {
+        self.synced + self.pending + self.already_synced + self.skipped + self.quarantined + self.staged
+            + self.conflicts + self.failed + self.unsettled.len()
+    }
+
+    pub fn print(&self) {
+        println!("\n=== Sync Summary ===");
+        println!("Total files: {}", self.total());
+        println!("Synced: {}", self.synced);
+        println!("Already synced: {}", self.already_synced);
+        println!("Pending: {}", self.pending);
+        println!("Skipped: {}", self.skipped);
+        if self.quarantined > 0 {
+            println!("Quarantined (unknown type): {}", self.quarantined);
+        }
+        if self.staged > 0 {
+            println!("Staged (run `fo commit` to move them into place): {}", self.staged);
+        }
+        println!("Conflicts: {}", self.conflicts);
+        if !self.unsettled.is_empty() {
+            println!("Still being written or unreadable (not synced yet): {}", self.unsettled.len());
+        }
+        println!("Failed: {}", self.failed);
+
+        if !self.failures.is_empty() {
+            println!("\nFailed files:");
+            for (path, reason) in &self.failures {
+                println!("  {}: {}", path.display(), reason);
+            }
+        }
+        println!("====================\n");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{DriveConfig, SourceConfig, TextSniffing};
+    use crate::drive::MockDriveProvider;
+    use std::collections::HashMap;
+    use tempfile::TempDir;
+
+    fn test_config(source: &Path, drive_path: &Path) -> Config {
+        let mut drives = HashMap::new();
+        drives.insert(
+            "test-drive".to_string(),
+            DriveConfig {
+                label: "TestUSB".to_string(),
+                target: "images".to_string(),
+                path: Some(drive_path.to_path_buf()),
+                last_seen: None,
+                ..Default::default()
+            },
+        );
+
+        Config {
+            version: crate::config::CURRENT_CONFIG_VERSION,
+            source: SourceConfig { path: source.to_path_buf(), ..Default::default() },
+            rules: Config::default_config().rules,
+            drives,
+            sync: Default::default(),
+            notifications: Default::default(),
+            hooks: Default::default(),
+            folder_names: Default::default(),
+            metrics_addr: None,
+            rules_include: None,
+            relative_paths: Default::default(),
+        }
+    }
+
+    /// A manager whose only drive is bound to `drive` and plugged in or out
+    /// through the returned mock
+    fn mock_drive_manager(source: &Path, drive: &Path, db: &Path) -> (SyncManager, MockDriveProvider) {
+        let drives = MockDriveProvider::default();
+        let state = StateManager::new(db.join("state.db")).unwrap();
+        let manager = SyncManager::new(test_config(source, drive), state).with_drive_provider(drives.clone());
+        (manager, drives)
+    }
+
+    #[tokio::test]
+    async fn test_sync_when_drive_connected() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        drives.connect("TestUSB", drive.path());
+
+        let photo = source.path().join("photo.jpg");
+        fs::write(&photo, b"jpeg").unwrap();
+
+        assert!(matches!(sync_manager.sync_file(&photo).await.unwrap(), SyncResult::Synced(_)));
+        assert!(drive.path().join("images").join("photo.jpg").exists());
+        assert!(matches!(sync_manager.sync_file(&photo).await.unwrap(), SyncResult::AlreadySynced));
+    }
+
+    #[tokio::test]
+    async fn test_runs_are_recorded_with_their_failures() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        let mut sync_manager = sync_manager.with_command("sync-once");
+        drives.connect("TestUSB", drive.path());
+
+        fs::write(source.path().join("photo.jpg"), b"jpeg").unwrap();
+        // No drive takes music
+        let song = source.path().join("song.mp3");
+        fs::write(&song, b"mp3").unwrap();
+        sync_manager.sync_all().await.unwrap();
+        sync_manager.sync_modified_since(SystemTime::now()).await.unwrap();
+
+        let runs = sync_manager.state.get_runs(10).unwrap();
+        assert_eq!(runs.len(), 2);
+        assert_eq!(runs[0].kind, "sync-since");
+        let full = &runs[1];
+        assert_eq!((full.kind.as_str(), full.command.as_deref()), ("sync-all", Some("sync-once")));
+        assert_eq!((full.synced, full.failed), (1, 1));
+        assert_eq!(full.failures[0].0, song);
+        assert!(full.finished_at >= full.started_at);
+    }
+
+    #[tokio::test]
+    async fn test_sidecars_follow_their_video() {
+        let source = TempDir::new().unwrap();
+        let images = TempDir::new().unwrap();
+        let videos = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let mut config = test_config(source.path(), images.path());
+        config.drives.insert("video-drive".to_string(), DriveConfig {
+            label: "VideoUSB".to_string(),
+            target: "videos".to_string(),
+            path: Some(videos.path().to_path_buf()),
+            ..Default::default()
+        });
+        config.sync.group_sidecars = true;
+        let drives = MockDriveProvider::default();
+        let state = StateManager::new(db.path().join("state.db")).unwrap();
+        let mut sync_manager = SyncManager::new(config, state).with_drive_provider(drives.clone());
+
+        let movies = source.path().join("movies");
+        fs::create_dir(&movies).unwrap();
+        for name in ["movie.mkv", "movie.en.srt", "movie.jpg", "beach.jpg", "IMG_1.mov", "IMG_1.jpg"] {
+            fs::write(movies.join(name), name).unwrap();
+        }
+        drives.connect("TestUSB", images.path());
+
+        // By default images aren't sidecars, so a Live Photo's still stays
+        // with the images
+        let summary = sync_manager.sync_all().await.unwrap();
+        assert_eq!((summary.synced, summary.pending), (3, 3));
+        assert!(images.path().join("images/movies/IMG_1.jpg").exists());
+        assert!(images.path().join("images/movies/movie.jpg").exists());
+        fs::remove_file(movies.join("IMG_1.mov")).unwrap();
+        fs::remove_file(movies.join("IMG_1.jpg")).unwrap();
+        sync_manager.state.remove_file_state(&movies.join("movie.jpg")).unwrap();
+        sync_manager.state.remove_pending_sync(&movies.join("IMG_1.mov")).unwrap();
+        fs::remove_file(images.path().join("images/movies/movie.jpg")).unwrap();
+        sync_manager.config.sync.sidecar_extensions = Some(vec!["srt".to_string(), "jpg".to_string()]);
+
+        // Posters listed as sidecars follow the video; the subtitles came
+        // first by name, before the video was queued
+        sync_manager.sync_all().await.unwrap();
+        assert!(images.path().join("images/movies/beach.jpg").exists());
+        let queued: Vec<_> = sync_manager.state.get_pending_syncs("video-drive").unwrap();
+        assert_eq!(queued.len(), 3);
+
+        drives.connect("VideoUSB", videos.path());
+        sync_manager.check_and_sync_connected_drives().await.unwrap();
+        for name in ["movie.mkv", "movie.en.srt", "movie.jpg"] {
+            assert!(videos.path().join("videos/movies").join(name).exists(), "{} missing", name);
+        }
+        assert!(!images.path().join("images/movies/movie.jpg").exists());
+
+        // Without its video, a poster is just an image again
+        fs::remove_file(movies.join("movie.mkv")).unwrap();
+        fs::write(movies.join("movie.jpg"), b"new poster").unwrap();
+        sync_manager.state.remove_file_state(&movies.join("movie.mkv")).unwrap();
+        sync_manager.sync_file(&movies.join("movie.jpg")).await.unwrap();
+        assert!(images.path().join("images/movies/movie.jpg").exists());
+    }
+
+    #[tokio::test]
+    async fn test_orphans_of_a_removed_drive_are_found_and_fixed() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        let [a, b, c] = ["a.jpg", "b.jpg", "c.jpg"].map(|name| source.path().join(name));
+        for path in [&a, &b, &c] {
+            fs::write(path, path.to_string_lossy().as_bytes()).unwrap();
+        }
+        assert!(matches!(sync_manager.sync_file(&c).await.unwrap(), SyncResult::Pending(_)));
+        drives.connect("TestUSB", drive.path());
+        sync_manager.sync_file(&a).await.unwrap();
+        sync_manager.sync_file(&b).await.unwrap();
+        assert!(sync_manager.find_orphans().unwrap().is_empty());
+
+        // b also has a copy on the drive that stays; the config entry for
+        // the old drive is then replaced by hand
+        let state = sync_manager.state.clone();
+        let record = state.get_file_state(&b).unwrap().unwrap();
+        let replica = FileState { target_drive: "new-drive".to_string(), ..record.clone() };
+        state.save_file_state(&record.with_copy(replica)).unwrap();
+        state.add_tag(&b, "favorite").unwrap();
+        let moved = sync_manager.config.drives.remove("test-drive").unwrap();
+        sync_manager.config.drives.insert("new-drive".to_string(), moved);
+
+        let orphans = sync_manager.find_orphans().unwrap();
+        assert_eq!((orphans.pending.len(), orphans.records.len()), (1, 2));
+        let fix = sync_manager.fix_orphans(&orphans).unwrap();
+        assert_eq!((fix.requeued, fix.unqueued, fix.trimmed, fix.forgotten), (1, 0, 1, 1));
+
+        assert_eq!(state.get_pending_syncs("new-drive").unwrap()[0].source_path, c);
+        assert!(state.get_file_state(&a).unwrap().is_none());
+        let record = state.get_file_state(&b).unwrap().unwrap();
+        assert_eq!((record.target_drive.as_str(), record.replicas.len()), ("new-drive", 0));
+        assert_eq!(record.tags, vec!["favorite".to_string()]);
+        assert!(sync_manager.find_orphans().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_target_root_takes_every_category() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let root = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        fs::create_dir(source.path().join("trip")).unwrap();
+        fs::write(source.path().join("trip/photo.jpg"), b"jpeg").unwrap();
+        fs::write(source.path().join("song.mp3"), b"mp3").unwrap();
+
+        let mut config = test_config(source.path(), drive.path());
+        config.folder_names.insert("images".to_string(), "Photos".to_string());
+        config.hooks.post_sync = Some("touch {target}.hooked".to_string());
+        config.notifications.enabled = true;
+        assert!(config.clone().with_target_root(&source.path().join("scratch")).is_err());
+        let config = config.with_target_root(root.path()).unwrap();
+        assert!(config.hooks.post_sync.is_none());
+        assert!(!config.notifications.enabled);
+        // No drive is plugged in; the stand-ins don't need one
+        let state = StateManager::new(db.path().join("state.db")).unwrap();
+        let mut sync_manager = SyncManager::new(config, state).with_drive_provider(MockDriveProvider::default());
+
+        let summary = sync_manager.sync_all().await.unwrap();
+        assert_eq!(summary.synced, 2);
+        assert!(root.path().join("images/trip/photo.jpg").exists());
+        assert!(root.path().join("music/song.mp3").exists());
+        assert_eq!(fs::read_dir(drive.path()).unwrap().count(), 0);
+
+        let record = sync_manager.state.get_file_state(&source.path().join("song.mp3")).unwrap().unwrap();
+        assert_eq!(record.target_drive, format!("{}:music", crate::config::TARGET_ROOT_DRIVE));
+    }
+
+    #[tokio::test]
+    async fn test_tags_and_notes_survive_resync() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        drives.connect("TestUSB", drive.path());
+
+        let photo = source.path().join("photo.jpg");
+        fs::write(&photo, b"jpeg").unwrap();
+        assert!(!sync_manager.state.add_tag(&photo, "favorite").unwrap());
+        sync_manager.sync_file(&photo).await.unwrap();
+
+        let state = sync_manager.state.clone();
+        assert!(state.add_tag(&photo, "favorite").unwrap());
+        state.add_tag(&photo, "favorite").unwrap();
+        state.set_note(&photo, Some("Print this one".to_string())).unwrap();
+
+        fs::write(&photo, b"edited jpeg").unwrap();
+        assert!(matches!(sync_manager.sync_file(&photo).await.unwrap(), SyncResult::Synced(_)));
+        let tagged = state.get_by_tag("favorite").unwrap();
+        assert_eq!(tagged.len(), 1);
+        assert_eq!(tagged[0].tags, vec!["favorite".to_string()]);
+        assert_eq!(tagged[0].note.as_deref(), Some("Print this one"));
+        assert!(state.get_by_tag("to-review").unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_queue_pending_skips_synced_files() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        drives.connect("TestUSB", drive.path());
+
+        let synced = source.path().join("synced.jpg");
+        fs::write(&synced, b"jpeg").unwrap();
+        sync_manager.sync_file(&synced).await.unwrap();
+
+        let album = source.path().join("album");
+        fs::create_dir(&album).unwrap();
+        fs::write(album.join("new.jpg"), b"new jpeg").unwrap();
+        fs::write(album.join("notes.unknownext"), b"?").unwrap();
+
+        assert_eq!(sync_manager.queue_pending(&synced).await.unwrap(), 0);
+        assert_eq!(sync_manager.queue_pending(&album).await.unwrap(), 1);
+        assert_eq!(sync_manager.queue_pending(&source.path().join("gone.jpg")).await.unwrap(), 0);
+
+        let pending = sync_manager.state.get_all_pending_syncs().unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].source_path, album.join("new.jpg"));
+    }
+
+    #[test]
+    fn test_text_sniffing_is_opt_in() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, _drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+
+        let readme = source.path().join("README");
+        fs::write(&readme, b"Build with cargo build\n").unwrap();
+        let blob = source.path().join("blob");
+        fs::write(&blob, [0u8, 159, 146, 150, 1, 2, 3]).unwrap();
+        let category = |sync_manager: &SyncManager, path: &Path| {
+            let file_info = FileClassifier::get_file_info(path).unwrap();
+            sync_manager.categorize(Path::new(path.file_name().unwrap()), &file_info)
+        };
+
+        assert_eq!(category(&sync_manager, &readme), None);
+
+        sync_manager.config.rules.text_sniffing = Some(TextSniffing::default());
+        assert_eq!(category(&sync_manager, &readme).as_deref(), Some("documents"));
+        assert_eq!(category(&sync_manager, &blob), None);
+    }
+
+    #[tokio::test]
+    async fn test_undo_batch_reverts_copies_it_made() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        drives.connect("TestUSB", drive.path());
+
+        let kept = source.path().join("kept.jpg");
+        fs::write(&kept, b"first").unwrap();
+        sync_manager.sync_file(&kept).await.unwrap();
+        let before = sync_manager.state.get_file_state(&kept).unwrap().unwrap();
+
+        // The batch writes over kept.jpg's copy, puts renamed.jpg's next to
+        // its earlier copy that was moved on the drive, and adds two new files
+        let renamed = source.path().join("renamed.jpg");
+        fs::write(&renamed, b"renamed").unwrap();
+        sync_manager.sync_file(&renamed).await.unwrap();
+        let renamed_before = sync_manager.state.get_file_state(&renamed).unwrap().unwrap();
+        let mut moved = renamed_before.clone();
+        moved.target_path = drive.path().join("elsewhere.jpg");
+        fs::rename(&renamed_before.target_path, &moved.target_path).unwrap();
+        sync_manager.state.save_file_state(&moved).unwrap();
+        sync_manager.state.flush().unwrap();
+        fs::write(&renamed, b"renamed again").unwrap();
+        fs::write(&kept, b"second").unwrap();
+        fs::write(source.path().join("new.jpg"), b"new").unwrap();
+        fs::write(source.path().join("edited.jpg"), b"edited").unwrap();
+        sync_manager.sync_all().await.unwrap();
+        let batch = sync_manager.last_batch().unwrap();
+
+        // Someone edits one copy on the drive afterwards
+        let images = drive.path().join("images");
+        fs::write(images.join("edited.jpg"), b"changed on the drive").unwrap();
+
+        let report = sync_manager.undo_batch(None).unwrap();
+        assert_eq!(report.batch, batch);
+        assert_eq!(report.deleted, 3);
+        assert_eq!(report.restored, 1);
+        assert!(moved.target_path.exists());
+        assert_eq!(report.requeued, vec![kept.clone()]);
+        assert_eq!(report.changed, vec![images.join("edited.jpg")]);
+
+        assert!(!images.join("new.jpg").exists());
+        assert!(sync_manager.state.get_file_state(&source.path().join("new.jpg")).unwrap().is_none());
+        assert_eq!(sync_manager.state.get_file_state(&renamed).unwrap().unwrap().target_path, moved.target_path);
+        // kept.jpg's first copy was written over, so it syncs again rather than
+        // pointing at a file that is gone
+        assert!(sync_manager.state.get_file_state(&kept).unwrap().is_none());
+        let queued = sync_manager.state.get_pending_syncs("test-drive").unwrap();
+        assert!(queued.iter().any(|pending| pending.source_path == kept && pending.hash != before.hash));
+        assert_eq!(fs::read(images.join("edited.jpg")).unwrap(), b"changed on the drive");
+
+        // Fully handled, so there is nothing left to undo
+        assert!(sync_manager.state.get_batch(batch).unwrap().is_none());
+        assert!(sync_manager.undo_batch(None).is_err());
+    }
+
+    #[test]
+    fn test_category_priority_settles_content_and_extension_disagreeing() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, _drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+
+        // A PNG saved with a .pdf name
+        let scan = source.path().join("scan.pdf");
+        fs::write(&scan, b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR\0\0\0\x01\0\0\0\x01\x08\x06\0\0\0").unwrap();
+        let file_info = FileClassifier::get_file_info(&scan).unwrap();
+        assert_eq!(file_info.file_type, FileType::Image);
+
+        assert_eq!(sync_manager.categorize(Path::new("scan.pdf"), &file_info).as_deref(), Some("images"));
+        sync_manager.config.rules.category_priority = vec!["documents".to_string()];
+        assert_eq!(sync_manager.categorize(Path::new("scan.pdf"), &file_info).as_deref(), Some("documents"));
+    }
+
+    #[test]
+    fn test_unambiguous_extension_skips_content_read() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, _drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+
+        let scan = source.path().join("scan.pdf");
+        fs::write(&scan, b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR\0\0\0\x01\0\0\0\x01\x08\x06\0\0\0").unwrap();
+
+        // The content is checked unless extension-first is asked for
+        let file_info = sync_manager.file_info(&scan).unwrap();
+        assert_eq!(file_info.file_type, FileType::Image);
+        assert_eq!(sync_manager.categorize(Path::new("scan.pdf"), &file_info).as_deref(), Some("images"));
+
+        sync_manager.config.rules.classify_strategy = crate::config::ClassifyStrategy::ExtensionFirst;
+        let file_info = sync_manager.file_info(&scan).unwrap();
+        assert_eq!((file_info.file_type, file_info.mime), (FileType::Document, None));
+
+        // A MIME filter needs the detected type
+        sync_manager.config.rules.mime.insert("documents".to_string(), Default::default());
+        assert_eq!(sync_manager.file_info(&scan).unwrap().mime, Some("image/png"));
+    }
+
+    #[tokio::test]
+    async fn test_limited_sync_resumes_from_cursor() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        drives.connect("TestUSB", drive.path());
+        for name in ["e.jpg", "a.jpg", "d.jpg", "b.jpg", "c.jpg"] {
+            fs::write(source.path().join(name), name).unwrap();
+        }
+
+        let (summary, remaining) = sync_manager.sync_all_from_cursor(Some(2), false).await.unwrap();
+        assert_eq!((summary.synced, remaining), (2, 3));
+        assert_eq!(sync_manager.state.get_sync_cursor().unwrap(), Some(source.path().join("b.jpg")));
+
+        let (summary, remaining) = sync_manager.sync_all_from_cursor(Some(2), true).await.unwrap();
+        assert_eq!((summary.synced, summary.already_synced, remaining), (2, 0, 1));
+        assert!(drive.path().join("images").join("d.jpg").exists());
+        assert!(!drive.path().join("images").join("e.jpg").exists());
+
+        // Without --resume, files already synced don't count towards the limit
+        let (summary, remaining) = sync_manager.sync_all_from_cursor(Some(1), false).await.unwrap();
+        assert_eq!((summary.synced, summary.already_synced, remaining), (1, 4, 0));
+        assert_eq!(sync_manager.state.get_sync_cursor().unwrap(), None);
+    }
+
+    #[test]
+    fn test_fastest_selection_prefers_quickest_connected_drive() {
+        let source = TempDir::new().unwrap();
+        let slow = TempDir::new().unwrap();
+        let fast = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+
+        let mut config = test_config(source.path(), slow.path());
+        config.sync.drive_selection = DriveSelection::Fastest;
+        config.drives.insert("fast-drive".to_string(), DriveConfig {
+            label: "FastUSB".to_string(),
+            target: "images".to_string(),
+            path: Some(fast.path().to_path_buf()),
+            ..Default::default()
+        });
+        let drives = MockDriveProvider::default();
+        let state = StateManager::new(db.path().join("state.db")).unwrap();
+        let mut sync_manager = SyncManager::new(config, state).with_drive_provider(drives.clone());
+        drives.connect("TestUSB", slow.path());
+        drives.connect("FastUSB", fast.path());
+
+        let mb = 1024 * 1024;
+        let selected = |sync_manager: &mut SyncManager| sync_manager.select_drive("images", Some("jpg"), mb).unwrap().0;
+
+        // An unmeasured drive is tried so it gets a speed
+        sync_manager.state.record_copy_speed("test-drive", 10 * mb, std::time::Duration::from_secs(1)).unwrap();
+        assert_eq!(selected(&mut sync_manager), "fast-drive");
+
+        sync_manager.state.record_copy_speed("fast-drive", 40 * mb, std::time::Duration::from_secs(1)).unwrap();
+        assert_eq!(selected(&mut sync_manager), "fast-drive");
+
+        // Only connected drives count; with none, the first one queues it
+        drives.disconnect(fast.path());
+        assert_eq!(selected(&mut sync_manager), "test-drive");
+        drives.disconnect(slow.path());
+        assert_eq!(selected(&mut sync_manager), "fast-drive");
+    }
+
+    /// Manager with a second image drive, `b-drive` (picked first, by
+    /// UUID), besides `test-drive`; neither is connected
+    fn two_image_drives(source: &Path, first: &Path, second: &Path, db: &Path) -> (SyncManager, MockDriveProvider) {
+        let mut config = test_config(source, second);
+        config.drives.insert("b-drive".to_string(), DriveConfig {
+            label: "OtherUSB".to_string(),
+            target: "images".to_string(),
+            path: Some(first.to_path_buf()),
+            ..Default::default()
+        });
+        let drives = MockDriveProvider::default();
+        let state = StateManager::new(db.join("state.db")).unwrap();
+        (SyncManager::new(config, state).with_drive_provider(drives.clone()), drives)
+    }
+
+    #[tokio::test]
+    async fn test_interleaved_pending_drains_in_steps_until_unplugged() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        let mut sync_manager = sync_manager.with_interleaved_pending();
+
+        for name in ["a.jpg", "b.jpg", "c.jpg"] {
+            let photo = source.path().join(name);
+            fs::write(&photo, name).unwrap();
+            assert!(matches!(sync_manager.sync_file(&photo).await.unwrap(), SyncResult::Pending(_)));
+        }
+
+        // With no hash workers, one file per step
+        drives.connect("TestUSB", drive.path());
+        sync_manager.check_and_sync_connected_drives().await.unwrap();
+        assert_eq!(sync_manager.state.get_pending_syncs("test-drive").unwrap().len(), 2);
+        assert!(sync_manager.last_batch().is_none());
+        assert!(sync_manager.continue_pending().await.unwrap());
+        assert_eq!(sync_manager.state.get_pending_syncs("test-drive").unwrap().len(), 1);
+
+        drives.disconnect(drive.path());
+        assert!(!sync_manager.continue_pending().await.unwrap());
+        assert_eq!(sync_manager.state.get_pending_syncs("test-drive").unwrap().len(), 1);
+        assert!(!drive.path().join("images/c.jpg").exists());
+
+        // Both steps are one batch
+        let batch = sync_manager.last_batch().unwrap();
+        assert_eq!(sync_manager.state.get_batch(batch).unwrap().unwrap().entries.len(), 2);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_drain_stops_when_the_drive_goes_away() {
+        let source = TempDir::new().unwrap();
+        let mount = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let drive = mount.path().join("usb");
+        fs::create_dir(&drive).unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), &drive, db.path());
+
+        for name in ["a.jpg", "b.jpg", "c.jpg"] {
+            let photo = source.path().join(name);
+            fs::write(&photo, name).unwrap();
+            assert!(matches!(sync_manager.sync_file(&photo).await.unwrap(), SyncResult::Pending(_)));
+        }
+
+        // Pulled out after the first copy, though the drive list (read once
+        // for the batch) still shows it
+        let gone = mount.path().join("gone");
+        sync_manager.config.hooks.post_sync = Some(format!("mv '{}' '{}'", drive.display(), gone.display()));
+        drives.connect("TestUSB", &drive);
+        sync_manager.check_and_sync_connected_drives().await.unwrap();
+
+        assert!(gone.join("images/a.jpg").exists());
+        assert!(!drive.exists());
+        assert_eq!(sync_manager.state.get_pending_syncs("test-drive").unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_reassigned_pending_files_go_to_the_new_drive() {
+        let source = TempDir::new().unwrap();
+        let other = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = two_image_drives(source.path(), other.path(), drive.path(), db.path());
+
+        let photo = source.path().join("a.jpg");
+        fs::write(&photo, b"jpeg").unwrap();
+        assert!(matches!(sync_manager.sync_file(&photo).await.unwrap(), SyncResult::Pending(_)));
+        assert_eq!(sync_manager.state.get_pending_sync(&photo).unwrap().unwrap().target_drive, "b-drive");
+
+        assert!(sync_manager.reassign_pending("b-drive", "nope", None).is_err());
+        let report = sync_manager.reassign_pending("b-drive", "test-drive", Some("images")).unwrap();
+        assert_eq!((report.moved, report.incompatible.len()), (1, 0));
+        assert!(sync_manager.state.get_pending_syncs("b-drive").unwrap().is_empty());
+
+        // Still first in line, but the queue entry decides
+        drives.connect("TestUSB", drive.path());
+        sync_manager.process_pending_syncs("test-drive").await.unwrap();
+        assert!(drive.path().join("images/a.jpg").exists());
+        assert!(sync_manager.state.get_pending_sync(&photo).unwrap().is_none());
+
+        // A drive that doesn't take the extension leaves the file queued
+        drives.disconnect(drive.path());
+        let raw = source.path().join("b.jpg");
+        fs::write(&raw, b"another jpeg").unwrap();
+        sync_manager.sync_file(&raw).await.unwrap();
+        sync_manager.config.drives.get_mut("test-drive").unwrap().accept_extensions = Some(vec!["png".to_string()]);
+        let report = sync_manager.reassign_pending("b-drive", "test-drive", None).unwrap();
+        assert_eq!((report.moved, report.incompatible), (0, vec![raw.clone()]));
+        assert_eq!(sync_manager.state.get_pending_sync(&raw).unwrap().unwrap().target_drive, "b-drive");
+    }
+
+    #[tokio::test]
+    async fn test_fastest_selection_drains_unplugged_siblings_queue() {
+        let source = TempDir::new().unwrap();
+        let other = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = two_image_drives(source.path(), other.path(), drive.path(), db.path());
+
+        let photo = source.path().join("a.jpg");
+        fs::write(&photo, b"jpeg").unwrap();
+        sync_manager.sync_file(&photo).await.unwrap();
+        drives.connect("TestUSB", drive.path());
+
+        // `first` keeps waiting for the drive it picked
+        sync_manager.check_and_sync_connected_drives().await.unwrap();
+        assert_eq!(sync_manager.state.get_pending_syncs("b-drive").unwrap().len(), 1);
+
+        sync_manager.config.sync.drive_selection = DriveSelection::Fastest;
+        sync_manager.check_and_sync_connected_drives().await.unwrap();
+        assert!(drive.path().join("images/a.jpg").exists());
+        assert!(sync_manager.state.get_all_pending_syncs().unwrap().is_empty());
+        assert_eq!(sync_manager.state.get_file_state(&photo).unwrap().unwrap().target_drive, "test-drive");
+    }
+
+    #[tokio::test]
+    async fn test_replicated_file_waits_only_for_the_missing_drive() {
+        let source = TempDir::new().unwrap();
+        let other = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = two_image_drives(source.path(), other.path(), drive.path(), db.path());
+        sync_manager.config.sync.replicate = vec!["images".to_string()];
+        drives.connect("OtherUSB", other.path());
+
+        let photo = source.path().join("a.jpg");
+        fs::write(&photo, b"jpeg").unwrap();
+        assert!(matches!(sync_manager.sync_file(&photo).await.unwrap(), SyncResult::Synced(_)));
+        assert!(other.path().join("images/a.jpg").exists());
+        let pending = sync_manager.state.get_pending_syncs("test-drive").unwrap();
+        assert!(pending.len() == 1 && pending[0].replica);
+        let completeness = sync_manager.replication_completeness().unwrap();
+        assert_eq!((completeness["b-drive"], completeness["test-drive"]), ((1, 1), (0, 1)));
+
+        drives.connect("TestUSB", drive.path());
+        sync_manager.check_and_sync_connected_drives().await.unwrap();
+        assert_eq!(fs::read(drive.path().join("images/a.jpg")).unwrap(), b"jpeg");
+        assert!(sync_manager.state.get_all_pending_syncs().unwrap().is_empty());
+        let record = sync_manager.state.get_file_state(&photo).unwrap().unwrap();
+        assert_eq!((record.target_drive.as_str(), record.replicas.len()), ("b-drive", 1));
+        assert!(record.copy_on("test-drive").is_some());
+        assert_eq!(sync_manager.replication_completeness().unwrap()["test-drive"], (1, 1));
+        assert!(matches!(sync_manager.sync_file(&photo).await.unwrap(), SyncResult::AlreadySynced));
+
+        // An edit goes to both again, and a lost copy is put back on its own
+        fs::write(&photo, b"edited").unwrap();
+        assert!(matches!(sync_manager.sync_file(&photo).await.unwrap(), SyncResult::Synced(_)));
+        assert_eq!(fs::read(drive.path().join("images/a.jpg")).unwrap(), b"edited");
+        fs::remove_file(other.path().join("images/a.jpg")).unwrap();
+        assert!(matches!(sync_manager.sync_file(&photo).await.unwrap(), SyncResult::Synced(_)));
+        assert_eq!(fs::read(other.path().join("images/a.jpg")).unwrap(), b"edited");
+    }
+
+    #[tokio::test]
+    async fn test_replicated_copies_are_staged_and_undone() {
+        let source = TempDir::new().unwrap();
+        let other = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = two_image_drives(source.path(), other.path(), drive.path(), db.path());
+        sync_manager.config.sync.replicate = vec!["images".to_string()];
+        sync_manager.config.sync.staging = true;
+        drives.connect("OtherUSB", other.path());
+        drives.connect("TestUSB", drive.path());
+
+        // Each drive gets a staged copy of its own
+        let photo = source.path().join("a.jpg");
+        fs::write(&photo, b"jpeg").unwrap();
+        assert!(matches!(sync_manager.sync_file(&photo).await.unwrap(), SyncResult::Staged(_)));
+        for root in [other.path(), drive.path()] {
+            assert!(root.join(STAGING_DIR).join("images/a.jpg").exists());
+        }
+        assert_eq!(sync_manager.state.get_all_staged().unwrap().len(), 2);
+        assert!(sync_manager.state.get_file_state(&photo).unwrap().is_none());
+        assert!(matches!(sync_manager.sync_file(&photo).await.unwrap(), SyncResult::AlreadySynced));
+
+        assert_eq!(sync_manager.commit_staged(None).await.unwrap().committed.len(), 2);
+        let record = sync_manager.state.get_file_state(&photo).unwrap().unwrap();
+        assert!(record.copy_on("b-drive").is_some() && record.copy_on("test-drive").is_some());
+
+        // A run's copies to both drives are undone together
+        sync_manager.config.sync.staging = false;
+        fs::write(&photo, b"edited").unwrap();
+        sync_manager.sync_all().await.unwrap();
+        assert_eq!(fs::read(drive.path().join("images/a.jpg")).unwrap(), b"edited");
+        let report = sync_manager.undo_batch(None).unwrap();
+        assert_eq!((report.deleted, report.requeued.len()), (2, 2));
+        assert!(!other.path().join("images/a.jpg").exists() && !drive.path().join("images/a.jpg").exists());
+        assert!(sync_manager.state.get_file_state(&photo).unwrap().is_none());
+        for uuid in ["b-drive", "test-drive"] {
+            let pending = sync_manager.state.get_pending_syncs(uuid).unwrap();
+            assert!(pending.len() == 1 && pending[0].replica);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_drive_check_needed_only_with_pending_files() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        assert!(!sync_manager.drive_check_needed().unwrap());
+
+        let photo = source.path().join("photo.jpg");
+        fs::write(&photo, b"jpeg").unwrap();
+        assert!(matches!(sync_manager.sync_file(&photo).await.unwrap(), SyncResult::Pending(_)));
+        assert!(sync_manager.drive_check_needed().unwrap());
+
+        drives.connect("TestUSB", drive.path());
+        sync_manager.check_and_sync_connected_drives().await.unwrap();
+        assert!(!sync_manager.drive_check_needed().unwrap());
+
+        sync_manager.config.drives.get_mut("test-drive").unwrap().bidirectional = true;
+        assert!(sync_manager.drive_check_needed().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_drive_manifest_written_after_batch_and_verified() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        sync_manager.config.sync.drive_manifests = true;
+        drives.connect("TestUSB", drive.path());
+
+        fs::create_dir(source.path().join("trip")).unwrap();
+        for name in ["a.jpg", "trip/b.jpg"] {
+            fs::write(source.path().join(name), name.as_bytes()).unwrap();
+        }
+        sync_manager.sync_all().await.unwrap();
+
+        let folder = drive.path().join("images");
+        let written = manifest::read(&folder).unwrap().unwrap();
+        assert_eq!(written.drive, "test-drive");
+        let paths: Vec<&str> = written.files.iter().map(|file| file.path.as_str()).collect();
+        assert_eq!(paths, ["a.jpg", "trip/b.jpg"]);
+
+        // A single watched file only marks the manifest; it is written later
+        let late = source.path().join("c.jpg");
+        fs::write(&late, b"late").unwrap();
+        sync_manager.sync_file(&late).await.unwrap();
+        assert_eq!(manifest::read(&folder).unwrap().unwrap().files.len(), 2);
+        sync_manager.write_manifests();
+        assert_eq!(manifest::read(&folder).unwrap().unwrap().files.len(), 3);
+
+        // Checked without the database, past files at the drive's root
+        fs::write(drive.path().join(crate::drive::MARKER_FILE_NAME), b"test-drive").unwrap();
+        fs::write(folder.join("a.jpg"), b"corrupted").unwrap();
+        fs::remove_file(folder.join("trip/b.jpg")).unwrap();
+        let config = sync_manager.config.clone();
+        let mut offline_db = SyncManager::new(config, StateManager::temporary().unwrap())
+            .with_drive_provider(drives.clone());
+        let report = offline_db.verify_manifests(None, true).unwrap();
+        assert_eq!(report.ok, 1);
+        assert_eq!(report.mismatched, [folder.join("a.jpg")]);
+        assert_eq!(report.missing, [folder.join("trip").join("b.jpg")]);
+    }
+
+    #[tokio::test]
+    async fn test_recently_modified_files_wait_to_settle() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        sync_manager.config.sync.settle_seconds = 60;
+        drives.connect("TestUSB", drive.path());
+
+        let photo = source.path().join("photo.jpg");
+        fs::write(&photo, b"half a jpeg").unwrap();
+        match sync_manager.sync_file(&photo).await.unwrap() {
+            SyncResult::Unsettled(wait) => assert!(wait <= std::time::Duration::from_secs(60) && !wait.is_zero()),
+            other => panic!("expected Unsettled, got {:?}", other),
+        }
+        assert!(!drive.path().join("images").join("photo.jpg").exists());
+        let summary = sync_manager.sync_all().await.unwrap();
+        assert_eq!(summary.unsettled, std::slice::from_ref(&photo));
+
+        let written = SystemTime::now() - std::time::Duration::from_secs(61);
+        fs::File::options().write(true).open(&photo).unwrap().set_modified(written).unwrap();
+        assert!(matches!(sync_manager.sync_file(&photo).await.unwrap(), SyncResult::Synced(_)));
+    }
+
+    #[tokio::test]
+    async fn test_mock_clock_times_settling_and_records() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        let photo = source.path().join("photo.jpg");
+        fs::write(&photo, b"jpeg").unwrap();
+        let written = fs::metadata(&photo).unwrap().modified().unwrap();
+
+        let clock = crate::clock::MockClock::new(written + std::time::Duration::from_secs(10));
+        let mut sync_manager = sync_manager.with_clock(Arc::new(clock.clone()));
+        sync_manager.config.sync.settle_seconds = 60;
+        match sync_manager.sync_file(&photo).await.unwrap() {
+            SyncResult::Unsettled(wait) => assert_eq!(wait, std::time::Duration::from_secs(50)),
+            other => panic!("expected Unsettled, got {:?}", other),
+        }
+
+        clock.advance(std::time::Duration::from_secs(50));
+        assert!(matches!(sync_manager.sync_file(&photo).await.unwrap(), SyncResult::Pending(_)));
+        let queued = sync_manager.state.get_pending_syncs("test-drive").unwrap();
+        assert_eq!(queued[0].created_at, clock.timestamp());
+
+        clock.advance(std::time::Duration::from_secs(3600));
+        drives.connect("TestUSB", drive.path());
+        sync_manager.check_and_sync_connected_drives().await.unwrap();
+        let record = sync_manager.state.get_file_state(&photo).unwrap().unwrap();
+        assert_eq!(record.last_synced, clock.timestamp());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_target_modes_applied_except_on_fat() {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o7777;
+
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        sync_manager.config.sync.target_file_mode = Some(0o640);
+        sync_manager.config.sync.target_dir_mode = Some(0o750);
+        drives.connect_with_file_system("TestUSB", drive.path(), "ext4");
+
+        fs::create_dir(source.path().join("trip")).unwrap();
+        let photo = source.path().join("trip/photo.jpg");
+        fs::write(&photo, b"jpeg").unwrap();
+        sync_manager.sync_file(&photo).await.unwrap();
+
+        let images = drive.path().join("images");
+        assert_eq!(mode(&images.join("trip/photo.jpg")), 0o640);
+        assert_eq!(mode(&images.join("trip")), 0o750);
+        assert_eq!(mode(&images), 0o750);
+        // The drive's own root isn't ours to change
+        assert_ne!(mode(drive.path()), 0o750);
+
+        // FAT has no Unix permissions to set
+        let fat = TempDir::new().unwrap();
+        let (mut fat_manager, fat_drives) = mock_drive_manager(source.path(), fat.path(), db.path().join("fat").as_path());
+        fat_manager.config.sync.target_file_mode = Some(0o604);
+        fat_drives.connect("TestUSB", fat.path());
+        fat_manager.sync_file(&photo).await.unwrap();
+        assert_ne!(mode(&fat.path().join("images/trip/photo.jpg")), 0o604);
+    }
+
+    #[tokio::test]
+    async fn test_queue_checked_against_last_seen_capacity() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        drives.connect("TestUSB", drive.path());
+
+        let write = |name: &str| {
+            let path = source.path().join(name);
+            fs::write(&path, b"twelve bytes").unwrap();
+            path
+        };
+        sync_manager.sync_file(&write("a.jpg")).await.unwrap();
+        assert_eq!(sync_manager.state.get_drive_capacity("test-drive").unwrap(), Some(64 * 1024 * 1024 * 1024));
+
+        // A tiny drive, then unplugged: room for one queued file
+        sync_manager.state.record_drive_capacity("test-drive", 20).unwrap();
+        drives.disconnect(drive.path());
+        assert!(matches!(sync_manager.sync_file(&write("b.jpg")).await.unwrap(), SyncResult::Pending(_)));
+        // Only a warning by default
+        assert!(matches!(sync_manager.sync_file(&write("c.jpg")).await.unwrap(), SyncResult::Pending(_)));
+
+        sync_manager.config.sync.reject_over_capacity = true;
+        let rejected = write("d.jpg");
+        assert!(matches!(sync_manager.sync_file(&rejected).await.unwrap(), SyncResult::WouldExceedCapacity(_)));
+        let pending = sync_manager.state.get_pending_syncs("test-drive").unwrap();
+        assert_eq!(pending.len(), 2);
+        assert!(pending.iter().all(|entry| entry.source_path != rejected));
+    }
+
+    #[tokio::test]
+    async fn test_marked_drive_followed_to_new_mount_point() {
+        let source = TempDir::new().unwrap();
+        let old_mount = TempDir::new().unwrap();
+        let new_mount = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), old_mount.path(), db.path());
+        sync_manager.config.drives.get_mut("test-drive").unwrap().marker_id = Some("stick".to_string());
+
+        // Another stick turns up where the drive used to be mounted
+        crate::drive::ensure_marker(old_mount.path(), "someone-else").unwrap();
+        drives.connect("TestUSB", old_mount.path());
+        let photo = source.path().join("photo.jpg");
+        fs::write(&photo, b"jpeg").unwrap();
+        assert!(matches!(sync_manager.sync_file(&photo).await.unwrap(), SyncResult::Pending(_)));
+
+        crate::drive::ensure_marker(new_mount.path(), "stick").unwrap();
+        drives.connect("Renamed", new_mount.path());
+        sync_manager.check_and_sync_connected_drives().await.unwrap();
+        assert_eq!(sync_manager.config.drives["test-drive"].path.as_deref(), Some(new_mount.path()));
+        assert!(new_mount.path().join("images/photo.jpg").exists());
+        assert!(!old_mount.path().join("images").exists());
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    #[tokio::test]
+    async fn test_copy_xattrs_carries_attributes_to_the_copy() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+
+        let photo = source.path().join("tagged.jpg");
+        fs::write(&photo, b"photo").unwrap();
+        let name = std::ffi::OsStr::new("user.orchestrator.tag");
+        if xattr::set(&photo, name, b"Red").is_err() {
+            // The temp directory's file system has no user attributes
+            return;
+        }
+
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        sync_manager.config.sync.copy_xattrs = true;
+        drives.connect("TestUSB", drive.path());
+        sync_manager.sync_file(&photo).await.unwrap();
+
+        let copy = drive.path().join("images").join("tagged.jpg");
+        assert!(xattr::list(&copy).unwrap().any(|listed| listed == name));
+        assert_eq!(xattr::get(&copy, name).unwrap().as_deref(), Some(&b"Red"[..]));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_snapshots_link_unchanged_files_and_prune_old_ones() {
+        use std::os::unix::fs::MetadataExt;
+
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        sync_manager.config.drives.get_mut("test-drive").unwrap().snapshots = true;
+        drives.connect("TestUSB", drive.path());
+        let inode = |path: &Path| fs::metadata(path).unwrap().ino();
+
+        let kept = source.path().join("kept.jpg");
+        let edited = source.path().join("edited.jpg");
+        fs::write(&kept, b"kept").unwrap();
+        fs::write(&edited, b"first").unwrap();
+        let in_snapshot = |id: &str, name: &str| drive.path().join(SNAPSHOTS_DIR).join(id).join("images").join(name);
+
+        // Ids have one-second resolution, so these runs are given their own
+        sync_manager.snapshot_id = Some("2026-01-01_090000".to_string());
+        sync_manager.sync_file(&kept).await.unwrap();
+        sync_manager.sync_file(&edited).await.unwrap();
+        assert!(in_snapshot("2026-01-01_090000", "kept.jpg").exists());
+        assert_eq!(sync_manager.state.get_file_state(&kept).unwrap().unwrap().snapshot.as_deref(), Some("2026-01-01_090000"));
+        assert!(matches!(sync_manager.sync_file(&kept).await.unwrap(), SyncResult::AlreadySynced));
+
+        fs::write(&edited, b"second").unwrap();
+        sync_manager.snapshot_id = Some("2026-01-02_090000".to_string());
+        sync_manager.sync_file(&kept).await.unwrap();
+        sync_manager.sync_file(&edited).await.unwrap();
+        assert_eq!(
+            inode(&in_snapshot("2026-01-02_090000", "kept.jpg")),
+            inode(&in_snapshot("2026-01-01_090000", "kept.jpg")),
+        );
+        assert_eq!(fs::read(in_snapshot("2026-01-01_090000", "edited.jpg")).unwrap(), b"first");
+        assert_eq!(fs::read(in_snapshot("2026-01-02_090000", "edited.jpg")).unwrap(), b"second");
+        let record = sync_manager.state.get_file_state(&kept).unwrap().unwrap();
+        assert_eq!(record.snapshot.as_deref(), Some("2026-01-02_090000"));
+        assert_eq!(record.target_path, in_snapshot("2026-01-02_090000", "kept.jpg"));
+
+        let snapshots = sync_manager.list_snapshots("test-drive").unwrap();
+        let listed: Vec<_> = snapshots.iter().map(|s| (s.id.as_str(), s.files, s.current)).collect();
+        assert_eq!(listed, vec![("2026-01-01_090000", 2, 0), ("2026-01-02_090000", 2, 2)]);
+
+        // A full sync starts a snapshot of its own, then prunes down to the
+        // newest one
+        sync_manager.config.drives.get_mut("test-drive").unwrap().snapshot_retention = Some(1);
+        let kept_inode = inode(&in_snapshot("2026-01-02_090000", "kept.jpg"));
+        let summary = sync_manager.sync_all().await.unwrap();
+        assert_eq!(summary.synced, 2);
+        let latest = sync_manager.snapshot_id.clone().unwrap();
+        assert_ne!(latest, "2026-01-02_090000");
+        assert_eq!(inode(&in_snapshot(&latest, "kept.jpg")), kept_inode);
+        let snapshots = sync_manager.list_snapshots("test-drive").unwrap();
+        assert_eq!(snapshots.iter().map(|s| s.id.clone()).collect::<Vec<_>>(), vec![latest.clone()]);
+        assert_eq!(fs::read(in_snapshot(&latest, "edited.jpg")).unwrap(), b"second");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_snapshot_rewrite_leaves_earlier_snapshots_alone() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        let drive_config = sync_manager.config.drives.get_mut("test-drive").unwrap();
+        drive_config.snapshots = true;
+        drive_config.compress = true;
+        drive_config.target = "documents".to_string();
+        drives.connect("TestUSB", drive.path());
+        let in_snapshot = |id: &str| drive.path().join(SNAPSHOTS_DIR).join(id).join("documents").join("notes.txt.zst");
+
+        let notes = source.path().join("notes.txt");
+        fs::write(&notes, b"first ".repeat(100)).unwrap();
+        sync_manager.snapshot_id = Some("2026-01-01_090000".to_string());
+        sync_manager.sync_file(&notes).await.unwrap();
+        let first = fs::read(in_snapshot("2026-01-01_090000")).unwrap();
+
+        // Linked into the new snapshot, then edited within the same run
+        sync_manager.snapshot_id = Some("2026-01-02_090000".to_string());
+        sync_manager.sync_file(&notes).await.unwrap();
+        fs::write(&notes, b"second ".repeat(100)).unwrap();
+        sync_manager.sync_file(&notes).await.unwrap();
+        assert_eq!(fs::read(in_snapshot("2026-01-01_090000")).unwrap(), first);
+        assert_ne!(fs::read(in_snapshot("2026-01-02_090000")).unwrap(), first);
+
+        // Once the source is gone its last copy doesn't keep the snapshot
+        fs::remove_file(&notes).unwrap();
+        sync_manager.config.drives.get_mut("test-drive").unwrap().snapshot_retention = Some(1);
+        assert_eq!(sync_manager.list_snapshots("test-drive").unwrap()[1].current, 0);
+        assert_eq!(sync_manager.prune_snapshots("test-drive").unwrap(), vec!["2026-01-01_090000".to_string()]);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_same_device_strategy_links_instead_of_copying() {
+        use std::os::unix::fs::MetadataExt;
+
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        drives.connect_with_file_system("TestUSB", drive.path(), "ext4");
+        // Both temp dirs are on one device
+        let inode = |path: &Path| fs::metadata(path).unwrap().ino();
+
+        sync_manager.config.sync.same_device_strategy = SameDeviceStrategy::Hardlink;
+        let hard = source.path().join("hard.jpg");
+        fs::write(&hard, b"jpeg").unwrap();
+        sync_manager.sync_file(&hard).await.unwrap();
+        let record = sync_manager.state.get_file_state(&hard).unwrap().unwrap();
+        assert_eq!(record.link, Some(LinkKind::Hardlink));
+        assert_eq!(inode(&record.target_path), inode(&hard));
+        assert!(matches!(sync_manager.sync_file(&hard).await.unwrap(), SyncResult::AlreadySynced));
+
+        sync_manager.config.sync.same_device_strategy = SameDeviceStrategy::Symlink;
+        let soft = source.path().join("soft.jpg");
+        fs::write(&soft, b"jpeg").unwrap();
+        sync_manager.sync_file(&soft).await.unwrap();
+        let record = sync_manager.state.get_file_state(&soft).unwrap().unwrap();
+        assert_eq!(record.link, Some(LinkKind::Symlink));
+        assert_eq!(fs::read_link(&record.target_path).unwrap(), soft);
+
+        sync_manager.config.sync.same_device_strategy = SameDeviceStrategy::Copy;
+        let copy = source.path().join("copy.jpg");
+        fs::write(&copy, b"jpeg").unwrap();
+        sync_manager.sync_file(&copy).await.unwrap();
+        let record = sync_manager.state.get_file_state(&copy).unwrap().unwrap();
+        assert!(record.link.is_none() && !record.reflinked);
+        assert_ne!(inode(&record.target_path), inode(&copy));
+    }
+
+    #[tokio::test]
+    async fn test_fat_safe_names_are_stable() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        drives.connect("TestUSB", drive.path());
+
+        let colon = source.path().join("a:b.jpg");
+        let question = source.path().join("a?b.jpg");
+        fs::write(&colon, b"colon").unwrap();
+        fs::write(&question, b"question").unwrap();
+
+        let SyncResult::Synced(first) = sync_manager.sync_file(&colon).await.unwrap() else { panic!() };
+        let SyncResult::Synced(second) = sync_manager.sync_file(&question).await.unwrap() else { panic!() };
+        assert_eq!(first, drive.path().join("images").join("a_b.jpg"));
+        assert_ne!(first, second);
+
+        // Changed content goes back to the same names
+        fs::write(&colon, b"colon v2").unwrap();
+        fs::write(&question, b"question v2").unwrap();
+        assert!(matches!(sync_manager.sync_file(&colon).await.unwrap(), SyncResult::Synced(p) if p == first));
+        assert!(matches!(sync_manager.sync_file(&question).await.unwrap(), SyncResult::Synced(p) if p == second));
+    }
+
+    #[tokio::test]
+    async fn test_changing_hash_algorithm_keeps_synced_files() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let photo = source.path().join("photo.jpg");
+        fs::write(&photo, b"jpeg").unwrap();
+
+        {
+            let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+            drives.connect("TestUSB", drive.path());
+            assert!(matches!(sync_manager.sync_file(&photo).await.unwrap(), SyncResult::Synced(_)));
+        }
+
+        let mut config = test_config(source.path(), drive.path());
+        config.sync.hash_algorithm = HashAlgorithm::Sha256;
+        let drives = MockDriveProvider::default();
+        drives.connect("TestUSB", drive.path());
+        let state = StateManager::new(db.path().join("state.db")).unwrap();
+        let mut sync_manager = SyncManager::new(config, state).with_drive_provider(drives);
+
+        assert!(matches!(sync_manager.sync_file(&photo).await.unwrap(), SyncResult::AlreadySynced));
+        let record = sync_manager.state.get_file_state(&photo).unwrap().unwrap();
+        assert_eq!(record.hash_algorithm, HashAlgorithm::Sha256);
+        assert_eq!(record.hash, calculate_file_hash(&photo, HashAlgorithm::Sha256).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_unknown_files_are_quarantined() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, _drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        sync_manager.config.sync.quarantine_dir = Some(PathBuf::from("_unsorted"));
+
+        let mystery = source.path().join("notes").join("mystery.qqq");
+        fs::create_dir_all(mystery.parent().unwrap()).unwrap();
+        fs::write(&mystery, b"?").unwrap();
+
+        let summary = sync_manager.sync_directory(source.path()).await.unwrap();
+        assert_eq!(summary.quarantined, 1);
+
+        let quarantined = source.path().join("_unsorted").join("notes").join("mystery.qqq");
+        assert!(quarantined.exists());
+        assert!(!mystery.exists());
+        assert_eq!(sync_manager.get_stats().unwrap().quarantined, 1);
+
+        // The quarantine directory itself isn't scanned again
+        let summary = sync_manager.sync_directory(source.path()).await.unwrap();
+        assert_eq!(summary.total(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_slow_copy_times_out_and_is_cleaned_up() {
+        let drive = TempDir::new().unwrap();
+        let target = drive.path().join("slow.jpg");
+
+        // A writer that trickles one byte out every 50ms
+        let slow_target = target.clone();
+        let slow_copy = async move {
+            for _ in 0..100 {
+                let mut file = fs::OpenOptions::new().create(true).append(true).open(&slow_target)?;
+                std::io::Write::write_all(&mut file, b"x")?;
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            }
+            Ok(())
+        };
+
+        let result = with_copy_timeout(Some(std::time::Duration::from_millis(200)), &target, false, slow_copy).await;
+        assert!(matches!(result, Err(OrchestratorError::Timeout(_))));
+
+        // Cleanup runs on the blocking pool
+        for _ in 0..50 {
+            if !target.exists() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert!(!target.exists());
+
+        // A resumable copy only replaces the target once it's complete
+        fs::write(&target, b"previous copy").unwrap();
+        let hung = async {
+            tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+            Ok(())
+        };
+        let result = with_copy_timeout(Some(std::time::Duration::from_millis(100)), &target, true, hung).await;
+        assert!(matches!(result, Err(OrchestratorError::Timeout(_))));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        assert_eq!(fs::read(&target).unwrap(), b"previous copy");
+
+        let quick = with_copy_timeout(Some(std::time::Duration::from_secs(5)), &target, false, async { Ok(7) }).await;
+        assert_eq!(quick.unwrap(), 7);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_sync_hooks() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        drives.connect("TestUSB", drive.path());
+
+        let marker = db.path().join("hook.log");
+        sync_manager.config.hooks.pre_sync = Some("case {source} in *skip*) exit 1;; esac".to_string());
+        sync_manager.config.hooks.post_sync = Some(format!("echo {{category}} {{drive}} >> '{}'", marker.display()));
+
+        let keep = source.path().join("keep.jpg");
+        let skip = source.path().join("skip.jpg");
+        fs::write(&keep, b"keep").unwrap();
+        fs::write(&skip, b"skip").unwrap();
+
+        assert!(matches!(sync_manager.sync_file(&keep).await.unwrap(), SyncResult::Synced(_)));
+        assert!(matches!(sync_manager.sync_file(&skip).await.unwrap(), SyncResult::Skipped(_)));
+        assert!(!drive.path().join("images").join("skip.jpg").exists());
+        assert_eq!(fs::read_to_string(&marker).unwrap(), "images TestUSB\n");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unreadable_file_fails_after_retries() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        drives.connect("TestUSB", drive.path());
+
+        let photo = source.path().join("photo.jpg");
+        fs::write(&photo, b"jpeg").unwrap();
+        fs::set_permissions(&photo, fs::Permissions::from_mode(0o000)).unwrap();
+        if fs::File::open(&photo).is_ok() {
+            // Running as root, which ignores the permission bits
+            return;
+        }
+
+        for _ in 1..UNREADABLE_ATTEMPTS {
+            assert!(matches!(sync_manager.sync_file(&photo).await.unwrap(), SyncResult::Unsettled(UNREADABLE_RETRY)));
+        }
+        assert!(matches!(sync_manager.sync_file(&photo).await, Err(OrchestratorError::Unreadable(_))));
+
+        // Counted afresh once it has been given up on
+        assert!(matches!(sync_manager.sync_file(&photo).await.unwrap(), SyncResult::Unsettled(_)));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_source_written_during_copy_is_queued_not_recorded() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        drives.connect("TestUSB", drive.path());
+
+        // pre_sync runs after the hash, so this is a write the hash missed
+        sync_manager.config.hooks.pre_sync = Some("echo more >> {source}".to_string());
+        let photo = source.path().join("photo.jpg");
+        fs::write(&photo, b"jpeg").unwrap();
+
+        assert!(matches!(sync_manager.sync_file(&photo).await.unwrap(), SyncResult::Unsettled(_)));
+        assert!(sync_manager.state.get_file_state(&photo).unwrap().is_none());
+        assert!(!drive.path().join("images/photo.jpg").exists());
+        assert_eq!(sync_manager.state.get_pending_sync(&photo).unwrap().unwrap().target_drive, "test-drive");
+
+        sync_manager.config.hooks.pre_sync = None;
+        sync_manager.check_and_sync_connected_drives().await.unwrap();
+        let record = sync_manager.state.get_file_state(&photo).unwrap().unwrap();
+        assert_eq!(record.hash, calculate_file_hash(&photo, HashAlgorithm::Blake3).unwrap());
+        assert_eq!(fs::read(&record.target_path).unwrap(), fs::read(&photo).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_batch_progress_every_n_files() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+
+        for n in 0..7 {
+            fs::write(source.path().join(format!("{}.jpg", n)), n.to_string()).unwrap();
+        }
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let (sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        let mut sync_manager = sync_manager.with_progress_channel(tx);
+        sync_manager.config.sync.progress_every = 3;
+        drives.connect("TestUSB", drive.path());
+
+        let summary = sync_manager.sync_all().await.unwrap();
+        assert_eq!(summary.synced, 7);
+
+        let first = rx.recv().await.unwrap();
+        assert_eq!((first.processed, first.total, first.synced), (3, 7, 3));
+        assert_eq!(rx.recv().await.unwrap().processed, 6);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_unrouted_categories() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (sync_manager, _drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+
+        for file in ["a.jpg", "b.mp4", "c.mp4", "d.xyz"] {
+            fs::write(source.path().join(file), file).unwrap();
+        }
+
+        assert_eq!(sync_manager.unrouted_categories().unwrap(), vec![("videos".to_string(), 2)]);
+    }
+
+    #[tokio::test]
+    async fn test_moved_mount_point_is_detected_and_rebound() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        let old_mount = drive.path().join("old-mount");
+        sync_manager.config.drives.get_mut("test-drive").unwrap().path = Some(old_mount.clone());
+        drives.connect("TestUSB", drive.path());
+
+        sync_manager.config.sync.auto_bind_path = false;
+        assert_eq!(sync_manager.moved_drives(), vec![("test-drive".to_string(), drive.path().to_path_buf())]);
+        sync_manager.check_and_sync_connected_drives().await.unwrap();
+        assert_eq!(sync_manager.config.drives["test-drive"].path, Some(old_mount));
+        assert!(sync_manager.connected_drives().is_empty());
+
+        sync_manager.config.sync.auto_bind_path = true;
+        sync_manager.check_and_sync_connected_drives().await.unwrap();
+        let bound = &sync_manager.config.drives["test-drive"];
+        assert_eq!(bound.path.as_deref(), Some(drive.path()));
+        assert!(bound.last_seen.is_some());
+        assert!(sync_manager.state.get_drive_last_seen("test-drive").unwrap().is_some());
+        assert!(sync_manager.moved_drives().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_mime_allow_list_skips_lookalikes() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        drives.connect("TestUSB", drive.path());
+        sync_manager.config.rules.mime.insert("images".to_string(), crate::config::MimeFilter {
+            allow: Some(vec!["image/jpeg".to_string(), "image/png".to_string()]),
+            deny: Vec::new(),
+        });
+
+        let png = source.path().join("real.png");
+        fs::write(&png, b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+        let icon = source.path().join("favicon.ico");
+        fs::write(&icon, [0u8, 0, 1, 0, 1, 0, 16, 16]).unwrap();
+        let svg = source.path().join("logo.svg");
+        fs::write(&svg, b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>").unwrap();
+
+        assert!(matches!(sync_manager.sync_file(&png).await.unwrap(), SyncResult::Synced(_)));
+        assert!(matches!(sync_manager.sync_file(&icon).await.unwrap(), SyncResult::Skipped(_)));
+        assert!(matches!(sync_manager.sync_file(&svg).await.unwrap(), SyncResult::Skipped(_)));
+        assert!(!drive.path().join("images").join("favicon.ico").exists());
+    }
+
+    #[tokio::test]
+    async fn test_flatten_drive() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        drives.connect("TestUSB", drive.path());
+        let drive_config = sync_manager.config.drives.get_mut("test-drive").unwrap();
+        drive_config.flatten = true;
+        sync_manager.config.sync.conflict_policy = ConflictPolicy::Rename;
+
+        let first = source.path().join("2023").join("trip").join("beach.jpg");
+        let second = source.path().join("2024").join("beach.jpg");
+        for (path, content) in [(&first, "first"), (&second, "second")] {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+
+        let images = drive.path().join("images");
+        assert!(matches!(sync_manager.sync_file(&first).await.unwrap(), SyncResult::Synced(p) if p == images.join("beach.jpg")));
+        assert!(matches!(
+            sync_manager.sync_file(&second).await.unwrap(),
+            SyncResult::Conflict(ConflictPolicy::Rename, p) if p == images.join("beach-1.jpg")
+        ));
+
+        let record = sync_manager.state.get_file_state(&second).unwrap().unwrap();
+        assert_eq!(record.target_path, images.join("beach-1.jpg"));
+        assert!(matches!(sync_manager.sync_file(&second).await.unwrap(), SyncResult::AlreadySynced));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_read_only_drive_keeps_files_pending() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        drives.connect("TestUSB", drive.path());
+
+        fs::set_permissions(drive.path(), fs::Permissions::from_mode(0o555)).unwrap();
+        if is_writable(drive.path()) {
+            // Running as root, which ignores the permission bits
+            return;
+        }
+
+        let photo = source.path().join("photo.jpg");
+        fs::write(&photo, b"jpeg").unwrap();
+
+        assert!(matches!(sync_manager.sync_file(&photo).await.unwrap(), SyncResult::DriveReadOnly(_)));
+        assert_eq!(sync_manager.get_stats().unwrap().pending_syncs, 1);
+        assert_eq!(sync_manager.read_only_drives(), vec!["test-drive".to_string()]);
+
+        fs::set_permissions(drive.path(), fs::Permissions::from_mode(0o755)).unwrap();
+        sync_manager.check_and_sync_connected_drives().await.unwrap();
+        assert_eq!(sync_manager.get_stats().unwrap().pending_syncs, 0);
+    }
+
+    #[tokio::test]
+    async fn test_folder_names_and_reroute() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        drives.connect("TestUSB", drive.path());
+
+        let photo = source.path().join("photo.jpg");
+        fs::write(&photo, b"jpeg").unwrap();
+        sync_manager.sync_file(&photo).await.unwrap();
+        assert!(drive.path().join("images").join("photo.jpg").exists());
+
+        sync_manager.config.folder_names.insert("images".to_string(), "Photos".to_string());
+        let report = sync_manager.reroute(false).await.unwrap();
+        assert_eq!(report.moves.len(), 1);
+        assert!(drive.path().join("Photos").join("photo.jpg").exists());
+        assert!(!drive.path().join("images").join("photo.jpg").exists());
+
+        let report = sync_manager.verify(None, false, false).unwrap();
+        assert_eq!(report.ok, 1);
+    }
+
+    #[tokio::test]
+    async fn test_verify_requeue_keeps_mismatched_copies() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        drives.connect("TestUSB", drive.path());
+
+        let kept = source.path().join("kept.jpg");
+        let gone = source.path().join("gone.jpg");
+        for photo in [&kept, &gone] {
+            fs::write(photo, b"jpeg").unwrap();
+            sync_manager.sync_file(photo).await.unwrap();
+            fs::write(drive.path().join("images").join(photo.file_name().unwrap()), b"edited").unwrap();
+        }
+        fs::remove_file(&gone).unwrap();
+
+        let report = sync_manager.verify(None, true, true).unwrap();
+        assert_eq!(report.mismatched.len(), 2);
+        assert_eq!(report.requeued, 1);
+        // Neither copy is deleted; the only copy of gone.jpg keeps its record
+        assert_eq!(fs::read(drive.path().join("images/kept.jpg")).unwrap(), b"edited");
+        assert_eq!(fs::read(drive.path().join("images/gone.jpg")).unwrap(), b"edited");
+        assert!(sync_manager.state.get_file_state(&gone).unwrap().is_some());
+
+        // The re-sync goes through the conflict policy like any other
+        sync_manager.check_and_sync_connected_drives().await.unwrap();
+        assert_eq!(sync_manager.get_stats().unwrap().pending_syncs, 0);
+        let record = sync_manager.state.get_file_state(&kept).unwrap().unwrap();
+        assert_eq!(fs::read(&record.target_path).unwrap(), b"jpeg");
+    }
+
+    #[tokio::test]
+    async fn test_pause_at_percent_full() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        sync_manager.config.sync.pause_at_percent_full = Some(98);
+        drives.connect("TestUSB", drive.path());
+        // The mock drive holds 64 GiB; leave 1 GiB of it
+        drives.set_available_space(drive.path(), 1024 * 1024 * 1024);
+
+        let photo = source.path().join("photo.jpg");
+        fs::write(&photo, b"jpeg").unwrap();
+        assert!(matches!(sync_manager.sync_file(&photo).await.unwrap(), SyncResult::DriveFull(_)));
+        assert!(!drive.path().join("images").join("photo.jpg").exists());
+        assert_eq!(sync_manager.full_drives().len(), 1);
+
+        sync_manager.check_and_sync_connected_drives().await.unwrap();
+        assert_eq!(sync_manager.state.get_pending_syncs("test-drive").unwrap().len(), 1);
+
+        // Space freed: the queue goes on the next drive check
+        drives.set_available_space(drive.path(), 32 * 1024 * 1024 * 1024);
+        assert!(sync_manager.full_drives().is_empty());
+        sync_manager.check_and_sync_connected_drives().await.unwrap();
+        assert!(sync_manager.state.get_pending_syncs("test-drive").unwrap().is_empty());
+        assert!(drive.path().join("images").join("photo.jpg").exists());
+    }
+
+    #[tokio::test]
+    async fn test_subfolders_and_reroute() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        drives.connect("TestUSB", drive.path());
+        let rule = |pattern: &str, folder: &str| crate::config::SubfolderRule {
+            pattern: pattern.to_string(),
+            folder: PathBuf::from(folder),
+        };
+        sync_manager.config.rules.subfolders.insert(
+            "images".to_string(),
+            vec![rule("Screenshot_*", "Screenshots"), rule("IMG_*", "Camera")],
+        );
+        sync_manager.subfolders = sync_manager.config.rules.subfolder_classifiers().unwrap();
+
+        for name in ["Screenshot_1.png", "IMG_0001.jpg", "cat.jpg"] {
+            let path = source.path().join(name);
+            fs::write(&path, name).unwrap();
+            sync_manager.sync_file(&path).await.unwrap();
+        }
+        let images = drive.path().join("images");
+        assert!(images.join("Screenshots").join("Screenshot_1.png").exists());
+        assert!(images.join("Camera").join("IMG_0001.jpg").exists());
+        assert!(images.join("cat.jpg").exists());
+
+        // Without its rule, a camera photo goes back to the category folder
+        sync_manager.config.rules.subfolders.get_mut("images").unwrap().pop();
+        sync_manager.subfolders = sync_manager.config.rules.subfolder_classifiers().unwrap();
+        let report = sync_manager.reroute(false).await.unwrap();
+        assert_eq!(report.moves.len(), 1);
+        assert!(images.join("IMG_0001.jpg").exists());
+        assert!(!images.join("Camera").join("IMG_0001.jpg").exists());
+        assert!(sync_manager.reroute(true).await.unwrap().moves.is_empty());
+    }
+
+    #[test]
+    fn test_hash_cache_invalidated_by_changes() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("a.jpg");
+        fs::write(&path, b"first").unwrap();
+
+        let cache = HashCache::default();
+        cache.fill(&path, HashAlgorithm::Blake3, None);
+        assert_eq!(cache.take(&path, HashAlgorithm::Blake3), Some((calculate_file_hash(&path, HashAlgorithm::Blake3).unwrap(), None)));
+        assert_eq!(cache.take(&path, HashAlgorithm::Blake3), None);
+
+        cache.fill(&path, HashAlgorithm::Blake3, None);
+        fs::write(&path, b"changed size").unwrap();
+        assert_eq!(cache.take(&path, HashAlgorithm::Blake3), None);
+
+        cache.fill(&path, HashAlgorithm::Blake3, None);
+        assert_eq!(cache.take(&path, HashAlgorithm::Sha256), None);
+    }
+
+    #[tokio::test]
+    async fn test_sync_all_with_hash_workers() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        sync_manager.config.sync.hash_workers = 4;
+        drives.connect("TestUSB", drive.path());
+
+        for n in 0..20 {
+            fs::write(source.path().join(format!("{}.jpg", n)), n.to_string().repeat(1000)).unwrap();
+        }
+
+        let summary = sync_manager.sync_all().await.unwrap();
+        assert_eq!(summary.synced, 20);
+        for n in 0..20 {
+            let record = sync_manager.state.get_file_state(&source.path().join(format!("{}.jpg", n))).unwrap().unwrap();
+            assert_eq!(record.hash, calculate_file_hash(&record.target_path, HashAlgorithm::Blake3).unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_case_insensitive_collision_follows_policy() {
+        // The mock drive reports vfat, so names are compared ignoring case
+        // even though the temp directory itself is case-sensitive
+        let setup = |policy: ConflictPolicy| {
+            let source = TempDir::new().unwrap();
+            let drive = TempDir::new().unwrap();
+            let db = TempDir::new().unwrap();
+            let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+            drives.connect("TestUSB", drive.path());
+            sync_manager.config.sync.conflict_policy = policy;
+
+            fs::create_dir_all(drive.path().join("images")).unwrap();
+            fs::write(drive.path().join("images").join("Photo.JPG"), b"already there").unwrap();
+            let photo = source.path().join("photo.jpg");
+            fs::write(&photo, b"new photo").unwrap();
+            (sync_manager, photo, source, drive, db)
+        };
+
+        let (mut sync_manager, photo, _source, drive, _db) = setup(ConflictPolicy::Rename);
+        let images = drive.path().join("images");
+        assert!(matches!(
+            sync_manager.sync_file(&photo).await.unwrap(),
+            SyncResult::Conflict(ConflictPolicy::Rename, p) if p == images.join("photo-1.jpg")
+        ));
+        assert_eq!(fs::read(images.join("Photo.JPG")).unwrap(), b"already there");
+
+        let (mut sync_manager, photo, _source, drive, _db) = setup(ConflictPolicy::Skip);
+        assert!(matches!(sync_manager.sync_file(&photo).await.unwrap(), SyncResult::Conflict(ConflictPolicy::Skip, _)));
+        assert_eq!(fs::read(drive.path().join("images").join("Photo.JPG")).unwrap(), b"already there");
+
+        let (mut sync_manager, photo, _source, drive, _db) = setup(ConflictPolicy::Overwrite);
+        let existing = drive.path().join("images").join("Photo.JPG");
+        sync_manager.sync_file(&photo).await.unwrap();
+        assert_eq!(fs::read(&existing).unwrap(), b"new photo");
+        assert_eq!(sync_manager.state.get_file_state(&photo).unwrap().unwrap().target_path, existing);
+    }
+
+    #[tokio::test]
+    async fn test_queue_when_drive_disconnected() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, _drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+
+        let photo = source.path().join("photo.jpg");
+        fs::write(&photo, b"jpeg").unwrap();
+
+        assert!(matches!(sync_manager.sync_file(&photo).await.unwrap(), SyncResult::Pending(_)));
+        assert!(!drive.path().join("images").join("photo.jpg").exists());
+        assert_eq!(sync_manager.get_stats().unwrap().pending_syncs, 1);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_timed_out_copy_is_queued() {
+        use std::os::unix::ffi::OsStrExt;
+        use std::os::unix::fs::OpenOptionsExt;
+
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        drives.connect("TestUSB", drive.path());
+        sync_manager.config.sync.same_device_strategy = SameDeviceStrategy::Copy;
+        sync_manager.config.sync.per_file_timeout_secs = Some(1);
+
+        // Opening a FIFO for writing hangs until a reader turns up, like a
+        // drive that stopped responding; a second name lets the test reach it
+        // once the copy has been abandoned and its `.partial` removed
+        fs::create_dir(drive.path().join("images")).unwrap();
+        let fifo = drive.path().join("images/photo.jpg.partial");
+        let name = std::ffi::CString::new(fifo.as_os_str().as_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(name.as_ptr(), 0o644) }, 0);
+        let held = db.path().join("held");
+        fs::hard_link(&fifo, &held).unwrap();
+
+        let photo = source.path().join("photo.jpg");
+        fs::write(&photo, b"jpeg").unwrap();
+        let result = sync_manager.sync_file(&photo).await;
+        // Let the abandoned copy through, before anything can fail and leave
+        // it hanging; with nobody reading, it fails
+        drop(fs::OpenOptions::new().read(true).custom_flags(libc::O_NONBLOCK).open(&held).unwrap());
+        assert!(matches!(result, Err(OrchestratorError::Timeout(_))));
+        assert_eq!(sync_manager.get_stats().unwrap().pending_syncs, 1);
+
+        for _ in 0..50 {
+            if !fifo.exists() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        sync_manager.check_and_sync_connected_drives().await.unwrap();
+        assert_eq!(sync_manager.get_stats().unwrap().pending_syncs, 0);
+        assert_eq!(fs::read(drive.path().join("images/photo.jpg")).unwrap(), b"jpeg");
+    }
+
+    #[tokio::test]
+    async fn test_drain_pending_on_reconnect() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+
+        for name in ["a.jpg", "b.jpg"] {
+            fs::write(source.path().join(name), name.as_bytes()).unwrap();
+        }
+        let summary = sync_manager.sync_all().await.unwrap();
+        assert_eq!(summary.pending, 2);
+
+        drives.connect("TestUSB", drive.path());
+        sync_manager.check_and_sync_connected_drives().await.unwrap();
+
+        assert_eq!(sync_manager.get_stats().unwrap().pending_syncs, 0);
+        for name in ["a.jpg", "b.jpg"] {
+            assert!(drive.path().join("images").join(name).exists());
+        }
+
+        // Unplugged again, a new file goes back to the queue
+        drives.disconnect(drive.path());
+        let late = source.path().join("c.jpg");
+        fs::write(&late, b"late").unwrap();
+        assert!(matches!(sync_manager.sync_file(&late).await.unwrap(), SyncResult::Pending(_)));
+    }
+
+    #[tokio::test]
+    async fn test_rebuild_from_targets() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        drives.connect("TestUSB", drive.path());
+
+        for name in ["a.jpg", "b.jpg"] {
+            fs::write(source.path().join(name), name.as_bytes()).unwrap();
+        }
+        sync_manager.sync_all().await.unwrap();
+        // One copy changed on the drive since; it must not be vouched for
+        fs::write(drive.path().join("images").join("b.jpg"), b"edited").unwrap();
+
+        sync_manager.state.clear_all().unwrap();
+        assert_eq!(sync_manager.rebuild_from_targets().unwrap(), 1);
+        assert!(sync_manager.state.get_file_state(&source.path().join("a.jpg")).unwrap().is_some());
+        assert!(sync_manager.state.get_file_state(&source.path().join("b.jpg")).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_transient_retry_only_retries_busy_files() {
+        let retry = TransientRetry { attempts: 3, delay: std::time::Duration::from_millis(1) };
+        let path = Path::new("busy.jpg");
+
+        let mut calls = 0;
+        let result = retry.run(path, || {
+            calls += 1;
+            match calls {
+                1 | 2 => Err(std::io::Error::from(std::io::ErrorKind::ResourceBusy)),
+                _ => Ok(calls),
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+
+        // Out of attempts: the last error is returned
+        calls = 0;
+        let result: std::io::Result<()> = retry.run(path, || {
+            calls += 1;
+            Err(std::io::Error::from(std::io::ErrorKind::WouldBlock))
+        });
+        assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::WouldBlock);
+        assert_eq!(calls, 4);
+
+        for kind in [std::io::ErrorKind::PermissionDenied, std::io::ErrorKind::StorageFull] {
+            calls = 0;
+            let result: std::io::Result<()> = retry.run(path, || {
+                calls += 1;
+                Err(std::io::Error::from(kind))
+            });
+            assert_eq!(result.unwrap_err().kind(), kind);
+            assert_eq!(calls, 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_copy_file_replaces_existing_target() {
+        let dir = TempDir::new().unwrap();
+        let source = dir.path().join("video.mp4");
+        let target = dir.path().join("copy.mp4");
+        fs::write(&source, b"new contents").unwrap();
+        fs::write(&target, b"old").unwrap();
+
+        // Same file system, so a reflink is tried; tmpfs/ext4 fall back to a copy
+        copy_file(&source, &target, false, TransientRetry::default()).await.unwrap();
+        assert_eq!(fs::read(&target).unwrap(), b"new contents");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_sparse_copy_keeps_holes_except_on_fat() {
+        use std::io::{Seek, SeekFrom, Write};
+        use std::os::unix::fs::MetadataExt;
+
+        let allocated = |path: &Path| fs::metadata(path).unwrap().blocks() * 512;
+        let len = 16 * 1024 * 1024;
+
+        for file_system in ["ext4", "vfat"] {
+            let source = TempDir::new().unwrap();
+            let drive = TempDir::new().unwrap();
+            let db = TempDir::new().unwrap();
+            let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+            sync_manager.config.drives.get_mut("test-drive").unwrap().sparse_copy = true;
+            drives.connect_with_file_system("TestUSB", drive.path(), file_system);
+
+            // Data at both ends of a disk image, a hole in between
+            let image = source.path().join("disk.jpg");
+            let mut file = fs::File::create(&image).unwrap();
+            file.set_len(len).unwrap();
+            file.write_all(&[7u8; 4096]).unwrap();
+            file.seek(SeekFrom::Start(len - 4096)).unwrap();
+            file.write_all(&[9u8; 4096]).unwrap();
+            drop(file);
+            if allocated(&image) >= len {
+                // The temp directory's file system doesn't do holes
+                return;
+            }
+
+            assert!(matches!(sync_manager.sync_file(&image).await.unwrap(), SyncResult::Synced(_)));
+            let target = drive.path().join("images").join("disk.jpg");
+            assert_eq!(fs::read(&target).unwrap(), fs::read(&image).unwrap());
+
+            let recorded = sync_manager.state.get_file_state(&image).unwrap().unwrap();
+            if file_system == "vfat" {
+                assert!(!recorded.sparse);
+                assert!(allocated(&target) >= len);
+            } else {
+                assert!(recorded.sparse);
+                assert!(allocated(&target) < len / 2);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_copy_resumes_from_matching_partial() {
+        let source_dir = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (sync_manager, _drives) = mock_drive_manager(source_dir.path(), drive.path(), db.path());
+
+        let source = source_dir.path().join("movie.mp4");
+        let contents: Vec<u8> = (0..3 * HASH_CHUNK_SIZE).map(|i| (i % 251) as u8).collect();
+        fs::write(&source, &contents).unwrap();
+        let hash = calculate_file_hash(&source, HashAlgorithm::Blake3).unwrap();
+        let target = drive.path().join("movie.mp4");
+        let file_info = sync_manager.file_info(&source).unwrap();
+        let file = Outgoing {
+            source_path: &source,
+            relative_path: Path::new("movie.mp4"),
+            file_info: &file_info,
+            category: "videos",
+            hash: &hash,
+            chunks: None,
+            fingerprint: None,
+        };
+
+        // An earlier run got a third of the way before the drive was pulled
+        fs::write(partial_path(&target), &contents[..HASH_CHUNK_SIZE]).unwrap();
+        sync_manager.state.save_partial_copy(&PartialCopy {
+            source_path: source.clone(),
+            target_path: target.clone(),
+            expected_size: contents.len() as u64,
+            source_hash: hash.clone(),
+        }).unwrap();
+
+        sync_manager.copy_resumable(&file, &target, false, None).await.unwrap();
+
+        assert_eq!(fs::read(&target).unwrap(), contents);
+        assert!(!partial_path(&target).exists());
+        assert!(sync_manager.state.get_partial_copy(&target).unwrap().is_none());
+
+        // A partial whose bytes no longer match the source is started over
+        fs::write(partial_path(&target), vec![0xFFu8; 1024]).unwrap();
+        sync_manager.state.save_partial_copy(&PartialCopy {
+            source_path: source.clone(),
+            target_path: target.clone(),
+            expected_size: contents.len() as u64,
+            source_hash: hash.clone(),
+        }).unwrap();
+        sync_manager.copy_resumable(&file, &target, false, None).await.unwrap();
+        assert_eq!(fs::read(&target).unwrap(), contents);
+    }
+
+    #[tokio::test]
+    async fn test_source_changed_mid_copy_keeps_previous_copy() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        sync_manager.config.sync.same_device_strategy = SameDeviceStrategy::Copy;
+        drives.connect("TestUSB", drive.path());
+
+        let photo = source.path().join("photo.jpg");
+        fs::write(&photo, b"first").unwrap();
+        sync_manager.sync_file(&photo).await.unwrap();
+        let previous = sync_manager.state.get_file_state(&photo).unwrap().unwrap();
+
+        // Written to again after it was fingerprinted and hashed
+        fs::write(&photo, b"second").unwrap();
+        let hash = calculate_file_hash(&photo, HashAlgorithm::Blake3).unwrap();
+        let file_info = sync_manager.file_info(&photo).unwrap();
+        let file = Outgoing {
+            source_path: &photo,
+            relative_path: Path::new("photo.jpg"),
+            file_info: &file_info,
+            category: "images",
+            hash: &hash,
+            chunks: None,
+            fingerprint: Some((5, SystemTime::UNIX_EPOCH)),
+        };
+        let drive_config = sync_manager.config.drives["test-drive"].clone();
+        let placed = sync_manager.place_on_drive(&file, "test-drive", &drive_config, Some(&previous)).await.unwrap();
+        assert!(matches!(placed, Placement::SourceChanged));
+        assert_eq!(fs::read(&previous.target_path).unwrap(), b"first");
+        assert!(!partial_path(&previous.target_path).exists());
+    }
+
+    #[tokio::test]
+    async fn test_staged_files_wait_for_commit() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        sync_manager.config.sync.staging = true;
+        drives.connect("TestUSB", drive.path());
+
+        let photo = source.path().join("trip/photo.jpg");
+        fs::create_dir(source.path().join("trip")).unwrap();
+        fs::write(&photo, b"photo").unwrap();
+        let staged = drive.path().join(".staging/images/trip/photo.jpg");
+        let target = drive.path().join("images/trip/photo.jpg");
+
+        assert!(matches!(sync_manager.sync_file(&photo).await.unwrap(), SyncResult::Staged(ref path) if *path == staged));
+        assert!(!target.exists());
+        assert!(sync_manager.state.get_file_state(&photo).unwrap().is_none());
+        assert!(matches!(sync_manager.sync_file(&photo).await.unwrap(), SyncResult::AlreadySynced));
+
+        let commit = sync_manager.commit_staged(None).await.unwrap();
+        assert_eq!(commit.committed, vec![target.clone()]);
+        assert_eq!(fs::read(&target).unwrap(), b"photo");
+        assert!(!drive.path().join(".staging").exists());
+        assert_eq!(sync_manager.state.get_file_state(&photo).unwrap().unwrap().target_path, target);
+        assert!(sync_manager.state.get_all_staged().unwrap().is_empty());
+
+        // An edit is staged beside the committed copy, which stays until
+        // the discard
+        fs::write(&photo, b"edited").unwrap();
+        assert!(matches!(sync_manager.sync_file(&photo).await.unwrap(), SyncResult::Staged(_)));
+        assert_eq!(sync_manager.discard_staged(Some("other-drive")).await.unwrap(), 0);
+        assert_eq!(sync_manager.discard_staged(Some("test-drive")).await.unwrap(), 1);
+        assert!(!staged.exists());
+        assert_eq!(fs::read(&target).unwrap(), b"photo");
+        assert!(sync_manager.state.get_all_staged().unwrap().is_empty());
+
+        // Both a discard and a commit can be undone
+        assert_eq!(sync_manager.undo_batch(None).unwrap().restaged, 1);
+        assert_eq!(fs::read(&staged).unwrap(), b"edited");
+        assert_eq!(sync_manager.state.get_all_staged().unwrap().len(), 1);
+        sync_manager.commit_staged(None).await.unwrap();
+        assert_eq!(fs::read(&target).unwrap(), b"edited");
+        let report = sync_manager.undo_batch(None).unwrap();
+        assert_eq!((report.deleted, report.requeued.clone()), (1, vec![photo.clone()]));
+        assert!(!target.exists());
+    }
+
+    #[tokio::test]
+    async fn test_staged_overwrite_commits_over_foreign_file() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        sync_manager.config.sync.staging = true;
+        sync_manager.config.sync.conflict_policy = ConflictPolicy::Overwrite;
+        drives.connect("TestUSB", drive.path());
+
+        let target = drive.path().join("images/photo.jpg");
+        fs::create_dir_all(target.parent().unwrap()).unwrap();
+        fs::write(&target, b"already there").unwrap();
+        let photo = source.path().join("photo.jpg");
+        fs::write(&photo, b"new photo").unwrap();
+
+        assert!(matches!(sync_manager.sync_file(&photo).await.unwrap(), SyncResult::Staged(_)));
+        assert_eq!(sync_manager.state.get_all_staged().unwrap()[0].conflict, Some(ConflictPolicy::Overwrite));
+        let commit = sync_manager.commit_staged(None).await.unwrap();
+        assert_eq!((commit.committed, commit.failed.len()), (vec![target.clone()], 0));
+        assert_eq!(fs::read(&target).unwrap(), b"new photo");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_grown_file_keeps_unchanged_chunks() {
+        use std::io::Write;
+        use std::os::unix::fs::MetadataExt;
+
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        sync_manager.config.sync.chunk_hash = Some(HASH_CHUNK_SIZE as u64);
+        sync_manager.config.sync.same_device_strategy = SameDeviceStrategy::Copy;
+        drives.connect("TestUSB", drive.path());
+
+        let recording = source.path().join("recording.jpg");
+        let mut contents: Vec<u8> = (0..2 * HASH_CHUNK_SIZE + 100).map(|i| (i % 251) as u8).collect();
+        fs::write(&recording, &contents).unwrap();
+        sync_manager.sync_file(&recording).await.unwrap();
+        let target = drive.path().join("images/recording.jpg");
+        let chunks = sync_manager.state.get_chunk_hashes(&target).unwrap().unwrap();
+        assert_eq!(chunks.chunks.len(), 2);
+        let inode = fs::metadata(&target).unwrap().ino();
+
+        // Appending keeps the copy and its two full chunks
+        let tail = vec![9u8; HASH_CHUNK_SIZE];
+        fs::OpenOptions::new().append(true).open(&recording).unwrap().write_all(&tail).unwrap();
+        contents.extend_from_slice(&tail);
+        assert!(matches!(sync_manager.sync_file(&recording).await.unwrap(), SyncResult::Synced(_)));
+        assert_eq!(fs::read(&target).unwrap(), contents);
+        assert_eq!(fs::metadata(&target).unwrap().ino(), inode);
+        assert!(!partial_path(&target).exists() && !tail_path(&target).exists());
+        let record = sync_manager.state.get_file_state(&recording).unwrap().unwrap();
+        let chunks = sync_manager.state.get_chunk_hashes(&target).unwrap().unwrap();
+        assert_eq!((chunks.hash, chunks.chunks.len()), (record.hash, 3));
+
+        // An edit inside the first chunk leaves nothing to keep
+        contents[10] ^= 0xFF;
+        fs::write(&recording, &contents).unwrap();
+        sync_manager.sync_file(&recording).await.unwrap();
+        assert_eq!(fs::read(&target).unwrap(), contents);
+        assert_ne!(fs::metadata(&target).unwrap().ino(), inode);
+
+        // Forgetting the file forgets its copy's pieces
+        sync_manager.state.remove_file_state(&recording).unwrap();
+        assert!(sync_manager.state.get_chunk_hashes(&target).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_reroute_moves_copy_to_new_drive() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let raw_drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        drives.connect("TestUSB", drive.path());
+        drives.connect("RawUSB", raw_drive.path());
+
+        let photo = source.path().join("photo.jpg");
+        fs::write(&photo, b"jpeg").unwrap();
+        sync_manager.sync_file(&photo).await.unwrap();
+        let old_copy = drive.path().join("images").join("photo.jpg");
+        assert!(old_copy.exists());
+
+        // A drive dedicated to jpgs now takes them ahead of the general one
+        sync_manager.config.drives.insert(
+            "raw-drive".to_string(),
+            DriveConfig {
+                label: "RawUSB".to_string(),
+                target: "images".to_string(),
+                path: Some(raw_drive.path().to_path_buf()),
+                accept_extensions: Some(vec!["jpg".to_string()]),
+                ..Default::default()
+            },
+        );
+
+        let plan = sync_manager.reroute(true).await.unwrap();
+        assert_eq!(plan.moves.len(), 1);
+        assert!(old_copy.exists());
+
+        let report = sync_manager.reroute(false).await.unwrap();
+        assert_eq!(report.moves.len(), 1);
+        assert!(!old_copy.exists());
+        assert!(raw_drive.path().join("images").join("photo.jpg").exists());
+        let state = sync_manager.state.get_file_state(&photo).unwrap().unwrap();
+        assert_eq!(state.target_drive, "raw-drive");
+
+        assert!(sync_manager.reroute(true).await.unwrap().moves.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_sync_all_records_failures() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+
+        // No drive handles videos, so this file must fail
+        let video = source.path().join("clip.mp4");
+        fs::write(&video, b"not really a video").unwrap();
+
+        let config = test_config(source.path(), drive.path());
+        let state = StateManager::new(db.path().join("state.db")).unwrap();
+        let mut sync_manager = SyncManager::new(config, state);
+
+        let summary = sync_manager.sync_all().await.unwrap();
+
+        assert_eq!(summary.failed, 1);
+        assert_eq!(summary.failures.len(), 1);
+        assert_eq!(summary.failures[0].0, video);
+        assert!(summary.failures[0].1.contains("videos"));
+    }
+
+    #[test]
+    fn test_rename_policy_picks_free_name() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+
+        let target = drive.path().join("images").join("photo.jpg");
+        fs::create_dir_all(target.parent().unwrap()).unwrap();
+        fs::write(&target, b"someone else's photo").unwrap();
+
+        let mut config = test_config(source.path(), drive.path());
+        config.sync.conflict_policy = ConflictPolicy::Rename;
+        let state = StateManager::new(db.path().join("state.db")).unwrap();
+        let sync_manager = SyncManager::new(config, state);
+
+        let hash = blake3::hash(b"my photo").to_hex().to_string();
+        match sync_manager.resolve_target(target.clone(), &hash, None, false, false).unwrap() {
+            TargetResolution::Conflict(ConflictPolicy::Rename, path) => {
+                assert_eq!(path, drive.path().join("images").join("photo-1.jpg"));
+            }
+            _ => panic!("expected a rename"),
+        }
+
+        // Our own earlier copy is not a conflict
+        match sync_manager.resolve_target(target.clone(), &hash, Some(&target), false, false).unwrap() {
+            TargetResolution::Clear(path) => assert_eq!(path, target),
+            _ => panic!("expected the original path"),
+        }
+    }
+
+    #[test]
+    fn test_sort_pending() {
+        let pending = |name: &str, size, created_at| PendingSync {
+            source_path: PathBuf::from(name),
+            file_category: "images".to_string(),
+            target_drive: "test-drive".to_string(),
+            hash: String::new(),
+            size,
+            created_at,
+            replica: false,
+        };
+        let order = |queue: &[PendingSync]| -> Vec<String> {
+            queue.iter().map(|p| p.source_path.display().to_string()).collect()
+        };
+
+        let mut queue = vec![pending("big", 300, 1), pending("small", 10, 3), pending("mid", 10, 2)];
+
+        sort_pending(&mut queue, PendingOrder::Fifo);
+        assert_eq!(order(&queue), ["big", "mid", "small"]);
+
+        sort_pending(&mut queue, PendingOrder::SmallestFirst);
+        assert_eq!(order(&queue), ["mid", "small", "big"]);
+
+        sort_pending(&mut queue, PendingOrder::LargestFirst);
+        assert_eq!(order(&queue), ["big", "mid", "small"]);
+    }
+
+    #[tokio::test]
+    async fn test_compressed_copy_hashes_like_source() {
+        let dir = TempDir::new().unwrap();
+        let source = dir.path().join("notes.txt");
+        fs::write(&source, "compress me ".repeat(1000)).unwrap();
+
+        let target = compressed_path(&dir.path().join("copy").join("notes.txt"));
+        assert_eq!(target.file_name().unwrap(), "notes.txt.zst");
+        fs::create_dir_all(target.parent().unwrap()).unwrap();
+
+        let size = compress_file(&source, &target, TransientRetry::default()).await.unwrap();
+        assert!(size < fs::metadata(&source).unwrap().len());
+        assert_eq!(
+            hash_stored_file(&target, true, HashAlgorithm::Blake3).unwrap(),
+            calculate_file_hash(&source, HashAlgorithm::Blake3).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sync_directory_picks_up_nested_files() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+
+        // A whole tree appearing at once, as when a folder is moved in
+        let nested = source.path().join("trip").join("day1").join("raw");
+        fs::create_dir_all(&nested).unwrap();
+        for dir in ["trip", "trip/day1", "trip/day1/raw"] {
+            fs::write(source.path().join(dir).join("photo.jpg"), dir.as_bytes()).unwrap();
+        }
+
+        let mut config = test_config(source.path(), drive.path());
+        // A reachable network path counts as connected without a real mount
+        config.drives.get_mut("test-drive").unwrap().network = true;
+        let state = StateManager::new(db.path().join("state.db")).unwrap();
+        let mut sync_manager = SyncManager::new(config, state);
+
+        let summary = sync_manager.sync_directory(&source.path().join("trip")).await.unwrap();
+
+        assert_eq!(summary.synced, 3);
+        for dir in ["trip", "trip/day1", "trip/day1/raw"] {
+            assert!(drive.path().join("images").join(dir).join("photo.jpg").exists());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sync_modified_since_skips_old_files() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+
+        fs::write(source.path().join("new.jpg"), b"new").unwrap();
+        let old = source.path().join("old.jpg");
+        fs::write(&old, b"old").unwrap();
+        let week_ago = SystemTime::now() - std::time::Duration::from_secs(7 * 24 * 60 * 60);
+        fs::File::options().write(true).open(&old).unwrap().set_modified(week_ago).unwrap();
+
+        let mut config = test_config(source.path(), drive.path());
+        config.drives.get_mut("test-drive").unwrap().network = true;
+        let state = StateManager::new(db.path().join("state.db")).unwrap();
+        let mut sync_manager = SyncManager::new(config, state);
+
+        let cutoff = SystemTime::now() - std::time::Duration::from_secs(24 * 60 * 60);
+        let (summary, filtered) = sync_manager.sync_modified_since(cutoff).await.unwrap();
+
+        assert_eq!(summary.synced, 1);
+        assert_eq!(filtered, 1);
+        assert!(drive.path().join("images").join("new.jpg").exists());
+        assert!(!drive.path().join("images").join("old.jpg").exists());
+    }
+
+    #[tokio::test]
+    async fn test_catch_up_includes_unsynced_old_files() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        drives.connect("TestUSB", drive.path());
+
+        let week_ago = SystemTime::now() - std::time::Duration::from_secs(7 * 24 * 60 * 60);
+        let synced = source.path().join("synced.jpg");
+        fs::write(&synced, b"synced").unwrap();
+        sync_manager.sync_file(&synced).await.unwrap();
+        fs::File::options().write(true).open(&synced).unwrap().set_modified(week_ago).unwrap();
+
+        // Arrived while the watcher was down, keeping its old timestamp
+        let imported = source.path().join("imported.jpg");
+        fs::write(&imported, b"imported").unwrap();
+        fs::File::options().write(true).open(&imported).unwrap().set_modified(week_ago).unwrap();
+
+        let cutoff = SystemTime::now() - std::time::Duration::from_secs(60 * 60);
+        let (summary, unchanged) = sync_manager.catch_up_since(cutoff).await.unwrap();
+
+        assert_eq!(summary.synced, 1);
+        assert_eq!(summary.already_synced, 0);
+        assert_eq!(unchanged, 1);
+        assert!(drive.path().join("images").join("imported.jpg").exists());
+
+        // Rewritten keeping its old timestamp, and the last run failed to copy it
+        fs::write(&synced, b"rewritten").unwrap();
+        fs::File::options().write(true).open(&synced).unwrap().set_modified(week_ago).unwrap();
+        let failed = SyncSummary { failures: vec![(synced.clone(), "drive went away".to_string())], ..Default::default() };
+        sync_manager.record_run("catch-up", 0, &failed);
+
+        let (summary, unchanged) = sync_manager.catch_up_since(cutoff).await.unwrap();
+        assert_eq!(summary.synced, 1);
+        assert_eq!(unchanged, 1);
+        assert_eq!(fs::read(drive.path().join("images/synced.jpg")).unwrap(), b"rewritten");
+    }
+
+    #[test]
+    fn test_scan_honours_include_dirs_and_depth() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+
+        for file in ["top.jpg", "photos/a.jpg", "photos/deep/b.jpg", "other/c.jpg"] {
+            let path = source.path().join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, file).unwrap();
+        }
+
+        let mut config = test_config(source.path(), drive.path());
+        config.source.include_dirs = Some(vec![PathBuf::from("photos")]);
+        config.source.scan_max_depth = Some(1);
+        let state = StateManager::new(db.path().join("state.db")).unwrap();
+        let sync_manager = SyncManager::new(config, state);
+
+        let mut files = Vec::new();
+        sync_manager.collect_files_recursive(source.path(), &mut files).unwrap();
+
+        assert_eq!(files, vec![source.path().join("photos").join("a.jpg")]);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_special_and_empty_files_are_skipped() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let (mut sync_manager, drives) = mock_drive_manager(source.path(), drive.path(), db.path());
+        drives.connect("TestUSB", drive.path());
+
+        // Opening a FIFO for reading blocks until a writer turns up
+        let fifo = source.path().join("pipe.jpg");
+        let name = std::ffi::CString::new(fifo.as_os_str().as_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(name.as_ptr(), 0o644) }, 0);
+        let empty = source.path().join("empty.jpg");
+        fs::write(&empty, b"").unwrap();
+
+        assert_eq!(sync_manager.collect_files(source.path()).unwrap(), vec![empty.clone()]);
+        match sync_manager.sync_file(&fifo).await.unwrap() {
+            SyncResult::Skipped(reason) => assert_eq!(reason, "Not a regular file"),
+            other => panic!("{:?}", other),
+        }
+        assert!(!drive.path().join("images/pipe.jpg").exists());
+        assert!(matches!(sync_manager.sync_file(&empty).await.unwrap(), SyncResult::Synced(_)));
+
+        sync_manager.config.sync.sync_empty_files = false;
+        let another = source.path().join("another.jpg");
+        fs::write(&another, b"").unwrap();
+        assert!(sync_manager.collect_files(source.path()).unwrap().is_empty());
+        assert!(matches!(sync_manager.sync_file(&another).await.unwrap(), SyncResult::Skipped(_)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_dir_cache_skips_folders_until_they_change() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+
+        let album = source.path().join("album");
+        fs::create_dir(&album).unwrap();
+        fs::write(album.join("a.jpg"), b"a").unwrap();
+        let hour_ago = SystemTime::now() - std::time::Duration::from_secs(60 * 60);
+        let age = |dir: &Path| fs::File::open(dir).unwrap().set_modified(hour_ago).unwrap();
+        age(&album);
+        age(source.path());
+
+        let mut config = test_config(source.path(), drive.path());
+        config.source.dir_cache = true;
+        let state = StateManager::new(db.path().join("state.db")).unwrap();
+        let sync_manager = SyncManager::new(config.clone(), state.clone());
+        assert_eq!(sync_manager.collect_files(source.path()).unwrap(), vec![album.join("a.jpg")]);
+        assert!(state.get_dir_listing(&album).unwrap().is_some());
+
+        // A new file whose folder time doesn't show it stays unseen...
+        fs::write(album.join("b.jpg"), b"b").unwrap();
+        age(&album);
+        assert_eq!(sync_manager.collect_files(source.path()).unwrap().len(), 1);
+        // ...until a full walk, or the watcher's event, brings it in
+        let full_walk = SyncManager::new(config, state.clone()).with_full_walk();
+        assert_eq!(full_walk.collect_files(source.path()).unwrap().len(), 2);
+
+        fs::write(album.join("c.jpg"), b"c").unwrap();
+        age(&album);
+        sync_manager.forget_dir_listing(&album.join("c.jpg"));
+        assert_eq!(sync_manager.collect_files(source.path()).unwrap().len(), 3);
+
+        // A removed folder's listing goes with it
+        fs::remove_dir_all(&album).unwrap();
+        age(source.path());
+        sync_manager.forget_dir_listing(&album);
+        assert!(sync_manager.collect_files(source.path()).unwrap().is_empty());
+        assert!(state.get_dir_listing(&album).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_pulled_files_are_not_pushed_back() {
+        let source = TempDir::new().unwrap();
+        let drive = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+
+        let on_drive = drive.path().join("images").join("phone").join("new.jpg");
+        fs::create_dir_all(on_drive.parent().unwrap()).unwrap();
+        fs::write(&on_drive, b"taken on the go").unwrap();
+
+        let mut config = test_config(source.path(), drive.path());
+        config.drives.get_mut("test-drive").unwrap().network = true;
+        let state = StateManager::new(db.path().join("state.db")).unwrap();
+        let mut sync_manager = SyncManager::new(config, state);
+
+        let summary = sync_manager.pull_from_drive("test-drive").await.unwrap();
+        assert_eq!(summary.synced, 1);
+
+        let pulled = source.path().join("phone").join("new.jpg");
+        assert_eq!(fs::read(&pulled).unwrap(), b"taken on the go");
+        assert!(matches!(sync_manager.sync_file(&pulled).await.unwrap(), SyncResult::AlreadySynced));
+
+        // A second pull finds nothing new
+        let summary = sync_manager.pull_from_drive("test-drive").await.unwrap();
+        assert_eq!(summary.synced, 0);
+        assert_eq!(summary.already_synced, 1);
+
+        // A source synced to another drive keeps that record and its tags
+        let kept = source.path().join("kept.jpg");
+        fs::write(&kept, b"kept").unwrap();
+        fs::write(drive.path().join("images/kept.jpg"), b"kept").unwrap();
+        let record = sync_manager.state.get_file_state(&pulled).unwrap().unwrap();
+        sync_manager.state.save_file_state(&FileState {
+            source_path: kept.clone(),
+            hash: calculate_file_hash(&kept, record.hash_algorithm).unwrap(),
+            target_drive: "other-drive".to_string(),
+            target_path: PathBuf::from("/elsewhere/kept.jpg"),
+            direction: SyncDirection::Push,
+            ..record
+        }).unwrap();
+        sync_manager.state.add_tag(&kept, "keep").unwrap();
+
+        let summary = sync_manager.pull_from_drive("test-drive").await.unwrap();
+        assert_eq!(summary.synced, 1);
+        let record = sync_manager.state.get_file_state(&kept).unwrap().unwrap();
+        assert_eq!(record.target_drive, "other-drive");
+        assert_eq!(record.tags, vec!["keep".to_string()]);
+        assert_eq!(record.copy_on("test-drive").unwrap().target_path, drive.path().join("images/kept.jpg"));
     }
 }
@@ -0,0 +1,322 @@
+use std::collections::VecDeque;
+use std::io::{self, Stdout};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::execute;
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListItem, Paragraph, Row, Table};
+use ratatui::{Frame, Terminal};
+
+use crate::config::Config;
+use crate::error::Result;
+use crate::lock::InstanceLock;
+use crate::state::{DriveStats, FileState, StateManager, SyncStats};
+use crate::sync::{SyncManager, SyncResult};
+use crate::watcher::{AsyncFileWatcher, FileEvent};
+
+const TICK: Duration = Duration::from_millis(250);
+const DRIVE_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+const MAX_EVENTS: usize = 200;
+const RECENT_SYNCS: usize = 15;
+
+type Term = Terminal<CrosstermBackend<Stdout>>;
+
+/// Terminal dashboard: drive status, pending queue, recent syncs and live
+/// watcher events, with watching toggled from the keyboard
+pub async fn run_tui(config_path: &Path, db_path: &Path) -> Result<()> {
+    let config = Config::load(config_path)?;
+    let _lock = InstanceLock::acquire(db_path)?;
+    let state = StateManager::new(db_path)?;
+    let sync_manager = SyncManager::new(config.clone(), state).with_config_path(config_path).with_command("tui");
+
+    let mut app = TuiApp {
+        config,
+        sync_manager,
+        watcher: None,
+        last_drive_check: Instant::now(),
+        events: VecDeque::new(),
+        snapshot: Snapshot::default(),
+        settling: Vec::new(),
+    };
+    app.refresh();
+
+    let mut terminal = setup_terminal()?;
+    let result = app.run(&mut terminal).await;
+    restore_terminal(&mut terminal)?;
+
+    result
+}
+
+/// Figures shown on screen, re-read after anything that changes them
+#[derive(Default)]
+struct Snapshot {
+    stats: Option<SyncStats>,
+    by_drive: std::collections::HashMap<String, DriveStats>,
+    connected: Vec<String>,
+    /// Connected but not writable
+    read_only: Vec<String>,
+    /// Connected but past `pause_at_percent_full`, with how full they are
+    full: Vec<(String, f64)>,
+    recent: Vec<FileState>,
+}
+
+struct TuiApp {
+    config: Config,
+    sync_manager: SyncManager,
+    watcher: Option<AsyncFileWatcher>,
+    last_drive_check: Instant,
+    events: VecDeque<String>,
+    snapshot: Snapshot,
+    /// Files still being written, and when to try them again
+    settling: Vec<(PathBuf, Instant)>,
+}
+
+impl TuiApp {
+    async fn run(&mut self, terminal: &mut Term) -> Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+
+            // Keyboard input is polled between ticks so watcher events keep flowing
+            while event::poll(Duration::ZERO)? {
+                if let Event::Key(key) = event::read()? {
+                    if key.kind != KeyEventKind::Press {
+                        continue;
+                    }
+                    match key.code {
+                        KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                        KeyCode::Char('w') => self.toggle_watching(),
+                        KeyCode::Char('s') => self.sync_all().await,
+                        KeyCode::Char('r') => self.refresh(),
+                        _ => {}
+                    }
+                }
+            }
+
+            let event = match self.watcher.as_mut() {
+                Some(watcher) => tokio::time::timeout(TICK, watcher.next_event()).await.ok().flatten(),
+                None => {
+                    tokio::time::sleep(TICK).await;
+                    None
+                }
+            };
+
+            if let Some(event) = event {
+                self.handle_event(event).await;
+            }
+
+            let now = Instant::now();
+            let (due, waiting) = std::mem::take(&mut self.settling).into_iter().partition(|(_, at)| *at <= now);
+            self.settling = waiting;
+            for (path, _) in due {
+                self.handle_event(FileEvent::Modified(path)).await;
+            }
+
+            if self.watcher.is_some() && self.last_drive_check.elapsed() >= DRIVE_CHECK_INTERVAL {
+                self.last_drive_check = Instant::now();
+                if let Err(e) = self.sync_manager.check_and_sync_connected_drives().await {
+                    self.log(format!("Drive check failed: {}", e));
+                }
+                self.refresh();
+            }
+        }
+    }
+
+    fn toggle_watching(&mut self) {
+        if self.watcher.take().is_some() {
+            self.log("Stopped watching".to_string());
+            return;
+        }
+
+        match AsyncFileWatcher::watch(&self.config.source.path, self.config.source.watch_queue_capacity) {
+            Ok(watcher) => {
+                self.watcher = Some(watcher);
+                self.log(format!("Watching {}", self.config.source.path.display()));
+            }
+            Err(e) => self.log(format!("Failed to start watcher: {}", e)),
+        }
+    }
+
+    async fn sync_all(&mut self) {
+        self.log("Running full sync...".to_string());
+        match self.sync_manager.sync_all().await {
+            Ok(summary) => self.log(format!(
+                "Full sync: {} synced, {} pending, {} already synced, {} failed",
+                summary.synced, summary.pending, summary.already_synced, summary.failed
+            )),
+            Err(e) => self.log(format!("Full sync failed: {}", e)),
+        }
+        self.refresh();
+    }
+
+    async fn handle_event(&mut self, event: FileEvent) {
+        match event {
+            FileEvent::Created(path) | FileEvent::Modified(path) => {
+                if !self.config.source.in_scope(&path) {
+                    return;
+                }
+
+                let message = match self.sync_manager.sync_file(&path).await {
+                    Ok(SyncResult::Synced(target)) => format!("Synced {} -> {}", path.display(), target.display()),
+                    Ok(SyncResult::Pending(drive)) => format!("Queued {} for {}", path.display(), drive),
+                    Ok(SyncResult::DriveReadOnly(drive)) => {
+                        format!("Queued {}: {} is read-only", path.display(), drive)
+                    }
+                    Ok(SyncResult::DriveFull(drive)) => {
+                        format!("Queued {}: {} is nearly full, syncing to it is paused", path.display(), drive)
+                    }
+                    Ok(SyncResult::WouldExceedCapacity(drive)) => {
+                        format!("Not queued {}: {} would be over capacity", path.display(), drive)
+                    }
+                    Ok(SyncResult::AlreadySynced) => return,
+                    Ok(SyncResult::Skipped(reason)) => format!("Skipped {}: {}", path.display(), reason),
+                    Ok(SyncResult::Quarantined(target)) => {
+                        format!("Quarantined {} -> {}", path.display(), target.display())
+                    }
+                    Ok(SyncResult::Staged(target)) => format!("Staged {} -> {}", path.display(), target.display()),
+                    Ok(SyncResult::Conflict(policy, target)) => {
+                        format!("Conflict ({:?}) {} -> {}", policy, path.display(), target.display())
+                    }
+                    Ok(SyncResult::Unsettled(wait)) => {
+                        self.settling.retain(|(settling, _)| *settling != path);
+                        self.settling.push((path.clone(), Instant::now() + wait));
+                        format!("Waiting for {} to finish writing", path.display())
+                    }
+                    Err(e) => format!("Failed {}: {}", path.display(), e),
+                };
+                self.log(message);
+            }
+            FileEvent::DirectoryCreated(path) if !self.config.source.should_descend(&path) => return,
+            FileEvent::DirectoryCreated(path) => match self.sync_manager.sync_directory(&path).await {
+                Ok(summary) => self.log(format!(
+                    "Scanned {}: {} synced, {} pending",
+                    path.display(), summary.synced, summary.pending
+                )),
+                Err(e) => self.log(format!("Failed to scan {}: {}", path.display(), e)),
+            },
+            FileEvent::Removed(path) => self.log(format!("Removed {}", path.display())),
+        }
+        self.refresh();
+    }
+
+    fn refresh(&mut self) {
+        self.snapshot = Snapshot {
+            stats: self.sync_manager.get_stats().ok(),
+            by_drive: self.sync_manager.get_stats_by_drive().unwrap_or_default(),
+            connected: self.sync_manager.connected_drives(),
+            read_only: self.sync_manager.read_only_drives(),
+            full: self.sync_manager.full_drives(),
+            recent: self.sync_manager.recent_syncs(RECENT_SYNCS).unwrap_or_default(),
+        };
+    }
+
+    fn log(&mut self, message: String) {
+        let time = chrono::Local::now().format("%H:%M:%S");
+        self.events.push_front(format!("{} {}", time, message));
+        self.events.truncate(MAX_EVENTS);
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [header, drives, recent, events, footer] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Length(self.config.drives.len() as u16 + 3),
+            Constraint::Percentage(40),
+            Constraint::Min(5),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let (watch_text, watch_color) = if self.watcher.is_some() {
+            ("WATCHING", Color::Green)
+        } else {
+            ("STOPPED", Color::Red)
+        };
+        let (total, pending) = self
+            .snapshot
+            .stats
+            .as_ref()
+            .map(|s| (s.total_files, s.pending_syncs))
+            .unwrap_or_default();
+        let summary = Line::from(vec![
+            ratatui::text::Span::styled(watch_text, Style::default().fg(watch_color).add_modifier(Modifier::BOLD)),
+            format!("  Source: {}  Synced: {}  Pending: {}", self.config.source.path.display(), total, pending).into(),
+        ]);
+        frame.render_widget(Paragraph::new(summary).block(Block::bordered().title("File Orchestrator")), header);
+
+        let mut registered: Vec<_> = self.config.drives.iter().collect();
+        registered.sort_by(|a, b| a.1.label.cmp(&b.1.label));
+        let rows = registered.into_iter().map(|(uuid, drive)| {
+            let stats = self.snapshot.by_drive.get(uuid).cloned().unwrap_or_default();
+            let connected = self.snapshot.connected.contains(uuid);
+            let read_only = self.snapshot.read_only.contains(uuid);
+            let full = self.snapshot.full.iter().find(|(full, _)| full == uuid).map(|(_, percent)| *percent);
+            let (status, color) = match (connected, read_only, full) {
+                (true, true, _) => ("read-only".to_string(), Color::Yellow),
+                (true, false, Some(percent)) => (format!("{:.0}% full, paused", percent), Color::Yellow),
+                (true, false, None) => ("connected".to_string(), Color::Green),
+                _ => ("disconnected".to_string(), Color::DarkGray),
+            };
+            Row::new(vec![
+                drive.label.clone(),
+                drive.target.clone(),
+                status,
+                stats.file_count.to_string(),
+                stats.pending_count.to_string(),
+            ])
+            .style(Style::default().fg(color))
+        });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Percentage(30),
+                Constraint::Percentage(20),
+                Constraint::Percentage(20),
+                Constraint::Percentage(15),
+                Constraint::Percentage(15),
+            ],
+        )
+        .header(Row::new(["Drive", "Category", "Status", "Files", "Pending"]).style(Style::default().add_modifier(Modifier::BOLD)))
+        .block(Block::bordered().title("Drives"));
+        frame.render_widget(table, drives);
+
+        let recent_items: Vec<ListItem> = self
+            .snapshot
+            .recent
+            .iter()
+            .map(|state| {
+                let time = chrono::DateTime::from_timestamp(state.last_synced as i64, 0)
+                    .map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+                    .unwrap_or_default();
+                ListItem::new(format!("{} [{}] {}", time, state.file_category, state.source_path.display()))
+            })
+            .collect();
+        frame.render_widget(List::new(recent_items).block(Block::bordered().title("Recent syncs")), recent);
+
+        let event_items: Vec<ListItem> = self.events.iter().map(|e| ListItem::new(e.as_str())).collect();
+        frame.render_widget(List::new(event_items).block(Block::bordered().title("Events")), events);
+
+        frame.render_widget(
+            Paragraph::new(" w: start/stop watching   s: sync all   r: refresh   q: quit"),
+            footer,
+        );
+    }
+}
+
+fn setup_terminal() -> Result<Term> {
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen)?;
+    Ok(Terminal::new(CrosstermBackend::new(stdout))?)
+}
+
+fn restore_terminal(terminal: &mut Term) -> Result<()> {
+    disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    terminal.show_cursor()?;
+    Ok(())
+}
@@ -1,16 +1,113 @@
 use notify::{Config, Event, RecommendedWatcher, RecursiveMode, Watcher as NotifyWatcher, EventKind};
-use std::path::Path;
-use std::sync::mpsc::{channel, Receiver};
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
 use std::time::Duration;
 use tokio::sync::mpsc as tokio_mpsc;
+use tokio::sync::mpsc::error::TrySendError;
 use crate::error::{OrchestratorError, Result};
 use tracing::{info, warn, error};
 
+/// How often events held back by a full channel are offered again when no
+/// new ones arrive
+const BACKLOG_RETRY: Duration = Duration::from_millis(50);
+
 #[derive(Debug, Clone)]
 pub enum FileEvent {
     Created(std::path::PathBuf),
     Modified(std::path::PathBuf),
     Removed(std::path::PathBuf),
+    /// A new directory appeared; files written into it before the watch on
+    /// it was registered may not produce their own events
+    DirectoryCreated(std::path::PathBuf),
+}
+
+impl FileEvent {
+    fn path(&self) -> &Path {
+        match self {
+            FileEvent::Created(path)
+            | FileEvent::Modified(path)
+            | FileEvent::Removed(path)
+            | FileEvent::DirectoryCreated(path) => path,
+        }
+    }
+
+    /// One event standing for `self` followed by `later` on the same path.
+    /// A file is synced as it is when its event is handled, so a creation
+    /// followed by edits is still a creation, and the last word otherwise wins.
+    fn merge(self, later: FileEvent) -> FileEvent {
+        match (self, later) {
+            (earlier @ (FileEvent::Created(_) | FileEvent::DirectoryCreated(_)), FileEvent::Modified(_)) => earlier,
+            (FileEvent::Removed(_), FileEvent::Modified(path)) => FileEvent::Created(path),
+            (_, later) => later,
+        }
+    }
+}
+
+/// Events that didn't fit in the channel yet, at most one per path, in the
+/// order their paths first came up
+#[derive(Default)]
+struct Backlog {
+    order: VecDeque<PathBuf>,
+    events: HashMap<PathBuf, FileEvent>,
+    /// Events folded into one already waiting since the backlog was last empty
+    merged: usize,
+}
+
+impl Backlog {
+    fn push(&mut self, event: FileEvent) {
+        let path = event.path().to_path_buf();
+        match self.events.remove(&path) {
+            Some(earlier) => {
+                if self.merged == 0 {
+                    warn!("File events are arriving faster than they can be synced; merging repeated events for the same file");
+                }
+                self.merged += 1;
+                self.events.insert(path, earlier.merge(event));
+            }
+            None => {
+                self.order.push_back(path.clone());
+                self.events.insert(path, event);
+            }
+        }
+    }
+
+    fn pop(&mut self) -> Option<FileEvent> {
+        let event = self.order.pop_front().and_then(|path| self.events.remove(&path));
+        if self.order.is_empty() && self.merged > 0 {
+            info!("Caught up with a burst of file events; {} repeated event(s) were merged", self.merged);
+            self.merged = 0;
+        }
+        event
+    }
+
+    fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    /// Move waiting events into the channel while it has room. Once as many
+    /// paths are waiting as the channel holds, wait for room rather than
+    /// letting the backlog grow further. Returns false once the receiver is gone.
+    fn forward(&mut self, sender: &tokio_mpsc::Sender<FileEvent>) -> bool {
+        while !self.is_empty() {
+            match sender.try_reserve() {
+                Ok(permit) => permit.send(self.pop().expect("backlog is not empty")),
+                Err(TrySendError::Full(())) if self.len() < sender.max_capacity() => return true,
+                Err(TrySendError::Full(())) => {
+                    let event = self.pop().expect("backlog is not empty");
+                    if sender.blocking_send(event).is_err() {
+                        return false;
+                    }
+                }
+                Err(TrySendError::Closed(())) => return false,
+            }
+        }
+        true
+    }
 }
 
 pub struct FileWatcher {
@@ -54,7 +151,6 @@ impl FileWatcher {
     }
 
     /// Stop watching a directory
-    #[allow(dead_code)]
     pub fn unwatch<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
         let path = path.as_ref();
         
@@ -65,35 +161,44 @@ impl FileWatcher {
         Ok(())
     }
 
-    /// Process events and send simplified file events to a channel
-    pub async fn process_events(
-        &mut self,
-        event_sender: tokio_mpsc::UnboundedSender<FileEvent>,
-    ) -> Result<()> {
+    /// Process events and send simplified file events to a channel. Blocks,
+    /// so it runs on a thread of its own. While the channel is full, events
+    /// wait in a backlog where repeated ones for the same file are merged.
+    pub fn process_events(&mut self, event_sender: tokio_mpsc::Sender<FileEvent>) -> Result<()> {
+        let mut backlog = Backlog::default();
         loop {
-            match self.event_rx.recv() {
+            let received = if backlog.is_empty() {
+                self.event_rx.recv().map_err(|_| RecvTimeoutError::Disconnected)
+            } else {
+                self.event_rx.recv_timeout(BACKLOG_RETRY)
+            };
+
+            match received {
                 Ok(Ok(event)) => {
                     if let Some(file_event) = Self::convert_event(event) {
-                        if let Err(e) = event_sender.send(file_event.clone()) {
-                            error!("Failed to send file event to channel: {}", e);
-                            break;
-                        }
-                        
-                        match file_event {
+                        match &file_event {
                             FileEvent::Created(path) => info!("File created: {}", path.display()),
                             FileEvent::Modified(path) => info!("File modified: {}", path.display()),
                             FileEvent::Removed(path) => info!("File removed: {}", path.display()),
+                            FileEvent::DirectoryCreated(path) => info!("Directory created: {}", path.display()),
                         }
+                        backlog.push(file_event);
                     }
                 }
                 Ok(Err(e)) => {
                     warn!("Watch error: {}", e);
                 }
-                Err(e) => {
-                    error!("Failed to receive event: {}", e);
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    error!("Failed to receive event: watcher stopped");
                     break;
                 }
             }
+
+            if !backlog.forward(&event_sender) {
+                error!("Failed to send file event to channel: receiver closed");
+                break;
+            }
         }
 
         Ok(())
@@ -107,9 +212,13 @@ impl FileWatcher {
 
         let path = event.paths[0].clone();
 
-        // Filter out directories and only process files
+        // Filter out directories and only process files, except that a new
+        // directory is reported so its contents can be scanned
         if path.is_dir() {
-            return None;
+            return match event.kind {
+                EventKind::Create(_) => Some(FileEvent::DirectoryCreated(path)),
+                _ => None,
+            };
         }
 
         match event.kind {
@@ -123,13 +232,14 @@ impl FileWatcher {
 
 /// A simplified async file watcher that can be used in a tokio runtime
 pub struct AsyncFileWatcher {
-    event_rx: tokio_mpsc::UnboundedReceiver<FileEvent>,
+    event_rx: tokio_mpsc::Receiver<FileEvent>,
 }
 
 impl AsyncFileWatcher {
-    /// Create a new async file watcher and start watching a path
-    pub fn watch<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let (tx, rx) = tokio_mpsc::unbounded_channel();
+    /// Create a new async file watcher and start watching a path, holding up
+    /// to `capacity` events that haven't been received yet
+    pub fn watch<P: AsRef<Path>>(path: P, capacity: usize) -> Result<Self> {
+        let (tx, rx) = tokio_mpsc::channel(capacity.max(1));
         let path = path.as_ref().to_path_buf();
 
         // Spawn a blocking thread to handle the sync watcher
@@ -147,13 +257,9 @@ impl AsyncFileWatcher {
                 return;
             }
 
-            // Create a tokio runtime for the blocking thread
-            let rt = tokio::runtime::Runtime::new().unwrap();
-            rt.block_on(async {
-                if let Err(e) = watcher.process_events(tx).await {
-                    error!("Error processing events: {}", e);
-                }
-            });
+            if let Err(e) = watcher.process_events(tx) {
+                error!("Error processing events: {}", e);
+            }
         });
 
         Ok(Self { event_rx: rx })
@@ -163,12 +269,22 @@ impl AsyncFileWatcher {
     pub async fn next_event(&mut self) -> Option<FileEvent> {
         self.event_rx.recv().await
     }
+
+    /// Stop taking new events and return the ones already received but not
+    /// yet handled
+    pub fn close(&mut self) -> Vec<FileEvent> {
+        self.event_rx.close();
+        let mut events = Vec::new();
+        while let Ok(event) = self.event_rx.try_recv() {
+            events.push(event);
+        }
+        events
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs;
     use tempfile::TempDir;
 
     #[tokio::test]
@@ -185,4 +301,50 @@ mod tests {
         let result = watcher.watch(temp_dir.path());
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_full_channel_merges_repeated_events() {
+        let (tx, mut rx) = tokio_mpsc::channel(2);
+        let mut backlog = Backlog::default();
+        let path = |name: &str| PathBuf::from("/src").join(name);
+
+        for event in [
+            FileEvent::Created(path("a.jpg")),
+            FileEvent::Created(path("b.jpg")),
+            FileEvent::Created(path("c.jpg")),
+            FileEvent::Modified(path("c.jpg")),
+            FileEvent::Modified(path("c.jpg")),
+        ] {
+            backlog.push(event);
+            assert!(backlog.forward(&tx));
+        }
+        assert_eq!((backlog.len(), backlog.merged), (1, 2));
+
+        assert!(matches!(rx.try_recv(), Ok(FileEvent::Created(p)) if p == path("a.jpg")));
+        backlog.push(FileEvent::Removed(path("d.jpg")));
+        backlog.push(FileEvent::Modified(path("d.jpg")));
+        assert!(backlog.forward(&tx));
+        assert!(matches!(rx.try_recv(), Ok(FileEvent::Created(p)) if p == path("b.jpg")));
+        assert!(backlog.forward(&tx));
+
+        // Still in first-seen order, c's edits folded into its creation
+        assert!(matches!(rx.try_recv(), Ok(FileEvent::Created(p)) if p == path("c.jpg")));
+        assert!(matches!(rx.try_recv(), Ok(FileEvent::Created(p)) if p == path("d.jpg")));
+        assert!(rx.try_recv().is_err());
+        assert!(backlog.is_empty());
+        assert_eq!(backlog.merged, 0);
+    }
+
+    #[test]
+    fn test_directory_create_event() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().join("new");
+        std::fs::create_dir(&dir).unwrap();
+
+        let create = Event::new(EventKind::Create(notify::event::CreateKind::Folder)).add_path(dir.clone());
+        assert!(matches!(FileWatcher::convert_event(create), Some(FileEvent::DirectoryCreated(p)) if p == dir));
+
+        let modify = Event::new(EventKind::Modify(notify::event::ModifyKind::Any)).add_path(dir);
+        assert!(FileWatcher::convert_event(modify).is_none());
+    }
 }
@@ -0,0 +1,157 @@
+//! End-to-end syncs through the public API: a temp source folder, temp
+//! "drives" plugged in through `MockDriveProvider`, a temp state database
+//! and a config loaded from TOML.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use file_orchestrator::config::Config;
+use file_orchestrator::drive::MockDriveProvider;
+use file_orchestrator::state::StateManager;
+use file_orchestrator::sync::{SyncManager, SyncResult};
+use tempfile::TempDir;
+
+struct Setup {
+    source: TempDir,
+    photos: TempDir,
+    docs: TempDir,
+    _db: TempDir,
+    drives: MockDriveProvider,
+    state: StateManager,
+    sync_manager: SyncManager,
+}
+
+/// Source with an image, a document in a subfolder and a song; drives
+/// for images (PhotoUSB) and documents (DocsUSB), both unplugged
+fn setup() -> Setup {
+    let source = TempDir::new().unwrap();
+    let photos = TempDir::new().unwrap();
+    let docs = TempDir::new().unwrap();
+    let db = TempDir::new().unwrap();
+
+    fs::write(source.path().join("photo.jpg"), b"not really a jpeg").unwrap();
+    fs::create_dir(source.path().join("work")).unwrap();
+    fs::write(source.path().join("work/report.pdf"), b"not really a pdf").unwrap();
+    fs::write(source.path().join("song.mp3"), b"not really an mp3").unwrap();
+
+    let config_path = db.path().join("config.toml");
+    fs::write(
+        &config_path,
+        format!(
+            r#"
+[source]
+path = {:?}
+
+[rules]
+images = ["jpg"]
+videos = ["mp4"]
+music = ["mp3"]
+documents = ["pdf"]
+
+[drives."photo-drive"]
+label = "PhotoUSB"
+target = "images"
+path = {:?}
+
+[drives."docs-drive"]
+label = "DocsUSB"
+target = "documents"
+path = {:?}
+"#,
+            source.path().display().to_string(),
+            photos.path().display().to_string(),
+            docs.path().display().to_string(),
+        ),
+    )
+    .unwrap();
+
+    let config = Config::load(&config_path).unwrap();
+    let state = StateManager::new(db.path().join("state.db")).unwrap();
+    let drives = MockDriveProvider::default();
+    let sync_manager = SyncManager::new(config, state.clone()).with_drive_provider(drives.clone());
+
+    Setup { source, photos, docs, _db: db, drives, state, sync_manager }
+}
+
+fn target(drive: &TempDir, relative: &str) -> PathBuf {
+    drive.path().join(relative)
+}
+
+fn source_file(setup: &Setup, relative: &str) -> PathBuf {
+    setup.source.path().join(relative)
+}
+
+fn assert_recorded(state: &StateManager, path: &Path, drive: &str, category: &str) {
+    let record = state.get_file_state(path).unwrap().expect("no sync record");
+    assert_eq!(record.target_drive, drive);
+    assert_eq!(record.file_category, category);
+    assert_eq!(record.size, fs::metadata(path).unwrap().len());
+    assert!(record.target_path.exists(), "{:?} missing", record.target_path);
+}
+
+#[tokio::test]
+async fn full_sync_sorts_files_by_category_and_is_idempotent() {
+    let mut setup = setup();
+    setup.drives.connect("PhotoUSB", setup.photos.path());
+    setup.drives.connect("DocsUSB", setup.docs.path());
+
+    let summary = setup.sync_manager.sync_all().await.unwrap();
+    assert_eq!(summary.synced, 2);
+    // Music has no drive to go to
+    assert_eq!(summary.failed, 1);
+    assert_eq!(summary.failures[0].0, source_file(&setup, "song.mp3"));
+    assert!(summary.failures[0].1.contains("music"), "{}", summary.failures[0].1);
+
+    assert_eq!(fs::read(target(&setup.photos, "images/photo.jpg")).unwrap(), b"not really a jpeg");
+    assert_eq!(fs::read(target(&setup.docs, "documents/work/report.pdf")).unwrap(), b"not really a pdf");
+    assert!(!target(&setup.photos, "music").exists());
+    assert!(!target(&setup.docs, "music").exists());
+
+    assert_recorded(&setup.state, &source_file(&setup, "photo.jpg"), "photo-drive", "images");
+    assert_recorded(&setup.state, &source_file(&setup, "work/report.pdf"), "docs-drive", "documents");
+    assert!(setup.state.get_file_state(&source_file(&setup, "song.mp3")).unwrap().is_none());
+
+    let again = setup.sync_manager.sync_all().await.unwrap();
+    assert_eq!(again.synced, 0);
+    assert_eq!(again.already_synced, 2);
+
+    let photo = source_file(&setup, "photo.jpg");
+    assert!(matches!(setup.sync_manager.sync_file(&photo).await.unwrap(), SyncResult::AlreadySynced));
+}
+
+#[tokio::test]
+async fn changed_file_is_copied_again() {
+    let mut setup = setup();
+    setup.drives.connect("PhotoUSB", setup.photos.path());
+    let photo = source_file(&setup, "photo.jpg");
+    setup.sync_manager.sync_file(&photo).await.unwrap();
+
+    fs::write(&photo, b"edited since").unwrap();
+    assert!(matches!(setup.sync_manager.sync_file(&photo).await.unwrap(), SyncResult::Synced(_)));
+    assert_eq!(fs::read(target(&setup.photos, "images/photo.jpg")).unwrap(), b"edited since");
+    assert_recorded(&setup.state, &photo, "photo-drive", "images");
+}
+
+#[tokio::test]
+async fn disconnected_drive_queues_files_until_it_reconnects() {
+    let mut setup = setup();
+    setup.drives.connect("DocsUSB", setup.docs.path());
+
+    let photo = source_file(&setup, "photo.jpg");
+    assert!(matches!(setup.sync_manager.sync_file(&photo).await.unwrap(), SyncResult::Pending(_)));
+    let report = source_file(&setup, "work/report.pdf");
+    assert!(matches!(setup.sync_manager.sync_file(&report).await.unwrap(), SyncResult::Synced(_)));
+
+    let pending = setup.state.get_all_pending_syncs().unwrap();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].source_path, photo);
+    assert!(setup.state.get_file_state(&photo).unwrap().is_none());
+    assert!(!target(&setup.photos, "images/photo.jpg").exists());
+
+    setup.drives.connect("PhotoUSB", setup.photos.path());
+    setup.sync_manager.check_and_sync_connected_drives().await.unwrap();
+
+    assert!(setup.state.get_all_pending_syncs().unwrap().is_empty());
+    assert!(target(&setup.photos, "images/photo.jpg").exists());
+    assert_recorded(&setup.state, &photo, "photo-drive", "images");
+}